@@ -0,0 +1,60 @@
+//! Drives a complementary PWM pair with dead time on TIM1
+
+#![deny(unsafe_code)]
+// #![deny(warnings)]
+#![no_std]
+#![no_main]
+
+extern crate cortex_m;
+#[macro_use]
+extern crate cortex_m_rt as rt;
+extern crate panic_semihosting;
+extern crate stm32l4xx_hal as hal;
+
+use crate::hal::prelude::*;
+use crate::hal::pwm::{self, BreakPolarity, BreakSource};
+use crate::rt::ExceptionFrame;
+use crate::rt::entry;
+
+#[entry]
+fn main() -> ! {
+    let dp = hal::stm32::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.ahb2);
+
+    // TIM1_CH1 / TIM1_CH1N
+    let ch1 = gpioa.pa8.into_af1(&mut gpioa.moder, &mut gpioa.afrh);
+    let ch1n = gpioa.pa7.into_af1(&mut gpioa.moder, &mut gpioa.afrl);
+    // TIM1_CH2
+    let ch2 = gpioa.pa9.into_af1(&mut gpioa.moder, &mut gpioa.afrh);
+    // TIM1_BKIN
+    let _bkin = gpioa.pa6.into_af1(&mut gpioa.moder, &mut gpioa.afrl);
+
+    let ((mut high_side, mut aux), mut bdtr) =
+        pwm::tim1(dp.TIM1, ((ch1, ch1n), ch2), 20.khz(), clocks, &mut rcc.apb2).unwrap();
+
+    bdtr.set_dead_time(500);
+    bdtr.enable_break_source(BreakSource::Pin);
+    bdtr.enable_break(BreakPolarity::ActiveLow);
+    bdtr.set_automatic_output(true);
+    bdtr.enable_outputs();
+
+    let max = high_side.get_max_duty();
+    high_side.set_duty(max / 2);
+    high_side.enable();
+
+    aux.set_duty(max / 4);
+    aux.enable();
+
+    loop {}
+}
+
+#[exception]
+fn HardFault(ef: &ExceptionFrame) -> ! {
+    panic!("{:#?}", ef);
+}
@@ -67,7 +67,7 @@ fn main() -> ! {
     );
 
     // nss.set_low();
-    let data = [0x3C];
+    let data = [0x3C_u8];
     spi.write(&data).unwrap();
     spi.write(&data).unwrap();
     spi.write(&data).unwrap();
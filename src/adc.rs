@@ -0,0 +1,990 @@
+//! Analog to digital converter
+//!
+//! Single conversions are available through the `OneShot` trait. For sampling synchronized with
+//! a timer, e.g. motor current measurements centered on the PWM period, the regular channel can
+//! be started on an external trigger and an injected sequence of up to four channels can be
+//! configured with its own trigger and result registers. Channels can be converted
+//! differentially, and the hardware oversampler averages up to 256 conversions into one result.
+//!
+//! ``` ignore
+//! let mut adc = Adc::adc1(dp.ADC, clocks, &mut rcc.ahb2);
+//! let mut ia = gpioa.pa0.into_analog(&mut gpioa.moder, &mut gpioa.pupdr);
+//! let mut ib = gpioa.pa1.into_analog(&mut gpioa.moder, &mut gpioa.pupdr);
+//!
+//! adc.add_injected(&mut ia);
+//! adc.add_injected(&mut ib);
+//! adc.set_injected_trigger(InjectedTrigger::Tim1Trgo, TriggerEdge::Rising);
+//! adc.start_injected();
+//!
+//! // in the JEOS interrupt / polling loop
+//! block!(adc.wait_injected()).unwrap();
+//! let (a, b) = (adc.read_injected(0), adc.read_injected(1));
+//! ```
+//!
+//! Between samples, `power_down` puts the ADC in deep power down with its voltage regulator off,
+//! and `power_up` restarts it with the regulator startup time and restores its calibration;
+//! `sample` does both around a single conversion.
+//!
+//! ``` ignore
+//! adc.power_down();
+//!
+//! // every second
+//! let vbat: u16 = adc.sample(&mut pin);
+//! ```
+//!
+//! On the STM32L4x5/x6, ADC1 and ADC2 can be combined by `DualAdc` to convert two channels at
+//! the same instant, e.g. the voltage and the current of a load, or to interleave the
+//! conversions of one channel. Both results are read at once from the common data register,
+//! which can be streamed by DMA.
+//!
+//! ``` ignore
+//! let adc1 = Adc::adc1(dp.ADC1, clocks, &mut rcc.ahb2);
+//! let adc2 = Adc::adc2(dp.ADC2, clocks, &mut rcc.ahb2);
+//! let mut dual = DualAdc::new(adc1, adc2, dp.ADC_COMMON, DualMode::RegularSimultaneous);
+//!
+//! dual.set_external_trigger(ExternalTrigger::Tim6Trgo, TriggerEdge::Rising);
+//! dual.start(&mut voltage, &mut current);
+//! let samples = singleton!(: [u32; 64] = [0; 64]).unwrap();
+//! let (samples, chan, dual) = dual.read_dma(channels.1, samples).wait()?;
+//! // samples[i] & 0xffff is the voltage, samples[i] >> 16 the current
+//! ```
+
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+use core::sync::atomic::{self, Ordering};
+
+use core::ptr;
+
+use cast::u16;
+
+use crate::hal::adc::{Channel, OneShot};
+use nb;
+use void::Void;
+
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+use crate::stm32::ADC;
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+use crate::stm32::{ADC1, ADC2};
+#[cfg(feature = "stm32l4x5")]
+use crate::stm32::ADC123_COMMON as ADC_COMMON;
+#[cfg(feature = "stm32l4x6")]
+use crate::stm32::ADC_COMMON;
+use crate::stm32::RCC;
+
+use crate::gpio::gpioa::{PA0, PA1, PA2, PA3, PA4, PA5, PA6, PA7};
+use crate::gpio::gpiob::{PB0, PB1};
+use crate::gpio::gpioc::{PC0, PC1, PC2, PC3, PC4, PC5};
+use crate::gpio::Analog;
+use crate::rcc::{Clocks, AHB2};
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+use crate::dma::{dma1, Priority, Transfer, WriteBuffer, W};
+
+/// Sampling time, in ADC clock cycles
+#[derive(Clone, Copy)]
+pub enum SampleTime {
+    /// 2.5 cycles
+    Cycles2_5 = 0b000,
+    /// 6.5 cycles
+    Cycles6_5 = 0b001,
+    /// 12.5 cycles
+    Cycles12_5 = 0b010,
+    /// 24.5 cycles
+    Cycles24_5 = 0b011,
+    /// 47.5 cycles
+    Cycles47_5 = 0b100,
+    /// 92.5 cycles
+    Cycles92_5 = 0b101,
+    /// 247.5 cycles
+    Cycles247_5 = 0b110,
+    /// 640.5 cycles
+    Cycles640_5 = 0b111,
+}
+
+/// Conversion resolution
+pub enum Resolution {
+    /// 12 bits
+    Bits12 = 0b00,
+    /// 10 bits
+    Bits10 = 0b01,
+    /// 8 bits
+    Bits8 = 0b10,
+    /// 6 bits
+    Bits6 = 0b11,
+}
+
+/// Oversampling ratio, i.e. the number of conversions accumulated into one result
+pub enum OversamplingRatio {
+    /// 2x
+    X2 = 0b000,
+    /// 4x
+    X4 = 0b001,
+    /// 8x
+    X8 = 0b010,
+    /// 16x
+    X16 = 0b011,
+    /// 32x
+    X32 = 0b100,
+    /// 64x
+    X64 = 0b101,
+    /// 128x
+    X128 = 0b110,
+    /// 256x
+    X256 = 0b111,
+}
+
+/// Active edge of a hardware trigger
+pub enum TriggerEdge {
+    /// Rising edge
+    Rising = 0b01,
+    /// Falling edge
+    Falling = 0b10,
+    /// Both edges
+    Both = 0b11,
+}
+
+/// Hardware triggers of the regular channels
+pub enum ExternalTrigger {
+    /// TIM1 capture/compare 1
+    Tim1Cc1 = 0,
+    /// TIM1 capture/compare 2
+    Tim1Cc2 = 1,
+    /// TIM1 capture/compare 3
+    Tim1Cc3 = 2,
+    /// TIM2 capture/compare 2
+    Tim2Cc2 = 3,
+    /// TIM3 trigger output
+    Tim3Trgo = 4,
+    /// TIM4 capture/compare 4
+    Tim4Cc4 = 5,
+    /// EXTI line 11
+    Exti11 = 6,
+    /// TIM8 trigger output
+    Tim8Trgo = 7,
+    /// TIM8 trigger output 2
+    Tim8Trgo2 = 8,
+    /// TIM1 trigger output
+    Tim1Trgo = 9,
+    /// TIM1 trigger output 2
+    Tim1Trgo2 = 10,
+    /// TIM2 trigger output
+    Tim2Trgo = 11,
+    /// TIM4 trigger output
+    Tim4Trgo = 12,
+    /// TIM6 trigger output
+    Tim6Trgo = 13,
+    /// TIM15 trigger output
+    Tim15Trgo = 14,
+    /// TIM3 capture/compare 4
+    Tim3Cc4 = 15,
+}
+
+/// Hardware triggers of the injected channels
+pub enum InjectedTrigger {
+    /// TIM1 trigger output
+    Tim1Trgo = 0,
+    /// TIM1 capture/compare 4
+    Tim1Cc4 = 1,
+    /// TIM2 trigger output
+    Tim2Trgo = 2,
+    /// TIM2 capture/compare 1
+    Tim2Cc1 = 3,
+    /// TIM3 capture/compare 4
+    Tim3Cc4 = 4,
+    /// TIM4 trigger output
+    Tim4Trgo = 5,
+    /// EXTI line 15
+    Exti15 = 6,
+    /// TIM8 capture/compare 4
+    Tim8Cc4 = 7,
+    /// TIM1 trigger output 2
+    Tim1Trgo2 = 8,
+    /// TIM8 trigger output
+    Tim8Trgo = 9,
+    /// TIM8 trigger output 2
+    Tim8Trgo2 = 10,
+    /// TIM3 capture/compare 3
+    Tim3Cc3 = 11,
+    /// TIM3 trigger output
+    Tim3Trgo = 12,
+    /// TIM3 capture/compare 1
+    Tim3Cc1 = 13,
+    /// TIM6 trigger output
+    Tim6Trgo = 14,
+    /// TIM15 trigger output
+    Tim15Trgo = 15,
+}
+
+/// Interrupt events
+pub enum Event {
+    /// End of a regular conversion
+    EndOfConversion,
+    /// End of the injected sequence
+    EndOfInjectedSequence,
+}
+
+/// CCR of the ADC common registers, missing from the PAC of the STM32L4x1/x2/x3
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+const COMMON_CCR: *mut u32 = 0x5004_0308 as *mut u32;
+/// CCR: VREFEN
+const VREFEN: u32 = 1 << 22;
+/// CCR: TSEN
+const TSEN: u32 = 1 << 23;
+/// VREFINT converted at 30 °C with VDDA at 3.0 V, 12 bits
+const VREFINT_CAL: *const u16 = 0x1FFF_75AA as *const u16;
+/// Temperature sensor converted at 30 °C with VDDA at 3.0 V, 12 bits
+const TS_CAL1: *const u16 = 0x1FFF_75A8 as *const u16;
+/// Temperature sensor converted at `TS_CAL2_TEMP` with VDDA at 3.0 V, 12 bits
+const TS_CAL2: *const u16 = 0x1FFF_75CA as *const u16;
+/// Temperature of `TS_CAL2`, in thousandths of °C
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+const TS_CAL2_TEMP: i64 = 130_000;
+/// Temperature of `TS_CAL2`, in thousandths of °C
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+const TS_CAL2_TEMP: i64 = 110_000;
+
+/// Sets or clears `bits` of the common CCR
+fn set_common(bits: u32, set: bool) {
+    // NOTE(unsafe) the common CCR is only written in critical sections
+    cortex_m::interrupt::free(|_| unsafe {
+        #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+        {
+            let ccr = ptr::read_volatile(COMMON_CCR);
+            let ccr = if set { ccr | bits } else { ccr & !bits };
+            ptr::write_volatile(COMMON_CCR, ccr);
+        }
+        #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+        (*ADC_COMMON::ptr()).ccr.modify(|r, w| {
+            w.bits(if set { r.bits() | bits } else { r.bits() & !bits })
+        });
+    });
+}
+
+/// Internal voltage reference (VREFINT), converted on channel 0 of ADC1 once enabled by
+/// `Adc::enable_vref`
+///
+/// Its conversion measures VDDA, which the other conversions are relative to. The sample time
+/// has to be at least 4 us, e.g. `SampleTime::Cycles640_5` at 80 MHz.
+pub struct Vref;
+
+impl Vref {
+    /// Returns the factory conversion of VREFINT, with VDDA at 3.0 V
+    pub fn calibration() -> u16 {
+        // NOTE(unsafe) read of the system memory
+        unsafe { ptr::read_volatile(VREFINT_CAL) }
+    }
+
+    /// Returns VDDA in millivolts, from a 12-bit conversion of VREFINT
+    pub fn vdda(sample: u16) -> u32 {
+        3000 * u32::from(Self::calibration()) / u32::from(sample).max(1)
+    }
+}
+
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+impl Channel<ADC> for Vref {
+    type ID = u8;
+
+    fn channel() -> u8 {
+        0
+    }
+}
+
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+impl Channel<ADC1> for Vref {
+    type ID = u8;
+
+    fn channel() -> u8 {
+        0
+    }
+}
+
+/// Internal temperature sensor, converted on channel 17 of ADC1 once enabled by
+/// `Adc::enable_temperature`
+///
+/// The sample time has to be at least 5 us, e.g. `SampleTime::Cycles640_5` at 80 MHz.
+pub struct Temperature;
+
+impl Temperature {
+    /// Returns the temperature in thousandths of °C, from a 12-bit conversion of the sensor and
+    /// VDDA in millivolts (see `Vref::vdda`)
+    pub fn millicelsius(sample: u16, vdda: u32) -> i32 {
+        // NOTE(unsafe) reads of the system memory
+        let (cal1, cal2) = unsafe { (ptr::read_volatile(TS_CAL1), ptr::read_volatile(TS_CAL2)) };
+        // the calibration values are converted with VDDA at 3.0 V
+        let sample = i64::from(sample) * i64::from(vdda) / 3000;
+        let (cal1, cal2) = (i64::from(cal1), i64::from(cal2));
+        (30_000 + (sample - cal1) * (TS_CAL2_TEMP - 30_000) / (cal2 - cal1).max(1)) as i32
+    }
+}
+
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+impl Channel<ADC> for Temperature {
+    type ID = u8;
+
+    fn channel() -> u8 {
+        17
+    }
+}
+
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+impl Channel<ADC1> for Temperature {
+    type ID = u8;
+
+    fn channel() -> u8 {
+        17
+    }
+}
+
+/// Analog to digital converter
+pub struct Adc<ADC> {
+    adc: ADC,
+    sample_time: SampleTime,
+    injected: u8,
+    /// Startup time of the voltage regulator, in cycles of the system clock
+    regulator_delay: u32,
+    /// System clock frequency, in MHz rounded up
+    sysclk_mhz: u32,
+    /// Calibration factors, kept during the deep power down
+    calibration: u32,
+}
+
+macro_rules! hal {
+    ($($ADC:ident: ($adcX:ident),)+) => {
+        $(
+            impl Adc<$ADC> {
+                /// Powers up, calibrates and enables the ADC
+                ///
+                /// The ADC is clocked from the system clock.
+                pub fn $adcX(adc: $ADC, clocks: Clocks, ahb: &mut AHB2) -> Self {
+                    ahb.enr().modify(|_, w| w.adcen().set_bit());
+
+                    // NOTE(unsafe) ADCSEL is only ever written by this driver
+                    unsafe { (*RCC::ptr()).ccipr.modify(|_, w| w.adcsel().bits(0b11)) };
+
+                    // exit deep power down and start the voltage regulator
+                    // tADCVREG_STUP = 20 us
+                    let regulator_delay = clocks.sysclk().0 / 50_000 + 1;
+                    adc.cr.modify(|_, w| w.deeppwd().clear_bit());
+                    adc.cr.modify(|_, w| w.advregen().set_bit());
+                    cortex_m::asm::delay(regulator_delay);
+
+                    // single ended and differential calibration
+                    adc.cr.modify(|_, w| w.adcaldif().clear_bit().adcal().set_bit());
+                    while adc.cr.read().adcal().bit_is_set() {}
+                    adc.cr.modify(|_, w| w.adcaldif().set_bit().adcal().set_bit());
+                    while adc.cr.read().adcal().bit_is_set() {}
+
+                    let mut adc = Adc {
+                        adc,
+                        sample_time: SampleTime::Cycles47_5,
+                        injected: 0,
+                        regulator_delay,
+                        sysclk_mhz: clocks.sysclk().0.div_ceil(1_000_000),
+                        calibration: 0,
+                    };
+                    adc.enable();
+                    adc.calibration = adc.adc.calfact.read().bits();
+
+                    adc
+                }
+
+                fn enable(&mut self) {
+                    self.adc.isr.write(|w| w.adrdy().set_bit());
+                    self.adc.cr.modify(|_, w| w.aden().set_bit());
+                    while self.adc.isr.read().adrdy().bit_is_clear() {}
+                    self.adc.isr.write(|w| w.adrdy().set_bit());
+                }
+
+                fn disable(&mut self) {
+                    self.stop_regular();
+                    self.stop_injected();
+                    self.adc.cr.modify(|_, w| w.addis().set_bit());
+                    while self.adc.cr.read().aden().bit_is_set() {}
+                }
+
+                /// Enables or disables the internal voltage reference channel, see `Vref`
+                pub fn enable_vref(&mut self, enabled: bool) {
+                    set_common(VREFEN, enabled);
+                    if enabled {
+                        // tstart_vrefint = 12 us
+                        cortex_m::asm::delay(self.sysclk_mhz * 12);
+                    }
+                }
+
+                /// Enables or disables the temperature sensor channel, see `Temperature`
+                pub fn enable_temperature(&mut self, enabled: bool) {
+                    set_common(TSEN, enabled);
+                    if enabled {
+                        // tstart = 120 us
+                        cortex_m::asm::delay(self.sysclk_mhz * 120);
+                    }
+                }
+
+                /// Selects whether `pin` is converted single ended or differentially
+                ///
+                /// In differential mode the channel is measured against the next channel, e.g.
+                /// IN5 (PA0) against IN6 (PA1), which must be in analog mode as well and is no
+                /// longer usable on its own. The result is `2^(n-1) * (1 + (Vin+ - Vin-) / Vref)`
+                /// for an n-bit resolution. Stops all ongoing conversions.
+                pub fn set_differential<PIN>(&mut self, _pin: &mut PIN, differential: bool)
+                where
+                    PIN: Channel<$ADC, ID = u8>,
+                {
+                    let bit = 1 << PIN::channel();
+
+                    // DIFSEL can only be written while the ADC is disabled
+                    self.disable();
+                    self.adc.difsel.modify(|r, w| unsafe {
+                        w.bits(if differential { r.bits() | bit } else { r.bits() & !bit })
+                    });
+                    self.enable();
+                }
+
+                /// Enables the hardware oversampler for the regular and injected conversions
+                ///
+                /// `ratio` conversions are accumulated and the sum is shifted right by `shift`
+                /// bits (at most 8). The result must fit in 16 bits, e.g. `X256` with a shift of
+                /// 4 yields 16 bit results from a 12 bit resolution. Stops all ongoing conversions.
+                pub fn enable_oversampling(&mut self, ratio: OversamplingRatio, shift: u8) {
+                    assert!(shift <= 8);
+
+                    self.stop_regular();
+                    self.stop_injected();
+
+                    let ovsr = ratio as u32;
+                    let ovss = u32::from(shift);
+                    self.adc.cfgr2.modify(|r, w| unsafe {
+                        w.bits(
+                            (r.bits() & !0b111_1111_1111)
+                                | (ovss << 5)
+                                | (ovsr << 2)
+                                | 0b11, // JOVSE | ROVSE
+                        )
+                    });
+                }
+
+                /// Disables the hardware oversampler
+                pub fn disable_oversampling(&mut self) {
+                    self.stop_regular();
+                    self.stop_injected();
+
+                    self.adc.cfgr2.modify(|r, w| unsafe { w.bits(r.bits() & !0b11) });
+                }
+
+                /// Sets the sampling time used for the channels configured from now on
+                pub fn set_sample_time(&mut self, sample_time: SampleTime) {
+                    self.sample_time = sample_time;
+                }
+
+                /// Sets the conversion resolution
+                pub fn set_resolution(&mut self, resolution: Resolution) {
+                    let res = resolution as u32;
+                    self.adc
+                        .cfgr
+                        .modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << 3)) | (res << 3)) });
+                }
+
+                fn set_channel_sample_time(&mut self, channel: u8) {
+                    let smp = self.sample_time as u32;
+                    if channel < 10 {
+                        let offset = 3 * u32::from(channel);
+                        self.adc.smpr1.modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b111 << offset)) | (smp << offset))
+                        });
+                    } else {
+                        let offset = 3 * u32::from(channel - 10);
+                        self.adc.smpr2.modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b111 << offset)) | (smp << offset))
+                        });
+                    }
+                }
+
+                /// Converts the regular channel on every `edge` of `trigger` instead of by
+                /// software
+                pub fn set_external_trigger(&mut self, trigger: ExternalTrigger, edge: TriggerEdge) {
+                    let extsel = trigger as u32;
+                    let exten = edge as u32;
+                    self.adc.cfgr.modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(0b11_1111 << 6)) | (exten << 10) | (extsel << 6))
+                    });
+                }
+
+                /// Returns the regular channel to software triggered conversions
+                pub fn disable_external_trigger(&mut self) {
+                    self.adc
+                        .cfgr
+                        .modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << 10)) });
+                }
+
+                /// Starts converting `pin` on every external trigger
+                ///
+                /// The results are retrieved with `read_regular`.
+                pub fn start_regular<PIN>(&mut self, _pin: &mut PIN)
+                where
+                    PIN: Channel<$ADC, ID = u8>,
+                {
+                    let channel = PIN::channel();
+                    self.set_channel_sample_time(channel);
+                    // a sequence of length 1
+                    self.adc
+                        .sqr1
+                        .write(|w| unsafe { w.bits(u32::from(channel) << 6) });
+                    self.adc.isr.write(|w| w.eoc().set_bit().ovr().set_bit());
+                    self.adc.cr.modify(|_, w| w.adstart().set_bit());
+                }
+
+                /// Stops the regular conversions started by `start_regular`
+                pub fn stop_regular(&mut self) {
+                    if self.adc.cr.read().adstart().bit_is_set() {
+                        self.adc.cr.modify(|_, w| w.adstp().set_bit());
+                        while self.adc.cr.read().adstp().bit_is_set() {}
+                    }
+                }
+
+                /// Reads the result of the last regular conversion
+                pub fn read_regular(&mut self) -> nb::Result<u16, Void> {
+                    if self.adc.isr.read().eoc().bit_is_set() {
+                        // reading DR clears EOC
+                        Ok(u16(self.adc.dr.read().bits() & 0xffff).unwrap())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /// Appends `pin` to the injected sequence and returns its rank
+                ///
+                /// Panics if the sequence already holds four channels.
+                pub fn add_injected<PIN>(&mut self, _pin: &mut PIN) -> u8
+                where
+                    PIN: Channel<$ADC, ID = u8>,
+                {
+                    assert!(self.injected < 4);
+
+                    let channel = PIN::channel();
+                    self.set_channel_sample_time(channel);
+
+                    let rank = self.injected;
+                    let offset = 8 + 6 * u32::from(rank);
+                    self.adc.jsqr.modify(|r, w| unsafe {
+                        w.bits(
+                            (r.bits() & !(0b11 | (0b1_1111 << offset)))
+                                | (u32::from(channel) << offset)
+                                | u32::from(rank),
+                        )
+                    });
+                    self.injected += 1;
+
+                    rank
+                }
+
+                /// Empties the injected sequence
+                pub fn clear_injected(&mut self) {
+                    self.adc.jsqr.modify(|r, w| unsafe { w.bits(r.bits() & 0xff & !0b11) });
+                    self.injected = 0;
+                }
+
+                /// Starts the injected sequence on every `edge` of `trigger`
+                pub fn set_injected_trigger(&mut self, trigger: InjectedTrigger, edge: TriggerEdge) {
+                    let jextsel = trigger as u32;
+                    let jexten = edge as u32;
+                    self.adc.jsqr.modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(0b11_1111 << 2)) | (jexten << 6) | (jextsel << 2))
+                    });
+                }
+
+                /// Starts the injected sequence by software
+                pub fn disable_injected_trigger(&mut self) {
+                    self.adc.jsqr.modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << 6)) });
+                }
+
+                /// Arms the injected sequence
+                ///
+                /// With a hardware trigger the sequence then runs on every trigger edge,
+                /// otherwise it runs once right away.
+                pub fn start_injected(&mut self) {
+                    assert!(self.injected > 0);
+
+                    self.adc.isr.write(|w| w.jeos().set_bit().jeoc().set_bit());
+                    self.adc.cr.modify(|_, w| w.jadstart().set_bit());
+                }
+
+                /// Stops the injected conversions started by `start_injected`
+                pub fn stop_injected(&mut self) {
+                    if self.adc.cr.read().jadstart().bit_is_set() {
+                        self.adc.cr.modify(|_, w| w.jadstp().set_bit());
+                        while self.adc.cr.read().jadstp().bit_is_set() {}
+                    }
+                }
+
+                /// Waits for the end of the injected sequence and clears the flag
+                pub fn wait_injected(&mut self) -> nb::Result<(), Void> {
+                    if self.adc.isr.read().jeos().bit_is_set() {
+                        self.adc.isr.write(|w| w.jeos().set_bit().jeoc().set_bit());
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /// Reads the result of the injected conversion of `rank`
+                pub fn read_injected(&self, rank: u8) -> u16 {
+                    let bits = match rank {
+                        0 => self.adc.jdr1.read().bits(),
+                        1 => self.adc.jdr2.read().bits(),
+                        2 => self.adc.jdr3.read().bits(),
+                        3 => self.adc.jdr4.read().bits(),
+                        _ => panic!("invalid injected rank"),
+                    };
+                    bits as u16
+                }
+
+                /// Starts listening for an `event`
+                pub fn listen(&mut self, event: Event) {
+                    match event {
+                        Event::EndOfConversion => self.adc.ier.modify(|_, w| w.eocie().set_bit()),
+                        Event::EndOfInjectedSequence => {
+                            self.adc.ier.modify(|_, w| w.jeosie().set_bit())
+                        }
+                    }
+                }
+
+                /// Stops listening for an `event`
+                pub fn unlisten(&mut self, event: Event) {
+                    match event {
+                        Event::EndOfConversion => {
+                            self.adc.ier.modify(|_, w| w.eocie().clear_bit())
+                        }
+                        Event::EndOfInjectedSequence => {
+                            self.adc.ier.modify(|_, w| w.jeosie().clear_bit())
+                        }
+                    }
+                }
+
+                /// Disables the ADC, stops its voltage regulator and enters deep power down
+                ///
+                /// The ADC draws no current until `power_up`, which restores the calibration and
+                /// keeps the configuration. Stops all ongoing conversions; the other methods
+                /// mustn't be called meanwhile.
+                pub fn power_down(&mut self) {
+                    if self.is_powered_down() {
+                        return;
+                    }
+
+                    self.disable();
+                    self.adc.cr.modify(|_, w| w.advregen().clear_bit());
+                    self.adc.cr.modify(|_, w| w.deeppwd().set_bit());
+                }
+
+                /// Leaves deep power down, waits for the voltage regulator to start, and enables
+                /// the ADC with its calibration restored
+                pub fn power_up(&mut self) {
+                    if !self.is_powered_down() {
+                        return;
+                    }
+
+                    self.adc.cr.modify(|_, w| w.deeppwd().clear_bit());
+                    self.adc.cr.modify(|_, w| w.advregen().set_bit());
+                    cortex_m::asm::delay(self.regulator_delay);
+                    self.enable();
+
+                    // the calibration factors are lost in deep power down, and only writable
+                    // while the ADC is enabled and not converting
+                    let calibration = self.calibration;
+                    self.adc.calfact.write(|w| unsafe { w.bits(calibration) });
+                }
+
+                /// Returns `true` if the ADC is in deep power down, see `power_down`
+                pub fn is_powered_down(&self) -> bool {
+                    self.adc.cr.read().deeppwd().bit_is_set()
+                }
+
+                /// Powers up the ADC if needed, converts `pin` once and powers the ADC down
+                pub fn sample<WORD, PIN>(&mut self, pin: &mut PIN) -> WORD
+                where
+                    WORD: From<u16>,
+                    PIN: Channel<$ADC, ID = u8>,
+                {
+                    self.power_up();
+                    let value = nb::block!(self.read(pin)).unwrap();
+                    self.power_down();
+
+                    value
+                }
+
+                /// Disables the ADC and releases the peripheral
+                pub fn free(mut self) -> $ADC {
+                    self.power_down();
+                    self.adc
+                }
+            }
+
+            impl<WORD, PIN> OneShot<$ADC, WORD, PIN> for Adc<$ADC>
+            where
+                WORD: From<u16>,
+                PIN: Channel<$ADC, ID = u8>,
+            {
+                type Error = Void;
+
+                fn read(&mut self, _pin: &mut PIN) -> nb::Result<WORD, Void> {
+                    let channel = PIN::channel();
+                    self.set_channel_sample_time(channel);
+
+                    // software trigger for this conversion only
+                    let cfgr = self.adc.cfgr.read().bits();
+                    self.adc.cfgr.write(|w| unsafe { w.bits(cfgr & !(0b11 << 10)) });
+
+                    self.adc
+                        .sqr1
+                        .write(|w| unsafe { w.bits(u32::from(channel) << 6) });
+                    self.adc.isr.write(|w| w.eoc().set_bit());
+                    self.adc.cr.modify(|_, w| w.adstart().set_bit());
+                    while self.adc.isr.read().eoc().bit_is_clear() {}
+                    let value = u16(self.adc.dr.read().bits() & 0xffff).unwrap();
+
+                    self.adc.cfgr.write(|w| unsafe { w.bits(cfgr) });
+
+                    Ok(WORD::from(value))
+                }
+            }
+
+            // the DAC outputs are converted on their pins, PA4 and PA5
+            impl Channel<$ADC> for crate::dac::C1 {
+                type ID = u8;
+
+                fn channel() -> u8 {
+                    9
+                }
+            }
+
+            impl Channel<$ADC> for crate::dac::C2 {
+                type ID = u8;
+
+                fn channel() -> u8 {
+                    10
+                }
+            }
+
+            channels!($ADC: [
+                PC0: 1,
+                PC1: 2,
+                PC2: 3,
+                PC3: 4,
+                PA0: 5,
+                PA1: 6,
+                PA2: 7,
+                PA3: 8,
+                PA4: 9,
+                PA5: 10,
+                PA6: 11,
+                PA7: 12,
+                PC4: 13,
+                PC5: 14,
+                PB0: 15,
+                PB1: 16,
+            ]);
+        )+
+    }
+}
+
+macro_rules! channels {
+    ($ADC:ident: [$($PXi:ident: $channel:expr,)+]) => {
+        $(
+            impl Channel<$ADC> for $PXi<Analog> {
+                type ID = u8;
+
+                fn channel() -> u8 {
+                    $channel
+                }
+            }
+        )+
+    }
+}
+
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+hal! {
+    ADC: (adc1),
+}
+
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+hal! {
+    ADC1: (adc1),
+    ADC2: (adc2),
+}
+
+/// Dual mode of ADC1 and ADC2
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DualMode {
+    /// Both ADCs start converting their channel on the same trigger
+    RegularSimultaneous,
+    /// Both ADCs convert the same channel, ADC2 starting `delay` ADC clock cycles (5 to 12)
+    /// after ADC1, doubling the sample rate
+    Interleaved {
+        /// Delay between the two conversions, in ADC clock cycles
+        delay: u8,
+    },
+}
+
+/// CCR: MDMA, DELAY and DUAL
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+const DUAL_MASK: u32 = (0b11 << 14) | (0b1111 << 8) | 0b1_1111;
+
+/// ADC1 and ADC2 converting together, ADC1 being the master
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub struct DualAdc {
+    master: Adc<ADC1>,
+    slave: Adc<ADC2>,
+    common: ADC_COMMON,
+}
+
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+impl DualAdc {
+    /// Combines ADC1 and ADC2 in `mode`
+    ///
+    /// The conversions are triggered by ADC1: the trigger of ADC2 is ignored. Each result of
+    /// the common data register holds the ADC1 result in its low half word, and the ADC2 result
+    /// in its high half word.
+    pub fn new(master: Adc<ADC1>, slave: Adc<ADC2>, common: ADC_COMMON, mode: DualMode) -> Self {
+        let mut dual = DualAdc {
+            master,
+            slave,
+            common,
+        };
+
+        // the dual mode can only be changed while both ADCs are disabled
+        dual.master.disable();
+        dual.slave.disable();
+        let (mode, delay) = match mode {
+            DualMode::RegularSimultaneous => (0b00110, 0),
+            DualMode::Interleaved { delay } => {
+                assert!((5..=12).contains(&delay));
+                (0b00111, delay - 5)
+            }
+        };
+        // MDMA: one 32-bit transfer for both results
+        let ccr = (0b10 << 14) | (u32::from(delay) << 8) | mode;
+        // see `set_common`
+        let common = &dual.common;
+        cortex_m::interrupt::free(|_| {
+            common
+                .ccr
+                .modify(|r, w| unsafe { w.bits((r.bits() & !DUAL_MASK) | ccr) })
+        });
+        dual.master.enable();
+        dual.slave.enable();
+
+        dual
+    }
+
+    /// Converts on every `edge` of `trigger` instead of by software
+    pub fn set_external_trigger(&mut self, trigger: ExternalTrigger, edge: TriggerEdge) {
+        self.master.set_external_trigger(trigger, edge);
+    }
+
+    /// Starts converting `master_pin` with ADC1 and `slave_pin` with ADC2 on every trigger
+    ///
+    /// In interleaved mode, both pins have to be the same channel. The results are retrieved
+    /// with `read` or `read_dma`.
+    pub fn start<P1, P2>(&mut self, master_pin: &mut P1, _slave_pin: &mut P2)
+    where
+        P1: Channel<ADC1, ID = u8>,
+        P2: Channel<ADC2, ID = u8>,
+    {
+        let channel = P2::channel();
+        self.slave.set_channel_sample_time(channel);
+        self.slave
+            .adc
+            .sqr1
+            .write(|w| unsafe { w.bits(u32::from(channel) << 6) });
+        self.slave.adc.isr.write(|w| w.eoc().set_bit().ovr().set_bit());
+
+        self.master.start_regular(master_pin);
+    }
+
+    /// Stops the conversions started by `start`
+    pub fn stop(&mut self) {
+        self.master.stop_regular();
+        self.slave.stop_regular();
+    }
+
+    /// Reads the results of the last conversions, of ADC1 and ADC2
+    pub fn read(&mut self) -> nb::Result<(u16, u16), Void> {
+        if self.master.adc.isr.read().eoc().bit_is_set()
+            && self.slave.adc.isr.read().eoc().bit_is_set()
+        {
+            let cdr = self.common.cdr.read().bits();
+            self.master.adc.isr.write(|w| w.eoc().set_bit());
+            self.slave.adc.isr.write(|w| w.eoc().set_bit());
+            Ok((cdr as u16, (cdr >> 16) as u16))
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Stores the results of the next conversions into `buffer` by DMA, see `new`
+    pub fn read_dma<B>(self, mut chan: dma1::C1, mut buffer: B) -> Transfer<W, B, dma1::C1, Self>
+    where
+        B: WriteBuffer<Word = u32>,
+    {
+        {
+            // NOTE(unsafe) the buffer is owned by the transfer until it's over
+            let (ptr, len) = unsafe { buffer.write_buffer() };
+            chan.cmar().write(|w| w.ma().bits(ptr as usize as u32));
+            chan.cndtr().write(|w| w.ndt().bits(u16(len).unwrap()));
+            chan.cpar().write(|w| {
+                w.pa().bits(&self.common.cdr as *const _ as usize as u32)
+            });
+
+            // Tell DMA to request from ADC1
+            chan.cselr().modify(|_, w| w.c1s().bits(0b0000));
+
+            // TODO can we weaken this compiler barrier?
+            // NOTE(compiler_fence) operations on `buffer` should not be reordered after the next
+            // statement, which starts the DMA transfer
+            atomic::compiler_fence(Ordering::SeqCst);
+
+            let pl = chan.priority_or(Priority::High) as u8;
+            chan.ccr().modify(|_, w| unsafe {
+                w.mem2mem()
+                    .clear_bit()
+                    .pl()
+                    .bits(pl)
+                    // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                    .msize()
+                    .bits(0b10)
+                    // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                    .psize()
+                    .bits(0b10)
+                    // incr mem address
+                    .minc()
+                    .set_bit()
+                    .pinc()
+                    .clear_bit()
+                    .circ()
+                    .clear_bit()
+                    // peripheral to memory
+                    .dir()
+                    .clear_bit()
+                    .en()
+                    .set_bit()
+            });
+        }
+
+        Transfer::w(buffer, chan, self)
+    }
+
+    /// Returns ADC1 and ADC2 to independent mode, and releases them
+    pub fn free(mut self) -> (Adc<ADC1>, Adc<ADC2>, ADC_COMMON) {
+        self.master.disable();
+        self.slave.disable();
+        // see `set_common`
+        let common = &self.common;
+        cortex_m::interrupt::free(|_| {
+            common
+                .ccr
+                .modify(|r, w| unsafe { w.bits(r.bits() & !DUAL_MASK) })
+        });
+        self.master.enable();
+        self.slave.enable();
+
+        (self.master, self.slave, self.common)
+    }
+}
@@ -0,0 +1,63 @@
+//! Async/await support
+//!
+//! With the `async` feature enabled the serial, I2C and SPI drivers implement the
+//! `embedded-io-async` and `embedded-hal-async` traits. Every future checks the peripheral status
+//! flags, and when it has to wait it registers its `Waker` and enables the matching interrupt
+//! sources. The interrupt handler of the peripheral must then call `on_interrupt`, which masks
+//! those sources again and wakes the pending task:
+//!
+//! ``` ignore
+//! #[interrupt]
+//! fn USART2() {
+//!     hal::asynch::on_interrupt::<hal::stm32::USART2>();
+//! }
+//! ```
+//!
+//! The NVIC line of the peripheral has to be unmasked by the application.
+
+use core::future::poll_fn;
+use core::task::Poll;
+
+pub use atomic_waker::AtomicWaker;
+
+/// A peripheral whose futures are woken from its interrupt handler
+pub trait Interrupt {
+    /// Masks the interrupt sources enabled by pending futures and wakes them
+    fn on_interrupt();
+}
+
+/// Handles an interrupt of peripheral `P`, to be called from its interrupt handler
+pub fn on_interrupt<P: Interrupt>() {
+    P::on_interrupt()
+}
+
+/// Converts a non-blocking result into `None` while the operation would block
+pub(crate) fn ready<T, E>(result: nb::Result<T, E>) -> Option<Result<T, E>> {
+    match result {
+        Ok(value) => Some(Ok(value)),
+        Err(nb::Error::Other(e)) => Some(Err(e)),
+        Err(nb::Error::WouldBlock) => None,
+    }
+}
+
+/// Waits until `poll` returns `Some`
+///
+/// `listen` is called after the waker has been registered and must enable the interrupt sources
+/// that signal a change of the polled condition.
+pub(crate) async fn wait<T, P, L>(waker: &AtomicWaker, mut poll: P, mut listen: L) -> T
+where
+    P: FnMut() -> Option<T>,
+    L: FnMut(),
+{
+    poll_fn(|cx| match poll() {
+        Some(value) => Poll::Ready(value),
+        None => {
+            waker.register(cx.waker());
+            // NOTE the interrupt flags are level triggered, if the condition became true in the
+            // meantime the interrupt fires as soon as it is enabled
+            listen();
+            Poll::Pending
+        }
+    })
+    .await
+}
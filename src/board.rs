@@ -0,0 +1,9 @@
+//! Support of development boards
+//!
+//! Each board is enabled by its feature, which also selects the device of the board.
+
+#[cfg(feature = "disco-l476")]
+pub mod disco_l476;
+
+#[cfg(feature = "nucleo-l432kc")]
+pub mod nucleo_l432kc;
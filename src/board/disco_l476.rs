@@ -0,0 +1,300 @@
+//! 32L476GDISCOVERY board
+//!
+//! The LEDs, the joystick, and the current (IDD) measurement of the MCU done by the MFX, the
+//! multi-function expander of the board (a STM32L152 on I2C2). The MFX switches the shunts in
+//! series with the MCU supply (JP6 in IDD position), and measures the voltage across them.
+//!
+//! ``` ignore
+//! let scl = gpiob.pb10.into_open_drain_output(&mut gpiob.moder, &mut gpiob.otyper);
+//! let scl = scl.into_af4(&mut gpiob.moder, &mut gpiob.afrh);
+//! let sda = gpiob.pb11.into_open_drain_output(&mut gpiob.moder, &mut gpiob.otyper);
+//! let sda = sda.into_af4(&mut gpiob.moder, &mut gpiob.afrh);
+//! let i2c = I2c::i2c2(dp.I2C2, (scl, sda), 100.khz(), clocks, &mut rcc.apb1r1);
+//!
+//! let mut idd = Idd::new(i2c, IddConfig::default())?;
+//! let nanoamps = idd.measure()?;
+//! ```
+
+use crate::gpio::gpioa::{self, PA0, PA1, PA2, PA3, PA5};
+use crate::gpio::gpiob::{self, PB2};
+use crate::gpio::gpioe::{self, PE8};
+use crate::gpio::{Input, Output, PullDown, PushPull};
+use crate::hal::blocking::i2c::{Write, WriteRead};
+use crate::hal::digital::v2::{InputPin, OutputPin};
+
+/// Address of the MFX on I2C2
+pub const MFX_ADDRESS: u8 = 0x84;
+
+/// ID
+const REG_ID: u8 = 0x00;
+/// Pending interrupt sources
+const REG_IRQ_PENDING: u8 = 0x08;
+/// Measured current, 3 bytes MSB first
+const REG_IDD_VALUE: u8 = 0x14;
+/// System control
+const REG_SYS_CTRL: u8 = 0x40;
+/// Interrupt sources enable
+const REG_IRQ_SRC_EN: u8 = 0x42;
+/// Interrupt acknowledge
+const REG_IRQ_ACK: u8 = 0x44;
+/// IDD control
+const REG_IDD_CTRL: u8 = 0x80;
+/// IDD configuration, from the pre-delay to the gain and the minimum VDD
+const REG_IDD_PRE_DELAY: u8 = 0x81;
+/// IDD shunt stabilization delays
+const REG_IDD_SH0_STABILIZATION: u8 = 0x90;
+/// Number of measurements, then delay between them
+const REG_IDD_NBR_OF_MEAS: u8 = 0x96;
+/// IDs of the MFX firmwares
+const IDS: [u8; 2] = [0x7B, 0x79];
+/// SYS_CTRL: IDD_EN
+const IDD_EN: u8 = 1 << 2;
+/// IRQ: IDD measurement done
+const IRQ_IDD: u8 = 1 << 1;
+/// IRQ: error
+const IRQ_ERROR: u8 = 1 << 2;
+/// IDD_CTRL: REQ, starts a measurement
+const IDD_REQ: u8 = 1 << 0;
+/// Number of shunts of the board
+const SHUNTS: u8 = 4;
+
+/// Red LED, LD4
+pub type RedLed = PB2<Output<PushPull>>;
+/// Green LED, LD5
+pub type GreenLed = PE8<Output<PushPull>>;
+
+/// LEDs of the board
+pub struct Leds {
+    /// LD4
+    pub red: RedLed,
+    /// LD5
+    pub green: GreenLed,
+}
+
+impl Leds {
+    /// Configures the LED pins, the LEDs are off
+    pub fn new<M1, M2>(
+        red: PB2<M1>,
+        green: PE8<M2>,
+        gpiob_moder: &mut gpiob::MODER,
+        gpiob_otyper: &mut gpiob::OTYPER,
+        gpioe_moder: &mut gpioe::MODER,
+        gpioe_otyper: &mut gpioe::OTYPER,
+    ) -> Self {
+        let mut leds = Leds {
+            red: red.into_push_pull_output(gpiob_moder, gpiob_otyper),
+            green: green.into_push_pull_output(gpioe_moder, gpioe_otyper),
+        };
+        leds.red.set_low().ok();
+        leds.green.set_low().ok();
+        leds
+    }
+}
+
+/// Direction of the joystick
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    /// Pressed in the middle
+    Center,
+    /// Left
+    Left,
+    /// Right
+    Right,
+    /// Up
+    Up,
+    /// Down
+    Down,
+}
+
+/// 4-direction joystick with a center button, active high
+pub struct Joystick {
+    center: PA0<Input<PullDown>>,
+    left: PA1<Input<PullDown>>,
+    right: PA2<Input<PullDown>>,
+    up: PA3<Input<PullDown>>,
+    down: PA5<Input<PullDown>>,
+}
+
+impl Joystick {
+    /// Configures the joystick pins as pulled down inputs
+    pub fn new<M0, M1, M2, M3, M5>(
+        center: PA0<M0>,
+        left: PA1<M1>,
+        right: PA2<M2>,
+        up: PA3<M3>,
+        down: PA5<M5>,
+        moder: &mut gpioa::MODER,
+        pupdr: &mut gpioa::PUPDR,
+    ) -> Self {
+        Joystick {
+            center: center.into_pull_down_input(moder, pupdr),
+            left: left.into_pull_down_input(moder, pupdr),
+            right: right.into_pull_down_input(moder, pupdr),
+            up: up.into_pull_down_input(moder, pupdr),
+            down: down.into_pull_down_input(moder, pupdr),
+        }
+    }
+
+    /// Returns the direction the joystick is pushed to, if any
+    pub fn direction(&self) -> Option<Direction> {
+        let pressed = |pin: Result<bool, ()>| pin.unwrap_or(false);
+
+        if pressed(self.center.is_high()) {
+            Some(Direction::Center)
+        } else if pressed(self.left.is_high()) {
+            Some(Direction::Left)
+        } else if pressed(self.right.is_high()) {
+            Some(Direction::Right)
+        } else if pressed(self.up.is_high()) {
+            Some(Direction::Up)
+        } else if pressed(self.down.is_high()) {
+            Some(Direction::Down)
+        } else {
+            None
+        }
+    }
+}
+
+/// IDD measurement error
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error<E> {
+    /// I2C bus error
+    I2c(E),
+    /// The device at `MFX_ADDRESS` isn't a MFX, its ID is returned
+    UnknownDevice(u8),
+    /// The MFX reported an error, e.g. a current out of the range of the shunts
+    Measurement,
+}
+
+/// Configuration of the IDD measurement, the default matches the shunts of the board
+#[derive(Clone, Copy, Debug)]
+pub struct IddConfig {
+    /// Delay before the measurement, with bit 7 selecting units of 20 ms (set) or 0.5 ms
+    pub pre_delay: u8,
+    /// Shunt 0 value in milliohms, then shunts 1 to 4 in ohms; shunt 3 isn't fitted
+    pub shunts: [u16; 5],
+    /// Gain of the amplifier, in hundredths
+    pub gain: u16,
+    /// Minimum VDD, in millivolts
+    pub vdd_min: u16,
+    /// Stabilization delays of the shunts, in units of 0.5 ms
+    pub stabilization: [u8; 5],
+    /// Number of measurements averaged
+    pub measurements: u8,
+    /// Delay between the measurements, with bit 7 selecting units of 20 ms (set) or 0.5 ms
+    pub delta_delay: u8,
+}
+
+impl Default for IddConfig {
+    fn default() -> Self {
+        IddConfig {
+            pre_delay: 0x80 | 0x7F,
+            shunts: [1000, 24, 620, 0, 10_000],
+            gain: 4967,
+            vdd_min: 2000,
+            stabilization: [149, 149, 149, 0, 255],
+            measurements: 10,
+            delta_delay: 10,
+        }
+    }
+}
+
+/// Current measurement of the MCU by the MFX
+pub struct Idd<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C, E> Idd<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Checks the MFX and configures the measurement
+    pub fn new(i2c: I2C, config: IddConfig) -> Result<Self, Error<E>> {
+        let mut idd = Idd { i2c };
+
+        let id = idd.read_register(REG_ID)?;
+        if !IDS.contains(&id) {
+            return Err(Error::UnknownDevice(id));
+        }
+
+        let sys_ctrl = idd.read_register(REG_SYS_CTRL)?;
+        idd.write(&[REG_SYS_CTRL, sys_ctrl | IDD_EN])?;
+        idd.write(&[REG_IRQ_SRC_EN, IRQ_IDD | IRQ_ERROR])?;
+
+        // from the pre-delay at 0x81 to the minimum VDD at 0x8F, all 16-bit values MSB first
+        let mut block = [0; 16];
+        block[0] = REG_IDD_PRE_DELAY;
+        block[1] = config.pre_delay;
+        for (chunk, value) in block[2..]
+            .chunks_mut(2)
+            .zip(config.shunts.iter().chain(&[config.gain, config.vdd_min]))
+        {
+            chunk[0] = (value >> 8) as u8;
+            chunk[1] = *value as u8;
+        }
+        idd.write(&block)?;
+
+        let mut stabilization = [REG_IDD_SH0_STABILIZATION, 0, 0, 0, 0, 0];
+        stabilization[1..].copy_from_slice(&config.stabilization);
+        idd.write(&stabilization)?;
+        idd.write(&[REG_IDD_NBR_OF_MEAS, config.measurements, config.delta_delay])?;
+
+        Ok(idd)
+    }
+
+    /// Starts a measurement, see `read`
+    pub fn start(&mut self) -> Result<(), Error<E>> {
+        self.write(&[REG_IRQ_ACK, IRQ_IDD | IRQ_ERROR])?;
+        self.write(&[REG_IDD_CTRL, (SHUNTS << 1) | IDD_REQ])
+    }
+
+    /// Returns the current measured by the last measurement started, in nanoamps
+    ///
+    /// The MCU can sleep meanwhile, the measurement takes the pre-delay and the delays between
+    /// the measurements.
+    pub fn read(&mut self) -> nb::Result<u32, Error<E>> {
+        let pending = self.read_register(REG_IRQ_PENDING)?;
+        if pending & IRQ_ERROR != 0 {
+            self.write(&[REG_IRQ_ACK, IRQ_ERROR])?;
+            return Err(nb::Error::Other(Error::Measurement));
+        }
+        if pending & IRQ_IDD == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let mut value = [0; 3];
+        self.i2c
+            .write_read(MFX_ADDRESS, &[REG_IDD_VALUE], &mut value)
+            .map_err(Error::I2c)?;
+        self.write(&[REG_IRQ_ACK, IRQ_IDD])?;
+
+        // in units of 10 nA
+        let value = (u32::from(value[0]) << 16) | (u32::from(value[1]) << 8) | u32::from(value[2]);
+        Ok(value * 10)
+    }
+
+    /// Measures the current, in nanoamps, see `read`
+    pub fn measure(&mut self) -> Result<u32, Error<E>> {
+        self.start()?;
+        nb::block!(self.read())
+    }
+
+    /// Releases the I2C bus
+    pub fn free(self) -> I2C {
+        self.i2c
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u8, Error<E>> {
+        let mut value = [0];
+        self.i2c
+            .write_read(MFX_ADDRESS, &[register], &mut value)
+            .map_err(Error::I2c)?;
+        Ok(value[0])
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error<E>> {
+        self.i2c.write(MFX_ADDRESS, bytes).map_err(Error::I2c)
+    }
+}
@@ -0,0 +1,202 @@
+//! NUCLEO-L432KC board
+//!
+//! The pins are named after the Arduino Nano connectors of the board, `A0` to `A7` and `D0` to
+//! `D13`. `D13` also drives the green LED `LD3`, `A7` and `vcp_rx` are the USART2 pins of the
+//! virtual COM port of the ST-LINK. `D7` and `D8` are only connected with SB4 and SB6 closed.
+//!
+//! ``` ignore
+//! let mut rcc = dp.RCC.constrain();
+//! let mut pins = Pins::new(
+//!     dp.GPIOA.split(&mut rcc.ahb2),
+//!     dp.GPIOB.split(&mut rcc.ahb2),
+//!     dp.GPIOC.split(&mut rcc.ahb2),
+//! );
+//!
+//! let mut led: Led = pins
+//!     .d13
+//!     .into_push_pull_output(&mut pins.gpiob.moder, &mut pins.gpiob.otyper);
+//! ```
+
+use crate::gpio::gpioa::{
+    self, PA0, PA1, PA10, PA11, PA12, PA15, PA2, PA3, PA4, PA5, PA6, PA7, PA8, PA9,
+};
+use crate::gpio::gpiob::{self, PB0, PB1, PB3, PB4, PB5, PB6, PB7};
+use crate::gpio::gpioc::{self, PC14, PC15};
+use crate::gpio::{Floating, Input, Output, PushPull};
+
+/// Analog input A0
+pub type A0<MODE> = PA0<MODE>;
+/// Analog input A1
+pub type A1<MODE> = PA1<MODE>;
+/// Analog input A2
+pub type A2<MODE> = PA3<MODE>;
+/// Analog input A3
+pub type A3<MODE> = PA4<MODE>;
+/// Analog input A4
+pub type A4<MODE> = PA5<MODE>;
+/// Analog input A5
+pub type A5<MODE> = PA6<MODE>;
+/// Analog input A6
+pub type A6<MODE> = PA7<MODE>;
+/// Analog input A7, also the TX pin of the virtual COM port
+pub type A7<MODE> = PA2<MODE>;
+
+/// Digital pin D0
+pub type D0<MODE> = PA10<MODE>;
+/// Digital pin D1
+pub type D1<MODE> = PA9<MODE>;
+/// Digital pin D2
+pub type D2<MODE> = PA12<MODE>;
+/// Digital pin D3
+pub type D3<MODE> = PB0<MODE>;
+/// Digital pin D4
+pub type D4<MODE> = PB7<MODE>;
+/// Digital pin D5
+pub type D5<MODE> = PB6<MODE>;
+/// Digital pin D6
+pub type D6<MODE> = PB1<MODE>;
+/// Digital pin D7
+pub type D7<MODE> = PC14<MODE>;
+/// Digital pin D8
+pub type D8<MODE> = PC15<MODE>;
+/// Digital pin D9
+pub type D9<MODE> = PA8<MODE>;
+/// Digital pin D10
+pub type D10<MODE> = PA11<MODE>;
+/// Digital pin D11
+pub type D11<MODE> = PB5<MODE>;
+/// Digital pin D12
+pub type D12<MODE> = PB4<MODE>;
+/// Digital pin D13, also the LED
+pub type D13<MODE> = PB3<MODE>;
+
+/// Green LED, on D13
+pub type LD3<MODE> = PB3<MODE>;
+/// Green LED configured as an output
+pub type Led = LD3<Output<PushPull>>;
+/// RX pin of the virtual COM port
+pub type VcpRx<MODE> = PA15<MODE>;
+
+/// Registers of a port, to change the mode of its pins
+pub struct Registers<AFRL, AFRH, MODER, OTYPER, PUPDR> {
+    /// Opaque AFRL register
+    pub afrl: AFRL,
+    /// Opaque AFRH register
+    pub afrh: AFRH,
+    /// Opaque MODER register
+    pub moder: MODER,
+    /// Opaque OTYPER register
+    pub otyper: OTYPER,
+    /// Opaque PUPDR register
+    pub pupdr: PUPDR,
+}
+
+/// Pins of the board, in their reset state, and the registers of their ports
+pub struct Pins {
+    /// A0
+    pub a0: A0<Input<Floating>>,
+    /// A1
+    pub a1: A1<Input<Floating>>,
+    /// A2
+    pub a2: A2<Input<Floating>>,
+    /// A3
+    pub a3: A3<Input<Floating>>,
+    /// A4
+    pub a4: A4<Input<Floating>>,
+    /// A5
+    pub a5: A5<Input<Floating>>,
+    /// A6
+    pub a6: A6<Input<Floating>>,
+    /// A7, virtual COM port TX
+    pub a7: A7<Input<Floating>>,
+    /// D0
+    pub d0: D0<Input<Floating>>,
+    /// D1
+    pub d1: D1<Input<Floating>>,
+    /// D2
+    pub d2: D2<Input<Floating>>,
+    /// D3
+    pub d3: D3<Input<Floating>>,
+    /// D4
+    pub d4: D4<Input<Floating>>,
+    /// D5
+    pub d5: D5<Input<Floating>>,
+    /// D6
+    pub d6: D6<Input<Floating>>,
+    /// D7
+    pub d7: D7<Input<Floating>>,
+    /// D8
+    pub d8: D8<Input<Floating>>,
+    /// D9
+    pub d9: D9<Input<Floating>>,
+    /// D10
+    pub d10: D10<Input<Floating>>,
+    /// D11
+    pub d11: D11<Input<Floating>>,
+    /// D12
+    pub d12: D12<Input<Floating>>,
+    /// D13, LED LD3
+    pub d13: D13<Input<Floating>>,
+    /// Virtual COM port RX
+    pub vcp_rx: VcpRx<Input<Floating>>,
+    /// GPIOA registers
+    pub gpioa: Registers<gpioa::AFRL, gpioa::AFRH, gpioa::MODER, gpioa::OTYPER, gpioa::PUPDR>,
+    /// GPIOB registers
+    pub gpiob: Registers<gpiob::AFRL, gpiob::AFRH, gpiob::MODER, gpiob::OTYPER, gpiob::PUPDR>,
+    /// GPIOC registers
+    pub gpioc: Registers<gpioc::AFRL, gpioc::AFRH, gpioc::MODER, gpioc::OTYPER, gpioc::PUPDR>,
+}
+
+impl Pins {
+    /// Names the pins of the split ports
+    ///
+    /// The pins not on the connectors are dropped, e.g. the debug pins PA13 and PA14.
+    pub fn new(gpioa: gpioa::Parts, gpiob: gpiob::Parts, gpioc: gpioc::Parts) -> Self {
+        Pins {
+            a0: gpioa.pa0,
+            a1: gpioa.pa1,
+            a2: gpioa.pa3,
+            a3: gpioa.pa4,
+            a4: gpioa.pa5,
+            a5: gpioa.pa6,
+            a6: gpioa.pa7,
+            a7: gpioa.pa2,
+            d0: gpioa.pa10,
+            d1: gpioa.pa9,
+            d2: gpioa.pa12,
+            d3: gpiob.pb0,
+            d4: gpiob.pb7,
+            d5: gpiob.pb6,
+            d6: gpiob.pb1,
+            d7: gpioc.pc14,
+            d8: gpioc.pc15,
+            d9: gpioa.pa8,
+            d10: gpioa.pa11,
+            d11: gpiob.pb5,
+            d12: gpiob.pb4,
+            d13: gpiob.pb3,
+            vcp_rx: gpioa.pa15,
+            gpioa: Registers {
+                afrl: gpioa.afrl,
+                afrh: gpioa.afrh,
+                moder: gpioa.moder,
+                otyper: gpioa.otyper,
+                pupdr: gpioa.pupdr,
+            },
+            gpiob: Registers {
+                afrl: gpiob.afrl,
+                afrh: gpiob.afrh,
+                moder: gpiob.moder,
+                otyper: gpiob.otyper,
+                pupdr: gpiob.pupdr,
+            },
+            gpioc: Registers {
+                afrl: gpioc.afrl,
+                afrh: gpioc.afrh,
+                moder: gpioc.moder,
+                otyper: gpioc.otyper,
+                pupdr: gpioc.pupdr,
+            },
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! Parallel capture of camera and sensor array data, by timer and DMA
+//!
+//! TIM2 outputs the pixel clock on its channel 1 pin, and on every period DMA samples the input
+//! data register of a GPIO port into a frame buffer: the low 8 or 16 pins of the port, given as
+//! a `gpio::gpiox::Port`, are the data lines. This works on every part, without a DCMI, for low
+//! resolution cameras and sensor arrays clocked by the microcontroller (e.g. line-scan sensors),
+//! up to a few MHz depending on the bus load.
+//!
+//! The pixel clock rises in the middle of the period, and the data are sampled when it falls,
+//! so sensors that update their output on the rising edge get a half period of setup time.
+//!
+//! ``` ignore
+//! let data = Port::new()
+//!     .with_pin(pc0.downgrade())
+//!     .with_pin(pc1.downgrade()) /* ... */
+//!     .with_pin(pc7.downgrade());
+//! let capture = ParallelCapture::tim2(dp.TIM2, pa0, 1.mhz(), clocks, &mut rcc.apb1r1);
+//! let frame = singleton!(: [u8; 128] = [0; 128]).unwrap();
+//! let (frame, chan, capture) = capture.capture(&data, channels.2, frame).wait()?;
+//! ```
+//!
+//! `capture_frames` captures continuously into the two frames of a buffer, in turns: a frame is
+//! handed back by `Frames::next_frame` once complete, while the DMA fills the other one. The
+//! sensor being clocked by TIM2, frames have a fixed number of samples counted from the first
+//! pixel clock, there are no line or frame sync inputs.
+//!
+//! ``` ignore
+//! let frames = singleton!(: [[u8; 128]; 2] = [[0; 128]; 2]).unwrap();
+//! let mut frames = capture.capture_frames(&data, channels.2, frames);
+//! loop {
+//!     match frames.next_frame(|frame: &[u8]| process(frame)) {
+//!         Ok(result) => { /* ... */ }
+//!         Err(nb::Error::Other(dma::Error::Overrun)) => { /* a frame was lost */ }
+//!         Err(nb::Error::WouldBlock) => {}
+//!     }
+//! }
+//! ```
+
+use core::ops::DerefMut;
+use core::sync::atomic::{self, Ordering};
+
+use as_slice::{AsMutSlice, AsSlice};
+use cast::u16;
+use stable_deref_trait::StableDeref;
+
+use crate::dma::{dma1, Error, Half, Priority, Transfer, WriteBuffer, W};
+use crate::gpio::InputPort;
+use crate::pwm_input::Pin;
+use crate::rcc::{Clocks, APB1R1};
+use crate::stm32::TIM2;
+use crate::time::Hertz;
+
+/// Width of the samples stored in the frame buffer
+///
+/// # Safety
+///
+/// `MSIZE` must match the size of the type, the DMA writes that many bytes for
+/// every sample
+pub unsafe trait Sample {
+    #[doc(hidden)]
+    /// DMA memory size: 00: 8-bits, 01: 16-bits
+    const MSIZE: u8;
+}
+
+unsafe impl Sample for u8 {
+    const MSIZE: u8 = 0b00;
+}
+
+unsafe impl Sample for u16 {
+    const MSIZE: u8 = 0b01;
+}
+
+/// Parallel capture, clocked by TIM2
+pub struct ParallelCapture<PIN> {
+    tim: TIM2,
+    pin: PIN,
+}
+
+impl<PIN> ParallelCapture<PIN>
+where
+    PIN: Pin<TIM2>,
+{
+    /// Configures TIM2 to output a `pixel_clock` on `pin`, stopped until `capture`
+    pub fn tim2<F>(tim: TIM2, pin: PIN, pixel_clock: F, clocks: Clocks, apb: &mut APB1R1) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        apb.enr().modify(|_, w| w.tim2en().set_bit());
+        apb.rstr().modify(|_, w| w.tim2rst().set_bit());
+        apb.rstr().modify(|_, w| w.tim2rst().clear_bit());
+
+        let period = clocks.timclk1().0 / pixel_clock.into().0;
+        assert!(period >= 2);
+
+        tim.psc.write(|w| unsafe { w.bits(0) });
+        tim.arr.write(|w| unsafe { w.bits(period - 1) });
+        tim.ccr1.write(|w| unsafe { w.bits(period / 2) });
+
+        // PWM mode 2: low in the first half of the period, high in the second one
+        tim.ccmr1_output.write(|w| unsafe { w.oc1m().bits(0b111) });
+        tim.ccer.write(|w| w.cc1e().set_bit());
+        tim.egr.write(|w| w.ug().set_bit());
+
+        ParallelCapture { tim, pin }
+    }
+
+    /// Captures `buffer.len()` samples of `port` into `buffer`, clocking the sensor until the
+    /// capture is `stop`ped
+    pub fn capture<PORT, B, S>(
+        self,
+        port: &PORT,
+        mut chan: dma1::C2,
+        mut buffer: B,
+    ) -> Transfer<W, B, dma1::C2, Self>
+    where
+        PORT: InputPort,
+        B: WriteBuffer<Word = S>,
+        S: Sample,
+    {
+        // NOTE(unsafe) the buffer is owned by the transfer until it's over
+        let (ptr, len) = unsafe { buffer.write_buffer() };
+        self.start::<PORT, S>(port, &mut chan, ptr as usize, len, false);
+
+        Transfer::w(buffer, chan, self)
+    }
+
+    /// Captures frames of `port` continuously, alternately into the two halves of `buffer`
+    ///
+    /// Each half holds a frame, and the two halves hold up to 65535 samples together.
+    pub fn capture_frames<PORT, B, H, S>(
+        self,
+        port: &PORT,
+        mut chan: dma1::C2,
+        mut buffer: B,
+    ) -> Frames<B, PIN>
+    where
+        PORT: InputPort,
+        B: StableDeref<Target = [H; 2]> + DerefMut + 'static,
+        H: AsMutSlice<Element = S>,
+        S: Sample,
+    {
+        let frame = buffer[0].as_mut_slice();
+        let (ptr, len) = (frame.as_mut_ptr(), frame.len());
+        self.start::<PORT, S>(port, &mut chan, ptr as usize, 2 * len, true);
+
+        Frames {
+            buffer,
+            chan,
+            capture: self,
+            next: Half::First,
+        }
+    }
+
+    fn start<PORT, S>(
+        &self,
+        port: &PORT,
+        chan: &mut dma1::C2,
+        address: usize,
+        len: usize,
+        circular: bool,
+    ) where
+        PORT: InputPort,
+        S: Sample,
+    {
+        chan.cmar().write(|w| w.ma().bits(address as u32));
+        chan.cndtr().write(|w| w.ndt().bits(u16(len).unwrap()));
+        chan.cpar().write(|w| w.pa().bits(port.idr()));
+
+        // Tell DMA to request from the TIM2 update event
+        chan.cselr().modify(|_, w| w.c2s().bits(0b0100));
+
+        // TODO can we weaken this compiler barrier?
+        // NOTE(compiler_fence) operations on `buffer` should not be reordered after the next
+        // statement, which starts the DMA transfer
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        let pl = chan.priority_or(Priority::VeryHigh) as u8;
+        chan.ccr().modify(|_, w| unsafe {
+            w.mem2mem()
+                .clear_bit()
+                .pl()
+                .bits(pl)
+                // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                .msize()
+                .bits(S::MSIZE)
+                // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                .psize()
+                .bits(0b10)
+                // incr mem address
+                .minc()
+                .set_bit()
+                .pinc()
+                .clear_bit()
+                .circ()
+                .bit(circular)
+                // peripheral to memory
+                .dir()
+                .clear_bit()
+                .en()
+                .set_bit()
+        });
+
+        self.tim.dier.modify(|_, w| w.ude().set_bit());
+        self.tim.cr1.modify(|_, w| w.cen().set_bit());
+    }
+
+    /// Stops the pixel clock
+    pub fn stop(&mut self) {
+        self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+        self.tim.dier.modify(|_, w| w.ude().clear_bit());
+        self.tim.cnt.write(|w| unsafe { w.bits(0) });
+    }
+
+    /// Releases the TIM peripheral and the pin
+    pub fn free(mut self) -> (TIM2, PIN) {
+        self.stop();
+        (self.tim, self.pin)
+    }
+}
+
+/// Frames captured in turns into the two halves of a buffer, see
+/// `ParallelCapture::capture_frames`
+pub struct Frames<B, PIN> {
+    buffer: B,
+    chan: dma1::C2,
+    capture: ParallelCapture<PIN>,
+    next: Half,
+}
+
+impl<B, PIN> Frames<B, PIN>
+where
+    PIN: Pin<TIM2>,
+{
+    /// Hands the next complete frame to `f`, while the DMA fills the other one
+    ///
+    /// `f` has to return before the other frame is complete. Returns `Overrun` if it didn't, or
+    /// if a frame was missed since the last call; the next call then returns the last complete
+    /// frame.
+    pub fn next_frame<R, F, H, S>(&mut self, f: F) -> nb::Result<R, Error>
+    where
+        B: StableDeref<Target = [H; 2]>,
+        H: AsSlice<Element = S>,
+        F: FnOnce(&[S]) -> R,
+    {
+        let isr = self.chan.isr();
+        let (first, second) = (isr.htif2().bit_is_set(), isr.tcif2().bit_is_set());
+        let (complete, other) = match self.next {
+            Half::First => (first, second),
+            Half::Second => (second, first),
+        };
+        if !complete {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let half = self.next;
+        self.clear(half);
+        self.next = match half {
+            Half::First => Half::Second,
+            Half::Second => Half::First,
+        };
+        if other {
+            // the DMA is filling this frame again
+            return Err(nb::Error::Other(Error::Overrun));
+        }
+
+        // NOTE(compiler_fence) the frame must be read after the flag
+        atomic::compiler_fence(Ordering::SeqCst);
+        let result = match half {
+            Half::First => f(self.buffer[0].as_slice()),
+            Half::Second => f(self.buffer[1].as_slice()),
+        };
+
+        let isr = self.chan.isr();
+        let overwritten = match half {
+            Half::First => isr.tcif2().bit_is_set(),
+            Half::Second => isr.htif2().bit_is_set(),
+        };
+        if overwritten {
+            Err(nb::Error::Other(Error::Overrun))
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// Stops the capture, and releases the buffer, the channel and the `ParallelCapture`
+    pub fn stop(mut self) -> (B, dma1::C2, ParallelCapture<PIN>) {
+        self.capture.stop();
+        self.chan.ccr().modify(|_, w| w.en().clear_bit());
+        self.chan.clear_flags();
+
+        // NOTE(compiler_fence) operations on the buffer should not be reordered before the
+        // previous statements, which stop the DMA transfer
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        (self.buffer, self.chan, self.capture)
+    }
+
+    fn clear(&mut self, half: Half) {
+        match half {
+            Half::First => self.chan.ifcr().write(|w| w.chtif2().set_bit()),
+            Half::Second => self.chan.ifcr().write(|w| w.ctcif2().set_bit()),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Clock recovery system
+//!
+//! The CRS trims the HSI48 oscillator against a reference, either the USB start-of-frame packets
+//! or the LSE, which makes crystal-less USB operation possible.
+//!
+//! ``` ignore
+//! let clocks = rcc.cfgr.hsi48(true).freeze(&mut flash.acr);
+//! let mut crs = dp.CRS.constrain(clocks, &mut rcc.apb1r1);
+//! crs.configure(SyncSource::Usb, SyncDivider::Div1, SyncPolarity::Rising, 1.khz());
+//! crs.enable();
+//! ```
+
+use crate::rcc::{Clocks, APB1R1};
+use crate::stm32::CRS;
+use crate::time::Hertz;
+
+/// HSI48 frequency
+const HSI48: u32 = 48_000_000;
+
+/// CRS error
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// No synchronization event was received before the counter reached its maximum
+    SyncMissed,
+    /// The frequency error was outside of the trimmable range
+    SyncError,
+    /// The trimming value reached its limit
+    TrimOverflow,
+}
+
+/// Interrupt events
+pub enum Event {
+    /// Synchronization within the error limit
+    SyncOk,
+    /// Synchronization with an error above the warning limit, the trim value was adjusted
+    SyncWarning,
+    /// Synchronization error, missed synchronization or trimming overflow
+    Error,
+    /// The counter reached zero, a synchronization event is expected
+    ExpectedSync,
+}
+
+/// Synchronization sources
+pub enum SyncSource {
+    /// The CRS_SYNC pin
+    Gpio = 0b00,
+    /// The LSE oscillator
+    Lse = 0b01,
+    /// USB start-of-frame packets
+    Usb = 0b10,
+}
+
+/// Active edge of the synchronization signal
+pub enum SyncPolarity {
+    /// Rising edge
+    Rising,
+    /// Falling edge
+    Falling,
+}
+
+/// Divider applied to the synchronization signal
+pub enum SyncDivider {
+    /// Not divided
+    Div1 = 0b000,
+    /// Divided by 2
+    Div2 = 0b001,
+    /// Divided by 4
+    Div4 = 0b010,
+    /// Divided by 8
+    Div8 = 0b011,
+    /// Divided by 16
+    Div16 = 0b100,
+    /// Divided by 32
+    Div32 = 0b101,
+    /// Divided by 64
+    Div64 = 0b110,
+    /// Divided by 128
+    Div128 = 0b111,
+}
+
+/// Extension trait to constrain the CRS peripheral
+pub trait CrsExt {
+    /// Enables the CRS, HSI48 has to be enabled in the clock configuration
+    fn constrain(self, clocks: Clocks, apb1: &mut APB1R1) -> Crs;
+}
+
+impl CrsExt for CRS {
+    fn constrain(self, clocks: Clocks, apb1: &mut APB1R1) -> Crs {
+        assert!(clocks.hsi48());
+
+        apb1.enr().modify(|_, w| w.crsen().set_bit());
+        // CRSRST is missing from the SVD of the STM32L4x3
+        apb1.reset::<CRS>();
+
+        Crs { crs: self }
+    }
+}
+
+/// Constrained CRS peripheral
+pub struct Crs {
+    crs: CRS,
+}
+
+impl Crs {
+    /// Configures the synchronization signal, whose frequency after division is `frequency`
+    ///
+    /// The reload value and the frequency error limit are derived from `frequency` using the
+    /// typical trimming step of 0.14%. Must be called while the CRS is disabled.
+    pub fn configure<F>(
+        &mut self,
+        source: SyncSource,
+        divider: SyncDivider,
+        polarity: SyncPolarity,
+        frequency: F,
+    ) where
+        F: Into<Hertz>,
+    {
+        let frequency = frequency.into().0;
+        let reload = HSI48 / frequency - 1;
+        assert!(reload <= 0xffff);
+        // FELIM = (fTARGET / fSYNC) * STEP / 2, rounded up
+        let felim = ((reload + 1) * 14).div_ceil(20_000);
+        assert!(felim <= 0xff);
+
+        let syncpol = match polarity {
+            SyncPolarity::Rising => false,
+            SyncPolarity::Falling => true,
+        };
+
+        self.crs.cfgr.write(|w| unsafe {
+            w.syncpol()
+                .bit(syncpol)
+                .syncsrc()
+                .bits(source as u8)
+                .syncdiv()
+                .bits(divider as u8)
+                .felim()
+                .bits(felim as u8)
+                .reload()
+                .bits(reload as u16)
+        });
+    }
+
+    /// Synchronizes with the 1 kHz USB start-of-frame packets
+    pub fn sync_usb(&mut self) {
+        self.configure(SyncSource::Usb, SyncDivider::Div1, SyncPolarity::Rising, Hertz(1_000));
+    }
+
+    /// Synchronizes with the 32.768 kHz LSE, which has to be running
+    pub fn sync_lse(&mut self) {
+        self.configure(SyncSource::Lse, SyncDivider::Div1, SyncPolarity::Rising, Hertz(32_768));
+    }
+
+    /// Overrides the frequency error limit computed by `configure`
+    pub fn set_error_limit(&mut self, felim: u8) {
+        self.crs.cfgr.modify(|_, w| unsafe { w.felim().bits(felim) });
+    }
+
+    /// Starts the frequency error counter with automatic trimming
+    pub fn enable(&mut self) {
+        self.crs.cr.modify(|_, w| w.autotrimen().set_bit().cen().set_bit());
+    }
+
+    /// Stops the frequency error counter
+    pub fn disable(&mut self) {
+        self.crs.cr.modify(|_, w| w.cen().clear_bit().autotrimen().clear_bit());
+    }
+
+    /// Generates a synchronization event by software
+    pub fn sync(&mut self) {
+        self.crs.cr.modify(|_, w| w.swsync().set_bit());
+    }
+
+    /// Returns the current HSI48 trimming value
+    pub fn trim(&self) -> u8 {
+        self.crs.cr.read().trim().bits()
+    }
+
+    /// Returns `true` once a synchronization within the error limit has occurred, and clears
+    /// the flag
+    ///
+    /// Returns the error of a failed synchronization, and clears it.
+    pub fn synchronized(&mut self) -> Result<bool, Error> {
+        let isr = self.crs.isr.read();
+        if isr.errf().bit_is_set() {
+            self.crs.icr.write(|w| w.errc().set_bit());
+            return Err(if isr.syncmiss().bit_is_set() {
+                Error::SyncMissed
+            } else if isr.trimovf().bit_is_set() {
+                Error::TrimOverflow
+            } else {
+                Error::SyncError
+            });
+        }
+
+        if isr.syncokf().bit_is_set() {
+            self.crs.icr.write(|w| w.syncokc().set_bit());
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Starts listening for an `event`
+    pub fn listen(&mut self, event: Event) {
+        match event {
+            Event::SyncOk => self.crs.cr.modify(|_, w| w.syncokie().set_bit()),
+            Event::SyncWarning => self.crs.cr.modify(|_, w| w.syncwarnie().set_bit()),
+            Event::Error => self.crs.cr.modify(|_, w| w.errie().set_bit()),
+            Event::ExpectedSync => self.crs.cr.modify(|_, w| w.esyncie().set_bit()),
+        }
+    }
+
+    /// Stops listening for an `event`
+    pub fn unlisten(&mut self, event: Event) {
+        match event {
+            Event::SyncOk => self.crs.cr.modify(|_, w| w.syncokie().clear_bit()),
+            Event::SyncWarning => self.crs.cr.modify(|_, w| w.syncwarnie().clear_bit()),
+            Event::Error => self.crs.cr.modify(|_, w| w.errie().clear_bit()),
+            Event::ExpectedSync => self.crs.cr.modify(|_, w| w.esyncie().clear_bit()),
+        }
+    }
+
+    /// Clears the flag of an `event`
+    pub fn clear(&mut self, event: Event) {
+        match event {
+            Event::SyncOk => self.crs.icr.write(|w| w.syncokc().set_bit()),
+            Event::SyncWarning => self.crs.icr.write(|w| w.syncwarnc().set_bit()),
+            Event::Error => self.crs.icr.write(|w| w.errc().set_bit()),
+            Event::ExpectedSync => self.crs.icr.write(|w| w.esyncc().set_bit()),
+        }
+    }
+
+    /// Releases the CRS peripheral
+    pub fn free(mut self) -> CRS {
+        self.disable();
+        self.crs
+    }
+}
@@ -0,0 +1,331 @@
+//! Digital to analog converter
+//!
+//! Each channel either outputs the last written value, or, when a trigger is selected, updates
+//! its output on every trigger event. Triggered channels can stream samples from memory through
+//! DMA, paced by a timer, or generate noise and triangle waves in hardware.
+//!
+//! ``` ignore
+//! let pa4 = gpioa.pa4.into_analog(&mut gpioa.moder, &mut gpioa.pupdr);
+//! let mut ch1 = dac::dac(dp.DAC1, pa4, &mut rcc.apb1r1);
+//!
+//! // 48 kHz sample rate
+//! let mut timer = Timer::tim2(dp.TIM2, 48.khz(), clocks, &mut rcc.apb1r1);
+//! timer.set_trigger_output(TriggerOutput::Update);
+//!
+//! ch1.set_trigger(Trigger::Tim2Trgo);
+//! ch1.enable();
+//! let transfer = ch1.write_circular(channels.3, &SINE);
+//! ```
+
+use core::sync::atomic::{self, Ordering};
+
+use cast::u16;
+
+#[cfg(not(feature = "stm32l4x6"))]
+use crate::stm32::DAC1;
+#[cfg(feature = "stm32l4x6")]
+use crate::stm32::DAC as DAC1;
+
+use crate::dma::{dma1, Priority, ReadBuffer, Transfer, R};
+use crate::gpio::gpioa::{PA4, PA5};
+use crate::gpio::Analog;
+use crate::rcc::APB1R1;
+
+/// DAC channel 1
+pub struct C1 {
+    _0: (),
+}
+
+/// DAC channel 2
+pub struct C2 {
+    _0: (),
+}
+
+/// Pins that can be driven by the DAC, and the channels they map to
+pub trait Pins<DAC> {
+    /// The channel handles returned for these pins
+    type Output;
+}
+
+impl Pins<DAC1> for PA4<Analog> {
+    type Output = C1;
+}
+
+impl Pins<DAC1> for PA5<Analog> {
+    type Output = C2;
+}
+
+impl Pins<DAC1> for (PA4<Analog>, PA5<Analog>) {
+    type Output = (C1, C2);
+}
+
+/// Conversion triggers
+pub enum Trigger {
+    /// TIM6 trigger output
+    Tim6Trgo = 0b000,
+    /// TIM8 trigger output
+    Tim8Trgo = 0b001,
+    /// TIM7 trigger output
+    Tim7Trgo = 0b010,
+    /// TIM5 trigger output
+    Tim5Trgo = 0b011,
+    /// TIM2 trigger output
+    Tim2Trgo = 0b100,
+    /// TIM4 trigger output
+    Tim4Trgo = 0b101,
+    /// EXTI line 9
+    Exti9 = 0b110,
+    /// Software trigger, see `trigger`
+    Software = 0b111,
+}
+
+/// Hardware wave generation, added to the value of the data register on every trigger
+pub enum Wave {
+    /// No wave generation
+    Disabled,
+    /// Pseudo noise, the `u8` is the number of random bits (1 to 12)
+    Noise(u8),
+    /// Triangle wave with an amplitude of `2^n - 1` where `n` (1 to 12) is the `u8`
+    Triangle(u8),
+}
+
+/// Enables the DAC and splits it into the channels selected by `pins`
+pub fn dac<PINS>(_dac: DAC1, _pins: PINS, apb: &mut APB1R1) -> PINS::Output
+where
+    PINS: Pins<DAC1>,
+    PINS::Output: Channels,
+{
+    apb.enr().modify(|_, w| w.dac1en().set_bit());
+    apb.rstr().modify(|_, w| w.dac1rst().set_bit());
+    apb.rstr().modify(|_, w| w.dac1rst().clear_bit());
+
+    PINS::Output::new()
+}
+
+/// Disables the channels returned by `dac`, stops the DAC clock and releases the DAC
+pub fn free<CHANNELS>(_channels: CHANNELS, apb: &mut APB1R1) -> DAC1
+where
+    CHANNELS: Channels,
+{
+    apb.rstr().modify(|_, w| w.dac1rst().set_bit());
+    apb.rstr().modify(|_, w| w.dac1rst().clear_bit());
+    apb.enr().modify(|_, w| w.dac1en().clear_bit());
+
+    // NOTE(unsafe) the channels, the only handles to the DAC, were given back; the device
+    // peripherals were already taken to get the DAC in the first place
+    let peripherals = unsafe { crate::stm32::Peripherals::steal() };
+    #[cfg(not(feature = "stm32l4x6"))]
+    let dac = peripherals.DAC1;
+    #[cfg(feature = "stm32l4x6")]
+    let dac = peripherals.DAC;
+    dac
+}
+
+/// Channel handles that can be created by `dac`
+pub trait Channels {
+    #[doc(hidden)]
+    fn new() -> Self;
+}
+
+impl Channels for C1 {
+    fn new() -> Self {
+        C1 { _0: () }
+    }
+}
+
+impl Channels for C2 {
+    fn new() -> Self {
+        C2 { _0: () }
+    }
+}
+
+impl Channels for (C1, C2) {
+    fn new() -> Self {
+        (C1 { _0: () }, C2 { _0: () })
+    }
+}
+
+macro_rules! channels {
+    ($($CX:ident: (
+        $enX:ident, $modeX:ident, $tenX:ident, $tselX:ident, $waveX:ident, $mampX:ident,
+        $dmaenX:ident, $swtrigX:ident, $dhr12rX:ident, $dorX:ident, $dma_chan:ident, $cxs:ident, $request:expr
+    ),)+) => {
+        $(
+            impl $CX {
+                /// Enables the output buffer and the channel
+                ///
+                /// The channel drives its pin through the output buffer. The buffer is only
+                /// configured while the channel is disabled, as the mode can't change otherwise.
+                pub fn enable(&mut self) {
+                    // NOTE(unsafe) read-modify-write of the bits of this channel, in a critical
+                    // section as CR and MCR are shared by both channels
+                    cortex_m::interrupt::free(|_| unsafe {
+                        let dac = &*DAC1::ptr();
+                        if dac.cr.read().$enX().bit_is_clear() {
+                            // MODE = 000: normal mode, connected to the pin with the buffer on
+                            dac.mcr.modify(|_, w| w.$modeX().bits(0b000));
+                        }
+                        dac.cr.modify(|_, w| w.$enX().set_bit())
+                    });
+                }
+
+                /// Disables the channel
+                pub fn disable(&mut self) {
+                    // NOTE(unsafe) see `enable`
+                    cortex_m::interrupt::free(|_| unsafe {
+                        (*DAC1::ptr()).cr.modify(|_, w| w.$enX().clear_bit())
+                    });
+                }
+
+                /// Writes a 12-bit value, output right away or on the next trigger
+                pub fn set_value(&mut self, value: u16) {
+                    // NOTE(unsafe) this register is owned by this channel
+                    unsafe { (*DAC1::ptr()).$dhr12rX.write(|w| w.bits(u32::from(value))) };
+                }
+
+                /// Returns the value currently output
+                pub fn get_value(&self) -> u16 {
+                    // NOTE(unsafe) atomic read with no side effects
+                    u16(unsafe { (*DAC1::ptr()).$dorX.read().bits() } & 0xfff).unwrap()
+                }
+
+                /// Updates the output on every `trigger` event only
+                ///
+                /// Must be called while the channel is disabled.
+                pub fn set_trigger(&mut self, trigger: Trigger) {
+                    let tsel = trigger as u8;
+                    // NOTE(unsafe) see `enable`
+                    cortex_m::interrupt::free(|_| unsafe {
+                        (*DAC1::ptr())
+                            .cr
+                            .modify(|_, w| w.$tselX().bits(tsel).$tenX().set_bit())
+                    });
+                }
+
+                /// Updates the output as soon as a value is written
+                pub fn disable_trigger(&mut self) {
+                    // NOTE(unsafe) see `enable`
+                    cortex_m::interrupt::free(|_| unsafe {
+                        (*DAC1::ptr()).cr.modify(|_, w| w.$tenX().clear_bit())
+                    });
+                }
+
+                /// Fires the software trigger
+                pub fn trigger(&mut self) {
+                    // NOTE(unsafe) atomic write to a stateless register
+                    unsafe { (*DAC1::ptr()).swtrigr.write(|w| w.$swtrigX().set_bit()) };
+                }
+
+                /// Selects the hardware wave generation, which requires a trigger
+                pub fn set_wave(&mut self, wave: Wave) {
+                    let (mode, mamp) = match wave {
+                        Wave::Disabled => (0b00, 0),
+                        Wave::Noise(n) => (0b01, n),
+                        Wave::Triangle(n) => (0b10, n),
+                    };
+                    assert!(mode == 0 || (1..=12).contains(&mamp));
+                    let mamp = if mode == 0 { 0 } else { mamp - 1 };
+
+                    // NOTE(unsafe) see `enable`
+                    cortex_m::interrupt::free(|_| unsafe {
+                        (*DAC1::ptr())
+                            .cr
+                            .modify(|_, w| w.$waveX().bits(mode).$mampX().bits(mamp))
+                    });
+                }
+
+                /// Streams the 12-bit samples of `buffer` once, one sample per trigger event
+                pub fn write_dma<B>(
+                    self,
+                    chan: dma1::$dma_chan,
+                    buffer: B,
+                ) -> Transfer<R, B, dma1::$dma_chan, Self>
+                where
+                    B: ReadBuffer<Word = u16>,
+                {
+                    self.start_dma(chan, buffer, false)
+                }
+
+                /// Streams the 12-bit samples of `buffer` in a loop, one sample per trigger event
+                ///
+                /// `Transfer::wait` stops the stream at the end of the current pass.
+                pub fn write_circular<B>(
+                    self,
+                    chan: dma1::$dma_chan,
+                    buffer: B,
+                ) -> Transfer<R, B, dma1::$dma_chan, Self>
+                where
+                    B: ReadBuffer<Word = u16>,
+                {
+                    self.start_dma(chan, buffer, true)
+                }
+
+                fn start_dma<B>(
+                    self,
+                    mut chan: dma1::$dma_chan,
+                    buffer: B,
+                    circular: bool,
+                ) -> Transfer<R, B, dma1::$dma_chan, Self>
+                where
+                    B: ReadBuffer<Word = u16>,
+                {
+                    {
+                        // NOTE(unsafe) the buffer is owned by the transfer until it's over
+                        let (ptr, len) = unsafe { buffer.read_buffer() };
+                        chan.cmar().write(|w| w.ma().bits(ptr as usize as u32));
+                        chan.cndtr().write(|w| w.ndt().bits(u16(len).unwrap()));
+                        chan.cpar().write(|w| unsafe {
+                            w.pa().bits(&(*DAC1::ptr()).$dhr12rX as *const _ as usize as u32)
+                        });
+
+                        // Tell DMA to request from the DAC
+                        chan.cselr().modify(|_, w| w.$cxs().bits($request));
+
+                        // TODO can we weaken this compiler barrier?
+                        // NOTE(compiler_fence) operations on `buffer` should not be reordered
+                        // after the next statement, which starts the DMA transfer
+                        atomic::compiler_fence(Ordering::SeqCst);
+
+                        let pl = chan.priority_or(Priority::High) as u8;
+                        chan.ccr().modify(|_, w| unsafe {
+                            w.mem2mem()
+                                .clear_bit()
+                                .pl()
+                                .bits(pl)
+                                // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                                .msize()
+                                .bits(0b01)
+                                // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                                .psize()
+                                .bits(0b10)
+                                // incr mem address
+                                .minc()
+                                .set_bit()
+                                .pinc()
+                                .clear_bit()
+                                .circ()
+                                .bit(circular)
+                                // memory to peripheral
+                                .dir()
+                                .set_bit()
+                                .en()
+                                .set_bit()
+                        });
+                    }
+
+                    // NOTE(unsafe) see `enable`
+                    cortex_m::interrupt::free(|_| unsafe {
+                        (*DAC1::ptr()).cr.modify(|_, w| w.$dmaenX().set_bit())
+                    });
+
+                    Transfer::r(buffer, chan, self)
+                }
+            }
+        )+
+    }
+}
+
+channels! {
+    C1: (en1, mode1, ten1, tsel1, wave1, mamp1, dmaen1, swtrig1, dhr12r1, dor1, C3, c3s, 0b0110),
+    C2: (en2, mode2, ten2, tsel2, wave2, mamp2, dmaen2, swtrig2, dhr12r2, dor2, C4, c4s, 0b0101),
+}
@@ -1,4 +1,10 @@
 //! Date and timer units & helper functions
+//!
+//! `Date` and `Time` are displayed in the ISO 8601 extended format, e.g. `2019-03-14` and
+//! `13:05:09`, and are serialized with `serde` when the `serde` feature is enabled.
+
+use core::cmp::Ordering;
+use core::fmt;
 
 /// Seconds
 #[derive(Clone, Copy, Debug)]
@@ -81,6 +87,8 @@ impl U32Ext for u32 {
 }
 
 #[derive(Clone,Copy,Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Time {
     pub hours: u32,
     pub minutes: u32,
@@ -100,6 +108,8 @@ impl Time {
 }
 
 #[derive(Clone,Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Date {
     pub day: u32,
     pub date: u32,
@@ -118,6 +128,187 @@ impl Date {
     }
 }
 
+/// Number of seconds in a day
+const SECONDS_PER_DAY: u32 = 86_400;
+/// Number of days from 0000-03-01 to 1970-01-01, in the proleptic Gregorian calendar
+const DAYS_TO_EPOCH: u32 = 719_468;
+
+impl Time {
+    /// Returns the number of seconds elapsed since midnight
+    pub fn seconds_since_midnight(&self) -> u32 {
+        self.hours * 3600 + self.minutes * 60 + self.seconds
+    }
+
+    /// Creates a time from the number of seconds elapsed since midnight (modulo a day)
+    pub fn from_seconds_since_midnight(seconds: u32, daylight_savings: bool) -> Self {
+        let seconds = seconds % SECONDS_PER_DAY;
+        Self {
+            hours: seconds / 3600,
+            minutes: seconds / 60 % 60,
+            seconds: seconds % 60,
+            daylight_savings,
+        }
+    }
+}
+
+impl Date {
+    /// Returns the number of days elapsed since 1970-01-01
+    ///
+    /// The date must not be earlier than 1970-01-01.
+    pub fn days_since_epoch(&self) -> u32 {
+        // the year is counted from March, so that the leap day is the last day of the year
+        let year = if self.month <= 2 { self.year - 1 } else { self.year };
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let month = if self.month > 2 { self.month - 3 } else { self.month + 9 };
+        let day_of_year = (153 * month + 2) / 5 + self.date - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+        era * 146_097 + day_of_era - DAYS_TO_EPOCH
+    }
+
+    /// Creates a date from the number of days elapsed since 1970-01-01, the day of the week
+    /// included
+    pub fn from_days_since_epoch(days: u32) -> Self {
+        let days = days + DAYS_TO_EPOCH;
+        let era = days / 146_097;
+        let day_of_era = days % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let date = day_of_year - (153 * month + 2) / 5 + 1;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            day: weekday(days - DAYS_TO_EPOCH),
+            date,
+            month,
+            year,
+        }
+    }
+
+    /// Returns the day of the week, from 1 (Monday) to 7 (Sunday) as counted by the RTC
+    pub fn weekday(&self) -> u32 {
+        weekday(self.days_since_epoch())
+    }
+
+    /// Returns the date `days` days later (or earlier if negative), the day of the week included
+    ///
+    /// Panics if the result is earlier than 1970-01-01.
+    pub fn add_days(&self, days: i32) -> Self {
+        let days = i64::from(self.days_since_epoch()) + i64::from(days);
+        assert!(days >= 0);
+        Self::from_days_since_epoch(days as u32)
+    }
+}
+
+/// Times are compared by their time of day, the daylight savings flag is ignored
+impl PartialEq for Time {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Time {}
+
+impl PartialOrd for Time {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Time {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.hours, self.minutes, self.seconds).cmp(&(other.hours, other.minutes, other.seconds))
+    }
+}
+
+/// `HH:MM:SS`
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.hours, self.minutes, self.seconds)
+    }
+}
+
+/// Dates are compared by year, month and date, the day of the week is ignored
+impl PartialEq for Date {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Date {}
+
+impl PartialOrd for Date {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Date {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.year, self.month, self.date).cmp(&(other.year, other.month, other.date))
+    }
+}
+
+/// `YYYY-MM-DD`
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.date)
+    }
+}
+
+/// Returns `true` if `year` has 366 days
+pub fn is_leap_year(year: u32) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+/// Returns the number of days of `month` (1-12) in `year`
+pub fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Returns the day of the week, from 1 (Monday) to 7 (Sunday), of the day `days` days after
+/// 1970-01-01, a Thursday
+fn weekday(days: u32) -> u32 {
+    (days + 3) % 7 + 1
+}
+
+/// Returns the number of seconds elapsed since the Unix epoch (1970-01-01 00:00:00)
+///
+/// `date` and `time` are taken as UTC, the daylight savings flag is ignored.
+pub fn to_unix(date: &Date, time: &Time) -> u32 {
+    date.days_since_epoch() * SECONDS_PER_DAY + time.seconds_since_midnight()
+}
+
+/// Splits the number of seconds elapsed since the Unix epoch into a date and a time, e.g. to
+/// set the RTC from a network time
+pub fn from_unix(seconds: u32) -> (Date, Time) {
+    (
+        Date::from_days_since_epoch(seconds / SECONDS_PER_DAY),
+        Time::from_seconds_since_midnight(seconds, false),
+    )
+}
+
+/// Returns the date and time `seconds` seconds later (or earlier if negative)
+///
+/// The daylight savings flag of `time` is kept. Panics if the result is earlier than the Unix
+/// epoch.
+pub fn add_seconds(date: &Date, time: &Time, seconds: i32) -> (Date, Time) {
+    let total = i64::from(to_unix(date, time)) + i64::from(seconds);
+    assert!((0..=i64::from(u32::MAX)).contains(&total));
+    let (date, mut result) = from_unix(total as u32);
+    result.daylight_savings = time.daylight_savings;
+    (date, result)
+}
+
 impl Into<Second> for Minute {
     fn into(self) -> Second {
         Second(self.0 * 60)
@@ -0,0 +1,145 @@
+//! Debounced buttons
+//!
+//! A `Button` samples an input pin and reports a press or a release once the pin has held its new
+//! level for the debounce interval, measured by a `Clock`, e.g. the `MonoTimer`. It can be polled
+//! from the main loop or a periodic timer interrupt, or from the EXTI interrupt of the pin (see
+//! `exti`): an edge starts the interval, and a timer has to poll again while `is_settling`, since
+//! the pin may not change anymore once it has settled.
+//!
+//! ``` ignore
+//! let pin = gpioc.pc13.into_floating_input(&mut gpioc.moder, &mut gpioc.pupdr);
+//! let timer = MonoTimer::new(cp.DWT, clocks);
+//! // 20 ms
+//! let interval = timer.frequency().0 / 50;
+//! let mut button = Button::new(pin, timer, interval, Active::Low);
+//!
+//! loop {
+//!     match button.poll() {
+//!         Ok(Some(Event::Pressed)) => led.toggle(),
+//!         _ => {}
+//!     }
+//! }
+//! ```
+
+use cortex_m::peripheral::DWT;
+
+use crate::hal::digital::v2::InputPin;
+use crate::time::MonoTimer;
+
+/// Source of the timestamps of a `Button`
+pub trait Clock {
+    /// Returns the current time in ticks, wrapping around at `u32::MAX`
+    fn ticks(&self) -> u32;
+}
+
+impl Clock for MonoTimer {
+    /// Returns the DWT cycle count, see `MonoTimer::frequency`
+    fn ticks(&self) -> u32 {
+        DWT::get_cycle_count()
+    }
+}
+
+impl<F> Clock for F
+where
+    F: Fn() -> u32,
+{
+    fn ticks(&self) -> u32 {
+        self()
+    }
+}
+
+/// Level of the pin while the button is pressed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Active {
+    /// Pressed when low, e.g. a button to ground with a pull-up
+    Low,
+    /// Pressed when high
+    High,
+}
+
+/// Change of the debounced state of a button
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// The button has been pressed
+    Pressed,
+    /// The button has been released
+    Released,
+}
+
+/// Button on an input pin, debounced
+pub struct Button<PIN, CLOCK> {
+    pin: PIN,
+    clock: CLOCK,
+    interval: u32,
+    active: Active,
+    pressed: bool,
+    /// Time the pin changed to the opposite of `pressed`, if it hasn't changed back since
+    changed: Option<u32>,
+}
+
+impl<PIN, CLOCK> Button<PIN, CLOCK>
+where
+    PIN: InputPin,
+    CLOCK: Clock,
+{
+    /// Creates a button on `pin`, which has to hold a new level for `interval` ticks of `clock`
+    ///
+    /// The button starts released, a button held down is reported as pressed after `interval`.
+    pub fn new(pin: PIN, clock: CLOCK, interval: u32, active: Active) -> Self {
+        Button {
+            pin,
+            clock,
+            interval,
+            active,
+            pressed: false,
+            changed: None,
+        }
+    }
+
+    /// Samples the pin, and returns an event if the debounced state changes
+    pub fn poll(&mut self) -> Result<Option<Event>, PIN::Error> {
+        let level = self.pin.is_high()?;
+        let now = self.clock.ticks();
+
+        let pressed = level == (self.active == Active::High);
+        if pressed == self.pressed {
+            // a bounce back to the debounced state
+            self.changed = None;
+            return Ok(None);
+        }
+
+        match self.changed {
+            Some(changed) if now.wrapping_sub(changed) >= self.interval => {
+                self.changed = None;
+                self.pressed = !self.pressed;
+                Ok(Some(if self.pressed {
+                    Event::Pressed
+                } else {
+                    Event::Released
+                }))
+            }
+            Some(_) => Ok(None),
+            None => {
+                self.changed = Some(now);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns the debounced state of the button, as of the last `poll`
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Returns `true` if the pin has changed and `poll` has to be called again once the debounce
+    /// interval is over
+    pub fn is_settling(&self) -> bool {
+        self.changed.is_some()
+    }
+
+    /// Releases the pin and the clock
+    pub fn free(self) -> (PIN, CLOCK) {
+        (self.pin, self.clock)
+    }
+}
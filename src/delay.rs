@@ -1,13 +1,20 @@
 //! Delays
 
-use cast::u32;
+use cast::{u32, u64};
+use cortex_m::asm;
 use cortex_m::peripheral::syst::SystClkSource;
 use cortex_m::peripheral::SYST;
 
 use crate::hal::blocking::delay::{DelayMs, DelayUs};
+use crate::hal::watchdog::Watchdog;
 use crate::rcc::Clocks;
 
+/// Largest SysTick reload value
+const MAX_RVR: u64 = 0x00ff_ffff;
+
 /// System timer (SysTick) as a delay provider
+///
+/// The SysTick is owned until `free`; `SharedDelay` leaves it to other code, e.g. an RTOS.
 pub struct Delay {
     clocks: Clocks,
     syst: SYST,
@@ -25,11 +32,123 @@ impl Delay {
     pub fn free(self) -> SYST {
         self.syst
     }
+
+    /// Returns a delay provider that feeds `watchdog` at least every `interval_us` microseconds
+    /// while waiting
+    pub fn with_watchdog<'a, W>(
+        &'a mut self,
+        watchdog: &'a mut W,
+        interval_us: u32,
+    ) -> WatchdogDelay<'a, W>
+    where
+        W: Watchdog,
+    {
+        let interval = u64(interval_us) * u64(self.clocks.sysclk().0) / 1_000_000;
+        WatchdogDelay {
+            delay: self,
+            watchdog,
+            interval,
+        }
+    }
+
+    fn ticks_us(&self, us: u64) -> u64 {
+        us * u64(self.clocks.sysclk().0) / 1_000_000
+    }
+
+    /// Waits for `ticks` core clock cycles, in chunks of at most `chunk` cycles, calling `f`
+    /// after every chunk
+    fn delay_ticks<F>(&mut self, mut ticks: u64, chunk: u64, mut f: F)
+    where
+        F: FnMut(),
+    {
+        let chunk = if chunk == 0 || chunk > MAX_RVR { MAX_RVR } else { chunk };
+
+        while ticks > 0 {
+            let rvr = if ticks > chunk { chunk } else { ticks };
+            ticks -= rvr;
+
+            // NOTE(as) `rvr` is at most 24 bits wide
+            self.syst.set_reload(rvr as u32);
+            self.syst.clear_current();
+            self.syst.enable_counter();
+
+            while !self.syst.has_wrapped() {}
+
+            self.syst.disable_counter();
+            f();
+        }
+    }
+}
+
+/// A `Delay` that feeds a watchdog during long waits
+pub struct WatchdogDelay<'a, W> {
+    delay: &'a mut Delay,
+    watchdog: &'a mut W,
+    interval: u64,
+}
+
+impl<'a, W> DelayMs<u32> for WatchdogDelay<'a, W>
+where
+    W: Watchdog,
+{
+    fn delay_ms(&mut self, ms: u32) {
+        let ticks = self.delay.ticks_us(u64(ms) * 1_000);
+        let watchdog = &mut *self.watchdog;
+        self.delay.delay_ticks(ticks, self.interval, || watchdog.feed());
+    }
+}
+
+impl<'a, W> DelayMs<u16> for WatchdogDelay<'a, W>
+where
+    W: Watchdog,
+{
+    fn delay_ms(&mut self, ms: u16) {
+        self.delay_ms(u32(ms));
+    }
+}
+
+impl<'a, W> DelayMs<u8> for WatchdogDelay<'a, W>
+where
+    W: Watchdog,
+{
+    fn delay_ms(&mut self, ms: u8) {
+        self.delay_ms(u32(ms));
+    }
+}
+
+impl<'a, W> DelayUs<u32> for WatchdogDelay<'a, W>
+where
+    W: Watchdog,
+{
+    fn delay_us(&mut self, us: u32) {
+        let ticks = self.delay.ticks_us(u64(us));
+        let watchdog = &mut *self.watchdog;
+        self.delay.delay_ticks(ticks, self.interval, || watchdog.feed());
+    }
+}
+
+impl<'a, W> DelayUs<u16> for WatchdogDelay<'a, W>
+where
+    W: Watchdog,
+{
+    fn delay_us(&mut self, us: u16) {
+        self.delay_us(u32(us))
+    }
+}
+
+impl<'a, W> DelayUs<u8> for WatchdogDelay<'a, W>
+where
+    W: Watchdog,
+{
+    fn delay_us(&mut self, us: u8) {
+        self.delay_us(u32(us))
+    }
 }
 
 impl DelayMs<u32> for Delay {
     fn delay_ms(&mut self, ms: u32) {
-        self.delay_us(ms * 1_000);
+        let ticks = self.ticks_us(u64(ms) * 1_000);
+        self.delay_ticks(ticks, MAX_RVR, || {});
     }
 }
 
@@ -47,28 +166,184 @@ impl DelayMs<u8> for Delay {
 
 impl DelayUs<u32> for Delay {
     fn delay_us(&mut self, us: u32) {
-        let rvr = us * (self.clocks.sysclk().0 / 1_000_000);
+        let ticks = self.ticks_us(u64(us));
+        self.delay_ticks(ticks, MAX_RVR, || {});
+    }
+}
+
+impl DelayUs<u16> for Delay {
+    fn delay_us(&mut self, us: u16) {
+        self.delay_us(u32(us))
+    }
+}
 
-        // assert!(rvr < (1 << 24)); //TODO fix this assertion
+impl DelayUs<u8> for Delay {
+    fn delay_us(&mut self, us: u8) {
+        self.delay_us(u32(us))
+    }
+}
+
+/// Delay provider reading a SysTick owned by other code, e.g. the periodic tick of an RTOS
+///
+/// Unlike `Delay`, it doesn't own the SysTick, which keeps running with its reload value and
+/// interrupt: the delay counts the cycles elapsed on the current value of the counter. The
+/// SysTick has to be running, clocked by the core clock; the delay is at least the requested
+/// time, and longer if the SysTick wraps around more than once between two reads, e.g. while a
+/// long interrupt handler runs.
+#[derive(Clone, Copy)]
+pub struct SharedDelay {
+    sysclk: u32,
+}
 
-        self.syst.set_reload(rvr);
-        self.syst.clear_current();
-        self.syst.enable_counter();
+impl SharedDelay {
+    /// Creates a delay provider for the core clock frequency of `clocks`
+    pub fn new(clocks: Clocks) -> Self {
+        SharedDelay {
+            sysclk: clocks.sysclk().0,
+        }
+    }
 
-        while !self.syst.has_wrapped() {}
+    /// Waits for `ticks` core clock cycles
+    fn delay_ticks(&self, ticks: u64) {
+        // the counter counts down from the reload value to 0
+        let period = u64(SYST::get_reload()) + 1;
+        let mut previous = SYST::get_current();
+        let mut elapsed = 0;
 
-        self.syst.disable_counter();
+        while elapsed < ticks {
+            let current = SYST::get_current();
+            elapsed += if current <= previous {
+                u64(previous - current)
+            } else {
+                // wrapped around
+                u64(previous) + period - u64(current)
+            };
+            previous = current;
+        }
     }
 }
 
-impl DelayUs<u16> for Delay {
+impl DelayMs<u32> for SharedDelay {
+    fn delay_ms(&mut self, ms: u32) {
+        self.delay_ticks(u64(ms) * u64(self.sysclk) / 1_000);
+    }
+}
+
+impl DelayMs<u16> for SharedDelay {
+    fn delay_ms(&mut self, ms: u16) {
+        self.delay_ms(u32(ms));
+    }
+}
+
+impl DelayMs<u8> for SharedDelay {
+    fn delay_ms(&mut self, ms: u8) {
+        self.delay_ms(u32(ms));
+    }
+}
+
+impl DelayUs<u32> for SharedDelay {
+    fn delay_us(&mut self, us: u32) {
+        self.delay_ticks(u64(us) * u64(self.sysclk) / 1_000_000);
+    }
+}
+
+impl DelayUs<u16> for SharedDelay {
     fn delay_us(&mut self, us: u16) {
         self.delay_us(u32(us))
     }
 }
 
-impl DelayUs<u8> for Delay {
+impl DelayUs<u8> for SharedDelay {
     fn delay_us(&mut self, us: u8) {
         self.delay_us(u32(us))
     }
 }
+
+/// Busy-wait delay provider counting core clock cycles
+///
+/// Unlike `Delay`, it doesn't need the SysTick and has no setup cost, e.g. for the short delays
+/// of bit-banged 1-Wire. It only guarantees a minimum delay: it waits for at least the requested
+/// time, and flash wait states, the call itself and interrupts serviced meanwhile lengthen it.
+#[derive(Clone, Copy)]
+pub struct CyclesDelay {
+    sysclk: u32,
+}
+
+impl CyclesDelay {
+    /// Creates a delay provider for the core clock frequency of `clocks`
+    pub fn new(clocks: Clocks) -> Self {
+        CyclesDelay {
+            sysclk: clocks.sysclk().0,
+        }
+    }
+
+    /// Waits for at least `cycles` core clock cycles
+    #[inline(always)]
+    pub fn delay_cycles(&self, cycles: u32) {
+        asm::delay(cycles);
+    }
+
+    /// Waits for at least `ns` nanoseconds
+    #[inline]
+    pub fn delay_ns(&self, ns: u32) {
+        // round up, so that the delay is never shorter than requested
+        let cycles = (u64(ns) * u64(self.sysclk)).div_ceil(1_000_000_000);
+        self.delay_cycles_u64(cycles);
+    }
+
+    fn delay_cycles_u64(&self, mut cycles: u64) {
+        while cycles > 0 {
+            let chunk = if cycles > u64(u32::MAX) {
+                u32::MAX
+            } else {
+                // NOTE(as) checked just above
+                cycles as u32
+            };
+            asm::delay(chunk);
+            cycles -= u64(chunk);
+        }
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl embedded_hal_1::delay::DelayNs for CyclesDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        CyclesDelay::delay_ns(self, ns);
+    }
+}
+
+impl DelayUs<u32> for CyclesDelay {
+    fn delay_us(&mut self, us: u32) {
+        self.delay_cycles_u64(u64(us) * u64(self.sysclk) / 1_000_000);
+    }
+}
+
+impl DelayUs<u16> for CyclesDelay {
+    fn delay_us(&mut self, us: u16) {
+        self.delay_us(u32(us))
+    }
+}
+
+impl DelayUs<u8> for CyclesDelay {
+    fn delay_us(&mut self, us: u8) {
+        self.delay_us(u32(us))
+    }
+}
+
+impl DelayMs<u32> for CyclesDelay {
+    fn delay_ms(&mut self, ms: u32) {
+        self.delay_cycles_u64(u64(ms) * u64(self.sysclk) / 1_000);
+    }
+}
+
+impl DelayMs<u16> for CyclesDelay {
+    fn delay_ms(&mut self, ms: u16) {
+        self.delay_ms(u32(ms));
+    }
+}
+
+impl DelayMs<u8> for CyclesDelay {
+    fn delay_ms(&mut self, ms: u8) {
+        self.delay_ms(u32(ms));
+    }
+}
@@ -2,18 +2,56 @@
 
 #![allow(dead_code)]
 
+use core::fmt;
 use core::marker::PhantomData;
 use core::ops;
 
 use crate::rcc::AHB1;
 use stable_deref_trait::StableDeref;
 
+/// Buffers of the one-shot transfers
+///
+/// The circular transfers (`CircBuffer`, `capture::Frames`) and the serial `TxQueue` go through
+/// the two halves of a `StableDeref<Target = [H; 2]>` buffer instead, which these traits can't
+/// describe.
+pub use embedded_dma::{ReadBuffer, WriteBuffer};
+
+/// DMA error
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum Error {
+    /// Both halves of a circular buffer were written before the readable one was read
     Overrun,
+    /// The closure of `partial_peek` failed
     BufferError,
-    #[doc(hidden)]
-    _Extensible,
+    /// Transfer error (TEIF): the channel accessed a reserved address, and was disabled by the
+    /// hardware
+    Transfer,
+}
+
+/// A transfer that failed, along with its parts
+pub struct TransferError<BUFFER, CHANNEL, PAYLOAD> {
+    /// Cause of the failure
+    pub error: Error,
+    /// Buffer of the transfer, partially transferred
+    pub buffer: BUFFER,
+    /// Channel of the transfer, disabled
+    pub channel: CHANNEL,
+    /// Payload of the transfer
+    pub payload: PAYLOAD,
+}
+
+impl<BUFFER, CHANNEL, PAYLOAD> fmt::Debug for TransferError<BUFFER, CHANNEL, PAYLOAD> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TransferError").field("error", &self.error).finish()
+    }
+}
+
+impl<BUFFER, CHANNEL, PAYLOAD> From<TransferError<BUFFER, CHANNEL, PAYLOAD>> for Error {
+    fn from(error: TransferError<BUFFER, CHANNEL, PAYLOAD>) -> Self {
+        error.error
+    }
 }
 
 pub enum Event {
@@ -21,6 +59,22 @@ pub enum Event {
     TransferComplete,
 }
 
+/// Channel priority, used to arbitrate between the requests of the channels of a controller
+///
+/// Among channels of the same priority, the channel with the lowest number wins.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Priority {
+    /// Lowest priority, the reset value
+    Low = 0b00,
+    /// Medium priority
+    Medium = 0b01,
+    /// High priority
+    High = 0b10,
+    /// Highest priority
+    VeryHigh = 0b11,
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum Half {
     First,
@@ -53,8 +107,13 @@ impl<BUFFER, CHANNEL> CircBuffer<BUFFER, CHANNEL> {
 
 pub trait DmaExt {
     type Channels;
+    type LazyChannels;
 
     fn split(self, ahb: &mut AHB1) -> Self::Channels;
+
+    /// Turns the DMA controller into a handle from which the channels are taken one at a time,
+    /// see `singleton`
+    fn split_lazy(self, ahb: &mut AHB1) -> Self::LazyChannels;
 }
 
 pub struct Transfer<MODE, BUFFER, CHANNEL, PAYLOAD> {
@@ -66,7 +125,7 @@ pub struct Transfer<MODE, BUFFER, CHANNEL, PAYLOAD> {
 
 impl<BUFFER, CHANNEL, PAYLOAD> Transfer<R, BUFFER, CHANNEL, PAYLOAD>
 where
-    BUFFER: StableDeref + 'static,
+    BUFFER: ReadBuffer,
 {
     pub(crate) fn r(buffer: BUFFER, channel: CHANNEL, payload: PAYLOAD) -> Self {
         Transfer {
@@ -80,7 +139,7 @@ where
 
 impl<BUFFER, CHANNEL, PAYLOAD> Transfer<W, BUFFER, CHANNEL, PAYLOAD>
 where
-    BUFFER: StableDeref + 'static,
+    BUFFER: WriteBuffer,
 {
     pub(crate) fn w(buffer: BUFFER, channel: CHANNEL, payload: PAYLOAD) -> Self {
         Transfer {
@@ -100,6 +159,9 @@ impl<BUFFER, CHANNEL, PAYLOAD> ops::Deref for Transfer<R, BUFFER, CHANNEL, PAYLO
     }
 }
 
+/// SCR.SEVONPEND, pending interrupts send events
+const SCB_SCR_SEVONPEND: u32 = 1 << 4;
+
 /// Read transfer
 pub struct R;
 
@@ -109,6 +171,7 @@ pub struct W;
 macro_rules! dma {
     ($($DMAX:ident: ($dmaX:ident, $dmaXen:ident, $dmaXrst:ident, {
         $($CX:ident: (
+            $x:expr,
             $ccrX:ident,
             $CCRX:ident,
             $cndtrX:ident,
@@ -119,6 +182,7 @@ macro_rules! dma {
             $CMARX:ident,
             $htifX:ident,
             $tcifX:ident,
+            $teifX:ident,
             $chtifX:ident,
             $ctcifX:ident,
             $cgifX:ident
@@ -126,18 +190,58 @@ macro_rules! dma {
     }),)+) => {
         $(
             pub mod $dmaX {
-                use core::sync::atomic::{self, Ordering};
+                use core::sync::atomic::{self, AtomicU32, Ordering};
                 use stable_deref_trait::StableDeref;
                 use as_slice::AsSlice;
                 use crate::stm32::{$DMAX, dma1};
 
-                use crate::dma::{CircBuffer, DmaExt, Error, Event, Half, Transfer, W};
+                use cortex_m::peripheral::{NVIC, SCB};
+
+                use crate::dma::{
+                    CircBuffer, DmaExt, Error, Event, Half, Priority, Transfer, TransferError, W,
+                    SCB_SCR_SEVONPEND,
+                };
+                use crate::interrupts::HasInterrupt;
+                use crate::pwr::Busy;
                 use crate::rcc::AHB1;
+                use crate::serial::framed;
+                use crate::singleton::{self, Singleton};
 
                 pub struct Channels((), $(pub $CX),+);
 
+                /// Channels taken from `LazyChannels`
+                static TAKEN: AtomicU32 = AtomicU32::new(0);
+
+                /// DMA handle, see `DmaExt::split_lazy`
+                #[derive(Clone, Copy)]
+                pub struct LazyChannels {
+                    _0: (),
+                }
+
+                impl LazyChannels {
+                    /// Takes the channel `C`, returns `None` if it was already taken
+                    pub fn split_one<C>(&self) -> Option<C>
+                    where
+                        C: Singleton<LazyChannels>,
+                    {
+                        if singleton::take(&TAKEN, C::BIT) {
+                            Some(C::new())
+                        } else {
+                            None
+                        }
+                    }
+                }
+
                 $(
-                    pub struct $CX { _0: () }
+                    pub struct $CX { priority: Option<Priority> }
+
+                    unsafe impl Singleton<LazyChannels> for $CX {
+                        const BIT: u8 = $x;
+
+                        fn new() -> Self {
+                            $CX { priority: None }
+                        }
+                    }
 
                     impl $CX {
                         pub fn listen(&mut self, event: Event) {
@@ -160,6 +264,33 @@ macro_rules! dma {
                             }
                         }
 
+                        /// Sets the priority of the channel
+                        ///
+                        /// The priority is kept by the transfers started on the channel, which
+                        /// otherwise use a default set by the driver starting them. It can't be
+                        /// changed while a transfer runs, as the PL bits are read-only while the
+                        /// channel is enabled.
+                        pub fn set_priority(&mut self, priority: Priority) {
+                            self.priority = Some(priority);
+                            self.ccr().modify(|_, w| w.pl().bits(priority as u8));
+                        }
+
+                        /// Returns the priority of the channel
+                        pub fn priority(&self) -> Priority {
+                            // NOTE(unsafe) atomic read with no side effects
+                            match unsafe { (*$DMAX::ptr()).$ccrX.read().pl().bits() } {
+                                0b00 => Priority::Low,
+                                0b01 => Priority::Medium,
+                                0b10 => Priority::High,
+                                _ => Priority::VeryHigh,
+                            }
+                        }
+
+                        /// Returns the priority set with `set_priority`, `default` if none was
+                        pub(crate) fn priority_or(&self, default: Priority) -> Priority {
+                            self.priority.unwrap_or(default)
+                        }
+
                         pub(crate) fn isr(&self) -> dma1::isr::R {
                             // NOTE(unsafe) atomic read with no side effects
                             unsafe { (*$DMAX::ptr()).isr.read() }
@@ -194,6 +325,12 @@ macro_rules! dma {
                             unsafe { (*$DMAX::ptr()).$cndtrX.read().bits() }
                         }
 
+                        /// Clears the flags of the channel, e.g. of a transfer stopped by the
+                        /// driver
+                        pub(crate) fn clear_flags(&mut self) {
+                            self.ifcr().write(|w| w.$cgifX().set_bit());
+                        }
+
                     }
 
                     impl<B> CircBuffer<B, $CX> {
@@ -263,6 +400,7 @@ macro_rules! dma {
                             let second_half_is_done = isr.$tcifX().bit_is_set();
 
                             if first_half_is_done && second_half_is_done {
+                                trace!("DMA: circular buffer overrun");
                                 return Err(Error::Overrun);
                             }
 
@@ -293,22 +431,266 @@ macro_rules! dma {
                         }
                     }
 
+                    impl<B, H> CircBuffer<B, $CX>
+                    where
+                        B: StableDeref<Target = [H; 2]>,
+                        H: AsSlice<Element = u8>,
+                    {
+                        /// Returns the number of received bytes that haven't been read yet
+                        pub fn bytes_available(&mut self) -> Result<usize, Error> {
+                            let (writing, done) = self.writing_half()?;
+                            let capacity = self.buffer[0].as_slice().len();
+
+                            Ok(if done {
+                                // the DMA has moved on to the half after the completed one
+                                capacity - self.consumed_offset + self.written(self.readable_half)
+                            } else {
+                                self.written(writing).max(self.consumed_offset)
+                                    - self.consumed_offset
+                            })
+                        }
+
+                        /// Moves received bytes into `buffer`, returns the number of bytes read
+                        ///
+                        /// Unlike `peek`, this reads the bytes as soon as the DMA has written
+                        /// them, and keeps track of the bytes already read, so the `CircBuffer`
+                        /// can be used as a FIFO. Returns `Error::Overrun` if the DMA has
+                        /// overwritten bytes that weren't read yet.
+                        pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+                            let mut count = 0;
+                            self.consume(|data| {
+                                let n = data.len().min(buffer.len() - count);
+                                buffer[count..count + n].copy_from_slice(&data[..n]);
+                                count += n;
+                                n
+                            })?;
+
+                            Ok(count)
+                        }
+
+                        /// Passes the unread bytes to `f`, in up to two slices, and marks the
+                        /// number of bytes returned by `f` as read
+                        fn consume<F>(&mut self, mut f: F) -> Result<(), Error>
+                        where
+                            F: FnMut(&[u8]) -> usize,
+                        {
+                            let (writing, done) = self.writing_half()?;
+
+                            if done {
+                                let buf = match writing {
+                                    Half::First => &self.buffer[0],
+                                    Half::Second => &self.buffer[1],
+                                };
+                                let data = &buf.as_slice()[self.consumed_offset..];
+                                let len = data.len();
+                                let n = f(data);
+                                self.consumed_offset += n;
+                                if n < len {
+                                    return Ok(());
+                                }
+
+                                match writing {
+                                    Half::First => {
+                                        self.channel.ifcr().write(|w| w.$chtifX().set_bit())
+                                    }
+                                    Half::Second => {
+                                        self.channel.ifcr().write(|w| w.$ctcifX().set_bit())
+                                    }
+                                }
+                                self.readable_half = writing;
+                                self.consumed_offset = 0;
+                            }
+
+                            let writing = match self.readable_half {
+                                Half::First => Half::Second,
+                                Half::Second => Half::First,
+                            };
+                            // the half may have been completed after the flags were read, its
+                            // end is then picked up by the next call
+                            let end = self.written(writing).max(self.consumed_offset);
+                            let buf = match writing {
+                                Half::First => &self.buffer[0],
+                                Half::Second => &self.buffer[1],
+                            };
+                            let n = f(&buf.as_slice()[self.consumed_offset..end]);
+                            self.consumed_offset += n;
+
+                            Ok(())
+                        }
+
+                        /// Returns the half being written by the DMA, and whether it has been
+                        /// completed since the last read
+                        fn writing_half(&self) -> Result<(Half, bool), Error> {
+                            let isr = self.channel.isr();
+                            let first_half_is_done = isr.$htifX().bit_is_set();
+                            let second_half_is_done = isr.$tcifX().bit_is_set();
+
+                            if first_half_is_done && second_half_is_done {
+                                trace!("DMA: circular buffer overrun");
+                                return Err(Error::Overrun);
+                            }
+
+                            Ok(match self.readable_half {
+                                Half::First => (Half::Second, second_half_is_done),
+                                Half::Second => (Half::First, first_half_is_done),
+                            })
+                        }
+
+                        /// Returns the number of bytes written in `half` during the current pass
+                        /// of the DMA, derived from CNDTR
+                        fn written(&self, half: Half) -> usize {
+                            let capacity = self.buffer[0].as_slice().len();
+                            // CNDTR counts down from the size of the whole buffer
+                            let written = 2 * capacity - self.channel.get_cndtr() as usize;
+
+                            match half {
+                                Half::First => written.min(capacity),
+                                Half::Second => written.saturating_sub(capacity),
+                            }
+                        }
+                    }
+
+                    impl<B, H> framed::Source for CircBuffer<B, $CX>
+                    where
+                        B: StableDeref<Target = [H; 2]>,
+                        H: AsSlice<Element = u8>,
+                    {
+                        fn drain<F>(&mut self, mut f: F) -> Result<(), framed::Error>
+                        where
+                            F: FnMut(&[u8]),
+                        {
+                            self.consume(|data| {
+                                f(data);
+                                data.len()
+                            })
+                            .map_err(|_| framed::Error::Overrun)
+                        }
+                    }
+
                     impl<BUFFER, PAYLOAD, MODE> Transfer<MODE, BUFFER, $CX, PAYLOAD> {
                         pub fn is_done(&self) -> bool {
                             self.channel.isr().$tcifX().bit_is_set()
                         }
 
-                        pub fn wait(mut self) -> (BUFFER, $CX, PAYLOAD) {
-                            // XXX should we check for transfer errors here?
-                            // The manual says "A DMA transfer error can be generated by reading
-                            // from or writing to a reserved address space". I think it's impossible
-                            // to get to that state with our type safe API and *safe* Rust.
-                            while !self.is_done() {}
+                        /// Returns `true` if the transfer failed, see `Error::Transfer`
+                        pub fn is_error(&self) -> bool {
+                            self.channel.isr().$teifX().bit_is_set()
+                        }
 
-                            self.channel.ifcr().write(|w| w.$cgifX().set_bit());
+                        /// Waits for the end of the transfer and returns its parts
+                        ///
+                        /// The manual says "A DMA transfer error can be generated by reading
+                        /// from or writing to a reserved address space", which the type safe API
+                        /// shouldn't allow; the parts are still returned if it happens.
+                        pub fn wait(
+                            self,
+                        ) -> Result<(BUFFER, $CX, PAYLOAD), TransferError<BUFFER, $CX, PAYLOAD>>
+                        {
+                            let error = loop {
+                                let isr = self.channel.isr();
+                                if isr.$teifX().bit_is_set() {
+                                    trace!(
+                                        "{=str}: transfer error on channel {=u8}",
+                                        stringify!($DMAX),
+                                        $x
+                                    );
+                                    break true;
+                                }
+                                if isr.$tcifX().bit_is_set() {
+                                    break false;
+                                }
+                            };
+
+                            let (buffer, channel, payload) = self.stop();
+                            if error {
+                                Err(TransferError {
+                                    error: Error::Transfer,
+                                    buffer,
+                                    channel,
+                                    payload,
+                                })
+                            } else {
+                                Ok((buffer, channel, payload))
+                            }
+                        }
+
+                        /// Waits for the end of the transfer in Sleep mode and returns its
+                        /// parts, see `wait`
+                        ///
+                        /// The transfer complete and error interrupts of the channel are enabled,
+                        /// and the core sleeps on WFE until one of them is pending (SEVONPEND).
+                        /// The interrupt doesn't have to be enabled in the NVIC, and its pending
+                        /// state is cleared on return; if it's enabled, its handler runs
+                        /// meanwhile. Other interrupts and events wake the core up too, which
+                        /// goes back to sleep. The end of the transfer is still detected if the
+                        /// handler clears the flags of the channel: the counter reached 0, or the
+                        /// channel was disabled by a transfer error.
+                        pub fn wait_low_power(
+                            mut self,
+                            scb: &mut SCB,
+                        ) -> Result<(BUFFER, $CX, PAYLOAD), TransferError<BUFFER, $CX, PAYLOAD>>
+                        where
+                            $CX: HasInterrupt,
+                        {
+                            // a pending state left over wouldn't send an event
+                            NVIC::unpend(<$CX as HasInterrupt>::INTERRUPT);
+                            let ccr = self.channel.ccr().read();
+                            let (tcie, teie) = (ccr.tcie().bit(), ccr.teie().bit());
+                            self.channel.ccr().modify(|_, w| w.tcie().set_bit().teie().set_bit());
+                            // NOTE(unsafe) SCR.SEVONPEND, the other bits are preserved
+                            let scr = scb.scr.read();
+                            unsafe { scb.scr.write(scr | SCB_SCR_SEVONPEND) };
+
+                            let error = loop {
+                                let isr = self.channel.isr();
+                                if isr.$teifX().bit_is_set() {
+                                    break true;
+                                }
+                                if isr.$tcifX().bit_is_set() {
+                                    break false;
+                                }
+                                // the flags may have been cleared by the interrupt handler
+                                if self.channel.ccr().read().en().bit_is_clear() {
+                                    break true;
+                                }
+                                if self.channel.get_cndtr() == 0 {
+                                    break false;
+                                }
+                                // an interrupt pending between the check and WFE sets the event
+                                // register, WFE returns right away
+                                cortex_m::asm::wfe();
+                            };
 
+                            unsafe { scb.scr.write(scr) };
+                            self.channel.ccr().modify(|_, w| w.tcie().bit(tcie).teie().bit(teie));
+                            NVIC::unpend(<$CX as HasInterrupt>::INTERRUPT);
+
+                            let (buffer, channel, payload) = self.stop();
+                            if error {
+                                Err(TransferError {
+                                    error: Error::Transfer,
+                                    buffer,
+                                    channel,
+                                    payload,
+                                })
+                            } else {
+                                Ok((buffer, channel, payload))
+                            }
+                        }
+
+                        /// Stops the transfer wherever it is and returns its parts
+                        ///
+                        /// The channel is disabled after the data item in flight, the buffer is
+                        /// partially transferred.
+                        pub fn abort(self) -> (BUFFER, $CX, PAYLOAD) {
+                            self.stop()
+                        }
+
+                        fn stop(mut self) -> (BUFFER, $CX, PAYLOAD) {
                             self.channel.ccr().modify(|_, w| w.en().clear_bit());
 
+                            self.channel.ifcr().write(|w| w.$cgifX().set_bit());
+
                             // TODO can we weaken this compiler barrier?
                             // NOTE(compiler_fence) operations on `buffer` should not be reordered
                             // before the previous statement, which marks the DMA transfer as done
@@ -318,6 +700,12 @@ macro_rules! dma {
                         }
                     }
 
+                    impl<BUFFER, PAYLOAD, MODE> Busy for Transfer<MODE, BUFFER, $CX, PAYLOAD> {
+                        fn is_busy(&self) -> bool {
+                            !self.is_done() && !self.is_error()
+                        }
+                    }
+
                     impl<BUFFER, PAYLOAD> Transfer<W, &'static mut BUFFER, $CX, PAYLOAD> {
                         pub fn peek<T>(&self) -> &[T]
                         where
@@ -334,6 +722,7 @@ macro_rules! dma {
 
                 impl DmaExt for $DMAX {
                     type Channels = Channels;
+                    type LazyChannels = LazyChannels;
 
                     fn split(self, ahb: &mut AHB1) -> Channels {
                         ahb.enr().modify(|_, w| w.$dmaXen().set_bit());
@@ -343,7 +732,13 @@ macro_rules! dma {
                             self.$ccrX.reset();
                         )+
 
-                        Channels((), $($CX { _0: () }),+)
+                        Channels((), $($CX { priority: None }),+)
+                    }
+
+                    fn split_lazy(self, ahb: &mut AHB1) -> LazyChannels {
+                        let _ = self.split(ahb);
+
+                        LazyChannels { _0: () }
                     }
                 }
             }
@@ -354,59 +749,66 @@ macro_rules! dma {
 dma! {
     DMA1: (dma1, dma1en, dma1rst, {
         C1: (
+            1,
             ccr1, CCR1,
             cndtr1, CNDTR1,
             cpar1, CPAR1,
             cmar1, CMAR1,
-            htif1, tcif1,
+            htif1, tcif1, teif1,
             chtif1, ctcif1, cgif1
         ),
         C2: (
+            2,
             ccr2, CCR2,
             cndtr2, CNDTR2,
             cpar2, CPAR2,
             cmar2, CMAR2,
-            htif2, tcif2,
+            htif2, tcif2, teif2,
             chtif2, ctcif2, cgif2
         ),
         C3: (
+            3,
             ccr3, CCR3,
             cndtr3, CNDTR3,
             cpar3, CPAR3,
             cmar3, CMAR3,
-            htif3, tcif3,
+            htif3, tcif3, teif3,
             chtif3, ctcif3, cgif3
         ),
         C4: (
+            4,
             ccr4, CCR4,
             cndtr4, CNDTR4,
             cpar4, CPAR4,
             cmar4, CMAR4,
-            htif4, tcif4,
+            htif4, tcif4, teif4,
             chtif4, ctcif4, cgif4
         ),
         C5: (
+            5,
             ccr5, CCR5,
             cndtr5, CNDTR5,
             cpar5, CPAR5,
             cmar5, CMAR5,
-            htif5, tcif5,
+            htif5, tcif5, teif5,
             chtif5, ctcif5, cgif5
         ),
         C6: (
+            6,
             ccr6, CCR6,
             cndtr6, CNDTR6,
             cpar6, CPAR6,
             cmar6, CMAR6,
-            htif6, tcif6,
+            htif6, tcif6, teif6,
             chtif6, ctcif6, cgif6
         ),
         C7: (
+            7,
             ccr7, CCR7,
             cndtr7, CNDTR7,
             cpar7, CPAR7,
             cmar7, CMAR7,
-            htif7, tcif7,
+            htif7, tcif7, teif7,
             chtif7, ctcif7, cgif7
         ),
     }),
@@ -0,0 +1,378 @@
+//! Errors of the drivers
+//!
+//! Each driver has its own error type, e.g. `serial::Error`. They all convert into the
+//! crate-wide `Error`, so an application can propagate the errors of different drivers with
+//! `?`, and they implement `DriverError`, which sorts them into a few kinds for a common
+//! handling path, e.g. retrying after an overrun but resetting a bus after a bus error.
+//! `DriverError` isn't part of the prelude, as its `kind` would be ambiguous with the one of the
+//! embedded-hal 1.0 error traits:
+//!
+//! ``` ignore
+//! use stm32l4xx_hal::error::{DriverError, Error, ErrorKind};
+//!
+//! fn poll(serial: &mut Rx<USART2>, i2c: &mut I2c<I2C1, PINS>) -> Result<(), Error> {
+//!     let byte = block!(serial.read())?;
+//!     i2c.write(ADDRESS, &[byte])?;
+//!     Ok(())
+//! }
+//!
+//! match poll(&mut rx, &mut i2c) {
+//!     Err(error) if error.kind() == ErrorKind::Overrun => {}
+//!     Err(error) => panic!("{:?}", error),
+//!     Ok(()) => {}
+//! }
+//! ```
+//!
+//! The errors of the drivers generic over their bus convert into `Error` when they run on the
+//! drivers of this crate: `i2c::eeprom::Error<i2c::Error>`, `spi::bus::Error<spi::Error, ()>`
+//! with GPIO chip selects, and the IDD measurement of the Discovery L476 on `i2c::Error`. The ADC
+//! conversions can't fail, their `Void` error converts into `Error` as well.
+//! `tsc::MaxCountError` isn't an error but the count limit of the configuration, exceeding it is
+//! `tsc::Error::MaxCountError`.
+
+use core::fmt;
+
+use void::Void;
+
+#[cfg(feature = "disco-l476")]
+use crate::board::disco_l476;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+use crate::crs;
+use crate::flash::{self, iap, storage};
+use crate::rcc::ClockError;
+#[cfg(feature = "modbus")]
+use crate::serial::modbus;
+use crate::i2c::eeprom;
+use crate::serial::{self, framed};
+#[cfg(feature = "embedded-hal-1")]
+use crate::spi::bus;
+use crate::{dma, i2c, pwm, pwr, rng, spi, tsc};
+
+/// Kind of a driver error
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Received data was lost before it could be read
+    Overrun,
+    /// Data was corrupted: parity, framing, noise, CRC or encoding error
+    Corrupted,
+    /// Bus error, e.g. a NACK, an arbitration loss or a DMA transfer error
+    Bus,
+    /// The peripheral or the bus was busy
+    Busy,
+    /// A clock couldn't be generated or synchronized
+    Clock,
+    /// The flash memory or its contents
+    Memory,
+    /// A buffer was too small, or full
+    Buffer,
+    /// Any other error
+    Other,
+}
+
+/// Error of a driver
+pub trait DriverError: fmt::Debug {
+    /// Returns the kind of the error
+    fn kind(&self) -> ErrorKind;
+}
+
+/// Error of any driver
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Clock error
+    Clock(ClockError),
+    /// CRS error
+    #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+    Crs(crs::Error),
+    /// DMA error
+    Dma(dma::Error),
+    /// Flash error
+    Flash(flash::Error),
+    /// IAP error
+    Iap(iap::Error),
+    /// Flash storage error
+    Storage(storage::Error),
+    /// I2C error
+    I2c(i2c::Error),
+    /// I2C EEPROM error
+    Eeprom(eeprom::Error<i2c::Error>),
+    /// Power error
+    Pwr(pwr::Error),
+    /// PWM error
+    Pwm(pwm::Error),
+    /// RNG error
+    Rng(rng::Error),
+    /// Serial error
+    Serial(serial::Error),
+    /// Serial framing error
+    Framed(framed::Error),
+    /// Modbus error
+    #[cfg(feature = "modbus")]
+    Modbus(modbus::Error),
+    /// SPI error
+    Spi(spi::Error),
+    /// Shared SPI bus error
+    #[cfg(feature = "embedded-hal-1")]
+    SpiBus(bus::Error<spi::Error, ()>),
+    /// TSC error
+    Tsc(tsc::Error),
+    /// IDD measurement error of the Discovery L476
+    #[cfg(feature = "disco-l476")]
+    Idd(disco_l476::Error<i2c::Error>),
+}
+
+impl DriverError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Clock(error) => error.kind(),
+            #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+            Error::Crs(error) => error.kind(),
+            Error::Dma(error) => error.kind(),
+            Error::Flash(error) => error.kind(),
+            Error::Iap(error) => error.kind(),
+            Error::Storage(error) => error.kind(),
+            Error::I2c(error) => error.kind(),
+            Error::Eeprom(error) => error.kind(),
+            Error::Pwr(error) => error.kind(),
+            Error::Pwm(error) => error.kind(),
+            Error::Rng(error) => error.kind(),
+            Error::Serial(error) => error.kind(),
+            Error::Framed(error) => error.kind(),
+            #[cfg(feature = "modbus")]
+            Error::Modbus(error) => error.kind(),
+            Error::Spi(error) => error.kind(),
+            #[cfg(feature = "embedded-hal-1")]
+            Error::SpiBus(error) => error.kind(),
+            Error::Tsc(error) => error.kind(),
+            #[cfg(feature = "disco-l476")]
+            Error::Idd(error) => error.kind(),
+        }
+    }
+}
+
+macro_rules! errors {
+    ($($ERROR:ty: $Variant:ident,)+) => {
+        $(
+            impl From<$ERROR> for Error {
+                fn from(error: $ERROR) -> Self {
+                    Error::$Variant(error)
+                }
+            }
+        )+
+    }
+}
+
+errors! {
+    ClockError: Clock,
+    dma::Error: Dma,
+    flash::Error: Flash,
+    iap::Error: Iap,
+    storage::Error: Storage,
+    i2c::Error: I2c,
+    eeprom::Error<i2c::Error>: Eeprom,
+    pwr::Error: Pwr,
+    pwm::Error: Pwm,
+    rng::Error: Rng,
+    serial::Error: Serial,
+    framed::Error: Framed,
+    spi::Error: Spi,
+    tsc::Error: Tsc,
+}
+
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+errors! {
+    crs::Error: Crs,
+}
+
+#[cfg(feature = "modbus")]
+errors! {
+    modbus::Error: Modbus,
+}
+
+#[cfg(feature = "embedded-hal-1")]
+errors! {
+    bus::Error<spi::Error, ()>: SpiBus,
+}
+
+#[cfg(feature = "disco-l476")]
+errors! {
+    disco_l476::Error<i2c::Error>: Idd,
+}
+
+impl From<Void> for Error {
+    fn from(error: Void) -> Self {
+        match error {}
+    }
+}
+
+impl DriverError for ClockError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Clock
+    }
+}
+
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+impl DriverError for crs::Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Clock
+    }
+}
+
+impl DriverError for dma::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            dma::Error::Overrun => ErrorKind::Overrun,
+            dma::Error::BufferError => ErrorKind::Buffer,
+            dma::Error::Transfer => ErrorKind::Bus,
+        }
+    }
+}
+
+impl DriverError for flash::Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Memory
+    }
+}
+
+impl DriverError for iap::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            iap::Error::Flash(error) => error.kind(),
+            iap::Error::Checksum => ErrorKind::Corrupted,
+            iap::Error::Alignment => ErrorKind::Memory,
+        }
+    }
+}
+
+impl DriverError for storage::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            storage::Error::Flash(error) => error.kind(),
+            storage::Error::Alignment => ErrorKind::Memory,
+            storage::Error::Key => ErrorKind::Other,
+            storage::Error::TooLarge | storage::Error::Buffer(_) | storage::Error::Full => {
+                ErrorKind::Buffer
+            }
+        }
+    }
+}
+
+impl DriverError for i2c::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            i2c::Error::Bus | i2c::Error::Arbitration | i2c::Error::Nack => ErrorKind::Bus,
+            i2c::Error::Busy => ErrorKind::Busy,
+        }
+    }
+}
+
+impl<E> DriverError for eeprom::Error<E>
+where
+    E: DriverError,
+{
+    fn kind(&self) -> ErrorKind {
+        match self {
+            eeprom::Error::I2c(error) => error.kind(),
+            eeprom::Error::OutOfRange => ErrorKind::Memory,
+            eeprom::Error::Timeout => ErrorKind::Busy,
+        }
+    }
+}
+
+impl DriverError for pwr::Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Busy
+    }
+}
+
+impl DriverError for pwm::Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Clock
+    }
+}
+
+impl DriverError for rng::Error {
+    fn kind(&self) -> ErrorKind {
+        match *self {}
+    }
+}
+
+impl DriverError for serial::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            serial::Error::Overrun => ErrorKind::Overrun,
+            serial::Error::Framing | serial::Error::Noise | serial::Error::Parity => {
+                ErrorKind::Corrupted
+            }
+            serial::Error::AutoBaud => ErrorKind::Clock,
+        }
+    }
+}
+
+impl DriverError for framed::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            framed::Error::Overflow => ErrorKind::Buffer,
+            framed::Error::Overrun => ErrorKind::Overrun,
+            framed::Error::Decode => ErrorKind::Corrupted,
+        }
+    }
+}
+
+#[cfg(feature = "modbus")]
+impl DriverError for modbus::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            modbus::Error::Serial(error) => error.kind(),
+            modbus::Error::Overflow => ErrorKind::Buffer,
+            modbus::Error::TooShort | modbus::Error::Crc => ErrorKind::Corrupted,
+        }
+    }
+}
+
+impl DriverError for spi::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            spi::Error::Overrun => ErrorKind::Overrun,
+            spi::Error::ModeFault => ErrorKind::Bus,
+            spi::Error::Crc => ErrorKind::Corrupted,
+        }
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<SPI, CS> DriverError for bus::Error<SPI, CS>
+where
+    SPI: DriverError,
+    CS: fmt::Debug,
+{
+    fn kind(&self) -> ErrorKind {
+        match self {
+            bus::Error::Spi(error) => error.kind(),
+            bus::Error::ChipSelect(_) => ErrorKind::Other,
+        }
+    }
+}
+
+impl DriverError for tsc::Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+#[cfg(feature = "disco-l476")]
+impl<E> DriverError for disco_l476::Error<E>
+where
+    E: DriverError,
+{
+    fn kind(&self) -> ErrorKind {
+        match self {
+            disco_l476::Error::I2c(error) => error.kind(),
+            disco_l476::Error::UnknownDevice(_) => ErrorKind::Other,
+            disco_l476::Error::Measurement => ErrorKind::Other,
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! External interrupts of the GPIO pins
+//!
+//! The EXTI lines 0 to 15 are shared by the pins with the same number on all the ports, and share
+//! 7 interrupts: `EXTI0` to `EXTI4`, `EXTI9_5` and `EXTI15_10`. `ExtiDispatcher` owns these lines,
+//! and dispatches their interrupts to a handler per line, or records them as flags to be polled.
+//! The same `on_interrupt` is called from all the interrupt handlers.
+//!
+//! ``` ignore
+//! let mut exti = ExtiDispatcher::new(dp.EXTI);
+//! exti.listen(&mut syscfg, Port::C, 13, Trigger::Falling, Some(on_button));
+//! exti.listen(&mut syscfg, Port::A, 0, Trigger::Both, None);
+//! unsafe {
+//!     NVIC::unmask(exti::interrupt(13));
+//!     NVIC::unmask(exti::interrupt(0));
+//! }
+//!
+//! #[interrupt]
+//! fn EXTI15_10() {
+//!     exti.on_interrupt();
+//! }
+//!
+//! // main loop
+//! if exti.poll() & (1 << 0) != 0 {
+//!     // PA0 toggled
+//! }
+//! ```
+
+use cortex_m::interrupt;
+
+use crate::pwr::exti_listen;
+use crate::stm32::{Interrupt, EXTI};
+use crate::syscfg::{Port, SysCfg};
+
+/// Lines handled by the dispatcher
+const LINES: u32 = 0xFFFF;
+
+/// Edges triggering an EXTI line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    /// Rising edges
+    Rising,
+    /// Falling edges
+    Falling,
+    /// Both edges
+    Both,
+}
+
+/// Handler of an EXTI line, called with the line number
+pub type Handler = fn(u8);
+
+/// Returns the interrupt of the EXTI `line` (0 to 15)
+pub fn interrupt(line: u8) -> Interrupt {
+    match line {
+        0 => Interrupt::EXTI0,
+        1 => Interrupt::EXTI1,
+        2 => Interrupt::EXTI2,
+        3 => Interrupt::EXTI3,
+        4 => Interrupt::EXTI4,
+        5..=9 => Interrupt::EXTI9_5,
+        _ => Interrupt::EXTI15_10,
+    }
+}
+
+/// Owner of the EXTI lines 0 to 15
+pub struct ExtiDispatcher {
+    exti: EXTI,
+    handlers: [Option<Handler>; 16],
+    flags: u16,
+}
+
+impl ExtiDispatcher {
+    /// Takes the EXTI lines 0 to 15, all masked
+    ///
+    /// The lines above 15, e.g. of the PVD (see `pwr`), are left alone.
+    pub fn new(exti: EXTI) -> Self {
+        // NOTE(unsafe) the EXTI registers are modified in critical sections, see `pwr`
+        interrupt::free(|_| exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() & !LINES) }));
+        exti.pr1.write(|w| unsafe { w.bits(LINES) });
+
+        ExtiDispatcher {
+            exti,
+            handlers: [None; 16],
+            flags: 0,
+        }
+    }
+
+    /// Connects `line` (0 to 15) to the pin with the same number on `port` and unmasks it
+    ///
+    /// The triggers of the line call `handler` from `on_interrupt`, or are recorded as flags
+    /// (see `poll`) without handler. The pin has to be an input. Panics if `line` is out of
+    /// range.
+    pub fn listen(
+        &mut self,
+        syscfg: &mut SysCfg,
+        port: Port,
+        line: u8,
+        trigger: Trigger,
+        handler: Option<Handler>,
+    ) {
+        assert!(line < 16);
+
+        self.unlisten(line);
+        syscfg.select_exti_source(line, port);
+        self.handlers[usize::from(line)] = handler;
+        exti_listen(line, trigger != Trigger::Falling, trigger != Trigger::Rising);
+    }
+
+    /// Masks `line`, and discards its pending trigger and flag
+    pub fn unlisten(&mut self, line: u8) {
+        assert!(line < 16);
+
+        let bit = 1 << line;
+        exti_listen(line, false, false);
+        self.exti.pr1.write(|w| unsafe { w.bits(bit) });
+        self.handlers[usize::from(line)] = None;
+        self.flags &= !(bit as u16);
+    }
+
+    /// Triggers `line` by software, as an edge of its pin would
+    pub fn trigger(&mut self, line: u8) {
+        assert!(line < 16);
+
+        self.exti.swier1.write(|w| unsafe { w.bits(1 << line) });
+    }
+
+    /// Acknowledges the pending lines, and calls their handlers or records their flags
+    ///
+    /// To be called from the `EXTI0` to `EXTI4`, `EXTI9_5` and `EXTI15_10` interrupt handlers.
+    pub fn on_interrupt(&mut self) {
+        let pending = self.exti.pr1.read().bits() & self.exti.imr1.read().bits() & LINES;
+        self.exti.pr1.write(|w| unsafe { w.bits(pending) });
+
+        for line in 0..16 {
+            if pending & (1 << line) != 0 {
+                match self.handlers[usize::from(line)] {
+                    Some(handler) => handler(line),
+                    None => self.flags |= 1 << line,
+                }
+            }
+        }
+    }
+
+    /// Returns and clears the flags of the lines triggered since the last call, bit `n` being
+    /// line `n`
+    ///
+    /// Without interrupt, the pending lines are acknowledged first, see `on_interrupt`.
+    pub fn poll(&mut self) -> u16 {
+        self.on_interrupt();
+
+        let flags = self.flags;
+        self.flags = 0;
+        flags
+    }
+
+    /// Masks the lines 0 to 15 and releases the EXTI peripheral
+    pub fn free(self) -> EXTI {
+        let exti = &self.exti;
+        interrupt::free(|_| exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() & !LINES) }));
+        self.exti
+    }
+}
@@ -0,0 +1,204 @@
+//! Fault log in the backup registers
+//!
+//! `record` saves the program counter and link register of a fault, with the time of the RTC, in
+//! the last backup registers (`REGISTERS`), which survive the reset that follows, e.g. by the
+//! `panic` handler or the independent watchdog. At the next boot, `take` returns the fault
+//! recorded, if any, along with the cause of the reset, and clears them both.
+//!
+//! ``` ignore
+//! #[exception]
+//! fn HardFault(frame: &ExceptionFrame) -> ! {
+//!     fault::record(Kind::HardFault, frame.pc, frame.lr);
+//!     SCB::sys_reset()
+//! }
+//!
+//! // at boot
+//! let mut backup = rcc.bdcr.backup_domain(&mut rcc.apb1r1, &mut pwr.cr1);
+//! let report = fault::take(&mut backup, &mut rcc.csr);
+//! if let Some(fault) = report.fault {
+//!     writeln!(tx, "{:?} at {:#010x}, after reset {:?}", fault.kind, fault.pc, report.reset)
+//!         .ok();
+//! }
+//! ```
+//!
+//! The write access to the backup domain (see `BDCR::backup_domain`) has to be enabled for
+//! `record` to save anything; it stays enabled until the next reset.
+
+use core::ops::Range;
+use core::ptr;
+
+use crate::datetime::{Date, Time};
+use crate::rcc::{backup_register, BackupDomain, CSR};
+use crate::stm32::RTC;
+
+/// Backup registers used by the log, the application can use the others
+pub const REGISTERS: Range<usize> = 28..32;
+
+/// Marks a recorded fault in the high half word of the first register
+const MAGIC: u32 = 0xFA17_0000;
+
+/// Kind of fault
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Kind {
+    /// HardFault exception
+    HardFault,
+    /// Panic
+    Panic,
+    /// Fault defined by the application
+    Application(u8),
+}
+
+impl Kind {
+    fn bits(self) -> u32 {
+        match self {
+            Kind::HardFault => 0x100,
+            Kind::Panic => 0x200,
+            Kind::Application(code) => 0x300 | u32::from(code),
+        }
+    }
+
+    fn from_bits(bits: u32) -> Option<Self> {
+        match bits & 0xF00 {
+            0x100 => Some(Kind::HardFault),
+            0x200 => Some(Kind::Panic),
+            0x300 => Some(Kind::Application(bits as u8)),
+            _ => None,
+        }
+    }
+}
+
+/// Fault recorded before the last reset
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Fault {
+    /// Kind of fault
+    pub kind: Kind,
+    /// Program counter
+    pub pc: u32,
+    /// Link register
+    pub lr: u32,
+    /// Time of the RTC in seconds since 1970-01-01, 0 if the RTC wasn't set
+    pub timestamp: u32,
+}
+
+/// Cause of the last reset, from the reset flags of RCC_CSR
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResetReason {
+    /// Power-on or brown-out reset
+    BrownOut,
+    /// Independent watchdog
+    IndependentWatchdog,
+    /// Window watchdog
+    WindowWatchdog,
+    /// Illegal Stop, Standby or Shutdown mode entry
+    LowPower,
+    /// Software reset, e.g. `SCB::sys_reset`
+    Software,
+    /// Firewall
+    Firewall,
+    /// Option bytes loading
+    OptionBytes,
+    /// NRST pin
+    Pin,
+    /// No reset flag was set, e.g. they were already cleared
+    Unknown,
+}
+
+/// Cause of the last reset and fault recorded before it
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Report {
+    /// Cause of the reset
+    pub reset: ResetReason,
+    /// Fault recorded before the reset, if any
+    pub fault: Option<Fault>,
+}
+
+/// Records a fault at `pc`, replacing the previous one
+///
+/// To be called from the fault handlers, just before the reset. Nothing is saved if the write
+/// access to the backup domain isn't enabled.
+pub fn record(kind: Kind, pc: u32, lr: u32) {
+    // NOTE(unsafe) the registers of the log aren't used by the application; the first register
+    // is written last, a fault recorded partially isn't reported
+    unsafe {
+        ptr::write_volatile(backup_register(REGISTERS.start), 0);
+        ptr::write_volatile(backup_register(REGISTERS.start + 1), pc);
+        ptr::write_volatile(backup_register(REGISTERS.start + 2), lr);
+        ptr::write_volatile(backup_register(REGISTERS.start + 3), timestamp());
+        ptr::write_volatile(backup_register(REGISTERS.start), MAGIC | kind.bits());
+    }
+}
+
+/// Returns the cause of the last reset and the fault recorded before it, and clears them
+pub fn take(backup: &mut BackupDomain, csr: &mut CSR) -> Report {
+    let header = backup.read_backup_register(REGISTERS.start);
+    let fault = if header & 0xFFFF_0000 == MAGIC {
+        Kind::from_bits(header).map(|kind| Fault {
+            kind,
+            pc: backup.read_backup_register(REGISTERS.start + 1),
+            lr: backup.read_backup_register(REGISTERS.start + 2),
+            timestamp: backup.read_backup_register(REGISTERS.start + 3),
+        })
+    } else {
+        None
+    };
+    backup.write_backup_register(REGISTERS.start, 0);
+
+    let flags = csr.csr().read();
+    let reset = if flags.borrstf().bit_is_set() {
+        ResetReason::BrownOut
+    } else if flags.iwdgrstf().bit_is_set() {
+        ResetReason::IndependentWatchdog
+    } else if flags.wwdgrstf().bit_is_set() {
+        ResetReason::WindowWatchdog
+    } else if flags.lpwrstf().bit_is_set() {
+        ResetReason::LowPower
+    } else if flags.sftrstf().bit_is_set() {
+        ResetReason::Software
+    } else if flags.firewallrstf().bit_is_set() {
+        ResetReason::Firewall
+    } else if flags.oblrstf().bit_is_set() {
+        ResetReason::OptionBytes
+    } else if flags.pinrstf().bit_is_set() {
+        // the internal resets also drive the NRST pin, so this flag comes last
+        ResetReason::Pin
+    } else {
+        ResetReason::Unknown
+    };
+    csr.csr().modify(|_, w| w.rmvf().set_bit());
+
+    Report { reset, fault }
+}
+
+/// Returns the time of the RTC in seconds since 1970-01-01, 0 if it wasn't set
+fn timestamp() -> u32 {
+    // NOTE(unsafe) atomic reads with no side effects; DR is locked until TR is read
+    let rtc = unsafe { &*RTC::ptr() };
+    if rtc.isr.read().inits().bit_is_clear() {
+        return 0;
+    }
+
+    let tr = rtc.tr.read().bits();
+    let dr = rtc.dr.read().bits();
+    let bcd = |value: u32| (value >> 4) * 10 + (value & 0xF);
+
+    // the year is counted from 1970, see `Rtc::set_date`
+    let date = Date {
+        day: dr >> 13 & 0x7,
+        date: bcd(dr & 0x3F),
+        month: bcd(dr >> 8 & 0x1F),
+        year: 1970 + bcd(dr >> 16 & 0xFF),
+    };
+    // 24 hour format
+    let time = Time {
+        hours: bcd(tr >> 16 & 0x3F),
+        minutes: bcd(tr >> 8 & 0x7F),
+        seconds: bcd(tr & 0x7F),
+        daylight_savings: false,
+    };
+
+    date.days_since_epoch() * 86_400 + time.seconds_since_midnight()
+}
@@ -1,6 +1,35 @@
 //! Flash memory
+//!
+//! The main flash memory is erased in pages of `PAGE_SIZE` bytes and programmed in double words,
+//! through the `CR` proxy once unlocked:
+//!
+//! ``` ignore
+//! let mut flash = dp.FLASH.constrain();
+//! let mut prog = flash.cr.unlock()?;
+//! prog.erase_page(0x0808_0000)?;
+//! prog.program(0x0808_0000, &[0x0123_4567_89AB_CDEF])?;
+//! ```
+//!
+//! On the STM32L4x5/x6 the flash is split into two banks, the one that is not executed from can
+//! be erased and programmed without stalling the CPU. The bank mapped at `FLASH_START` is chosen
+//! by the BFB2 option, so a new firmware image written to the upper half becomes active with
+//! `OPTR::swap_banks`.
+
+use core::ptr;
 
 use crate::stm32::{flash, FLASH};
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+use crate::stm32::SYSCFG;
+
+/// Address of the main flash memory
+pub const FLASH_START: u32 = 0x0800_0000;
+/// Size of an erase page in bytes
+pub const PAGE_SIZE: u32 = 2048;
+/// Address of the flash size data register, in KB
+const FLASH_SIZE: *const u16 = 0x1FFF_75E0 as *const u16;
+
+pub mod iap;
+pub mod storage;
 
 /// Extension trait to constrain the FLASH peripheral
 pub trait FlashExt {
@@ -12,6 +41,8 @@ impl FlashExt for FLASH {
     fn constrain(self) -> Parts {
         Parts {
             acr: ACR { _0: () },
+            optr: OPTR { _0: () },
+            cr: CR { _0: () },
         }
     }
 }
@@ -20,6 +51,399 @@ impl FlashExt for FLASH {
 pub struct Parts {
     /// Opaque ACR register
     pub acr: ACR,
+    /// Opaque option register
+    pub optr: OPTR,
+    /// Opaque control register, used to erase and program the main flash memory
+    pub cr: CR,
+}
+
+/// Returns the size of the main flash memory in bytes
+pub fn size() -> u32 {
+    // NOTE(unsafe) read of a factory programmed value
+    u32::from(unsafe { ptr::read_volatile(FLASH_SIZE) }) * 1024
+}
+
+/// Flash bank
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Bank {
+    /// Bank 1
+    Bank1,
+    /// Bank 2
+    Bank2,
+}
+
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+impl Bank {
+    /// Returns the other bank
+    pub fn other(self) -> Bank {
+        match self {
+            Bank::Bank1 => Bank::Bank2,
+            Bank::Bank2 => Bank::Bank1,
+        }
+    }
+}
+
+/// Returns the bank mapped at `FLASH_START`, i.e. the bank the device booted from
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub fn active_bank() -> Bank {
+    // NOTE(unsafe) atomic read with no side effects
+    if unsafe { (*SYSCFG::ptr()).memrmp.read().fb_mode().bit_is_set() } {
+        Bank::Bank2
+    } else {
+        Bank::Bank1
+    }
+}
+
+/// Returns the size of a bank in bytes, 0 if the flash is a single bank
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub fn bank_size() -> u32 {
+    // NOTE(unsafe) atomic read with no side effects
+    let dual = unsafe { (*FLASH::ptr()).optr.read().dualbank().bit_is_set() };
+    // 1 MB devices are always dual bank
+    if dual || size() == 1024 * 1024 {
+        size() / 2
+    } else {
+        0
+    }
+}
+
+/// Returns the address the inactive bank is mapped at, `None` if the flash is a single bank
+///
+/// The inactive bank is always mapped in the upper half of the flash, whatever bank is active.
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub fn inactive_bank_address() -> Option<u32> {
+    match bank_size() {
+        0 => None,
+        size => Some(FLASH_START + size),
+    }
+}
+
+/// Flash error
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Operation error
+    Operation,
+    /// Programming error
+    Programming,
+    /// Write protection error
+    WriteProtection,
+    /// Programming alignment error
+    Alignment,
+    /// Size error
+    Size,
+    /// Programming sequence error
+    Sequence,
+    /// Option validity error
+    OptionValidity,
+    /// The address is outside of the main flash memory
+    Address,
+}
+
+/// Waits for the end of the ongoing flash operation and checks its status
+fn wait_ready(flash: &flash::RegisterBlock) -> Result<(), Error> {
+    while flash.sr.read().bsy().bit_is_set() {}
+
+    let sr = flash.sr.read();
+    let result = if sr.operr().bit_is_set() {
+        Err(Error::Operation)
+    } else if sr.progerr().bit_is_set() {
+        Err(Error::Programming)
+    } else if sr.wrperr().bit_is_set() {
+        Err(Error::WriteProtection)
+    } else if sr.pgaerr().bit_is_set() {
+        Err(Error::Alignment)
+    } else if sr.sizerr().bit_is_set() {
+        Err(Error::Size)
+    } else if sr.pgserr().bit_is_set() {
+        Err(Error::Sequence)
+    } else if sr.optverr().bit_is_set() {
+        Err(Error::OptionValidity)
+    } else {
+        Ok(())
+    };
+
+    if result.is_err() {
+        trace!("flash: {}, SR = {=u32:#x}", result, sr.bits());
+    }
+
+    // clear all flags, they are write 1 to clear
+    flash.sr.write(|w| unsafe { w.bits(sr.bits()) });
+
+    result
+}
+
+/// Brown-out reset level, the device is held in reset while VDD is below it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BorLevel {
+    /// About 1.7 V, the lowest operating voltage
+    V1_7 = 0b000,
+    /// About 2.0 V
+    V2_0 = 0b001,
+    /// About 2.2 V
+    V2_2 = 0b010,
+    /// About 2.5 V
+    V2_5 = 0b011,
+    /// About 2.8 V
+    V2_8 = 0b100,
+}
+
+/// Unlocks the flash control register
+fn unlock(flash: &flash::RegisterBlock) {
+    if flash.cr.read().lock().bit_is_set() {
+        flash.keyr.write(|w| unsafe { w.keyr().bits(0x4567_0123) });
+        flash.keyr.write(|w| unsafe { w.keyr().bits(0xCDEF_89AB) });
+    }
+}
+
+/// Programs the option bytes modified by `f` then reloads them, which resets the device
+fn program_options<F>(f: F) -> Result<(), Error>
+where
+    F: FnOnce(&flash::OPTR),
+{
+    // NOTE(unsafe) the callers grant exclusive access to the option bytes, the flash control
+    // register is only touched while no other flash operation is ongoing
+    let flash = unsafe { &*FLASH::ptr() };
+
+    wait_ready(flash)?;
+    unlock(flash);
+    if flash.cr.read().optlock().bit_is_set() {
+        flash.optkeyr.write(|w| unsafe { w.optkeyr().bits(0x0819_2A3B) });
+        flash.optkeyr.write(|w| unsafe { w.optkeyr().bits(0x4C5D_6E7F) });
+    }
+
+    f(&flash.optr);
+    flash.cr.modify(|_, w| w.optstrt().set_bit());
+    wait_ready(flash)?;
+
+    // reloading the option bytes resets the device
+    flash.cr.modify(|_, w| w.obl_launch().set_bit());
+    loop {
+        cortex_m::asm::nop();
+    }
+}
+
+/// Opaque option register
+pub struct OPTR {
+    _0: (),
+}
+
+impl OPTR {
+    /// Returns `true` if the SRAM2 parity check is enabled
+    pub fn sram2_parity_enabled(&self) -> bool {
+        // NOTE(unsafe) atomic read with no side effects; SRAM2_PE is active low
+        unsafe { (*FLASH::ptr()).optr.read().sram2_pe().bit_is_clear() }
+    }
+
+    /// Enables or disables the SRAM2 parity check
+    ///
+    /// The option bytes are programmed and reloaded, which resets the device; this function
+    /// only returns if programming failed or the option already had the requested value. With
+    /// parity checking enabled a parity error raises an NMI, see `syscfg::SysCfg`.
+    pub fn set_sram2_parity(&mut self, enabled: bool) -> Result<(), Error> {
+        if self.sram2_parity_enabled() == enabled {
+            return Ok(());
+        }
+
+        program_options(|optr| optr.modify(|_, w| w.sram2_pe().bit(!enabled)))
+    }
+
+    /// Returns the brown-out reset level
+    pub fn bor_level(&self) -> BorLevel {
+        // NOTE(unsafe) atomic read with no side effects
+        match unsafe { (*FLASH::ptr()).optr.read().bor_lev().bits() } {
+            0b000 => BorLevel::V1_7,
+            0b001 => BorLevel::V2_0,
+            0b010 => BorLevel::V2_2,
+            0b011 => BorLevel::V2_5,
+            _ => BorLevel::V2_8,
+        }
+    }
+
+    /// Sets the brown-out reset level
+    ///
+    /// The option bytes are programmed and reloaded, which resets the device; this function
+    /// only returns if programming failed or the option already had the requested value.
+    pub fn set_bor_level(&mut self, level: BorLevel) -> Result<(), Error> {
+        if self.bor_level() == level {
+            return Ok(());
+        }
+
+        program_options(|optr| optr.modify(|_, w| unsafe { w.bor_lev().bits(level as u8) }))
+    }
+
+    /// Returns `true` if the device boots from bank 2 when it holds a valid image (BFB2)
+    #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+    pub fn bfb2(&self) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*FLASH::ptr()).optr.read().bfb2().bit_is_set() }
+    }
+
+    /// Returns `true` if the flash is organized in two banks
+    #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+    pub fn dual_bank(&self) -> bool {
+        bank_size() != 0
+    }
+
+    /// Swaps the banks: the device restarts from the currently inactive bank
+    ///
+    /// BFB2 is set when running from bank 1 and cleared when running from bank 2, then the option
+    /// bytes are reloaded, which resets the device; this function only returns if programming
+    /// failed. The bootloader in system memory checks that the selected bank starts with a valid
+    /// stack pointer, and falls back to the other bank otherwise.
+    #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+    pub fn swap_banks(&mut self) -> Result<(), Error> {
+        if !self.dual_bank() {
+            return Err(Error::Address);
+        }
+
+        let bfb2 = active_bank() == Bank::Bank1;
+        program_options(|optr| optr.modify(|_, w| w.bfb2().bit(bfb2)))
+    }
+}
+
+/// Opaque flash control register
+pub struct CR {
+    _0: (),
+}
+
+impl CR {
+    /// Unlocks the flash for erasing and programming, it is locked again when the returned value
+    /// is dropped
+    pub fn unlock(&mut self) -> Result<UnlockedFlash<'_>, Error> {
+        // NOTE(unsafe) this proxy grants exclusive access to the control register
+        let flash = unsafe { &*FLASH::ptr() };
+
+        wait_ready(flash)?;
+        unlock(flash);
+
+        Ok(UnlockedFlash { _cr: self })
+    }
+}
+
+/// Unlocked flash, see `CR::unlock`
+pub struct UnlockedFlash<'a> {
+    _cr: &'a mut CR,
+}
+
+impl<'a> UnlockedFlash<'a> {
+    /// Erases the page containing `address`
+    ///
+    /// On the STM32L4x5/x6 the address is translated to the bank currently mapped at it.
+    pub fn erase_page(&mut self, address: u32) -> Result<(), Error> {
+        if address < FLASH_START || address >= FLASH_START + size() {
+            return Err(Error::Address);
+        }
+        let offset = address - FLASH_START;
+
+        #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+        {
+            let bank_size = bank_size();
+            if bank_size != 0 {
+                let bank = if offset < bank_size {
+                    active_bank()
+                } else {
+                    active_bank().other()
+                };
+                return self.erase_bank_page(bank, (offset % bank_size) / PAGE_SIZE);
+            }
+        }
+
+        self.erase(false, offset / PAGE_SIZE)
+    }
+
+    /// Erases the `page` of `bank`, whatever address the bank is mapped at
+    #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+    pub fn erase_bank_page(&mut self, bank: Bank, page: u32) -> Result<(), Error> {
+        self.erase(bank == Bank::Bank2, page)
+    }
+
+    /// Erases a whole bank
+    ///
+    /// The bank the code runs from must not be erased.
+    #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+    pub fn erase_bank(&mut self, bank: Bank) -> Result<(), Error> {
+        let flash = self.flash();
+
+        wait_ready(flash)?;
+        flash.cr.modify(|_, w| match bank {
+            Bank::Bank1 => w.mer1().set_bit(),
+            Bank::Bank2 => w.mer2().set_bit(),
+        });
+        flash.cr.modify(|_, w| w.start().set_bit());
+        let result = wait_ready(flash);
+        flash.cr.modify(|_, w| w.mer1().clear_bit().mer2().clear_bit());
+
+        result
+    }
+
+    /// Programs `data` starting at `address`, which must be aligned to 8 bytes
+    ///
+    /// The double words have to be erased beforehand.
+    pub fn program(&mut self, address: u32, data: &[u64]) -> Result<(), Error> {
+        if !address.is_multiple_of(8) {
+            return Err(Error::Alignment);
+        }
+        if address < FLASH_START || address + 8 * data.len() as u32 > FLASH_START + size() {
+            return Err(Error::Address);
+        }
+
+        let flash = self.flash();
+
+        wait_ready(flash)?;
+        flash.cr.modify(|_, w| w.pg().set_bit());
+
+        let mut result = Ok(());
+        for (i, word) in data.iter().enumerate() {
+            let dst = (address as usize + 8 * i) as *mut u32;
+            // NOTE(unsafe) programming is enabled, the address is in the main flash memory; the
+            // two words of a double word have to be written one after the other
+            unsafe {
+                ptr::write_volatile(dst, *word as u32);
+                ptr::write_volatile(dst.add(1), (*word >> 32) as u32);
+            }
+
+            result = wait_ready(flash);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        flash.cr.modify(|_, w| w.pg().clear_bit());
+        result
+    }
+
+    fn erase(&mut self, bank2: bool, page: u32) -> Result<(), Error> {
+        if page >= 256 {
+            return Err(Error::Address);
+        }
+
+        let flash = self.flash();
+
+        wait_ready(flash)?;
+        flash.cr.modify(|_, w| unsafe {
+            w.per().set_bit().pnb().bits(page as u8).bker().bit(bank2)
+        });
+        flash.cr.modify(|_, w| w.start().set_bit());
+        let result = wait_ready(flash);
+        flash.cr.modify(|_, w| w.per().clear_bit());
+
+        result
+    }
+
+    fn flash(&self) -> &flash::RegisterBlock {
+        // NOTE(unsafe) `CR` grants exclusive access to the control register
+        unsafe { &*FLASH::ptr() }
+    }
+}
+
+impl<'a> Drop for UnlockedFlash<'a> {
+    fn drop(&mut self) {
+        self.flash().cr.modify(|_, w| w.lock().set_bit());
+    }
 }
 
 /// Opaque ACR register
@@ -0,0 +1,205 @@
+//! In-application programming helpers
+//!
+//! A firmware image written to flash is checked with the CRC unit before it is used: `Iap`
+//! computes the same CRC-32 as zlib (`crc32` of Python's `binascii`, `crc32fast`, ...), so the
+//! checksum can be computed by the build tools and sent along with the image.
+//!
+//! Once verified, the image is recorded in a flag page. The record is programmed before a final
+//! marker double word, and a double word is programmed at once, so a record is either complete or
+//! ignored if the device is reset during the update.
+//!
+//! ``` ignore
+//! let mut iap = Iap::new(dp.CRC, &mut rcc.ahb1);
+//! let image = Image { address: 0x0808_0000, len, crc };
+//!
+//! let mut prog = flash.cr.unlock()?;
+//! iap.mark_valid(&mut prog, FLAG_PAGE, &image)?;
+//!
+//! // on the next boot
+//! if let Some(image) = iap.valid_image(FLAG_PAGE) {
+//!     // jump to image.address
+//! }
+//! ```
+
+use core::ptr;
+
+use crate::rcc::AHB1;
+use crate::stm32::CRC;
+
+use super::{UnlockedFlash, FLASH_START, PAGE_SIZE};
+
+/// CR: RESET
+const RESET: u32 = 1 << 0;
+/// CR: REV_IN, bit reversal by byte
+const REV_IN_BYTE: u32 = 0b01 << 5;
+/// CR: REV_IN, bit reversal by word
+const REV_IN_WORD: u32 = 0b11 << 5;
+/// CR: REV_OUT
+const REV_OUT: u32 = 1 << 7;
+
+/// Marker programmed last in the flag page
+const MARKER: u64 = 0x5641_4C49_4449_4D47;
+
+/// IAP error
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Flash error
+    Flash(super::Error),
+    /// The image doesn't match its CRC
+    Checksum,
+    /// The flag page is not aligned to a page
+    Alignment,
+}
+
+impl From<super::Error> for Error {
+    fn from(error: super::Error) -> Self {
+        Error::Flash(error)
+    }
+}
+
+/// Returns `true` if the `len` bytes starting at `address` are in the main flash memory
+fn in_flash(address: u32, len: u32) -> bool {
+    let end = address.checked_add(len);
+    address >= FLASH_START && end.is_some_and(|end| end <= FLASH_START + super::size())
+}
+
+/// Firmware image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Image {
+    /// Address of the image in flash
+    pub address: u32,
+    /// Length of the image in bytes
+    pub len: u32,
+    /// CRC-32 of the image
+    pub crc: u32,
+}
+
+/// Image checker, using the CRC unit
+pub struct Iap {
+    crc: CRC,
+}
+
+impl Iap {
+    /// Enables the CRC unit
+    pub fn new(crc: CRC, ahb: &mut AHB1) -> Self {
+        ahb.enr().modify(|_, w| w.crcen().set_bit());
+
+        // default polynomial (0x04C1_1DB7) and initial value (0xFFFF_FFFF)
+        crc.init.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        crc.pol.write(|w| unsafe { w.bits(0x04C1_1DB7) });
+
+        Iap { crc }
+    }
+
+    /// Returns the CRC-32 of `len` bytes of the main flash memory starting at `address`
+    pub fn crc32(&mut self, address: u32, len: u32) -> Result<u32, Error> {
+        if !in_flash(address, len) {
+            return Err(Error::Flash(super::Error::Address));
+        }
+
+        self.crc
+            .cr
+            .write(|w| unsafe { w.bits(REV_OUT | REV_IN_WORD | RESET) });
+
+        let words = len / 4;
+        for i in 0..words {
+            // NOTE(unsafe) reads of the main flash memory, checked above
+            let word = unsafe { ptr::read_volatile((address + 4 * i) as *const u32) };
+            self.crc.dr.write(|w| unsafe { w.bits(word) });
+        }
+
+        // the remaining bytes are fed one by one, with 8-bit writes
+        self.crc
+            .cr
+            .write(|w| unsafe { w.bits(REV_OUT | REV_IN_BYTE) });
+        // DR is at offset 0
+        let dr = CRC::ptr() as *mut u8;
+        for i in 4 * words..len {
+            // NOTE(unsafe) see above; the data register accepts byte accesses
+            unsafe {
+                let byte = ptr::read_volatile((address + i) as *const u8);
+                ptr::write_volatile(dr, byte);
+            }
+        }
+
+        Ok(!self.crc.dr.read().bits())
+    }
+
+    /// Returns `true` if `image` is in the main flash memory and matches its CRC
+    pub fn verify(&mut self, image: &Image) -> bool {
+        self.crc32(image.address, image.len).is_ok_and(|crc| crc == image.crc)
+    }
+
+    /// Verifies `image` then records it in the flag page starting at `flag_page`
+    ///
+    /// The flag page is erased first, so it has to be dedicated to the record.
+    pub fn mark_valid(
+        &mut self,
+        flash: &mut UnlockedFlash,
+        flag_page: u32,
+        image: &Image,
+    ) -> Result<(), Error> {
+        if flag_page < FLASH_START || !(flag_page - FLASH_START).is_multiple_of(PAGE_SIZE) {
+            return Err(Error::Alignment);
+        }
+        if !self.verify(image) {
+            return Err(Error::Checksum);
+        }
+
+        flash.erase_page(flag_page)?;
+        flash.program(
+            flag_page,
+            &[
+                u64::from(image.address) | (u64::from(image.len) << 32),
+                u64::from(image.crc),
+            ],
+        )?;
+        flash.program(flag_page + 16, &[MARKER])?;
+
+        Ok(())
+    }
+
+    /// Erases the record of the flag page starting at `flag_page`
+    pub fn invalidate(&mut self, flash: &mut UnlockedFlash, flag_page: u32) -> Result<(), Error> {
+        flash.erase_page(flag_page).map_err(Error::Flash)
+    }
+
+    /// Returns the image recorded in the flag page starting at `flag_page`, if the record is
+    /// complete and the image still matches its CRC
+    pub fn valid_image(&mut self, flag_page: u32) -> Option<Image> {
+        if !in_flash(flag_page, 24) {
+            return None;
+        }
+
+        // NOTE(unsafe) reads of the main flash memory, checked above
+        let (header, crc, marker) = unsafe {
+            (
+                ptr::read_volatile(flag_page as *const u64),
+                ptr::read_volatile((flag_page + 8) as *const u64),
+                ptr::read_volatile((flag_page + 16) as *const u64),
+            )
+        };
+        if marker != MARKER {
+            return None;
+        }
+
+        let image = Image {
+            address: header as u32,
+            len: (header >> 32) as u32,
+            crc: crc as u32,
+        };
+        if self.verify(&image) {
+            Some(image)
+        } else {
+            None
+        }
+    }
+
+    /// Disables the CRC unit and releases it
+    pub fn free(self, ahb: &mut AHB1) -> CRC {
+        ahb.enr().modify(|_, w| w.crcen().clear_bit());
+        self.crc
+    }
+}
@@ -0,0 +1,396 @@
+//! Key-value storage in flash
+//!
+//! `Storage` keeps small values, e.g. a serial number or calibration constants, in two flash
+//! pages reserved for it (they have to be left out of `memory.x`). Each write appends a record to
+//! the active page, so a page is only erased once it's full: the latest value of every key is
+//! then copied to the other page, which becomes the active one.
+//!
+//! A record is a header double word (key, length and CRC-32 of the record) followed by the value,
+//! padded to double words. The header is programmed first: a record cut by a reset fails its CRC
+//! and is ignored, the previous value of its key is still found. The header of a page is
+//! programmed once its records have been copied, so an interrupted copy leaves the previous page
+//! active.
+//!
+//! ``` ignore
+//! let mut storage = Storage::at_end();
+//!
+//! let mut prog = flash.cr.unlock()?;
+//! storage.write(&mut prog, SERIAL_NUMBER, b"L4-000042")?;
+//!
+//! let mut buffer = [0; 16];
+//! if let Some(len) = storage.read(SERIAL_NUMBER, &mut buffer)? {
+//!     // &buffer[..len]
+//! }
+//! ```
+
+use core::ptr;
+
+use super::{UnlockedFlash, FLASH_START, PAGE_SIZE};
+
+/// Largest value, a record has to fit in a page along with the headers of the page and record
+pub const MAX_VALUE_LEN: usize = PAGE_SIZE as usize - 16;
+
+/// Key of the free space, not usable
+const FREE_KEY: u16 = 0xFFFF;
+/// Length flag of the records removing their key
+const REMOVED: u16 = 0x8000;
+/// Low word of the header of an active page, the high word is its generation
+const MAGIC: u32 = 0x5453_564B;
+/// Erased double word
+const ERASED: u64 = !0;
+
+/// Storage error
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Flash error
+    Flash(super::Error),
+    /// The pages are not aligned to a page
+    Alignment,
+    /// `0xFFFF` is not a valid key
+    Key,
+    /// The value is longer than `MAX_VALUE_LEN`
+    TooLarge,
+    /// The buffer is too small for the value, which is that long
+    Buffer(usize),
+    /// The latest values of all the keys fill a page
+    Full,
+}
+
+impl From<super::Error> for Error {
+    fn from(error: super::Error) -> Self {
+        Error::Flash(error)
+    }
+}
+
+/// Record found in a page
+#[derive(Clone, Copy)]
+struct Record {
+    address: u32,
+    key: u16,
+    /// Length field, with the `REMOVED` flag
+    len: u16,
+    valid: bool,
+}
+
+impl Record {
+    /// Size of the record in flash, header included
+    fn size(&self) -> u32 {
+        8 + padded(usize::from(self.len & !REMOVED))
+    }
+
+    /// Returns the bytes of the value
+    fn value(&self) -> impl Iterator<Item = u8> {
+        let address = self.address + 8;
+        // NOTE(unsafe) reads of the main flash memory
+        (0..u32::from(self.len & !REMOVED))
+            .map(move |i| unsafe { ptr::read_volatile((address + i) as *const u8) })
+    }
+}
+
+/// Key-value store in two consecutive flash pages
+pub struct Storage {
+    pages: [u32; 2],
+}
+
+impl Storage {
+    /// Uses the two pages starting at `address`, which have to be in the main flash memory
+    pub fn new(address: u32) -> Result<Self, Error> {
+        if address < FLASH_START || !(address - FLASH_START).is_multiple_of(PAGE_SIZE) {
+            return Err(Error::Alignment);
+        }
+        if address - FLASH_START > super::size() - 2 * PAGE_SIZE {
+            return Err(Error::Flash(super::Error::Address));
+        }
+
+        Ok(Storage {
+            pages: [address, address + PAGE_SIZE],
+        })
+    }
+
+    /// Uses the last two pages of the main flash memory
+    pub fn at_end() -> Self {
+        let address = FLASH_START + super::size() - 2 * PAGE_SIZE;
+        Storage {
+            pages: [address, address + PAGE_SIZE],
+        }
+    }
+
+    /// Reads the value of `key` into `buffer`, and returns its length, or `None` if `key` has
+    /// no value
+    pub fn read(&self, key: u16, buffer: &mut [u8]) -> Result<Option<usize>, Error> {
+        let page = match self.active_page() {
+            Some(page) => page,
+            None => return Ok(None),
+        };
+
+        match latest(page, key) {
+            Some(record) => {
+                let len = usize::from(record.len);
+                if buffer.len() < len {
+                    return Err(Error::Buffer(len));
+                }
+                for (byte, value) in buffer.iter_mut().zip(record.value()) {
+                    *byte = value;
+                }
+                Ok(Some(len))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the value of `key`
+    ///
+    /// Nothing is written if `key` already has this value. The pages are erased first if neither
+    /// is active, e.g. on the first write.
+    pub fn write(
+        &mut self,
+        flash: &mut UnlockedFlash,
+        key: u16,
+        value: &[u8],
+    ) -> Result<(), Error> {
+        if value.len() > MAX_VALUE_LEN {
+            return Err(Error::TooLarge);
+        }
+
+        let current = self.active_page().and_then(|page| latest(page, key));
+        if let Some(record) = current {
+            if usize::from(record.len) == value.len() && record.value().eq(value.iter().cloned()) {
+                return Ok(());
+            }
+        }
+
+        self.append(flash, key, value.len() as u16, value)
+    }
+
+    /// Removes the value of `key`
+    pub fn remove(&mut self, flash: &mut UnlockedFlash, key: u16) -> Result<(), Error> {
+        let page = self.active_page();
+        if page.and_then(|page| latest(page, key)).is_none() {
+            return Ok(());
+        }
+
+        self.append(flash, key, REMOVED, &[])
+    }
+
+    /// Erases both pages, removing all the values
+    pub fn format(&mut self, flash: &mut UnlockedFlash) -> Result<(), Error> {
+        flash.erase_page(self.pages[1])?;
+        flash.erase_page(self.pages[0])?;
+        flash.program(self.pages[0], &[u64::from(MAGIC)])?;
+
+        Ok(())
+    }
+
+    fn append(
+        &mut self,
+        flash: &mut UnlockedFlash,
+        key: u16,
+        len: u16,
+        value: &[u8],
+    ) -> Result<(), Error> {
+        if key == FREE_KEY {
+            return Err(Error::Key);
+        }
+
+        let page = match self.active_page() {
+            Some(page) => page,
+            None => {
+                self.format(flash)?;
+                self.pages[0]
+            }
+        };
+
+        let size = 8 + padded(value.len());
+        let mut end = free_space(page);
+        if end + size > page + PAGE_SIZE {
+            end = self.compact(flash, page)?;
+            if end + size > self.other(page) + PAGE_SIZE {
+                return Err(Error::Full);
+            }
+        }
+
+        let crc = crc(key, len, value.iter().cloned());
+        let header = u64::from(key) | (u64::from(len) << 16) | (u64::from(crc) << 32);
+        flash.program(end, &[header])?;
+        for (i, chunk) in value.chunks(8).enumerate() {
+            let mut bytes = [0xFF; 8];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            flash.program(end + 8 + 8 * i as u32, &[u64::from_le_bytes(bytes)])?;
+        }
+
+        Ok(())
+    }
+
+    /// Copies the latest values from `page` to the other page, activates it, and returns the
+    /// address of its free space
+    fn compact(&mut self, flash: &mut UnlockedFlash, page: u32) -> Result<u32, Error> {
+        let other = self.other(page);
+        flash.erase_page(other)?;
+
+        let mut end = other + 8;
+        for record in Records::new(page) {
+            let superseded =
+                Records::after(&record).any(|later| later.valid && later.key == record.key);
+            if !record.valid || superseded || record.len & REMOVED != 0 {
+                continue;
+            }
+
+            for offset in (0..record.size()).step_by(8) {
+                flash.program(end + offset, &[read(record.address + offset)])?;
+            }
+            end += record.size();
+        }
+
+        let generation = (read(page) >> 32) as u32;
+        flash.program(
+            other,
+            &[u64::from(MAGIC) | (u64::from(generation.wrapping_add(1)) << 32)],
+        )?;
+        flash.erase_page(page)?;
+
+        Ok(end)
+    }
+
+    /// Returns the page with a valid header and the latest generation
+    fn active_page(&self) -> Option<u32> {
+        let header = |page| {
+            let header = read(page);
+            if header as u32 == MAGIC {
+                Some((header >> 32) as u32)
+            } else {
+                None
+            }
+        };
+
+        match (header(self.pages[0]), header(self.pages[1])) {
+            (Some(first), Some(second)) => {
+                // the generations wrap around
+                if second.wrapping_sub(first) as i32 > 0 {
+                    Some(self.pages[1])
+                } else {
+                    Some(self.pages[0])
+                }
+            }
+            (Some(_), None) => Some(self.pages[0]),
+            (None, Some(_)) => Some(self.pages[1]),
+            (None, None) => None,
+        }
+    }
+
+    fn other(&self, page: u32) -> u32 {
+        if page == self.pages[0] {
+            self.pages[1]
+        } else {
+            self.pages[0]
+        }
+    }
+}
+
+/// Records of a page, from `address` to the free space
+struct Records {
+    address: u32,
+    end: u32,
+}
+
+impl Records {
+    /// Iterates over the records of `page`
+    fn new(page: u32) -> Self {
+        Records {
+            address: page + 8,
+            end: page + PAGE_SIZE,
+        }
+    }
+
+    /// Iterates over the records following `record` in its page
+    fn after(record: &Record) -> Self {
+        let page = record.address - (record.address - FLASH_START) % PAGE_SIZE;
+        Records {
+            address: record.address + record.size(),
+            end: page + PAGE_SIZE,
+        }
+    }
+}
+
+impl Iterator for Records {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        if self.address + 8 > self.end {
+            return None;
+        }
+
+        let header = read(self.address);
+        if header == ERASED {
+            return None;
+        }
+
+        let key = header as u16;
+        let len = (header >> 16) as u16;
+        let mut record = Record {
+            address: self.address,
+            key,
+            len,
+            valid: false,
+        };
+        // a header cut by a reset can hold any length
+        if self.address + record.size() > self.end {
+            return None;
+        }
+
+        record.valid = (header >> 32) as u32 == crc(key, len, record.value());
+
+        self.address += record.size();
+        Some(record)
+    }
+}
+
+/// Returns the latest record of `key` in `page`, unless it removes the key
+fn latest(page: u32, key: u16) -> Option<Record> {
+    Records::new(page)
+        .filter(|record| record.valid && record.key == key)
+        .last()
+        .filter(|record| record.len & REMOVED == 0)
+}
+
+/// Returns the address of the free space of `page`
+fn free_space(page: u32) -> u32 {
+    Records::new(page)
+        .last()
+        .map(|record| record.address + record.size())
+        .unwrap_or(page + 8)
+}
+
+/// Returns `len` rounded up to double words
+fn padded(len: usize) -> u32 {
+    (len as u32 + 7) & !7
+}
+
+fn read(address: u32) -> u64 {
+    // NOTE(unsafe) read of the main flash memory
+    unsafe { ptr::read_volatile(address as *const u64) }
+}
+
+/// Returns the CRC-32 (zlib) of the key, the length field and the value of a record
+fn crc<I>(key: u16, len: u16, value: I) -> u32
+where
+    I: Iterator<Item = u8>,
+{
+    let crc = key
+        .to_le_bytes()
+        .iter()
+        .chain(len.to_le_bytes().iter())
+        .cloned()
+        .chain(value)
+        .fold(!0, |crc, byte| {
+            (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+                if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                }
+            })
+        });
+    !crc
+}
@@ -0,0 +1,275 @@
+//! Flexible memory controller, on the STM32L4x5/x6
+//!
+//! The NOR/SRAM bank of the FMC maps asynchronous memories in the address space from
+//! `0x6000_0000`, one 64 MB window per chip select (NE1 to NE4), so that they are read and
+//! written with plain pointer accesses: external SRAMs, or 8080-style TFT panels whose
+//! command/data (RS) line is wired to an address line.
+//!
+//! The pins have to be configured as alternate function 12 beforehand, see `into_af12`. The
+//! memory handles borrow the `Fmc`, which can't be disabled or released while they're alive.
+//!
+//! ``` ignore
+//! let fmc = Fmc::new(dp.FMC, &mut rcc.ahb3);
+//! let timing = Timing::from_ns(clocks, 10, 30, 10);
+//! let mut display = fmc.display(ChipSelect::Ne1, 16, timing, None);
+//! display.write_command(0x2C);
+//! display.write_data(0xF800);
+//! ```
+
+use core::marker::PhantomData;
+use core::ptr;
+
+use crate::rcc::{Clocks, AHB3};
+use crate::stm32::FMC;
+
+/// Address of the NOR/SRAM bank
+const BANK1: usize = 0x6000_0000;
+/// Size of the window of a chip select
+const WINDOW: usize = 64 * 1024 * 1024;
+/// Offset of the write timing registers
+const BWTR: usize = 0x104;
+/// BCR: memory bank enable
+const MBKEN: u32 = 1 << 0;
+/// BCR: write enable
+const WREN: u32 = 1 << 12;
+/// BCR: extended mode, i.e. separate write timings
+const EXTMOD: u32 = 1 << 14;
+/// BCR1: FMC controller enable
+const FMCEN: u32 = 1 << 31;
+/// BCR: MUXEN, MTYP, MWID, FACCEN
+const MEMORY_MASK: u32 = 0b111_1110;
+
+/// Chip select of the NOR/SRAM bank
+#[derive(Clone, Copy)]
+pub enum ChipSelect {
+    /// NE1, from `0x6000_0000`
+    Ne1,
+    /// NE2, from `0x6400_0000`
+    Ne2,
+    /// NE3, from `0x6800_0000`
+    Ne3,
+    /// NE4, from `0x6C00_0000`
+    Ne4,
+}
+
+impl ChipSelect {
+    fn index(self) -> usize {
+        match self {
+            ChipSelect::Ne1 => 0,
+            ChipSelect::Ne2 => 1,
+            ChipSelect::Ne3 => 2,
+            ChipSelect::Ne4 => 3,
+        }
+    }
+}
+
+/// Width of the data bus
+#[derive(Clone, Copy)]
+pub enum DataWidth {
+    /// D0 to D7
+    Bits8,
+    /// D0 to D15
+    Bits16,
+}
+
+/// Asynchronous access timings, in HCLK periods (access mode A)
+#[derive(Clone, Copy)]
+pub struct Timing {
+    /// Address setup phase duration (0 to 15)
+    pub address_setup: u8,
+    /// Address hold phase duration (1 to 15), only used with multiplexed memories
+    pub address_hold: u8,
+    /// Data phase duration (1 to 255)
+    pub data_setup: u8,
+    /// Bus turnaround phase duration (0 to 15), between consecutive accesses
+    pub bus_turnaround: u8,
+}
+
+impl Timing {
+    /// Computes the timings from the durations in nanoseconds given by the datasheet of the
+    /// memory, rounding up
+    pub fn from_ns(clocks: Clocks, address_setup: u32, data_setup: u32, bus_turnaround: u32) -> Self {
+        let hclk = u64::from(clocks.hclk().0);
+        let cycles = |ns: u32| (u64::from(ns) * hclk).div_ceil(1_000_000_000) as u32;
+
+        Timing {
+            address_setup: cycles(address_setup).min(15) as u8,
+            address_hold: 1,
+            data_setup: cycles(data_setup).clamp(1, 255) as u8,
+            bus_turnaround: cycles(bus_turnaround).min(15) as u8,
+        }
+    }
+
+    fn bits(self) -> u32 {
+        assert!(self.address_setup < 16 && self.bus_turnaround < 16);
+        assert!(self.address_hold >= 1 && self.address_hold < 16 && self.data_setup >= 1);
+
+        u32::from(self.address_setup)
+            | (u32::from(self.address_hold) << 4)
+            | (u32::from(self.data_setup) << 8)
+            | (u32::from(self.bus_turnaround) << 16)
+    }
+}
+
+/// Flexible memory controller
+pub struct Fmc {
+    fmc: FMC,
+}
+
+impl Fmc {
+    /// Enables the FMC
+    pub fn new(fmc: FMC, ahb: &mut AHB3) -> Self {
+        ahb.enr().modify(|_, w| w.fmcen().set_bit());
+        ahb.rstr().modify(|_, w| w.fmcrst().set_bit());
+        ahb.rstr().modify(|_, w| w.fmcrst().clear_bit());
+
+        Fmc { fmc }
+    }
+
+    /// Maps an asynchronous SRAM on `cs`, with `write` timings if they differ from the `read`
+    /// ones
+    pub fn sram(
+        &self,
+        cs: ChipSelect,
+        width: DataWidth,
+        read: Timing,
+        write: Option<Timing>,
+    ) -> Sram<'_> {
+        self.enable(cs, width, read, write);
+
+        Sram {
+            base: (BANK1 + cs.index() * WINDOW) as *mut u8,
+            _fmc: PhantomData,
+        }
+    }
+
+    /// Maps an 8080-style display on `cs`, whose RS line (low for commands, high for data) is
+    /// wired to the address line `rs_line` (e.g. 16 for FMC_A16)
+    pub fn display(
+        &self,
+        cs: ChipSelect,
+        rs_line: u8,
+        read: Timing,
+        write: Option<Timing>,
+    ) -> DisplayBus<'_> {
+        // FMC_A0 to FMC_A24
+        assert!(rs_line < 25);
+        self.enable(cs, DataWidth::Bits16, read, write);
+
+        // with a 16-bit bus, FMC_A[n] is driven by the address bit n + 1
+        let base = BANK1 + cs.index() * WINDOW;
+        DisplayBus {
+            command: base as *mut u16,
+            data: (base | (1 << (rs_line + 1))) as *mut u16,
+            _fmc: PhantomData,
+        }
+    }
+
+    /// Unmaps the memory on `cs`
+    pub fn disable(&mut self, cs: ChipSelect) {
+        // NOTE(unsafe) the FMC is owned by `self`
+        unsafe {
+            let bcr = Self::reg(8 * cs.index());
+            ptr::write_volatile(bcr, ptr::read_volatile(bcr) & !MBKEN);
+        }
+    }
+
+    /// Stops the FMC clock and releases the FMC
+    pub fn free(self, ahb: &mut AHB3) -> FMC {
+        ahb.enr().modify(|_, w| w.fmcen().clear_bit());
+        self.fmc
+    }
+
+    fn enable(&self, cs: ChipSelect, width: DataWidth, read: Timing, write: Option<Timing>) {
+        let mwid = match width {
+            DataWidth::Bits8 => 0b00,
+            DataWidth::Bits16 => 0b01,
+        };
+        let extmod = if write.is_some() { EXTMOD } else { 0 };
+
+        // NOTE(unsafe) the FMC is owned by `self`; the registers of the chip selects are accessed
+        // by address so that they can be selected at run time
+        unsafe {
+            let bcr = Self::reg(8 * cs.index());
+            let btr = Self::reg(8 * cs.index() + 4);
+            let bwtr = Self::reg(BWTR + 8 * cs.index());
+
+            ptr::write_volatile(btr, read.bits());
+            if let Some(write) = write {
+                ptr::write_volatile(bwtr, write.bits());
+            }
+
+            // SRAM (MTYP = 0), not multiplexed
+            let value = ptr::read_volatile(bcr) & !(MEMORY_MASK | EXTMOD);
+            ptr::write_volatile(bcr, value | (mwid << 4) | WREN | extmod | MBKEN);
+
+            // the controller is enabled from the register of NE1
+            let bcr1 = Self::reg(0);
+            ptr::write_volatile(bcr1, ptr::read_volatile(bcr1) | FMCEN);
+        }
+    }
+
+    fn reg(offset: usize) -> *mut u32 {
+        (FMC::ptr() as usize + offset) as *mut u32
+    }
+}
+
+/// External SRAM
+pub struct Sram<'a> {
+    base: *mut u8,
+    _fmc: PhantomData<&'a Fmc>,
+}
+
+impl<'a> Sram<'a> {
+    /// Returns the address of the memory, to be accessed by bytes
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.base
+    }
+
+    /// Returns the address of the memory, to be accessed by half-words
+    pub fn as_mut_ptr_u16(&self) -> *mut u16 {
+        self.base as *mut u16
+    }
+
+    /// Returns the size of the window the memory is mapped in
+    pub fn window(&self) -> usize {
+        WINDOW
+    }
+}
+
+/// 16-bit 8080-style display bus
+pub struct DisplayBus<'a> {
+    command: *mut u16,
+    data: *mut u16,
+    _fmc: PhantomData<&'a Fmc>,
+}
+
+impl<'a> DisplayBus<'a> {
+    /// Writes a command, with RS low
+    pub fn write_command(&mut self, command: u16) {
+        // NOTE(unsafe) the address is mapped by the FMC
+        unsafe { ptr::write_volatile(self.command, command) }
+    }
+
+    /// Writes a data word, with RS high
+    pub fn write_data(&mut self, data: u16) {
+        // NOTE(unsafe) see `write_command`
+        unsafe { ptr::write_volatile(self.data, data) }
+    }
+
+    /// Writes data words, e.g. pixels
+    pub fn write_data_iter<I>(&mut self, data: I)
+    where
+        I: IntoIterator<Item = u16>,
+    {
+        for word in data {
+            self.write_data(word);
+        }
+    }
+
+    /// Reads a data word, with RS high
+    pub fn read_data(&mut self) -> u16 {
+        // NOTE(unsafe) see `write_command`
+        unsafe { ptr::read_volatile(self.data) }
+    }
+}
@@ -4,7 +4,10 @@
 // https://github.com/japaric/stm32f30x-hal/blob/master/src/gpio.rs
 
 use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
+use crate::hal::digital::OutputPin;
 use crate::rcc::AHB2;
 
 /// Extension trait to split a GPIO peripheral in independent pins and registers
@@ -12,8 +15,15 @@ pub trait GpioExt {
     /// The to split the GPIO into
     type Parts;
 
+    /// The handle to take the pins and registers from one at a time
+    type LazyParts;
+
     /// Splits the GPIO block into independent pins and registers
     fn split(self, ahb: &mut AHB2) -> Self::Parts;
+
+    /// Turns the GPIO block into a handle from which the pins and registers are taken one at a
+    /// time, see `singleton`
+    fn split_lazy(self, ahb: &mut AHB2) -> Self::LazyParts;
 }
 
 /// Input mode (type state)
@@ -38,6 +48,9 @@ pub struct PushPull;
 /// Open drain output (type state)
 pub struct OpenDrain;
 
+/// Analog mode (type state)
+pub struct Analog;
+
 /// Alternate mode (type state)
 pub struct Alternate<AF, MODE>
 {
@@ -93,6 +106,177 @@ pub struct AF14;
 /// Alternate function 15 (type state)
 pub struct AF15;
 
+/// Alternate function number, see `into_alternate`
+pub struct Af<const N: u8>;
+
+/// Maps an alternate function number to its type state
+pub trait AfNumber {
+    /// The alternate function type state, e.g. `AF7` for `Af<7>`
+    type AF;
+}
+
+macro_rules! af_numbers {
+    ($($n:expr => $AFn:ident,)+) => {
+        $(
+            impl AfNumber for Af<$n> {
+                type AF = $AFn;
+            }
+        )+
+    }
+}
+
+af_numbers! {
+    0 => AF0,
+    1 => AF1,
+    2 => AF2,
+    3 => AF3,
+    4 => AF4,
+    5 => AF5,
+    6 => AF6,
+    7 => AF7,
+    8 => AF8,
+    9 => AF9,
+    10 => AF10,
+    11 => AF11,
+    12 => AF12,
+    13 => AF13,
+    14 => AF14,
+    15 => AF15,
+}
+
+// FIXME these should be "closed" traits
+/// Output pin that can be turned into a `SharedPin` - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// `pin` must return the address of a GPIO port and the number of one of its
+/// pins, `SharedPin` writes the BSRR of that port
+pub unsafe trait IntoShared {
+    #[doc(hidden)]
+    /// Returns the address of the port ORed with the pin number
+    fn pin(&self) -> usize;
+}
+
+// FIXME these should be "closed" traits
+/// Group of input pins whose levels can be sampled by DMA - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// `idr` must return the address of the IDR of a GPIO port, the DMA reads it
+/// on every request
+pub unsafe trait InputPort {
+    #[doc(hidden)]
+    /// Returns the address of the input data register
+    fn idr(&self) -> u32;
+}
+
+/// Offset of BSRR in the GPIO register block
+const BSRR: usize = 0x18;
+
+/// Output pin driven through a shared reference, without critical section
+///
+/// Only BSRR writes are performed, which are atomic, so the pin can be driven from both thread
+/// and interrupt context, e.g. for trigger pins toggled in high priority interrupts. Copies of a
+/// `SharedPin` drive the same pin.
+#[derive(Clone, Copy)]
+pub struct SharedPin {
+    pin: usize,
+}
+
+impl SharedPin {
+    /// Consumes `pin` to drive it through shared references
+    pub fn new<PIN>(pin: PIN) -> Self
+    where
+        PIN: IntoShared,
+    {
+        SharedPin { pin: pin.pin() }
+    }
+
+    /// Drives the pin high
+    pub fn set_high(&self) {
+        bsrr(self.pin, 0);
+    }
+
+    /// Drives the pin low
+    pub fn set_low(&self) {
+        bsrr(self.pin, 16);
+    }
+}
+
+impl OutputPin for SharedPin {
+    fn set_high(&mut self) {
+        SharedPin::set_high(self)
+    }
+
+    fn set_low(&mut self) {
+        SharedPin::set_low(self)
+    }
+}
+
+/// `SharedPin` that can be placed in a `static` and bound at run time
+///
+/// ``` ignore
+/// static TRIGGER: AtomicPin = AtomicPin::new();
+///
+/// TRIGGER.bind(SharedPin::new(pa8.into_push_pull_output(&mut gpioa.moder, &mut gpioa.otyper)));
+///
+/// // in any context, a no-op until `bind` has been called
+/// TRIGGER.set_high();
+/// ```
+pub struct AtomicPin {
+    pin: AtomicUsize,
+}
+
+impl AtomicPin {
+    /// Creates an unbound pin
+    pub const fn new() -> Self {
+        AtomicPin {
+            pin: AtomicUsize::new(0),
+        }
+    }
+
+    /// Binds `pin`, replacing the previously bound one
+    pub fn bind(&self, pin: SharedPin) {
+        self.pin.store(pin.pin, Ordering::Release);
+    }
+
+    /// Returns the bound pin, if any
+    pub fn get(&self) -> Option<SharedPin> {
+        match self.pin.load(Ordering::Acquire) {
+            0 => None,
+            pin => Some(SharedPin { pin }),
+        }
+    }
+
+    /// Drives the bound pin high
+    pub fn set_high(&self) {
+        if let Some(pin) = self.get() {
+            pin.set_high();
+        }
+    }
+
+    /// Drives the bound pin low
+    pub fn set_low(&self) {
+        if let Some(pin) = self.get() {
+            pin.set_low();
+        }
+    }
+}
+
+impl Default for AtomicPin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Writes the bit of `pin` in the set (`shift` = 0) or reset (`shift` = 16) half of BSRR
+fn bsrr(pin: usize, shift: usize) {
+    let port = pin & !0xf;
+    let i = pin & 0xf;
+    // NOTE(unsafe) atomic write to a stateless register of a port owned by a pin
+    unsafe { ptr::write_volatile((port + BSRR) as *mut u32, 1 << (i + shift)) }
+}
+
 macro_rules! gpio {
     ($GPIOX:ident, $gpiox:ident, $gpioy:ident, $iopxenr:ident, $iopxrst:ident, $PXx:ident, [
         $($PXi:ident: ($pxi:ident, $i:expr, $MODE:ty, $AFR:ident),)+
@@ -100,14 +284,16 @@ macro_rules! gpio {
         /// GPIO
         pub mod $gpiox {
             use core::marker::PhantomData;
+            use core::sync::atomic::AtomicU32;
 
             use crate::hal::digital::{OutputPin, InputPin};
             use crate::stm32::{$gpioy, $GPIOX};
 
             use crate::rcc::AHB2;
+            use crate::singleton::{self, Singleton};
             use super::{
-                Alternate, AF4, AF5, AF6, AF7, AF8, AF9, Floating, GpioExt, Input, OpenDrain, Output,
-                PullDown, PullUp, PushPull,
+                Af, AfNumber, Alternate, Analog, AF1, AF2, AF3, AF4, AF5, AF6, AF7, AF8, AF9, AF10, AF12, AF14, Floating,
+                GpioExt, Input, OpenDrain, Output, PullDown, PullUp, PushPull,
             };
 
             /// GPIO parts
@@ -128,8 +314,61 @@ macro_rules! gpio {
                 )+
             }
 
+            /// Pins and registers taken from `LazyParts`, the registers use the bits 16 to 20
+            static TAKEN: AtomicU32 = AtomicU32::new(0);
+
+            /// GPIO handle, see `GpioExt::split_lazy`
+            #[derive(Clone, Copy)]
+            pub struct LazyParts {
+                _0: (),
+            }
+
+            impl LazyParts {
+                /// Takes the pin (in its reset state) or register proxy `P`, returns `None` if
+                /// it was already taken
+                pub fn split_one<P>(&self) -> Option<P>
+                where
+                    P: Singleton<LazyParts>,
+                {
+                    if singleton::take(&TAKEN, P::BIT) {
+                        Some(P::new())
+                    } else {
+                        None
+                    }
+                }
+            }
+
+            macro_rules! singleton {
+                ($REG:ident, $bit:expr) => {
+                    unsafe impl Singleton<LazyParts> for $REG {
+                        const BIT: u8 = $bit;
+
+                        fn new() -> Self {
+                            $REG { _0: () }
+                        }
+                    }
+                }
+            }
+
+            singleton!(AFRL, 16);
+            singleton!(AFRH, 17);
+            singleton!(MODER, 18);
+            singleton!(OTYPER, 19);
+            singleton!(PUPDR, 20);
+
+            $(
+                unsafe impl Singleton<LazyParts> for $PXi<$MODE> {
+                    const BIT: u8 = $i;
+
+                    fn new() -> Self {
+                        $PXi { _mode: PhantomData }
+                    }
+                }
+            )+
+
             impl GpioExt for $GPIOX {
                 type Parts = Parts;
+                type LazyParts = LazyParts;
 
                 fn split(self, ahb: &mut AHB2) -> Parts {
                     ahb.enr().modify(|_, w| w.$iopxenr().set_bit());
@@ -147,6 +386,14 @@ macro_rules! gpio {
                         )+
                     }
                 }
+
+                fn split_lazy(self, ahb: &mut AHB2) -> LazyParts {
+                    ahb.enr().modify(|_, w| w.$iopxenr().set_bit());
+                    ahb.rstr().modify(|_, w| w.$iopxrst().set_bit());
+                    ahb.rstr().modify(|_, w| w.$iopxrst().clear_bit());
+
+                    LazyParts { _0: () }
+                }
             }
 
             /// Opaque AFRL register
@@ -224,6 +471,104 @@ macro_rules! gpio {
                 }
             }
 
+            unsafe impl<MODE> super::IntoShared for $PXx<Output<MODE>> {
+                fn pin(&self) -> usize {
+                    $GPIOX::ptr() as usize | self.i as usize
+                }
+            }
+
+            /// Pins of the port read and written all at once, e.g. the data lines of a
+            /// parallel bus
+            ///
+            /// Values are given with the bit `i` standing for the pin `i` of the port, bits of
+            /// pins not in the group being ignored.
+            pub struct Port<MODE> {
+                mask: u16,
+                _mode: PhantomData<MODE>,
+            }
+
+            impl<MODE> Default for Port<MODE> {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+
+            impl<MODE> Port<MODE> {
+                /// Creates an empty group
+                pub fn new() -> Self {
+                    Port {
+                        mask: 0,
+                        _mode: PhantomData,
+                    }
+                }
+
+                /// Adds `pin` to the group
+                pub fn with_pin(mut self, pin: $PXx<MODE>) -> Self {
+                    self.mask |= 1 << pin.i;
+                    self
+                }
+
+                /// Removes the pin `i` from the group, if it is part of it
+                pub fn remove(&mut self, i: u8) -> Option<$PXx<MODE>> {
+                    if i < 16 && self.mask & (1 << i) != 0 {
+                        self.mask &= !(1 << i);
+                        Some($PXx { i, _mode: PhantomData })
+                    } else {
+                        None
+                    }
+                }
+
+                /// Returns the pins of the group as a bit mask
+                pub fn mask(&self) -> u16 {
+                    self.mask
+                }
+
+                /// Returns the input levels of the pins, sampled at once
+                pub fn read(&self) -> u16 {
+                    // NOTE(unsafe) atomic read with no side effects
+                    let idr = unsafe { (*$GPIOX::ptr()).idr.read().bits() };
+                    idr as u16 & self.mask
+                }
+            }
+
+            unsafe impl<MODE> super::InputPort for Port<Input<MODE>> {
+                fn idr(&self) -> u32 {
+                    // NOTE(unsafe) only the address is taken
+                    unsafe { &(*$GPIOX::ptr()).idr as *const _ as u32 }
+                }
+            }
+
+            impl<MODE> Port<Output<MODE>> {
+                /// Drives the pins to `value`, all in the same cycle
+                pub fn write(&mut self, value: u16) {
+                    let set = u32::from(value & self.mask);
+                    let reset = u32::from(!value & self.mask);
+                    // NOTE(unsafe) atomic write to a stateless register
+                    unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits((reset << 16) | set)) }
+                }
+
+                /// Drives the pins selected by `bits` high, leaving the others untouched
+                pub fn set_high(&mut self, bits: u16) {
+                    let set = u32::from(bits & self.mask);
+                    // NOTE(unsafe) atomic write to a stateless register
+                    unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(set)) }
+                }
+
+                /// Drives the pins selected by `bits` low, leaving the others untouched
+                pub fn set_low(&mut self, bits: u16) {
+                    let reset = u32::from(bits & self.mask);
+                    // NOTE(unsafe) atomic write to a stateless register
+                    unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(reset << 16)) }
+                }
+
+                /// Returns the levels the pins are driven to
+                pub fn read_output(&self) -> u16 {
+                    // NOTE(unsafe) atomic read with no side effects
+                    let odr = unsafe { (*$GPIOX::ptr()).odr.read().bits() };
+                    odr as u16 & self.mask
+                }
+            }
+
             $(
                 /// Pin
                 pub struct $PXi<MODE> {
@@ -231,6 +576,151 @@ macro_rules! gpio {
                 }
 
                 impl<MODE> $PXi<MODE> {
+                    /// Configures the pin to serve as alternate function `N`, e.g.
+                    /// `into_alternate::<7>(..)` for AF7
+                    pub fn into_alternate<const N: u8>(
+                        self,
+                        moder: &mut MODER,
+                        afr: &mut $AFR,
+                    ) -> $PXi<Alternate<<Af<N> as AfNumber>::AF, MODE>>
+                    where
+                        Af<N>: AfNumber,
+                    {
+                        let offset = 2 * $i;
+
+                        // alternate function mode
+                        let mode = 0b10;
+                        moder.moder().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b11 << offset)) | (mode << offset))
+                        });
+
+                        let af = u32::from(N);
+                        let offset = 4 * ($i % 8);
+                        afr.afr().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b1111 << offset)) | (af << offset))
+                        });
+
+                        $PXi { _mode: PhantomData }
+                    }
+
+                    /// Lends the pin to `f` as alternate function `N`, then restores its previous
+                    /// configuration and returns it along with the result of `f`
+                    ///
+                    /// `f` gets the pin by value, e.g. to build a driver, and has to give it back
+                    /// along with its result, e.g. from the `free` method of the driver. As with
+                    /// the `into_afN` methods, the lent pin keeps `MODE` as the mode of its
+                    /// alternate function.
+                    ///
+                    /// ``` ignore
+                    /// // PA2 is a floating input, sampled at boot, then lent to USART2
+                    /// let strap = pa2.is_high();
+                    /// let (pa2, usart2) =
+                    ///     pa2.with_alternate::<7, _, _>(&mut gpioa.moder, &mut gpioa.afrl, |tx| {
+                    ///         let mut serial = Serial::usart2(usart2, (tx, rx), 9_600.bps(), clocks, apb);
+                    ///         block!(serial.write(COMMAND)).ok();
+                    ///         let (usart2, (tx, _rx)) = serial.free();
+                    ///         (tx, usart2)
+                    ///     });
+                    /// let strap = pa2.is_high();
+                    /// ```
+                    pub fn with_alternate<const N: u8, R, F>(
+                        self,
+                        moder: &mut MODER,
+                        afr: &mut $AFR,
+                        f: F,
+                    ) -> (Self, R)
+                    where
+                        Af<N>: AfNumber,
+                        F: FnOnce(
+                            $PXi<Alternate<<Af<N> as AfNumber>::AF, MODE>>,
+                        ) -> ($PXi<Alternate<<Af<N> as AfNumber>::AF, MODE>>, R),
+                    {
+                        let offset = 2 * $i;
+                        let af_offset = 4 * ($i % 8);
+                        let mode = moder.moder().read().bits() & (0b11 << offset);
+                        let af = afr.afr().read().bits() & (0b1111 << af_offset);
+
+                        let (_pin, result) = f(self.into_alternate::<N>(moder, afr));
+
+                        moder.moder().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b11 << offset)) | mode)
+                        });
+                        afr.afr().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b1111 << af_offset)) | af)
+                        });
+
+                        ($PXi { _mode: PhantomData }, result)
+                    }
+
+                    /// Configures the pin to serve as alternate function 1 (AF1)
+                    pub fn into_af1(
+                        self,
+                        moder: &mut MODER,
+                        afr: &mut $AFR,
+                    ) -> $PXi<Alternate<AF1, MODE>> {
+                        let offset = 2 * $i;
+
+                        // alternate function mode
+                        let mode = 0b10;
+                        moder.moder().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b11 << offset)) | (mode << offset))
+                        });
+
+                        let af = 1;
+                        let offset = 4 * ($i % 8);
+                        afr.afr().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b1111 << offset)) | (af << offset))
+                        });
+
+                        $PXi { _mode: PhantomData }
+                    }
+
+                    /// Configures the pin to serve as alternate function 2 (AF2)
+                    pub fn into_af2(
+                        self,
+                        moder: &mut MODER,
+                        afr: &mut $AFR,
+                    ) -> $PXi<Alternate<AF2, MODE>> {
+                        let offset = 2 * $i;
+
+                        // alternate function mode
+                        let mode = 0b10;
+                        moder.moder().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b11 << offset)) | (mode << offset))
+                        });
+
+                        let af = 2;
+                        let offset = 4 * ($i % 8);
+                        afr.afr().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b1111 << offset)) | (af << offset))
+                        });
+
+                        $PXi { _mode: PhantomData }
+                    }
+
+                    /// Configures the pin to serve as alternate function 3 (AF3)
+                    pub fn into_af3(
+                        self,
+                        moder: &mut MODER,
+                        afr: &mut $AFR,
+                    ) -> $PXi<Alternate<AF3, MODE>> {
+                        let offset = 2 * $i;
+
+                        // alternate function mode
+                        let mode = 0b10;
+                        moder.moder().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b11 << offset)) | (mode << offset))
+                        });
+
+                        let af = 3;
+                        let offset = 4 * ($i % 8);
+                        afr.afr().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b1111 << offset)) | (af << offset))
+                        });
+
+                        $PXi { _mode: PhantomData }
+                    }
+
                     /// Configures the pin to serve as alternate function 4 (AF4)
                     pub fn into_af4(
                         self,
@@ -372,6 +862,78 @@ macro_rules! gpio {
                         $PXi { _mode: PhantomData }
                     }
 
+                    /// Configures the pin to serve as alternate function 10 (AF10)
+                    pub fn into_af10(
+                        self,
+                        moder: &mut MODER,
+                        afr: &mut $AFR,
+                    ) -> $PXi<Alternate<AF10, MODE>> {
+                        let offset = 2 * $i;
+
+                        // alternate function mode
+                        let mode = 0b10;
+                        moder.moder().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b11 << offset)) | (mode << offset))
+                        });
+
+                        let af = 10;
+                        let offset = 4 * ($i % 8);
+
+                        afr.afr().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b1111 << offset)) | (af << offset))
+                        });
+
+                        $PXi { _mode: PhantomData }
+                    }
+
+                    /// Configures the pin to serve as alternate function 12 (AF12)
+                    pub fn into_af12(
+                        self,
+                        moder: &mut MODER,
+                        afr: &mut $AFR,
+                    ) -> $PXi<Alternate<AF12, MODE>> {
+                        let offset = 2 * $i;
+
+                        // alternate function mode
+                        let mode = 0b10;
+                        moder.moder().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b11 << offset)) | (mode << offset))
+                        });
+
+                        let af = 12;
+                        let offset = 4 * ($i % 8);
+
+                        afr.afr().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b1111 << offset)) | (af << offset))
+                        });
+
+                        $PXi { _mode: PhantomData }
+                    }
+
+                    /// Configures the pin to serve as alternate function 14 (AF14)
+                    pub fn into_af14(
+                        self,
+                        moder: &mut MODER,
+                        afr: &mut $AFR,
+                    ) -> $PXi<Alternate<AF14, MODE>> {
+                        let offset = 2 * $i;
+
+                        // alternate function mode
+                        let mode = 0b10;
+                        moder.moder().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b11 << offset)) | (mode << offset))
+                        });
+
+                        let af = 14;
+                        let offset = 4 * ($i % 8);
+
+                        afr.afr().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b1111 << offset)) | (af << offset))
+                        });
+
+                        $PXi { _mode: PhantomData }
+                    }
+
                     /// Configures the pin to operate as a floating input pin
                     pub fn into_floating_input(
                         self,
@@ -393,6 +955,39 @@ macro_rules! gpio {
                         $PXi { _mode: PhantomData }
                     }
 
+                    /// Configures the pin to operate as an analog pin, e.g. an ADC input
+                    ///
+                    /// On the STM32L4x5/x6 the analog switch to the ADC is closed as well.
+                    pub fn into_analog(
+                        self,
+                        moder: &mut MODER,
+                        pupdr: &mut PUPDR,
+                    ) -> $PXi<Analog> {
+                        let offset = 2 * $i;
+
+                        // no pull-up or pull-down
+                        pupdr
+                            .pupdr()
+                            .modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << offset)) });
+
+                        // analog mode
+                        moder
+                            .moder()
+                            .modify(|r, w| unsafe { w.bits(r.bits() | (0b11 << offset)) });
+
+                        #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+                        {
+                            // NOTE(unsafe) ASCR sits at offset 0x2C of every port (missing from
+                            // some SVDs); the read-modify-write is done in a critical section
+                            let ascr = ($GPIOX::ptr() as usize + 0x2c) as *mut u32;
+                            cortex_m::interrupt::free(|_| unsafe {
+                                ascr.write_volatile(ascr.read_volatile() | (1 << $i))
+                            });
+                        }
+
+                        $PXi { _mode: PhantomData }
+                    }
+
                     /// Configures the pin to operate as a pulled down input pin
                     pub fn into_pull_down_input(
                         self,
@@ -500,6 +1095,22 @@ macro_rules! gpio {
                         let od = self.into_push_pull_output(moder, otyper);
                         od.into_af9(moder, afr)
                     }
+
+                    /// Configures the pin to operate as an I2C SCL or SDA pin: alternate function
+                    /// 4 (AF4), open drain, with the internal pull up
+                    ///
+                    /// The internal pull up is only strong enough for short buses at 100 kHz.
+                    pub fn into_af4_open_drain_with_pullup(
+                        self,
+                        moder: &mut MODER,
+                        otyper: &mut OTYPER,
+                        pupdr: &mut PUPDR,
+                        afr: &mut $AFR,
+                    ) -> $PXi<Alternate<AF4, Output<OpenDrain>>> {
+                        let mut od = self.into_open_drain_output(moder, otyper);
+                        od.internal_pull_up(pupdr, true);
+                        od.into_af4(moder, afr)
+                    }
                 }
 
                 impl $PXi<Output<OpenDrain>> {
@@ -519,12 +1130,12 @@ macro_rules! gpio {
                     }
                 }
 
-                impl<MODE> $PXi<Output<MODE>> {
+                impl<MODE> $PXi<MODE> {
                     /// Erases the pin number from the type
                     ///
                     /// This is useful when you want to collect the pins into an array where you
                     /// need all the elements to have the same type
-                    pub fn downgrade(self) -> $PXx<Output<MODE>> {
+                    pub fn downgrade(self) -> $PXx<MODE> {
                         $PXx {
                             i: $i,
                             _mode: self._mode,
@@ -544,6 +1155,12 @@ macro_rules! gpio {
                     }
                 }
 
+                unsafe impl<MODE> super::IntoShared for $PXi<Output<MODE>> {
+                    fn pin(&self) -> usize {
+                        $GPIOX::ptr() as usize | $i
+                    }
+                }
+
                 impl<MODE> InputPin for $PXi<Input<MODE>> {
                     fn is_high(&self) -> bool {
                         !self.is_low()
@@ -591,6 +1208,29 @@ gpio!(GPIOB, gpiob, gpiob, gpioben, gpiobrst, PBx, [
     PB9: (pb9, 9, Input<Floating>, AFRH),
     PB10: (pb10, 10, Input<Floating>, AFRH),
     PB11: (pb11, 11, Input<Floating>, AFRH),
+    PB12: (pb12, 12, Input<Floating>, AFRH),
+    PB13: (pb13, 13, Input<Floating>, AFRH),
+    PB14: (pb14, 14, Input<Floating>, AFRH),
+    PB15: (pb15, 15, Input<Floating>, AFRH),
+]);
+
+gpio!(GPIOC, gpioc, gpioc, gpiocen, gpiocrst, PCx, [
+    PC0: (pc0, 0, Input<Floating>, AFRL),
+    PC1: (pc1, 1, Input<Floating>, AFRL),
+    PC2: (pc2, 2, Input<Floating>, AFRL),
+    PC3: (pc3, 3, Input<Floating>, AFRL),
+    PC4: (pc4, 4, Input<Floating>, AFRL),
+    PC5: (pc5, 5, Input<Floating>, AFRL),
+    PC6: (pc6, 6, Input<Floating>, AFRL),
+    PC7: (pc7, 7, Input<Floating>, AFRL),
+    PC8: (pc8, 8, Input<Floating>, AFRH),
+    PC9: (pc9, 9, Input<Floating>, AFRH),
+    PC10: (pc10, 10, Input<Floating>, AFRH),
+    PC11: (pc11, 11, Input<Floating>, AFRH),
+    PC12: (pc12, 12, Input<Floating>, AFRH),
+    PC13: (pc13, 13, Input<Floating>, AFRH),
+    PC14: (pc14, 14, Input<Floating>, AFRH),
+    PC15: (pc15, 15, Input<Floating>, AFRH),
 ]);
 
 gpio!(GPIOD, gpiod, gpioc, gpioden, gpiodrst, PDx, [
@@ -603,6 +1243,13 @@ gpio!(GPIOD, gpiod, gpioc, gpioden, gpiodrst, PDx, [
     PD6: (pd6, 6, Input<Floating>, AFRL),
     PD7: (pd7, 7, Input<Floating>, AFRL),
     PD8: (pd8, 8, Input<Floating>, AFRH),
+    PD9: (pd9, 9, Input<Floating>, AFRH),
+    PD10: (pd10, 10, Input<Floating>, AFRH),
+    PD11: (pd11, 11, Input<Floating>, AFRH),
+    PD12: (pd12, 12, Input<Floating>, AFRH),
+    PD13: (pd13, 13, Input<Floating>, AFRH),
+    PD14: (pd14, 14, Input<Floating>, AFRH),
+    PD15: (pd15, 15, Input<Floating>, AFRH),
 ]);
 
 gpio!(GPIOE, gpioe, gpioc, gpioeen, gpioerst, PEx, [
@@ -615,4 +1262,57 @@ gpio!(GPIOE, gpioe, gpioc, gpioeen, gpioerst, PEx, [
     PE6: (pe6, 6, Input<Floating>, AFRL),
     PE7: (pe7, 7, Input<Floating>, AFRL),
     PE8: (pe8, 8, Input<Floating>, AFRH),
+    PE9: (pe9, 9, Input<Floating>, AFRH),
+    PE10: (pe10, 10, Input<Floating>, AFRH),
+    PE11: (pe11, 11, Input<Floating>, AFRH),
+    PE12: (pe12, 12, Input<Floating>, AFRH),
+    PE13: (pe13, 13, Input<Floating>, AFRH),
+    PE14: (pe14, 14, Input<Floating>, AFRH),
+    PE15: (pe15, 15, Input<Floating>, AFRH),
+]);
+
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+gpio!(GPIOF, gpiof, gpioc, gpiofen, gpiofrst, PFx, [
+    PF0: (pf0, 0, Input<Floating>, AFRL),
+    PF1: (pf1, 1, Input<Floating>, AFRL),
+    PF2: (pf2, 2, Input<Floating>, AFRL),
+    PF3: (pf3, 3, Input<Floating>, AFRL),
+    PF4: (pf4, 4, Input<Floating>, AFRL),
+    PF5: (pf5, 5, Input<Floating>, AFRL),
+    PF6: (pf6, 6, Input<Floating>, AFRL),
+    PF7: (pf7, 7, Input<Floating>, AFRL),
+    PF8: (pf8, 8, Input<Floating>, AFRH),
+    PF9: (pf9, 9, Input<Floating>, AFRH),
+    PF10: (pf10, 10, Input<Floating>, AFRH),
+    PF11: (pf11, 11, Input<Floating>, AFRH),
+    PF12: (pf12, 12, Input<Floating>, AFRH),
+    PF13: (pf13, 13, Input<Floating>, AFRH),
+    PF14: (pf14, 14, Input<Floating>, AFRH),
+    PF15: (pf15, 15, Input<Floating>, AFRH),
+]);
+
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+gpio!(GPIOG, gpiog, gpioc, gpiogen, gpiogrst, PGx, [
+    PG0: (pg0, 0, Input<Floating>, AFRL),
+    PG1: (pg1, 1, Input<Floating>, AFRL),
+    PG2: (pg2, 2, Input<Floating>, AFRL),
+    PG3: (pg3, 3, Input<Floating>, AFRL),
+    PG4: (pg4, 4, Input<Floating>, AFRL),
+    PG5: (pg5, 5, Input<Floating>, AFRL),
+    PG6: (pg6, 6, Input<Floating>, AFRL),
+    PG7: (pg7, 7, Input<Floating>, AFRL),
+    PG8: (pg8, 8, Input<Floating>, AFRH),
+    PG9: (pg9, 9, Input<Floating>, AFRH),
+    PG10: (pg10, 10, Input<Floating>, AFRH),
+    PG11: (pg11, 11, Input<Floating>, AFRH),
+    PG12: (pg12, 12, Input<Floating>, AFRH),
+    PG13: (pg13, 13, Input<Floating>, AFRH),
+    PG14: (pg14, 14, Input<Floating>, AFRH),
+    PG15: (pg15, 15, Input<Floating>, AFRH),
+]);
+
+gpio!(GPIOH, gpioh, gpioc, gpiohen, gpiohrst, PHx, [
+    PH0: (ph0, 0, Input<Floating>, AFRL),
+    PH1: (ph1, 1, Input<Floating>, AFRL),
+    PH3: (ph3, 3, Input<Floating>, AFRL),
 ]);
@@ -1,17 +1,31 @@
 //! Inter-Integrated Circuit (I2C) bus
 
 use cast::u8;
-use crate::stm32::{I2C1, I2C2};
+use crate::stm32::{I2C1, I2C2, I2C3, RCC};
 
-use crate::gpio::gpioa::{PA10, PA9};
-use crate::gpio::gpiob::{PB6, PB7, PB10, PB11};
+use crate::gpio::gpioa::{PA10, PA7, PA9};
+use crate::gpio::gpiob::{PB4, PB6, PB7, PB10, PB11};
+use crate::gpio::gpioc::{PC0, PC1};
 use crate::gpio::{AF4, Alternate, OpenDrain, Output};
 use crate::hal::blocking::i2c::{Write, WriteRead, Read};
-use crate::rcc::{APB1R1, Clocks};
+use crate::rcc::{APB1R1, ClockError, Clocks};
 use crate::time::Hertz;
 
+use cortex_m::interrupt;
+#[cfg(feature = "async")]
+use embedded_hal_1::i2c::{ErrorKind, NoAcknowledgeSource, Operation};
+#[cfg(feature = "async")]
+use crate::asynch::{self, AtomicWaker};
+#[cfg(feature = "async")]
+use crate::stm32::i2c1;
+
+pub mod bus;
+pub mod eeprom;
+
 /// I2C error
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum Error {
     /// Bus error
     Bus,
@@ -19,12 +33,24 @@ pub enum Error {
     Arbitration,
     /// NACK
     Nack,
+    /// The bus was still used by another master after all retries
+    Busy,
     // Overrun, // slave mode only
     // Pec, // SMBUS mode only
     // Timeout, // SMBUS mode only
     // Alert, // SMBUS mode only
-    #[doc(hidden)]
-    _Extensible,
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_1::i2c::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::Bus => ErrorKind::Bus,
+            Error::Arbitration => ErrorKind::ArbitrationLoss,
+            Error::Nack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            Error::Busy => ErrorKind::Other,
+        }
+    }
 }
 
 // FIXME these should be "closed" traits
@@ -41,23 +67,66 @@ unsafe impl SclPin<I2C1> for PB6<Alternate<AF4, Output<OpenDrain>>> {}
 unsafe impl SdaPin<I2C1> for PB7<Alternate<AF4, Output<OpenDrain>>> {}
 unsafe impl SclPin<I2C2> for PB10<Alternate<AF4, Output<OpenDrain>>> {}
 unsafe impl SdaPin<I2C2> for PB11<Alternate<AF4, Output<OpenDrain>>> {}
+unsafe impl SclPin<I2C3> for PA7<Alternate<AF4, Output<OpenDrain>>> {}
+unsafe impl SdaPin<I2C3> for PB4<Alternate<AF4, Output<OpenDrain>>> {}
+unsafe impl SclPin<I2C3> for PC0<Alternate<AF4, Output<OpenDrain>>> {}
+unsafe impl SdaPin<I2C3> for PC1<Alternate<AF4, Output<OpenDrain>>> {}
+
+/// How transfers cope with other masters on the bus
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Number of times a transfer is restarted after losing the arbitration, or after finding
+    /// the bus busy
+    pub retries: u8,
+    /// Number of times the BUSY flag is polled, waiting for the bus to be free, before each
+    /// attempt
+    pub busy_polls: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 3,
+            busy_polls: 10_000,
+        }
+    }
+}
 
 /// I2C peripheral operating in master mode
 pub struct I2c<I2C, PINS> {
     i2c: I2C,
     pins: PINS,
+    retry: RetryPolicy,
+    freq: u32,
+    clk: u32,
 }
 
+/// Frequency of HSI16, the kernel clock of a peripheral waking up from Stop mode
+const HSI16: u32 = 16_000_000;
+
+/// CCIPR: I2CxSEL, PCLK
+const SEL_PCLK: u8 = 0b00;
+/// CCIPR: I2CxSEL, HSI16
+const SEL_HSI16: u8 = 0b10;
+
 macro_rules! busy_wait {
     ($i2c:expr, $flag:ident) => {
         loop {
             let isr = $i2c.isr.read();
 
+            // NOTE the peripheral releases the bus on errors: a STOP is sent after a NACK, and
+            // the peripheral switches to slave mode after an arbitration loss
             if isr.berr().bit_is_set() {
+                $i2c.icr.write(|w| w.berrcf().set_bit());
+                trace!("I2C: bus error");
                 return Err(Error::Bus);
             } else if isr.arlo().bit_is_set() {
+                $i2c.icr.write(|w| w.arlocf().set_bit());
+                trace!("I2C: arbitration loss");
                 return Err(Error::Arbitration);
             } else if isr.nackf().bit_is_set() {
+                $i2c.icr.write(|w| w.nackcf().set_bit());
+                trace!("I2C: NACK");
                 return Err(Error::Nack);
             } else if isr.$flag().bit_is_set() {
                 break;
@@ -68,11 +137,143 @@ macro_rules! busy_wait {
     };
 }
 
+/// Waits for an ISR flag, returning early (and clearing the flag) on bus errors
+#[cfg(feature = "async")]
+async fn wait_flag<F>(
+    i2c: &i2c1::RegisterBlock,
+    waker: &AtomicWaker,
+    flag: F,
+) -> Result<(), Error>
+where
+    F: Fn(&i2c1::isr::R) -> bool,
+{
+    asynch::wait(
+        waker,
+        || {
+            let isr = i2c.isr.read();
+
+            if isr.berr().bit_is_set() {
+                i2c.icr.write(|w| w.berrcf().set_bit());
+                Some(Err(Error::Bus))
+            } else if isr.arlo().bit_is_set() {
+                i2c.icr.write(|w| w.arlocf().set_bit());
+                Some(Err(Error::Arbitration))
+            } else if isr.nackf().bit_is_set() {
+                i2c.icr.write(|w| w.nackcf().set_bit());
+                Some(Err(Error::Nack))
+            } else if flag(&isr) {
+                Some(Ok(()))
+            } else {
+                None
+            }
+        },
+        || interrupt::free(|_| {
+            i2c.cr1.modify(|_, w| {
+                w.txie()
+                    .set_bit()
+                    .rxie()
+                    .set_bit()
+                    .tcie()
+                    .set_bit()
+                    .stopie()
+                    .set_bit()
+                    .nackie()
+                    .set_bit()
+                    .errie()
+                    .set_bit()
+            })
+        }),
+    ).await
+}
+
+/// Returns true if a non-empty operation in the same direction follows `operations[index]`
+///
+/// Empty operations in between are skipped, they are merged into the surrounding ones.
+#[cfg(feature = "async")]
+fn continues(operations: &[Operation<'_>], index: usize, read: bool) -> bool {
+    for operation in &operations[index + 1..] {
+        let (is_read, empty) = match operation {
+            Operation::Read(buffer) => (true, buffer.is_empty()),
+            Operation::Write(bytes) => (false, bytes.is_empty()),
+        };
+        if is_read != read {
+            return false;
+        } else if !empty {
+            return true;
+        }
+    }
+    false
+}
+
+/// Computes PRESC, SCLL, SCLH, SDADEL and SCLDEL for a bus frequency of `freq` from a kernel
+/// clock of `i2cclk`
+fn timing(i2cclk: u32, freq: u32) -> Result<(u8, u8, u8, u8, u8), ClockError> {
+    if freq == 0 || freq > 1_000_000 {
+        return Err(ClockError::Timing);
+    }
+
+    // TODO review compliance with the timing requirements of I2C
+    // t_I2CCLK = 1 / PCLK1
+    // t_PRESC  = (PRESC + 1) * t_I2CCLK
+    // t_SCLL   = (SCLL + 1) * t_PRESC
+    // t_SCLH   = (SCLH + 1) * t_PRESC
+    //
+    // t_SYNC1 + t_SYNC2 > 4 * t_I2CCLK
+    // t_SCL ~= t_SYNC1 + t_SYNC2 + t_SCLL + t_SCLH
+    let sub = |a: u32, b: u32| a.checked_sub(b).ok_or(ClockError::Timing);
+    let ratio = sub(i2cclk / freq, 4)?;
+    let (presc, scll, sclh, sdadel, scldel) = if freq >= 100_000 {
+        // fast-mode or fast-mode plus
+        // here we pick SCLL + 1 = 2 * (SCLH + 1)
+        let presc = ratio / 387;
+
+        let sclh = sub(ratio / (presc + 1), 3)? / 3;
+        let scll = 2 * (sclh + 1) - 1;
+
+        let (sdadel, scldel) = if freq > 400_000 {
+            // fast-mode plus
+            let sdadel = 0;
+            let scldel = sub(i2cclk / 4_000_000 / (presc + 1), 1)?;
+
+            (sdadel, scldel)
+        } else {
+            // fast-mode
+            let sdadel = i2cclk / 8_000_000 / (presc + 1);
+            let scldel = sub(i2cclk / 2_000_000 / (presc + 1), 1)?;
+
+            (sdadel, scldel)
+        };
+
+        (presc, scll, sclh, sdadel, scldel)
+    } else {
+        // standard-mode
+        // here we pick SCLL = SCLH
+        let presc = ratio / 514;
+
+        let sclh = sub(ratio / (presc + 1), 2)? / 2;
+        let scll = sclh;
+
+        let sdadel = i2cclk / 2_000_000 / (presc + 1);
+        let scldel = sub(i2cclk / 800_000 / (presc + 1), 1)?;
+
+        (presc, scll, sclh, sdadel, scldel)
+    };
+
+    if presc >= 16 || scldel >= 16 || sdadel >= 16 {
+        return Err(ClockError::Timing);
+    }
+    let byte = |value: u32| u8(value).map_err(|_| ClockError::Timing);
+
+    Ok((byte(presc)?, byte(scll)?, byte(sclh)?, byte(sdadel)?, byte(scldel)?))
+}
+
 macro_rules! hal {
-    ($($I2CX:ident: ($i2cX:ident, $i2cXen:ident, $i2cXrst:ident),)+) => {
+    ($($I2CX:ident: ($i2cX:ident, $try_i2cX:ident, $i2cXen:ident, $i2cXrst:ident, $i2cXsel:ident),)+) => {
         $(
             impl<SCL, SDA> I2c<$I2CX, (SCL, SDA)> {
                 /// Configures the I2C peripheral to work in master mode
+                ///
+                /// Panics if `freq` can't be generated, see the `try_` constructor.
                 pub fn $i2cX<F>(
                     i2c: $I2CX,
                     pins: (SCL, SDA),
@@ -84,72 +285,133 @@ macro_rules! hal {
                     SCL: SclPin<$I2CX>,
                     SDA: SdaPin<$I2CX>,
                 {
+                    Self::$try_i2cX(i2c, pins, freq, clocks, apb1).unwrap()
+                }
+
+                /// Configures the I2C peripheral to work in master mode, fails if `freq` can't
+                /// be generated from the kernel clock
+                pub fn $try_i2cX<F>(
+                    i2c: $I2CX,
+                    pins: (SCL, SDA),
+                    freq: F,
+                    clocks: Clocks,
+                    apb1: &mut APB1R1,
+                ) -> Result<Self, ClockError> where
+                    F: Into<Hertz>,
+                    SCL: SclPin<$I2CX>,
+                    SDA: SdaPin<$I2CX>,
+                {
+                    let freq = freq.into().0;
+                    let clk = clocks.pclk1().0;
+                    // checked before the peripheral is touched
+                    timing(clk, freq)?;
+
                     apb1.enr().modify(|_, w| w.$i2cXen().set_bit());
                     apb1.rstr().modify(|_, w| w.$i2cXrst().set_bit());
                     apb1.rstr().modify(|_, w| w.$i2cXrst().clear_bit());
 
-                    let freq = freq.into().0;
+                    let mut i2c = I2c { i2c, pins, retry: RetryPolicy::default(), freq, clk };
+                    i2c.set_timing(clk)?;
 
-                    assert!(freq <= 1_000_000);
-
-                    // TODO review compliance with the timing requirements of I2C
-                    // t_I2CCLK = 1 / PCLK1
-                    // t_PRESC  = (PRESC + 1) * t_I2CCLK
-                    // t_SCLL   = (SCLL + 1) * t_PRESC
-                    // t_SCLH   = (SCLH + 1) * t_PRESC
-                    //
-                    // t_SYNC1 + t_SYNC2 > 4 * t_I2CCLK
-                    // t_SCL ~= t_SYNC1 + t_SYNC2 + t_SCLL + t_SCLH
-                    let i2cclk = clocks.pclk1().0;
-                    let ratio = i2cclk / freq - 4;
-                    let (presc, scll, sclh, sdadel, scldel) = if freq >= 100_000 {
-                        // fast-mode or fast-mode plus
-                        // here we pick SCLL + 1 = 2 * (SCLH + 1)
-                        let presc = ratio / 387;
-
-                        let sclh = ((ratio / (presc + 1)) - 3) / 3;
-                        let scll = 2 * (sclh + 1) - 1;
-
-                        let (sdadel, scldel) = if freq > 400_000 {
-                            // fast-mode plus
-                            let sdadel = 0;
-                            let scldel = i2cclk / 4_000_000 / (presc + 1) - 1;
-
-                            (sdadel, scldel)
-                        } else {
-                            // fast-mode
-                            let sdadel = i2cclk / 8_000_000 / (presc + 1);
-                            let scldel = i2cclk / 2_000_000 / (presc + 1) - 1;
+                    // Enable the peripheral
+                    i2c.i2c.cr1.write(|w| w.pe().set_bit());
 
-                            (sdadel, scldel)
-                        };
+                    Ok(i2c)
+                }
 
-                        (presc, scll, sclh, sdadel, scldel)
-                    } else {
-                        // standard-mode
-                        // here we pick SCLL = SCLH
-                        let presc = ratio / 514;
+                /// Releases the I2C peripheral and associated pins
+                pub fn free(self) -> ($I2CX, (SCL, SDA)) {
+                    (self.i2c, self.pins)
+                }
 
-                        let sclh = ((ratio / (presc + 1)) - 2) / 2;
-                        let scll = sclh;
+                /// Resets the I2C peripheral, stops its clock and releases it with the pins
+                ///
+                /// A transfer in progress is aborted, the pins are left as they were configured.
+                pub fn release(self, apb1: &mut APB1R1) -> ($I2CX, (SCL, SDA)) {
+                    apb1.deinit(&self.i2c);
+                    (self.i2c, self.pins)
+                }
+            }
 
-                        let sdadel = i2cclk / 2_000_000 / (presc + 1);
-                        let scldel = i2cclk / 800_000 / (presc + 1) - 1;
+            impl<PINS> I2c<$I2CX, PINS> {
+                /// Returns `true` while a communication is in progress on the bus, e.g. by
+                /// another master
+                pub fn bus_is_busy(&self) -> bool {
+                    self.i2c.isr.read().busy().bit_is_set()
+                }
 
-                        (presc, scll, sclh, sdadel, scldel)
-                    };
+                /// Sets how the blocking transfers cope with other masters on the bus
+                pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+                    self.retry = policy;
+                }
+
+                /// Wakes the device up from Stop mode when the 8-bit `address` is matched
+                ///
+                /// HSI16 is turned on, and kept on in Stop mode with HSIKERON, the kernel clock is
+                /// switched to it and the bus timings are computed again for it. HSI16 is left on
+                /// by `disable_wakeup`, as other peripherals may use it. The address is acknowledged with the clock stretched, and the EV interrupt
+                /// is raised, see `is_address_matched`. I2C1 and I2C3 can wake the device up from
+                /// Stop 0, 1 and 2, I2C2 only from Stop 0 and 1.
+                pub fn enable_wakeup(&mut self, address: u8) -> Result<(), ClockError> {
+                    timing(HSI16, self.freq)?;
+
+                    // the peripheral is disabled while its clock and timings change
+                    self.i2c.cr1.modify(|_, w| w.pe().clear_bit());
+                    // NOTE(unsafe) this peripheral owns its kernel clock selection, and HSI16 is
+                    // only ever turned on outside of `rcc`
+                    unsafe {
+                        let rcc = &*RCC::ptr();
+                        interrupt::free(|_| {
+                            rcc.cr.modify(|_, w| w.hsion().set_bit().hsikeron().set_bit())
+                        });
+                        while rcc.cr.read().hsirdy().bit_is_clear() {}
+                        rcc.ccipr.modify(|_, w| w.$i2cXsel().bits(SEL_HSI16));
+                    }
+                    self.set_timing(HSI16)?;
+
+                    // OA1 can only change while OA1EN is cleared
+                    self.i2c.oar1.write(|w| w.oa1en().clear_bit());
+                    self.i2c.oar1.write(|w| w.oa1().bits(u16::from(address & !1)));
+                    self.i2c.oar1.modify(|_, w| w.oa1en().set_bit());
+                    self.i2c.cr1.modify(|_, w| {
+                        w.wupen().set_bit().addrie().set_bit().pe().set_bit()
+                    });
+
+                    Ok(())
+                }
 
-                    let presc = u8(presc).unwrap();
-                    assert!(presc < 16);
-                    let scldel = u8(scldel).unwrap();
-                    assert!(scldel < 16);
-                    let sdadel = u8(sdadel).unwrap();
-                    assert!(sdadel < 16);
-                    let sclh = u8(sclh).unwrap();
-                    let scll = u8(scll).unwrap();
+                /// Stops waking the device up, and switches the kernel clock back to PCLK1
+                pub fn disable_wakeup(&mut self) {
+                    self.i2c.cr1.modify(|_, w| {
+                        w.wupen().clear_bit().addrie().clear_bit().pe().clear_bit()
+                    });
+                    self.i2c.oar1.write(|w| w.oa1en().clear_bit());
+
+                    // NOTE(unsafe) see `enable_wakeup`
+                    unsafe {
+                        (*RCC::ptr()).ccipr.modify(|_, w| w.$i2cXsel().bits(SEL_PCLK));
+                    }
+                    // NOTE(unwrap) the timings were computed by the constructor
+                    self.set_timing(self.clk).unwrap();
 
-                    // Configure for "fast mode" (400 KHz)
-                    i2c.timingr.write(|w| {
+                    self.i2c.cr1.modify(|_, w| w.pe().set_bit());
+                }
+
+                /// Returns `true` if the own address was matched, i.e. the device was woken up
+                pub fn is_address_matched(&self) -> bool {
+                    self.i2c.isr.read().addr().bit_is_set()
+                }
+
+                /// Releases the clock stretched after an address match
+                pub fn clear_address_match(&mut self) {
+                    self.i2c.icr.write(|w| w.addrcf().set_bit());
+                }
+
+                /// Configures the bus timings for the kernel clock `i2cclk`
+                fn set_timing(&mut self, i2cclk: u32) -> Result<(), ClockError> {
+                    let (presc, scll, sclh, sdadel, scldel) = timing(i2cclk, self.freq)?;
+
+                    self.i2c.timingr.write(|w| {
                         w.presc()
                             .bits(presc)
                             .scll()
@@ -162,22 +424,40 @@ macro_rules! hal {
                             .bits(scldel)
                     });
 
-                    // Enable the peripheral
-                    i2c.cr1.write(|w| w.pe().set_bit());
-
-                    I2c { i2c, pins }
+                    Ok(())
                 }
 
-                /// Releases the I2C peripheral and associated pins
-                pub fn free(self) -> ($I2CX, (SCL, SDA)) {
-                    (self.i2c, self.pins)
-                }
-            }
+                /// Runs `transfer` once the bus is free, restarting it after an arbitration
+                /// loss, as allowed by the retry policy
+                fn with_retries<F>(&mut self, mut transfer: F) -> Result<(), Error>
+                where
+                    F: FnMut(&mut Self) -> Result<(), Error>,
+                {
+                    let mut attempts = 0;
+                    loop {
+                        let mut polls = self.retry.busy_polls;
+                        while self.bus_is_busy() && polls > 0 {
+                            polls -= 1;
+                        }
+
+                        let result = if self.bus_is_busy() {
+                            Err(Error::Busy)
+                        } else {
+                            transfer(self)
+                        };
 
-            impl<PINS> Write for I2c<$I2CX, PINS> {
-                type Error = Error;
+                        match result {
+                            Err(Error::Arbitration) | Err(Error::Busy)
+                                if attempts < self.retry.retries =>
+                            {
+                                attempts += 1;
+                            }
+                            result => return result,
+                        }
+                    }
+                }
 
-                fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+                fn write_once(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
                     // TODO support transfers of more than 255 bytes
                     assert!(bytes.len() < 256 && bytes.len() > 0);
 
@@ -208,14 +488,8 @@ macro_rules! hal {
 
                     Ok(())
                 }
-            }
 
-            impl<PINS> Read for I2c<$I2CX, PINS> {
-                type Error = Error;
-
-                fn read(&mut self,
-                    addr: u8,
-                    buffer: &mut [u8],) -> Result<(), Error> {
+                fn read_once(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Error> {
                     self.i2c.cr2.write(|w| {
                         w.sadd()
                             .bits(addr as u16)
@@ -238,12 +512,8 @@ macro_rules! hal {
 
                     Ok(())
                 }
-            }
 
-            impl<PINS> WriteRead for I2c<$I2CX, PINS> {
-                type Error = Error;
-
-                fn write_read(
+                fn write_read_once(
                     &mut self,
                     addr: u8,
                     bytes: &[u8],
@@ -253,9 +523,6 @@ macro_rules! hal {
                     assert!(bytes.len() < 256 && bytes.len() > 0);
                     assert!(buffer.len() < 256 && buffer.len() > 0);
 
-                    // TODO do we have to explicitly wait here if the bus is busy (e.g. another
-                    // master is communicating)?
-
                     // START and prepare to send `bytes`
                     self.i2c.cr2.write(|w| {
                         w.sadd()
@@ -308,11 +575,186 @@ macro_rules! hal {
                     Ok(())
                 }
             }
+
+            impl<PINS> Write for I2c<$I2CX, PINS> {
+                type Error = Error;
+
+                fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+                    self.with_retries(|i2c| i2c.write_once(addr, bytes))
+                }
+            }
+
+            impl<PINS> Read for I2c<$I2CX, PINS> {
+                type Error = Error;
+
+                fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Error> {
+                    self.with_retries(|i2c| i2c.read_once(addr, buffer))
+                }
+            }
+
+            impl<PINS> WriteRead for I2c<$I2CX, PINS> {
+                type Error = Error;
+
+                fn write_read(
+                    &mut self,
+                    addr: u8,
+                    bytes: &[u8],
+                    buffer: &mut [u8],
+                ) -> Result<(), Error> {
+                    self.with_retries(|i2c| i2c.write_read_once(addr, bytes, buffer))
+                }
+            }
+
+            #[cfg(feature = "async")]
+            impl asynch::Interrupt for $I2CX {
+                fn on_interrupt() {
+                    // NOTE(unsafe) only clears interrupt enables set by a pending future
+                    unsafe {
+                        (*$I2CX::ptr()).cr1.modify(|_, w| {
+                            w.txie()
+                                .clear_bit()
+                                .rxie()
+                                .clear_bit()
+                                .tcie()
+                                .clear_bit()
+                                .stopie()
+                                .clear_bit()
+                                .nackie()
+                                .clear_bit()
+                                .errie()
+                                .clear_bit()
+                        })
+                    }
+                    I2c::<$I2CX, ()>::waker().wake();
+                }
+            }
+
+            #[cfg(feature = "async")]
+            impl<PINS> I2c<$I2CX, PINS> {
+                fn waker() -> &'static AtomicWaker {
+                    static WAKER: AtomicWaker = AtomicWaker::new();
+                    &WAKER
+                }
+            }
+
+            #[cfg(feature = "async")]
+            impl<PINS> embedded_hal_1::i2c::ErrorType for I2c<$I2CX, PINS> {
+                type Error = Error;
+            }
+
+            #[cfg(feature = "async")]
+            impl<PINS> embedded_hal_async::i2c::I2c for I2c<$I2CX, PINS> {
+                /// Executes `operations` as one transaction
+                ///
+                /// Unlike the blocking traits, `addr` is the 7-bit address without the R/W bit.
+                /// Adjacent operations in the same direction are merged using the reload mode
+                /// of the peripheral, so their length is not limited to 255 bytes.
+                async fn transaction(
+                    &mut self,
+                    addr: u8,
+                    operations: &mut [Operation<'_>],
+                ) -> Result<(), Error> {
+                    let i2c = unsafe { &*$I2CX::ptr() };
+                    let waker = Self::waker();
+                    let addr = u16::from(addr) << 1;
+
+                    // a STOP sent by the blocking API is still flagged
+                    i2c.icr.write(|w| w.stopcf().set_bit());
+
+                    // direction of the last chunk and if it was sent in reload mode
+                    let mut last: Option<(bool, bool)> = None;
+
+                    for index in 0..operations.len() {
+                        let read = match operations[index] {
+                            Operation::Read(_) => true,
+                            Operation::Write(_) => false,
+                        };
+                        let continues = continues(operations, index, read);
+                        let len = match operations[index] {
+                            Operation::Read(ref buffer) => buffer.len(),
+                            Operation::Write(bytes) => bytes.len(),
+                        };
+
+                        let mut offset = 0;
+                        loop {
+                            if len == 0 && last.map_or(false, |(dir, _)| dir == read) {
+                                // merged into the previous operation
+                                break;
+                            }
+                            let reloading = last == Some((read, true));
+
+                            let chunk = core::cmp::min(len - offset, 255);
+                            let more = offset + chunk < len || continues;
+
+                            if reloading {
+                                wait_flag(i2c, waker, |isr| isr.tcr().bit_is_set()).await?;
+                                i2c.cr2.modify(|_, w| {
+                                    w.nbytes().bits(chunk as u8).reload().bit(more)
+                                });
+                            } else {
+                                if last.is_some() {
+                                    // the previous direction has been completed
+                                    wait_flag(i2c, waker, |isr| isr.tc().bit_is_set()).await?;
+                                }
+
+                                // (re)START and prepare the chunk
+                                i2c.cr2.write(|w| {
+                                    w.sadd()
+                                        .bits(addr)
+                                        .rd_wrn()
+                                        .bit(read)
+                                        .nbytes()
+                                        .bits(chunk as u8)
+                                        .reload()
+                                        .bit(more)
+                                        .start()
+                                        .set_bit()
+                                        .autoend()
+                                        .clear_bit()
+                                });
+                            }
+
+                            match operations[index] {
+                                Operation::Read(ref mut buffer) => {
+                                    for byte in buffer[offset..offset + chunk].iter_mut() {
+                                        wait_flag(i2c, waker, |isr| isr.rxne().bit_is_set()).await?;
+                                        *byte = i2c.rxdr.read().rxdata().bits();
+                                    }
+                                }
+                                Operation::Write(bytes) => {
+                                    for byte in &bytes[offset..offset + chunk] {
+                                        wait_flag(i2c, waker, |isr| isr.txis().bit_is_set()).await?;
+                                        i2c.txdr.write(|w| w.txdata().bits(*byte));
+                                    }
+                                }
+                            }
+
+                            last = Some((read, more));
+                            offset += chunk;
+                            if offset == len {
+                                break;
+                            }
+                        }
+                    }
+
+                    if last.is_some() {
+                        wait_flag(i2c, waker, |isr| isr.tc().bit_is_set()).await?;
+
+                        // STOP and wait for it to be sent
+                        i2c.cr2.modify(|_, w| w.stop().set_bit());
+                        wait_flag(i2c, waker, |isr| isr.stopf().bit_is_set()).await?;
+                        i2c.icr.write(|w| w.stopcf().set_bit());
+                    }
+
+                    Ok(())
+                }
+            }
         )+
     }
 }
 
 hal! {
-    I2C1: (i2c1, i2c1en, i2c1rst),
-    I2C2: (i2c2, i2c2en, i2c2rst),
+    I2C1: (i2c1, try_i2c1, i2c1en, i2c1rst, i2c1sel),
+    I2C2: (i2c2, try_i2c2, i2c2en, i2c2rst, i2c2sel),
+    I2C3: (i2c3, try_i2c3, i2c3en, i2c3rst, i2c3sel),
 }
@@ -0,0 +1,94 @@
+//! I2C bus shared between drivers
+//!
+//! Driver crates take the bus by value, e.g. as `Write + WriteRead`, so a single `I2c` can only
+//! be given to one of them. A `BusManager` owns the bus and hands out `I2cProxy`s instead, which
+//! implement the same blocking traits and can be given to as many drivers as there are devices
+//! on the bus:
+//!
+//! ``` ignore
+//! let bus = BusManager::new(i2c);
+//!
+//! let mut eeprom = Eeprom::new(bus.acquire(), 0xA0, AddressWidth::Two, 64, 32 * 1024);
+//! let mut sensor = Sensor::new(bus.acquire());
+//!
+//! eeprom.read(0x1000, &mut calibration)?;
+//! let sample = sensor.read()?;
+//! ```
+//!
+//! The transactions of the drivers don't interleave: each one borrows the bus until it is over.
+//! The manager isn't `Sync`, it stays in the context it was created in, e.g. `main`; drivers
+//! that need a `'static` bus can get their proxies from a manager made `'static` by the
+//! `singleton!` macro of `cortex-m`.
+
+use core::cell::RefCell;
+
+use crate::hal::blocking::i2c::{Read, Write, WriteRead};
+
+/// Owner of an I2C bus shared by several drivers
+pub struct BusManager<I2C> {
+    bus: RefCell<I2C>,
+}
+
+impl<I2C> BusManager<I2C> {
+    /// Takes ownership of the bus
+    pub fn new(i2c: I2C) -> Self {
+        BusManager {
+            bus: RefCell::new(i2c),
+        }
+    }
+
+    /// Returns a new handle to the bus, for one driver
+    pub fn acquire(&self) -> I2cProxy<'_, I2C> {
+        I2cProxy { bus: &self.bus }
+    }
+
+    /// Releases the bus, once the proxies are dropped
+    pub fn free(self) -> I2C {
+        self.bus.into_inner()
+    }
+}
+
+/// Handle to a shared I2C bus, see `BusManager::acquire`
+pub struct I2cProxy<'a, I2C> {
+    bus: &'a RefCell<I2C>,
+}
+
+impl<'a, I2C> I2cProxy<'a, I2C> {
+    /// Runs `f` on the bus, no other transaction can be in progress as the manager isn't `Sync`
+    fn with_bus<R>(&mut self, f: impl FnOnce(&mut I2C) -> R) -> R {
+        f(&mut self.bus.borrow_mut())
+    }
+}
+
+impl<'a, I2C> Write for I2cProxy<'a, I2C>
+where
+    I2C: Write,
+{
+    type Error = I2C::Error;
+
+    fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.with_bus(|bus| bus.write(addr, bytes))
+    }
+}
+
+impl<'a, I2C> Read for I2cProxy<'a, I2C>
+where
+    I2C: Read,
+{
+    type Error = I2C::Error;
+
+    fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.with_bus(|bus| bus.read(addr, buffer))
+    }
+}
+
+impl<'a, I2C> WriteRead for I2cProxy<'a, I2C>
+where
+    I2C: WriteRead,
+{
+    type Error = I2C::Error;
+
+    fn write_read(&mut self, addr: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.with_bus(|bus| bus.write_read(addr, bytes, buffer))
+    }
+}
@@ -0,0 +1,159 @@
+//! 24xx EEPROMs and FRAMs
+//!
+//! `Eeprom` splits writes on page boundaries, and polls the memory for the end of its internal
+//! write cycle (it doesn't acknowledge its address meanwhile), so arbitrary ranges can be read
+//! and written. FRAMs have no write cycle and no pages, they can be used with a page size equal
+//! to their capacity.
+//!
+//! ``` ignore
+//! // 24LC256: 32 KB, 64 byte pages, 2 address bytes
+//! let mut eeprom = Eeprom::new(i2c, 0xA0, AddressWidth::Two, 64, 32 * 1024);
+//! eeprom.write(0x1000, b"calibration")?;
+//! eeprom.read(0x1000, &mut buffer)?;
+//! ```
+
+use crate::hal::blocking::i2c::{Write, WriteRead};
+
+/// Largest number of bytes transferred at once by the I2C driver
+const MAX_TRANSFER: usize = 255;
+
+/// EEPROM error
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error<E> {
+    /// I2C bus error
+    I2c(E),
+    /// The range accessed exceeds the capacity of the memory
+    OutOfRange,
+    /// The memory didn't complete its write cycle in time
+    Timeout,
+}
+
+/// Width of the memory address sent before the data
+#[derive(Clone, Copy, PartialEq)]
+pub enum AddressWidth {
+    /// One byte, the following address bits (up to 3) select the block in the device address,
+    /// e.g. 24C04 to 24C16
+    One,
+    /// Two bytes, e.g. 24C32 to 24C512
+    Two,
+}
+
+/// An EEPROM or FRAM on an I2C bus
+pub struct Eeprom<I2C> {
+    i2c: I2C,
+    address: u8,
+    width: AddressWidth,
+    page_size: u32,
+    capacity: u32,
+    polls: u32,
+}
+
+impl<I2C, E> Eeprom<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Creates a driver for a memory of `capacity` bytes written in pages of `page_size` bytes
+    ///
+    /// `address` is passed to the bus as is, with the block select bits cleared; with `I2c`
+    /// this is the 8-bit address, e.g. `0xA0`.
+    pub fn new(i2c: I2C, address: u8, width: AddressWidth, page_size: u32, capacity: u32) -> Self {
+        assert!(page_size > 0);
+
+        Eeprom {
+            i2c,
+            address,
+            width,
+            page_size,
+            capacity,
+            polls: 10_000,
+        }
+    }
+
+    /// Sets the number of times the memory is polled at the end of a write, before giving up
+    /// with `Error::Timeout`
+    pub fn set_write_polls(&mut self, polls: u32) {
+        self.polls = polls;
+    }
+
+    /// Reads `buffer.len()` bytes starting at `offset`
+    pub fn read(&mut self, offset: u32, buffer: &mut [u8]) -> Result<(), Error<E>> {
+        self.check_range(offset, buffer.len())?;
+
+        let mut offset = offset;
+        for chunk in buffer.chunks_mut(MAX_TRANSFER) {
+            let (address, header, len) = self.header(offset);
+            self.i2c
+                .write_read(address, &header[..len], chunk)
+                .map_err(Error::I2c)?;
+            offset += chunk.len() as u32;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `data` starting at `offset`, and waits for the end of the write
+    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Error<E>> {
+        self.check_range(offset, data.len())?;
+
+        let mut offset = offset;
+        let mut data = data;
+        while !data.is_empty() {
+            // up to the end of the page, in one transfer
+            let room = self.page_size - offset % self.page_size;
+            let len = (room as usize).min(data.len()).min(MAX_TRANSFER - 2);
+
+            let (address, header, header_len) = self.header(offset);
+            let mut frame = [0; MAX_TRANSFER];
+            frame[..header_len].copy_from_slice(&header[..header_len]);
+            frame[header_len..header_len + len].copy_from_slice(&data[..len]);
+            self.i2c
+                .write(address, &frame[..header_len + len])
+                .map_err(Error::I2c)?;
+
+            self.wait(address, &header[..header_len])?;
+
+            offset += len as u32;
+            data = &data[len..];
+        }
+
+        Ok(())
+    }
+
+    /// Releases the I2C bus
+    pub fn free(self) -> I2C {
+        self.i2c
+    }
+
+    fn check_range(&self, offset: u32, len: usize) -> Result<(), Error<E>> {
+        if u64::from(offset) + len as u64 > u64::from(self.capacity) {
+            Err(Error::OutOfRange)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the device address and the memory address bytes for `offset`
+    fn header(&self, offset: u32) -> (u8, [u8; 2], usize) {
+        match self.width {
+            AddressWidth::One => {
+                let block = ((offset >> 8) & 0b111) as u8;
+                (self.address | (block << 1), [offset as u8, 0], 1)
+            }
+            AddressWidth::Two => (self.address, [(offset >> 8) as u8, offset as u8], 2),
+        }
+    }
+
+    /// Acknowledge polling: the memory doesn't acknowledge its address until the end of the
+    /// write cycle; the memory address is written again, which only sets its address pointer
+    fn wait(&mut self, address: u8, header: &[u8]) -> Result<(), Error<E>> {
+        for _ in 0..self.polls {
+            if self.i2c.write(address, header).is_ok() {
+                return Ok(());
+            }
+        }
+
+        Err(Error::Timeout)
+    }
+}
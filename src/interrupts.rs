@@ -0,0 +1,242 @@
+//! Interrupt numbers of the peripherals
+//!
+//! `HasInterrupt` maps the peripherals, and the drivers owning them, to their `Interrupt`, so
+//! that generic code can enable the right interrupt without naming it, which also spares the
+//! differences between the device crates (e.g. `DMA1_CH1` / `DMA1_CHANNEL1`).
+//!
+//! ``` ignore
+//! serial.listen(Event::Rxne);
+//! let (tx, rx) = serial.split();
+//!
+//! // `Rx::<USART2>::INTERRUPT` is `Interrupt::USART2`
+//! interrupts::unmask::<Rx<USART2>>(&mut cp.NVIC);
+//! ```
+
+use cortex_m::peripheral::NVIC;
+
+use crate::adc::Adc;
+use crate::dma::dma1;
+use crate::i2c::I2c;
+use crate::lptim::{LowPowerTimer, Ticker};
+use crate::pwr::Pwr;
+use crate::rng::Rng;
+use crate::serial::{Rx, Serial, Tx};
+use crate::spi::Spi;
+use crate::stm32::Interrupt;
+use crate::stm32::{
+    I2C1, I2C2, I2C3, LPTIM1, LPTIM2, RNG, SPI1, SPI2, SPI3, TIM15, TIM16, TIM2, TIM6, TIM7, TSC,
+    USART1, USART2, USART3,
+};
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+use crate::stm32::ADC;
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+use crate::stm32::{ADC1, ADC2};
+use crate::timer::Timer;
+
+/// Peripheral with an interrupt
+pub trait HasInterrupt {
+    /// The interrupt of the peripheral; for the I2Cs, the event interrupt
+    const INTERRUPT: Interrupt;
+}
+
+macro_rules! interrupts {
+    ($($PER:ty: $INTERRUPT:ident,)+) => {
+        $(
+            impl HasInterrupt for $PER {
+                const INTERRUPT: Interrupt = Interrupt::$INTERRUPT;
+            }
+        )+
+    }
+}
+
+interrupts! {
+    I2C1: I2C1_EV,
+    I2C2: I2C2_EV,
+    I2C3: I2C3_EV,
+    LPTIM1: LPTIM1,
+    LPTIM2: LPTIM2,
+    SPI1: SPI1,
+    SPI2: SPI2,
+    SPI3: SPI3,
+    TIM2: TIM2,
+    TIM7: TIM7,
+    TSC: TSC,
+    USART1: USART1,
+    USART2: USART2,
+    USART3: USART3,
+}
+
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x6"))]
+interrupts! {
+    dma1::C1: DMA1_CH1,
+    dma1::C2: DMA1_CH2,
+    dma1::C3: DMA1_CH3,
+    dma1::C4: DMA1_CH4,
+    dma1::C5: DMA1_CH5,
+    dma1::C6: DMA1_CH6,
+    dma1::C7: DMA1_CH7,
+    Pwr: PVD_PVM,
+    TIM6: TIM6_DACUNDER,
+    TIM15: TIM1_BRK_TIM15,
+    TIM16: TIM1_UP_TIM16,
+}
+
+#[cfg(any(feature = "stm32l4x3", feature = "stm32l4x5"))]
+interrupts! {
+    dma1::C1: DMA1_CHANNEL1,
+    dma1::C2: DMA1_CHANNEL2,
+    dma1::C3: DMA1_CHANNEL3,
+    dma1::C4: DMA1_CHANNEL4,
+    dma1::C5: DMA1_CHANNEL5,
+    dma1::C6: DMA1_CHANNEL6,
+    dma1::C7: DMA1_CHANNEL7,
+    Pwr: PVD,
+    TIM6: TIM6_DAC,
+    TIM15: TIM15,
+    TIM16: TIM16,
+}
+
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2"))]
+interrupts! {
+    ADC: ADC1,
+    RNG: RNG,
+}
+
+#[cfg(feature = "stm32l4x3")]
+interrupts! {
+    ADC: ADC1_2,
+    RNG: RNG,
+}
+
+#[cfg(feature = "stm32l4x5")]
+interrupts! {
+    ADC1: ADC1_2,
+    ADC2: ADC1_2,
+    RNG: RNG,
+}
+
+#[cfg(feature = "stm32l4x6")]
+interrupts! {
+    ADC1: ADC1_2,
+    ADC2: ADC1_2,
+    RNG: RNG_HASH,
+}
+
+impl<ADC> HasInterrupt for Adc<ADC>
+where
+    ADC: HasInterrupt,
+{
+    const INTERRUPT: Interrupt = ADC::INTERRUPT;
+}
+
+impl<I2C, PINS> HasInterrupt for I2c<I2C, PINS>
+where
+    I2C: HasInterrupt,
+{
+    const INTERRUPT: Interrupt = I2C::INTERRUPT;
+}
+
+impl<LPTIM, PINS> HasInterrupt for LowPowerTimer<LPTIM, PINS>
+where
+    LPTIM: HasInterrupt,
+{
+    const INTERRUPT: Interrupt = LPTIM::INTERRUPT;
+}
+
+impl<LPTIM> HasInterrupt for Ticker<LPTIM>
+where
+    LPTIM: HasInterrupt,
+{
+    const INTERRUPT: Interrupt = LPTIM::INTERRUPT;
+}
+
+impl HasInterrupt for Rng {
+    const INTERRUPT: Interrupt = RNG::INTERRUPT;
+}
+
+impl<USART> HasInterrupt for Rx<USART>
+where
+    USART: HasInterrupt,
+{
+    const INTERRUPT: Interrupt = USART::INTERRUPT;
+}
+
+impl<USART, PINS> HasInterrupt for Serial<USART, PINS>
+where
+    USART: HasInterrupt,
+{
+    const INTERRUPT: Interrupt = USART::INTERRUPT;
+}
+
+impl<SPI, PINS> HasInterrupt for Spi<SPI, PINS>
+where
+    SPI: HasInterrupt,
+{
+    const INTERRUPT: Interrupt = SPI::INTERRUPT;
+}
+
+impl<TIM> HasInterrupt for Timer<TIM>
+where
+    TIM: HasInterrupt,
+{
+    const INTERRUPT: Interrupt = TIM::INTERRUPT;
+}
+
+impl<USART> HasInterrupt for Tx<USART>
+where
+    USART: HasInterrupt,
+{
+    const INTERRUPT: Interrupt = USART::INTERRUPT;
+}
+
+/// Enables the interrupt of `P` in the NVIC
+pub fn unmask<P>(nvic: &mut NVIC)
+where
+    P: HasInterrupt,
+{
+    nvic.enable(P::INTERRUPT);
+}
+
+/// Disables the interrupt of `P` in the NVIC
+pub fn mask<P>(nvic: &mut NVIC)
+where
+    P: HasInterrupt,
+{
+    nvic.disable(P::INTERRUPT);
+}
+
+/// Sets the priority of the interrupt of `P`, only the 4 most significant bits are used
+///
+/// # Safety
+///
+/// Changing priorities can break priority-based critical sections, e.g. RTIC resources.
+pub unsafe fn set_priority<P>(nvic: &mut NVIC, priority: u8)
+where
+    P: HasInterrupt,
+{
+    nvic.set_priority(P::INTERRUPT, priority);
+}
+
+/// Sets the interrupt of `P` pending
+pub fn pend<P>()
+where
+    P: HasInterrupt,
+{
+    NVIC::pend(P::INTERRUPT);
+}
+
+/// Clears the pending state of the interrupt of `P`
+pub fn unpend<P>()
+where
+    P: HasInterrupt,
+{
+    NVIC::unpend(P::INTERRUPT);
+}
+
+/// Returns `true` if the interrupt of `P` is pending
+pub fn is_pending<P>() -> bool
+where
+    P: HasInterrupt,
+{
+    NVIC::is_pending(P::INTERRUPT)
+}
@@ -0,0 +1,161 @@
+//! Infrared interface
+//!
+//! The IRTIM combines the channel 1 outputs of two timers into the IR_OUT pin: TIM16 generates
+//! the carrier, which is only output while the envelope generated by the second timer is active.
+//! The envelope timer is TIM17 on the STM32L4x5/x6, and TIM15 on the other parts.
+//!
+//! ``` ignore
+//! let ir_out = gpiob.pb9.into_af1(&mut gpiob.moder, &mut gpiob.afrh);
+//! let mut ir = IrTim::new(dp.TIM16, dp.TIM17, ir_out, 38.khz(), 33, clocks, &mut rcc.apb2);
+//!
+//! // NEC leading burst
+//! ir.on();
+//! delay.delay_us(9_000_u32);
+//! ir.off();
+//! delay.delay_us(4_500_u32);
+//! ```
+
+use cast::u16;
+
+use crate::stm32::TIM16;
+#[cfg(not(any(feature = "stm32l4x5", feature = "stm32l4x6")))]
+use crate::stm32::TIM15 as ENVELOPE;
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+use crate::stm32::TIM17 as ENVELOPE;
+
+use crate::gpio::gpioa::PA13;
+use crate::gpio::gpiob::PB9;
+use crate::gpio::{Alternate, AF1};
+use crate::rcc::{Clocks, APB2};
+use crate::time::Hertz;
+
+/// Timer generating the envelope, TIM17 on the STM32L4x5/x6 and TIM15 on the other parts
+pub type Envelope = ENVELOPE;
+
+// FIXME these should be "closed" traits
+/// IR_OUT pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be configured in the alternate function carrying IR_OUT
+pub unsafe trait IrOutPin {}
+
+unsafe impl<MODE> IrOutPin for PA13<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> IrOutPin for PB9<Alternate<AF1, MODE>> {}
+
+/// OC1M: PWM mode 1
+const OC1M_PWM1: u32 = 0b110 << 4;
+/// OC1M: forced inactive
+const OC1M_INACTIVE: u32 = 0b100 << 4;
+/// OC1M: forced active
+const OC1M_ACTIVE: u32 = 0b101 << 4;
+/// OC1PE
+const OC1PE: u32 = 1 << 3;
+/// CC1E
+const CC1E: u32 = 1 << 0;
+/// MOE
+const MOE: u32 = 1 << 15;
+
+/// Infrared transmitter
+pub struct IrTim<PIN> {
+    carrier: TIM16,
+    envelope: Envelope,
+    pin: PIN,
+    clk: u32,
+}
+
+impl<PIN> IrTim<PIN>
+where
+    PIN: IrOutPin,
+{
+    /// Configures a carrier of `frequency` with a duty cycle of `duty` percent, initially off
+    ///
+    /// `frequency` must be at most half of the timer clock, see `set_carrier`.
+    pub fn new<F>(
+        carrier: TIM16,
+        envelope: Envelope,
+        pin: PIN,
+        frequency: F,
+        duty: u8,
+        clocks: Clocks,
+        apb: &mut APB2,
+    ) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        apb.enr().modify(|_, w| w.tim16en().set_bit());
+        apb.rstr().modify(|_, w| w.tim16rst().set_bit());
+        apb.rstr().modify(|_, w| w.tim16rst().clear_bit());
+        #[cfg(not(any(feature = "stm32l4x5", feature = "stm32l4x6")))]
+        {
+            apb.enr().modify(|_, w| w.tim15en().set_bit());
+            apb.rstr().modify(|_, w| w.tim15rst().set_bit());
+            apb.rstr().modify(|_, w| w.tim15rst().clear_bit());
+        }
+        #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+        {
+            apb.enr().modify(|_, w| w.tim17en().set_bit());
+            apb.rstr().modify(|_, w| w.tim17rst().set_bit());
+            apb.rstr().modify(|_, w| w.tim17rst().clear_bit());
+        }
+
+        let mut ir = IrTim {
+            carrier,
+            envelope,
+            pin,
+            clk: clocks.timclk2().0,
+        };
+
+        // the envelope stays forced inactive until `on`
+        ir.envelope.ccmr1_output.write(|w| unsafe { w.bits(OC1M_INACTIVE) });
+        ir.envelope.ccer.write(|w| unsafe { w.bits(CC1E) });
+        ir.envelope.bdtr.write(|w| unsafe { w.bits(MOE) });
+
+        ir.carrier.ccmr1_output.write(|w| unsafe { w.bits(OC1M_PWM1 | OC1PE) });
+        ir.carrier.ccer.write(|w| unsafe { w.bits(CC1E) });
+        ir.carrier.bdtr.write(|w| unsafe { w.bits(MOE) });
+        ir.set_carrier(frequency, duty);
+        ir.carrier.cr1.modify(|_, w| w.cen().set_bit());
+
+        ir
+    }
+
+    /// Changes the carrier to `frequency` with a duty cycle of `duty` percent
+    ///
+    /// `frequency` must be at most half of the timer clock (`Clocks::timclk2`).
+    pub fn set_carrier<F>(&mut self, frequency: F, duty: u8)
+    where
+        F: Into<Hertz>,
+    {
+        let frequency = frequency.into().0;
+        assert!(duty <= 100);
+        assert!(frequency > 0 && frequency <= self.clk / 2);
+
+        let ticks = self.clk / frequency;
+        let psc = (ticks - 1) / (1 << 16);
+        let arr = ticks / (psc + 1);
+        let ccr = arr * u32::from(duty) / 100;
+
+        self.carrier.psc.write(|w| unsafe { w.bits(u32::from(u16(psc).unwrap())) });
+        self.carrier.arr.write(|w| unsafe { w.bits(arr - 1) });
+        self.carrier.ccr1.write(|w| unsafe { w.bits(ccr) });
+        self.carrier.egr.write(|w| w.ug().set_bit());
+    }
+
+    /// Outputs the carrier on IR_OUT
+    pub fn on(&mut self) {
+        self.envelope.ccmr1_output.write(|w| unsafe { w.bits(OC1M_ACTIVE) });
+    }
+
+    /// Stops outputting the carrier
+    pub fn off(&mut self) {
+        self.envelope.ccmr1_output.write(|w| unsafe { w.bits(OC1M_INACTIVE) });
+    }
+
+    /// Releases the timers and the pin
+    pub fn free(self) -> (TIM16, Envelope, PIN) {
+        self.carrier.cr1.modify(|_, w| w.cen().clear_bit());
+        self.envelope.ccmr1_output.write(|w| unsafe { w.bits(OC1M_INACTIVE) });
+        (self.carrier, self.envelope, self.pin)
+    }
+}
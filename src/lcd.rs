@@ -0,0 +1,304 @@
+//! Segment LCD controller, on the STM32L4x3/x6
+//!
+//! The controller drives up to 8 common and 44 segment lines from a display memory holding
+//! one bit per pixel, i.e. per (common, segment) pair. Writes to the display memory only show
+//! up once `update` has been called, so that frames are never displayed half written.
+//!
+//! The LCD is clocked by the RTC clock, which has to be configured beforehand, e.g. by
+//! `Rtc::rtc`; the frame rate is `RTCCLK / (2^prescaler * (16 + divider)) * duty`.
+//!
+//! ``` ignore
+//! let mut lcd = Lcd::new(dp.LCD, Config::default(), &mut rcc.apb1r1);
+//! lcd.set_pixel(0, 4, true);
+//! lcd.update();
+//! ```
+
+use core::ptr;
+
+use crate::rcc::APB1R1;
+use crate::stm32::LCD;
+
+/// Offset of the display memory
+const RAM: usize = 0x14;
+/// SR: update display request
+const UDR: u32 = 1 << 2;
+/// Number of common lines
+const COMMONS: u8 = 8;
+/// Number of segment lines
+const SEGMENTS: u8 = 44;
+
+/// Duty ratio, i.e. the number of common lines used
+#[derive(Clone, Copy)]
+pub enum Duty {
+    /// COM0 only
+    Static,
+    /// COM0 to COM1
+    Half,
+    /// COM0 to COM2
+    Third,
+    /// COM0 to COM3
+    Quarter,
+    /// COM0 to COM7, which takes over 4 segment lines
+    Eighth,
+}
+
+/// Bias, i.e. the voltage levels of the waveforms
+#[derive(Clone, Copy)]
+pub enum Bias {
+    /// 1/4
+    Quarter,
+    /// 1/2
+    Half,
+    /// 1/3
+    Third,
+}
+
+/// Pixels blinking
+#[derive(Clone, Copy)]
+pub enum Blink {
+    /// No blinking
+    Disabled,
+    /// SEG0 on COM0
+    Seg0Com0,
+    /// SEG0 on all the common lines
+    Seg0,
+    /// All the pixels
+    All,
+}
+
+/// Blinking frequency, as a division of the LCD clock
+#[derive(Clone, Copy)]
+pub enum BlinkFrequency {
+    /// LCDCLK / 8
+    Div8,
+    /// LCDCLK / 16
+    Div16,
+    /// LCDCLK / 32
+    Div32,
+    /// LCDCLK / 64
+    Div64,
+    /// LCDCLK / 128
+    Div128,
+    /// LCDCLK / 256
+    Div256,
+    /// LCDCLK / 512
+    Div512,
+    /// LCDCLK / 1024
+    Div1024,
+}
+
+/// LCD configuration
+#[derive(Clone, Copy)]
+pub struct Config {
+    /// Duty ratio
+    pub duty: Duty,
+    /// Bias
+    pub bias: Bias,
+    /// Contrast, i.e. the maximum voltage from 0 (2.60 V) to 7 (3.51 V)
+    pub contrast: u8,
+    /// Clock prescaler exponent (0 to 15)
+    pub prescaler: u8,
+    /// Clock divider (0 to 15), dividing by `16 + divider`
+    pub divider: u8,
+    /// Pulse on duration (0 to 7), in prescaled clock periods; longer pulses drive the glass
+    /// harder at the cost of a higher consumption
+    pub pulse_on: u8,
+    /// Dead time (0 to 7), in phase periods, between frames
+    pub dead_time: u8,
+    /// Drives the pins with low resistance during the pulse on duration
+    pub high_drive: bool,
+    /// Takes the LCD voltage from the VLCD pin instead of the internal step-up converter
+    pub external_voltage: bool,
+}
+
+impl Default for Config {
+    /// 1/4 duty and 1/3 bias, at about 60 frames per second with a 32.768 kHz RTC clock
+    fn default() -> Self {
+        Config {
+            duty: Duty::Quarter,
+            bias: Bias::Third,
+            contrast: 4,
+            prescaler: 3,
+            divider: 1,
+            pulse_on: 2,
+            dead_time: 0,
+            high_drive: false,
+            external_voltage: false,
+        }
+    }
+}
+
+/// Segment LCD
+pub struct Lcd {
+    lcd: LCD,
+}
+
+impl Lcd {
+    /// Configures and enables the LCD, with a blank display
+    pub fn new(lcd: LCD, config: Config, apb: &mut APB1R1) -> Self {
+        assert!(config.contrast < 8 && config.pulse_on < 8 && config.dead_time < 8);
+        assert!(config.prescaler < 16 && config.divider < 16);
+
+        apb.enr().modify(|_, w| w.lcden().set_bit());
+        apb.rstr().modify(|_, w| w.lcdrst().set_bit());
+        apb.rstr().modify(|_, w| w.lcdrst().clear_bit());
+
+        let mut lcd = Lcd { lcd };
+
+        lcd.modify_fcr(|w| unsafe {
+            w.ps()
+                .bits(config.prescaler)
+                .div()
+                .bits(config.divider)
+                .cc()
+                .bits(config.contrast)
+                .pon()
+                .bits(config.pulse_on)
+                .dead()
+                .bits(config.dead_time)
+                .hd()
+                .bit(config.high_drive)
+        });
+
+        let duty = match config.duty {
+            Duty::Static => 0b000,
+            Duty::Half => 0b001,
+            Duty::Third => 0b010,
+            Duty::Quarter => 0b011,
+            Duty::Eighth => 0b100,
+        };
+        let bias = match config.bias {
+            Bias::Quarter => 0b00,
+            Bias::Half => 0b01,
+            Bias::Third => 0b10,
+        };
+        lcd.lcd.cr.write(|w| unsafe {
+            w.duty()
+                .bits(duty)
+                .bias()
+                .bits(bias)
+                .vsel()
+                .bit(config.external_voltage)
+        });
+
+        lcd.clear();
+
+        lcd.lcd.cr.modify(|_, w| w.lcden().set_bit());
+        while lcd.lcd.sr.read().ens().bit_is_clear() {}
+        while lcd.lcd.sr.read().rdy().bit_is_clear() {}
+        lcd.update();
+
+        lcd
+    }
+
+    /// Sets the contrast, from 0 (2.60 V) to 7 (3.51 V)
+    pub fn set_contrast(&mut self, contrast: u8) {
+        assert!(contrast < 8);
+        self.modify_fcr(|w| unsafe { w.cc().bits(contrast) });
+    }
+
+    /// Selects the pixels that blink, and how fast
+    pub fn set_blink(&mut self, blink: Blink, frequency: BlinkFrequency) {
+        let blink = match blink {
+            Blink::Disabled => 0b00,
+            Blink::Seg0Com0 => 0b01,
+            Blink::Seg0 => 0b10,
+            Blink::All => 0b11,
+        };
+        let blinkf = match frequency {
+            BlinkFrequency::Div8 => 0b000,
+            BlinkFrequency::Div16 => 0b001,
+            BlinkFrequency::Div32 => 0b010,
+            BlinkFrequency::Div64 => 0b011,
+            BlinkFrequency::Div128 => 0b100,
+            BlinkFrequency::Div256 => 0b101,
+            BlinkFrequency::Div512 => 0b110,
+            BlinkFrequency::Div1024 => 0b111,
+        };
+        self.modify_fcr(|w| unsafe { w.blink().bits(blink).blinkf().bits(blinkf) });
+    }
+
+    /// Turns the pixel at `common`, `segment` on or off
+    pub fn set_pixel(&mut self, common: u8, segment: u8, on: bool) {
+        assert!(common < COMMONS && segment < SEGMENTS);
+
+        self.wait_ram();
+        let reg = Self::ram(common, segment / 32);
+        let bit = 1 << (segment % 32);
+        // NOTE(unsafe) the display memory is owned by `self`; the upper words are missing from
+        // the SVD, so the memory is accessed by address
+        unsafe {
+            let word = ptr::read_volatile(reg);
+            ptr::write_volatile(reg, if on { word | bit } else { word & !bit });
+        }
+    }
+
+    /// Sets all the segments of `common` at once, bit `i` of `segments` being the segment `i`
+    pub fn write_common(&mut self, common: u8, segments: u64) {
+        assert!(common < COMMONS);
+
+        self.wait_ram();
+        // NOTE(unsafe) see `set_pixel`
+        unsafe {
+            ptr::write_volatile(Self::ram(common, 0), segments as u32);
+            ptr::write_volatile(Self::ram(common, 1), (segments >> 32) as u32 & 0xfff);
+        }
+    }
+
+    /// Sets the pixels listed in `map` from the bits of `value`, bit `i` driving the pixel
+    /// `map[i]` given as (common, segment)
+    ///
+    /// This maps the segments of a glyph (e.g. the 14 segments of a character) to the wiring of
+    /// the glass.
+    pub fn write_mapped(&mut self, map: &[(u8, u8)], value: u32) {
+        assert!(map.len() <= 32);
+
+        for (i, &(common, segment)) in map.iter().enumerate() {
+            self.set_pixel(common, segment, value & (1 << i) != 0);
+        }
+    }
+
+    /// Turns all the pixels off
+    pub fn clear(&mut self) {
+        for common in 0..COMMONS {
+            self.write_common(common, 0);
+        }
+    }
+
+    /// Displays the content of the display memory from the next frame on, and waits for it
+    pub fn update(&mut self) {
+        self.lcd.clr.write(|w| w.uddc().set_bit());
+        self.lcd.sr.write(|w| w.udr().set_bit());
+
+        // the request is only served while the LCD is enabled
+        if self.lcd.cr.read().lcden().bit_is_set() {
+            while self.lcd.sr.read().udd().bit_is_clear() {}
+            self.lcd.clr.write(|w| w.uddc().set_bit());
+        }
+    }
+
+    /// Disables the LCD and releases the peripheral
+    pub fn free(self) -> LCD {
+        self.lcd.cr.modify(|_, w| w.lcden().clear_bit());
+        self.lcd
+    }
+
+    /// Modifies FCR, which has to be synchronized with the LCD clock between writes
+    fn modify_fcr<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut crate::stm32::lcd::fcr::W) -> &mut crate::stm32::lcd::fcr::W,
+    {
+        while self.lcd.sr.read().fcrsf().bit_is_clear() {}
+        self.lcd.fcr.modify(|_, w| f(w));
+    }
+
+    /// Waits for the display memory to be writable, i.e. no update is pending
+    fn wait_ram(&self) {
+        // UDR is missing from the read proxy of SR
+        while self.lcd.sr.read().bits() & UDR != 0 {}
+    }
+
+    fn ram(common: u8, word: u8) -> *mut u32 {
+        (LCD::ptr() as usize + RAM + 8 * common as usize + 4 * word as usize) as *mut u32
+    }
+}
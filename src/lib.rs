@@ -14,11 +14,18 @@
     feature = "stm32l4x1",
     feature = "stm32l4x2",
     feature = "stm32l4x3",
-    feature = "stm32l4x4",
     feature = "stm32l4x5",
     feature = "stm32l4x6",
     )))]
-compile_error!("This crate requires one of the following features enabled: stm32l4x1, stm32l4x2, stm32l4x3, stm32l4x4, stm32l4x5 or stm32l4x6");
+compile_error!("This crate requires one of the following features enabled: stm32l4x1, stm32l4x2, stm32l4x3, stm32l4x5 or stm32l4x6");
+
+#[cfg(any(
+    all(feature = "stm32l4x1", any(feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6")),
+    all(feature = "stm32l4x2", any(feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6")),
+    all(feature = "stm32l4x3", any(feature = "stm32l4x5", feature = "stm32l4x6")),
+    all(feature = "stm32l4x5", feature = "stm32l4x6"),
+    ))]
+compile_error!("Only one of the stm32l4x1, stm32l4x2, stm32l4x3, stm32l4x5 or stm32l4x6 features can be enabled");
 
 pub use embedded_hal as hal;
 
@@ -42,6 +49,21 @@ pub use stm32l4::stm32l4x6 as stm32;
 #[cfg(feature = "rt")]
 pub use self::stm32::interrupt;
 
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub use crate::error::Error;
+
+/// Driver trace point, logged with `defmt::trace!` when the `defmt` feature is enabled
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "defmt")]
+        {
+            defmt::trace!($($arg)+);
+        }
+    };
+}
+
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod error;
 #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
 pub mod dma;
 #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
@@ -65,11 +87,62 @@ pub mod spi;
 #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
 pub mod rtc;
 #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod selftest;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod fault;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
 pub mod pwr;
 pub mod datetime;
+pub mod ram;
 #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
 pub mod tsc;
 #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
 pub mod i2c;
 #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
 pub mod rng;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod pwm;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod pwm_input;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod soft_pwm;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod adc;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod dac;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+pub mod crs;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod syscfg;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod irtim;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod lptim;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod exti;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod debounce;
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod otg_fs;
+#[cfg(any(feature = "stm32l4x3", feature = "stm32l4x6"))]
+pub mod lcd;
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod fmc;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod capture;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod timestamp;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod singleton;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub mod interrupts;
+#[cfg(feature = "async")]
+pub mod asynch;
+#[cfg(feature = "panic-hook")]
+pub mod panic;
+#[cfg(feature = "ws2812")]
+pub mod ws2812;
+#[cfg(feature = "rtc-trim")]
+pub mod rtc_trim;
+#[cfg(any(feature = "disco-l476", feature = "nucleo-l432kc"))]
+pub mod board;
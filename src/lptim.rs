@@ -0,0 +1,491 @@
+//! Low-power timers
+//!
+//! The LPTIMs keep counting while the core sleeps, in Stop mode when their kernel clock is LSE,
+//! LSI or HSI16 (LPTIM1 down to Stop 2, LPTIM2 down to Stop 1); the oscillator has to be enabled
+//! beforehand, e.g. with `CFGR::lsi`. Two modes count external events:
+//!
+//! - pulse counting, every valid edge of IN1 increments the counter, e.g. for flow meters. The
+//!   counter is clocked by the pulses, the kernel clock only synchronizes the register accesses
+//!   and runs the optional filter
+//! - encoder mode (LPTIM1 only), the counter goes up or down following the quadrature signals on
+//!   IN1 and IN2, which are sampled with the kernel clock; it has to be at least 4 times faster
+//!   than the signals
+//!
+//! The counter is 16-bit and wraps at the reload value, which raises `Event::Reload`.
+//!
+//! ``` ignore
+//! let in1 = gpiob.pb5.into_af1(&mut gpiob.moder, &mut gpiob.afrl);
+//! let in2 = gpiob.pb7.into_af1(&mut gpiob.moder, &mut gpiob.afrl);
+//! let encoder = LowPowerTimer::lptim1_encoder(dp.LPTIM1, (in1, in2), ClockSource::Lse, Edge::Both, &mut rcc.apb1r1);
+//!
+//! pwr.guard().enter_stop(StopMode::Stop2, &mut cp.SCB)?;
+//! let position = encoder.count();
+//! ```
+//!
+//! A `Ticker` counts its kernel clock instead, and raises `Event::Reload` periodically: clocked by
+//! LSE, it is a scheduler tick and a monotonic clock that keeps running in Stop mode, without the
+//! SysTick; only a LPTIM1 ticker keeps running in Stop 2, a LPTIM2 one stops there.
+//!
+//! ``` ignore
+//! // 1024 Hz counter, ticking every 32 counts (31.25 ms)
+//! let mut ticker = Ticker::lptim1(dp.LPTIM1, ClockSource::Lse, 5, 32, &mut rcc.apb1r1);
+//!
+//! #[interrupt]
+//! fn LPTIM1() {
+//!     if ticker.on_reload() {
+//!         let now = ticker.now();
+//!         // schedule
+//!     }
+//! }
+//! ```
+//!
+//! With the `monotonic` feature, `Ticker::into_monotonic` turns a ticker into a RTIC monotonic.
+
+#[cfg(feature = "monotonic")]
+use fugit::{TimerDurationU64, TimerInstantU64};
+#[cfg(feature = "monotonic")]
+use rtic_monotonic::Monotonic;
+
+use crate::gpio::gpiob::{PB1, PB5, PB7};
+use crate::gpio::gpioc::{PC0, PC2};
+use crate::gpio::{Alternate, AF1, AF14};
+use crate::rcc::{APB1R1, APB1R2};
+use crate::stm32::{LPTIM1, LPTIM2, RCC};
+
+/// Kernel clock of a LPTIM
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockSource {
+    /// PCLK, stopped in Stop mode
+    Pclk = 0b00,
+    /// LSI
+    Lsi = 0b01,
+    /// HSI16
+    Hsi16 = 0b10,
+    /// LSE
+    Lse = 0b11,
+}
+
+/// Counted edges of the inputs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    /// Rising edges
+    Rising = 0b00,
+    /// Falling edges
+    Falling = 0b01,
+    /// Both edges, 4 counts per quadrature cycle in encoder mode
+    Both = 0b10,
+}
+
+/// Digital filter of the inputs, in kernel clock periods an input has to be stable
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    /// No filter
+    None = 0b00,
+    /// 2 kernel clock periods
+    Clocks2 = 0b01,
+    /// 4 kernel clock periods
+    Clocks4 = 0b10,
+    /// 8 kernel clock periods
+    Clocks8 = 0b11,
+}
+
+/// Interrupt events
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// The counter reached the reload value
+    Reload,
+    /// The counter matched the compare value
+    Compare,
+    /// Encoder mode: the counter direction changed to up
+    Up,
+    /// Encoder mode: the counter direction changed to down
+    Down,
+}
+
+impl Event {
+    /// Bit of the event in IER, ISR and ICR
+    fn bit(self) -> u32 {
+        match self {
+            Event::Compare => 1 << 0,
+            Event::Reload => 1 << 1,
+            Event::Up => 1 << 5,
+            Event::Down => 1 << 6,
+        }
+    }
+}
+
+// FIXME these should be "closed" traits
+/// IN1 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the IN1 input of `LPTIM`
+pub unsafe trait In1Pin<LPTIM> {}
+
+/// IN2 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the IN2 input of `LPTIM`
+pub unsafe trait In2Pin<LPTIM> {}
+
+unsafe impl<MODE> In1Pin<LPTIM1> for PB5<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> In1Pin<LPTIM1> for PC0<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> In2Pin<LPTIM1> for PB7<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> In2Pin<LPTIM1> for PC2<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> In1Pin<LPTIM2> for PB1<Alternate<AF14, MODE>> {}
+unsafe impl<MODE> In1Pin<LPTIM2> for PC0<Alternate<AF14, MODE>> {}
+
+/// RTIC monotonic on a `Ticker` counting at `FREQ` Hz, see `Ticker::into_monotonic`
+#[cfg(feature = "monotonic")]
+pub struct MonoTicker<LPTIM, const FREQ: u32> {
+    ticker: Ticker<LPTIM>,
+}
+
+/// Low-power timer counting external events
+pub struct LowPowerTimer<LPTIM, PINS> {
+    lptim: LPTIM,
+    pins: PINS,
+}
+
+/// Low-power timer counting its kernel clock, raising `Event::Reload` periodically
+pub struct Ticker<LPTIM> {
+    lptim: LPTIM,
+    period: u32,
+    periods: u32,
+}
+
+impl<IN1, IN2> LowPowerTimer<LPTIM1, (IN1, IN2)>
+where
+    IN1: In1Pin<LPTIM1>,
+    IN2: In2Pin<LPTIM1>,
+{
+    /// Counts the quadrature signals on IN1 and IN2 up and down, starting at 0
+    ///
+    /// A counter going down from 0 wraps to the reload value, 0xFFFF by default.
+    pub fn lptim1_encoder(
+        lptim: LPTIM1,
+        pins: (IN1, IN2),
+        clock: ClockSource,
+        edge: Edge,
+        apb: &mut APB1R1,
+    ) -> Self {
+        apb.enr().modify(|_, w| w.lptim1en().set_bit());
+        apb.rstr().modify(|_, w| w.lptim1rst().set_bit());
+        apb.rstr().modify(|_, w| w.lptim1rst().clear_bit());
+        // NOTE(unsafe) this driver owns the kernel clock selection of the LPTIM
+        unsafe {
+            (*RCC::ptr()).ccipr.modify(|_, w| w.lptim1sel().bits(clock as u8));
+        }
+
+        lptim.cfgr.write(|w| unsafe {
+            w.enc().set_bit().cksel().clear_bit().ckpol().bits(edge as u8)
+        });
+
+        let mut timer = LowPowerTimer { lptim, pins };
+        timer.start();
+        timer
+    }
+}
+
+macro_rules! hal {
+    ($($LPTIMX:ident: ($lptimX:ident, $lptimX_counter:ident, $APB:ident, $lptimXen:ident, $lptimXrst:ident, $lptimXsel:ident),)+) => {
+        $(
+            impl<IN1> LowPowerTimer<$LPTIMX, IN1>
+            where
+                IN1: In1Pin<$LPTIMX>,
+            {
+                /// Counts the `edge`s of IN1, starting at 0
+                pub fn $lptimX_counter(
+                    lptim: $LPTIMX,
+                    pin: IN1,
+                    clock: ClockSource,
+                    edge: Edge,
+                    filter: Filter,
+                    apb: &mut $APB,
+                ) -> Self {
+                    apb.enr().modify(|_, w| w.$lptimXen().set_bit());
+                    apb.rstr().modify(|_, w| w.$lptimXrst().set_bit());
+                    apb.rstr().modify(|_, w| w.$lptimXrst().clear_bit());
+                    // NOTE(unsafe) this driver owns the kernel clock selection of the LPTIM
+                    unsafe {
+                        (*RCC::ptr()).ccipr.modify(|_, w| w.$lptimXsel().bits(clock as u8));
+                    }
+
+                    // the counter is incremented by IN1, the kernel clock is internal
+                    lptim.cfgr.write(|w| unsafe {
+                        w.countmode()
+                            .set_bit()
+                            .cksel()
+                            .clear_bit()
+                            .ckpol()
+                            .bits(edge as u8)
+                            .ckflt()
+                            .bits(filter as u8)
+                    });
+
+                    let mut timer = LowPowerTimer { lptim, pins: pin };
+                    timer.start();
+                    timer
+                }
+            }
+
+            impl Ticker<$LPTIMX> {
+                /// Counts the kernel clock divided by 2^`prescaler` (up to 128), and raises
+                /// `Event::Reload` every `period` counts
+                ///
+                /// The event is enabled, it only has to be unmasked in the NVIC. Panics if
+                /// `prescaler` is greater than 7 or `period` is less than 2, as the reload value
+                /// has to be greater than the compare value.
+                pub fn $lptimX(
+                    lptim: $LPTIMX,
+                    clock: ClockSource,
+                    prescaler: u8,
+                    period: u16,
+                    apb: &mut $APB,
+                ) -> Self {
+                    assert!(prescaler <= 7 && period >= 2);
+
+                    apb.enr().modify(|_, w| w.$lptimXen().set_bit());
+                    apb.rstr().modify(|_, w| w.$lptimXrst().set_bit());
+                    apb.rstr().modify(|_, w| w.$lptimXrst().clear_bit());
+                    // NOTE(unsafe) this driver owns the kernel clock selection of the LPTIM
+                    unsafe {
+                        (*RCC::ptr()).ccipr.modify(|_, w| w.$lptimXsel().bits(clock as u8));
+                    }
+
+                    // CFGR and IER can only be written while the timer is disabled
+                    lptim.cfgr.write(|w| unsafe { w.presc().bits(prescaler) });
+                    lptim.ier.write(|w| unsafe { w.bits(Event::Reload.bit()) });
+
+                    lptim.cr.write(|w| w.enable().set_bit());
+                    lptim.arr.write(|w| unsafe { w.arr().bits(period - 1) });
+                    while lptim.isr.read().arrok().bit_is_clear() {}
+                    lptim.icr.write(|w| w.arrokcf().set_bit());
+                    lptim.cr.write(|w| w.enable().set_bit().cntstrt().set_bit());
+
+                    Ticker {
+                        lptim,
+                        period: u32::from(period),
+                        periods: 0,
+                    }
+                }
+
+                /// Acknowledges a pending `Event::Reload`, to be called from the interrupt
+                /// handler; returns `true` if a period elapsed
+                pub fn on_reload(&mut self) -> bool {
+                    if self.lptim.isr.read().arrm().bit_is_set() {
+                        self.lptim.icr.write(|w| w.arrmcf().set_bit());
+                        self.periods = self.periods.wrapping_add(1);
+                        true
+                    } else {
+                        false
+                    }
+                }
+
+                /// Returns the number of counts since the start of the ticker
+                ///
+                /// The elapsed periods are counted by `on_reload`, a period that elapsed while
+                /// its interrupt is pending is accounted for.
+                pub fn now(&self) -> u64 {
+                    let periods = u64::from(self.periods);
+                    let period = u64::from(self.period);
+
+                    let count = u64::from(self.count());
+                    if self.lptim.isr.read().arrm().bit_is_clear() {
+                        return periods * period + count;
+                    }
+
+                    // the counter matched the reload value, `count` may have been read before
+                    // or after it wrapped
+                    let count = u64::from(self.count());
+                    if count == period - 1 {
+                        periods * period + count
+                    } else {
+                        (periods + 1) * period + count
+                    }
+                }
+
+                /// Returns the number of counts of a period
+                pub fn period(&self) -> u32 {
+                    self.period
+                }
+
+                /// Stops the timer and releases the peripheral
+                pub fn free(self) -> $LPTIMX {
+                    self.lptim.cr.write(|w| w.enable().clear_bit());
+                    self.lptim
+                }
+
+                /// Turns the ticker into a RTIC monotonic, `FREQ` has to be its counting
+                /// frequency, e.g. 1024 Hz for LSE with a prescaler of 5
+                ///
+                /// The counter restarts from 0. The reload event extends the counter to 64
+                /// bits, so the interrupt stays enabled when no task is scheduled; a long
+                /// period keeps it rare.
+                #[cfg(feature = "monotonic")]
+                pub fn into_monotonic<const FREQ: u32>(self) -> MonoTicker<$LPTIMX, FREQ> {
+                    let reload = self.lptim.arr.read().arr().bits();
+                    self.lptim.cr.write(|w| w.enable().clear_bit());
+                    self.lptim.ier.write(|w| unsafe {
+                        w.bits(Event::Reload.bit() | Event::Compare.bit())
+                    });
+
+                    self.lptim.cr.write(|w| w.enable().set_bit());
+                    self.lptim.arr.write(|w| unsafe { w.arr().bits(reload) });
+                    while self.lptim.isr.read().arrok().bit_is_clear() {}
+                    self.lptim.icr.write(|w| w.arrokcf().set_bit());
+                    self.lptim.cr.write(|w| w.enable().set_bit().cntstrt().set_bit());
+
+                    MonoTicker {
+                        ticker: Ticker { periods: 0, ..self },
+                    }
+                }
+
+                fn count(&self) -> u16 {
+                    // see `LowPowerTimer::count`
+                    loop {
+                        let count = self.lptim.cnt.read().cnt().bits();
+                        if self.lptim.cnt.read().cnt().bits() == count {
+                            return count;
+                        }
+                    }
+                }
+            }
+
+            #[cfg(feature = "monotonic")]
+            impl<const FREQ: u32> Monotonic for MonoTicker<$LPTIMX, FREQ> {
+                // the reload interrupt counts the periods
+                const DISABLE_INTERRUPT_ON_EMPTY_QUEUE: bool = false;
+
+                type Instant = TimerInstantU64<FREQ>;
+                type Duration = TimerDurationU64<FREQ>;
+
+                fn now(&mut self) -> Self::Instant {
+                    Self::Instant::from_ticks(self.ticker.now())
+                }
+
+                fn set_compare(&mut self, instant: Self::Instant) {
+                    let lptim = &self.ticker.lptim;
+
+                    // only an instant of the current period can be compared, the reload
+                    // interrupt gives another chance to the later ones. A pending reload already
+                    // started the next period, which `on_reload` hasn't counted yet
+                    let pending = u64::from(lptim.isr.read().arrm().bit_is_set());
+                    let period = u64::from(self.ticker.period);
+                    let start = (u64::from(self.ticker.periods) + pending) * period;
+                    let ticks = instant.ticks();
+                    // the compare value has to be less than the reload value, `period - 1`
+                    let compare = if ticks >= start && ticks - start < period - 1 {
+                        ticks - start
+                    } else {
+                        period - 2
+                    };
+
+                    lptim.cmp.write(|w| unsafe { w.cmp().bits(compare as u16) });
+                    while lptim.isr.read().cmpok().bit_is_clear() {}
+                    lptim.icr.write(|w| w.cmpokcf().set_bit());
+                }
+
+                fn clear_compare_flag(&mut self) {
+                    self.ticker.lptim.icr.write(|w| w.cmpmcf().set_bit());
+                }
+
+                fn zero() -> Self::Instant {
+                    Self::Instant::from_ticks(0)
+                }
+
+                unsafe fn reset(&mut self) {
+                    // the counter started from 0 in `into_monotonic`
+                }
+
+                fn on_interrupt(&mut self) {
+                    self.ticker.on_reload();
+                }
+            }
+
+            impl<PINS> LowPowerTimer<$LPTIMX, PINS> {
+                /// Returns the counter value
+                pub fn count(&self) -> u16 {
+                    // the counter runs asynchronously to the APB clock: two consecutive reads
+                    // have to return the same value
+                    loop {
+                        let count = self.lptim.cnt.read().cnt().bits();
+                        if self.lptim.cnt.read().cnt().bits() == count {
+                            return count;
+                        }
+                    }
+                }
+
+                /// Sets the value the counter wraps at, which raises `Event::Reload`
+                pub fn set_reload(&mut self, reload: u16) {
+                    self.lptim.arr.write(|w| unsafe { w.arr().bits(reload) });
+                    while self.lptim.isr.read().arrok().bit_is_clear() {}
+                    self.lptim.icr.write(|w| w.arrokcf().set_bit());
+                }
+
+                /// Sets the value that raises `Event::Compare` when matched by the counter
+                pub fn set_compare(&mut self, compare: u16) {
+                    self.lptim.cmp.write(|w| unsafe { w.cmp().bits(compare) });
+                    while self.lptim.isr.read().cmpok().bit_is_clear() {}
+                    self.lptim.icr.write(|w| w.cmpokcf().set_bit());
+                }
+
+                /// Starts listening for an `event`
+                ///
+                /// The interrupt enable register can only be written while the timer is
+                /// disabled: the counter is restarted from 0.
+                pub fn listen(&mut self, event: Event) {
+                    let reload = self.lptim.arr.read().arr().bits();
+                    let compare = self.lptim.cmp.read().cmp().bits();
+                    self.lptim.cr.write(|w| w.enable().clear_bit());
+                    self.lptim.ier.modify(|r, w| unsafe { w.bits(r.bits() | event.bit()) });
+                    self.start_with(reload, compare);
+                }
+
+                /// Stops listening for an `event`, restarting the counter from 0 (see `listen`)
+                pub fn unlisten(&mut self, event: Event) {
+                    let reload = self.lptim.arr.read().arr().bits();
+                    let compare = self.lptim.cmp.read().cmp().bits();
+                    self.lptim.cr.write(|w| w.enable().clear_bit());
+                    self.lptim.ier.modify(|r, w| unsafe { w.bits(r.bits() & !event.bit()) });
+                    self.start_with(reload, compare);
+                }
+
+                /// Returns `true` if `event` occurred since its flag was last cleared
+                pub fn is_pending(&self, event: Event) -> bool {
+                    self.lptim.isr.read().bits() & event.bit() != 0
+                }
+
+                /// Clears the flag of `event`
+                pub fn clear(&mut self, event: Event) {
+                    self.lptim.icr.write(|w| unsafe { w.bits(event.bit()) });
+                }
+
+                /// Stops the timer and releases the peripheral and the pins
+                pub fn free(self) -> ($LPTIMX, PINS) {
+                    self.lptim.cr.write(|w| w.enable().clear_bit());
+                    (self.lptim, self.pins)
+                }
+
+                /// Enables the timer with the default reload value, and starts counting
+                fn start(&mut self) {
+                    self.start_with(0xFFFF, 0);
+                }
+
+                /// Enables the timer, writes its reload and compare values and starts counting
+                fn start_with(&mut self, reload: u16, compare: u16) {
+                    self.lptim.cr.write(|w| w.enable().set_bit());
+                    self.set_reload(reload);
+                    self.set_compare(compare);
+                    self.lptim.cr.write(|w| w.enable().set_bit().cntstrt().set_bit());
+                }
+            }
+        )+
+    }
+}
+
+hal! {
+    LPTIM1: (lptim1, lptim1_counter, APB1R1, lptim1en, lptim1rst, lptim1sel),
+    LPTIM2: (lptim2, lptim2_counter, APB1R2, lptim2en, lptim2rst, lptim2sel),
+}
@@ -0,0 +1,247 @@
+//! USB OTG full-speed, on the STM32L4x5/x6
+//!
+//! `Usb` brings the OTG_FS peripheral up: it enables its clock, powers the USB transceiver
+//! supply (VDDUSB) and the PHY, and configures the VBUS sensing. With the `otg-fs` feature, it
+//! implements the `UsbPeripheral` of `synopsys-usb-otg`, whose `UsbBus` is the `usb-device` bus
+//! driving the core.
+//!
+//! The 48 MHz USB clock has to be provided by MSI, i.e. `cfgr.msi(MsiFreq::RANGE48M)`, ideally
+//! trimmed by the LSE.
+//!
+//! ``` ignore
+//! static mut EP_MEMORY: [u32; 1024] = [0; 1024];
+//!
+//! let dm = gpioa.pa11.into_af10(&mut gpioa.moder, &mut gpioa.afrh);
+//! let dp = gpioa.pa12.into_af10(&mut gpioa.moder, &mut gpioa.afrh);
+//! let usb = Usb::new(otg, dm, dp, VbusSense::Disabled, &mut pwr, &mut rcc.ahb2, clocks);
+//! let usb_bus = UsbBus::new(usb, unsafe { &mut EP_MEMORY });
+//! ```
+//!
+//! `UsbBus` turns the VBUS sensing off when the device is built. A self-powered device built on
+//! `VbusSense::Enabled` turns it on again with `enable_vbus_sense`:
+//!
+//! ``` ignore
+//! let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd)).build();
+//! otg_fs::enable_vbus_sense(usb_dev.bus());
+//! ```
+
+use core::ptr;
+
+#[cfg(feature = "otg-fs")]
+use cortex_m::interrupt;
+
+#[cfg(feature = "stm32l4x5")]
+use crate::stm32::USB;
+#[cfg(feature = "stm32l4x6")]
+use crate::stm32::{OTG_FS_DEVICE, OTG_FS_GLOBAL, OTG_FS_PWRCLK};
+
+#[cfg(feature = "otg-fs")]
+pub use synopsys_usb_otg::UsbBus;
+#[cfg(feature = "otg-fs")]
+use synopsys_usb_otg::UsbPeripheral;
+
+use crate::gpio::gpioa::{PA11, PA12};
+use crate::gpio::{Alternate, AF10};
+#[cfg(feature = "otg-fs")]
+use crate::gpio::{Floating, Input};
+use crate::pwr::Pwr;
+use crate::rcc::{ClockError, Clocks, AHB2};
+use crate::time::Hertz;
+
+/// The OTG_FS peripheral, split in several register blocks by the SVD of some parts
+#[cfg(feature = "stm32l4x5")]
+pub type Otg = USB;
+/// The OTG_FS peripheral, split in several register blocks by the SVD of some parts
+#[cfg(feature = "stm32l4x6")]
+pub type Otg = (OTG_FS_GLOBAL, OTG_FS_DEVICE, OTG_FS_PWRCLK);
+
+/// Address of the OTG_FS register block
+pub const REGISTERS: *const () = 0x5000_0000 as *const ();
+/// Size of the OTG_FS FIFO RAM, in 32-bit words
+pub const FIFO_DEPTH_WORDS: usize = 320;
+/// Number of endpoints, endpoint 0 included
+pub const ENDPOINT_COUNT: usize = 6;
+
+/// Offset of the control and status register
+const GOTGCTL: usize = 0x000;
+/// Offset of the general core configuration register
+const GCCFG: usize = 0x038;
+/// GOTGCTL: B-peripheral session valid override enable and value
+const BVALOEN_BVALOVAL: u32 = (1 << 6) | (1 << 7);
+/// GCCFG: power down deactivated, i.e. transceiver on
+const PWRDWN: u32 = 1 << 16;
+/// GCCFG: VBUS detection enable
+const VBDEN: u32 = 1 << 21;
+
+// FIXME these should be "closed" traits
+/// DM pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be PA11 in the alternate function of OTG_FS_DM
+pub unsafe trait DmPin {}
+/// DP pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be PA12 in the alternate function of OTG_FS_DP
+pub unsafe trait DpPin {}
+
+unsafe impl<MODE> DmPin for PA11<Alternate<AF10, MODE>> {}
+unsafe impl<MODE> DpPin for PA12<Alternate<AF10, MODE>> {}
+
+/// VBUS sensing
+pub enum VbusSense {
+    /// VBUS is sensed on PA9, so that the device disconnects when the cable is unplugged; this is
+    /// required for devices that aren't powered by VBUS
+    ///
+    /// `UsbBus` (`synopsys-usb-otg` 0.4) disables the sensing again when the bus is enabled, see
+    /// `enable_vbus_sense`.
+    Enabled,
+    /// VBUS is considered always present, e.g. for bus-powered devices, or when PA9 is used for
+    /// something else
+    Disabled,
+}
+
+/// USB OTG_FS peripheral, ready to be driven by a device stack
+pub struct Usb<DM, DP> {
+    otg: Otg,
+    dm: DM,
+    dp: DP,
+    hclk: Hertz,
+}
+
+impl<DM, DP> Usb<DM, DP>
+where
+    DM: DmPin,
+    DP: DpPin,
+{
+    /// Enables the OTG_FS peripheral and its transceiver
+    ///
+    /// Panics if the 48 MHz clock isn't available, see `try_new`.
+    pub fn new(
+        otg: Otg,
+        dm: DM,
+        dp: DP,
+        vbus: VbusSense,
+        pwr: &mut Pwr,
+        ahb: &mut AHB2,
+        clocks: Clocks,
+    ) -> Self {
+        Self::try_new(otg, dm, dp, vbus, pwr, ahb, clocks).unwrap()
+    }
+
+    /// Enables the OTG_FS peripheral and its transceiver, fails if the 48 MHz clock isn't
+    /// available
+    pub fn try_new(
+        otg: Otg,
+        dm: DM,
+        dp: DP,
+        vbus: VbusSense,
+        pwr: &mut Pwr,
+        ahb: &mut AHB2,
+        clocks: Clocks,
+    ) -> Result<Self, ClockError> {
+        clocks.check_usb_rng()?;
+
+        // the USB transceiver has its own supply, which is isolated until declared valid
+        pwr.cr2.reg().modify(|_, w| w.usv().set_bit());
+
+        ahb.enr().modify(|_, w| w.otgfsen().set_bit());
+        ahb.rstr().modify(|_, w| w.otgfsrst().set_bit());
+        ahb.rstr().modify(|_, w| w.otgfsrst().clear_bit());
+
+        let usb = Usb {
+            otg,
+            dm,
+            dp,
+            hclk: clocks.hclk(),
+        };
+
+        // NOTE(unsafe) the registers are accessed by address, as their description is wrong in
+        // the SVD of these parts; the peripheral is owned by `usb`
+        unsafe {
+            match vbus {
+                VbusSense::Enabled => {
+                    ptr::write_volatile(Self::reg(GCCFG), PWRDWN | VBDEN);
+                }
+                VbusSense::Disabled => {
+                    ptr::write_volatile(Self::reg(GCCFG), PWRDWN);
+                    let gotgctl = ptr::read_volatile(Self::reg(GOTGCTL));
+                    ptr::write_volatile(Self::reg(GOTGCTL), gotgctl | BVALOEN_BVALOVAL);
+                }
+            }
+        }
+
+        Ok(usb)
+    }
+
+    /// Returns the frequency of the AHB, the clock of the OTG_FS core
+    pub fn ahb_frequency_hz(&self) -> u32 {
+        self.hclk.0
+    }
+
+    /// Powers the transceiver down, isolates VDDUSB and releases the peripheral and the pins
+    pub fn free(self, pwr: &mut Pwr, ahb: &mut AHB2) -> (Otg, DM, DP) {
+        // NOTE(unsafe) see `new`
+        unsafe { ptr::write_volatile(Self::reg(GCCFG), 0) };
+        ahb.enr().modify(|_, w| w.otgfsen().clear_bit());
+        pwr.cr2.reg().modify(|_, w| w.usv().clear_bit());
+
+        (self.otg, self.dm, self.dp)
+    }
+
+    fn reg(offset: usize) -> *mut u32 {
+        (REGISTERS as usize + offset) as *mut u32
+    }
+}
+
+/// Turns the VBUS sensing on again, once `UsbBus` has brought the core up
+///
+/// `UsbBus` forces a valid session and disables the sensing when it is enabled, i.e. when the
+/// `UsbDevice` is built. Call this with `UsbDevice::bus` right after `UsbDeviceBuilder::build`.
+#[cfg(feature = "otg-fs")]
+pub fn enable_vbus_sense<DM, DP>(_bus: &UsbBus<Usb<DM, DP>>)
+where
+    DM: DmPin + Send,
+    DP: DpPin + Send,
+{
+    // NOTE(unsafe) `UsbBus` owns the peripheral and only accesses the registers in critical
+    // sections
+    interrupt::free(|_| unsafe {
+        let gccfg = ptr::read_volatile(Usb::<DM, DP>::reg(GCCFG));
+        ptr::write_volatile(Usb::<DM, DP>::reg(GCCFG), gccfg | VBDEN);
+        let gotgctl = ptr::read_volatile(Usb::<DM, DP>::reg(GOTGCTL));
+        ptr::write_volatile(Usb::<DM, DP>::reg(GOTGCTL), gotgctl & !BVALOEN_BVALOVAL);
+    });
+}
+
+/// `UsbBus` on the pins as configured by `into_af10` after reset
+#[cfg(feature = "otg-fs")]
+pub type UsbBusType =
+    UsbBus<Usb<PA11<Alternate<AF10, Input<Floating>>>, PA12<Alternate<AF10, Input<Floating>>>>>;
+
+// NOTE(unsafe) `UsbPeripheral` requires `Sync`: the methods of a shared `Usb` don't access the
+// registers, and `UsbBus` only accesses them in critical sections
+#[cfg(feature = "otg-fs")]
+unsafe impl<DM, DP> Sync for Usb<DM, DP> {}
+
+#[cfg(feature = "otg-fs")]
+unsafe impl<DM, DP> UsbPeripheral for Usb<DM, DP>
+where
+    DM: DmPin + Send,
+    DP: DpPin + Send,
+{
+    const REGISTERS: *const () = REGISTERS;
+    const HIGH_SPEED: bool = false;
+    const FIFO_DEPTH_WORDS: usize = FIFO_DEPTH_WORDS;
+    const ENDPOINT_COUNT: usize = ENDPOINT_COUNT;
+
+    fn enable() {
+        // the clock and VDDUSB are enabled by `Usb::new`, which the bus is built from
+    }
+
+    fn ahb_frequency_hz(&self) -> u32 {
+        self.hclk.0
+    }
+}
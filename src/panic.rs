@@ -0,0 +1,158 @@
+//! Panic handler reporting over a USART
+//!
+//! Enabled by the `panic-hook` feature, this module provides the `#[panic_handler]` of the
+//! application, so no other panic crate (e.g. `panic-halt`) must be linked. Once a transmitter
+//! has been handed to `install`, the panic message is written to it with blocking writes, then
+//! the device is reset. A running independent watchdog is fed while the message is written.
+//!
+//! ``` ignore
+//! let serial = Serial::usart2(p.USART2, (tx, rx), 115_200.bps(), clocks, &mut rcc.apb1r1);
+//! let (tx, rx) = serial.split();
+//! panic::install(tx, Reset::System);
+//! ```
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use cortex_m::interrupt;
+
+use crate::serial::Tx;
+use crate::stm32::{usart1, IWDG, USART1, USART2, USART3};
+
+/// Address of the USART the panic message is written to, 0 if none
+static USART: AtomicUsize = AtomicUsize::new(0);
+/// `Reset` performed after the panic message was written
+static RESET: AtomicUsize = AtomicUsize::new(Reset::System as usize);
+
+/// How the device is restarted after a panic
+#[derive(Clone, Copy)]
+pub enum Reset {
+    /// Requests a system reset through the SCB
+    System,
+    /// Starts the independent watchdog with its shortest timeout (or shortens the timeout if it
+    /// is already running), so that the reset is flagged as a watchdog reset in RCC_CSR
+    Watchdog,
+    /// Halts in an endless loop, e.g. to attach a debugger
+    Halt,
+}
+
+/// USART transmitters that can report panics
+pub trait Output {
+    #[doc(hidden)]
+    fn ptr() -> *const usart1::RegisterBlock;
+}
+
+macro_rules! output {
+    ($($USARTX:ident,)+) => {
+        $(
+            impl Output for Tx<$USARTX> {
+                fn ptr() -> *const usart1::RegisterBlock {
+                    // NOTE(as) all the USARTs share the same register layout
+                    $USARTX::ptr() as *const _
+                }
+            }
+        )+
+    }
+}
+
+output! {
+    USART1,
+    USART2,
+    USART3,
+}
+
+/// Reports the following panics over `tx`, then restarts the device according to `reset`
+///
+/// The transmitter is consumed so that the panic message can't be interleaved with other
+/// writes. Before `install` is called, panics restart the device without a message.
+pub fn install<OUTPUT>(_tx: OUTPUT, reset: Reset)
+where
+    OUTPUT: Output,
+{
+    RESET.store(reset as usize, Ordering::SeqCst);
+    USART.store(OUTPUT::ptr() as usize, Ordering::SeqCst);
+}
+
+/// Reloads the counter of the independent watchdog, so that a running watchdog doesn't reset
+/// the device in the middle of the panic message
+fn feed_watchdog() {
+    // NOTE(unsafe) nothing else runs anymore; the reload has no effect if the watchdog is stopped
+    unsafe { (*IWDG::ptr()).kr.write(|w| w.key().bits(0xAAAA)) };
+}
+
+/// Blocking writer over the installed USART
+struct Writer(*const usart1::RegisterBlock);
+
+impl Writer {
+    fn write_byte(&mut self, byte: u8) {
+        // NOTE(unsafe) the transmitter was handed over in `install`
+        let usart = unsafe { &*self.0 };
+        while usart.isr.read().txe().bit_is_clear() {
+            feed_watchdog();
+        }
+        usart.tdr.write(|w| unsafe { w.bits(u32::from(byte)) });
+    }
+
+    fn flush(&mut self) {
+        // NOTE(unsafe) see `write_byte`
+        let usart = unsafe { &*self.0 };
+        while usart.isr.read().tc().bit_is_clear() {
+            feed_watchdog();
+        }
+    }
+}
+
+impl Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    interrupt::disable();
+
+    let usart = USART.load(Ordering::SeqCst);
+    if usart != 0 {
+        let mut writer = Writer(usart as *const _);
+        writeln!(writer, "{}", info).ok();
+        writer.flush();
+    }
+
+    let reset = match RESET.load(Ordering::SeqCst) {
+        0 => Reset::System,
+        1 => Reset::Watchdog,
+        _ => Reset::Halt,
+    };
+
+    match reset {
+        Reset::System => {
+            // NOTE(unsafe) nothing else runs anymore
+            unsafe { cortex_m::Peripherals::steal() }.SCB.system_reset();
+        }
+        Reset::Watchdog => {
+            // NOTE(unsafe) nothing else runs anymore
+            let iwdg = unsafe { &*IWDG::ptr() };
+            // start the watchdog, then unlock it and select a prescaler of 4 and a reload of 0
+            iwdg.kr.write(|w| unsafe { w.key().bits(0xCCCC) });
+            iwdg.kr.write(|w| unsafe { w.key().bits(0x5555) });
+            iwdg.pr.write(|w| w.pr().bits(0));
+            iwdg.rlr.write(|w| w.rl().bits(0));
+            // the new values are only used by the next reload, once they're updated
+            while iwdg.sr.read().pvu().bit_is_set() || iwdg.sr.read().rvu().bit_is_set() {}
+            iwdg.kr.write(|w| unsafe { w.key().bits(0xAAAA) });
+        }
+        Reset::Halt => {}
+    }
+
+    loop {
+        cortex_m::asm::nop();
+    }
+}
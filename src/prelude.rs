@@ -1,12 +1,27 @@
 //! Prelude - Include traits for hal
+//!
+//! The traits are imported anonymously (`as _`): their methods are in scope, their names aren't.
 
 pub use crate::hal::prelude::*; // embedded hal traits
 
-pub use crate::rcc::RccExt as _stm32l4_hal_RccExt;
-pub use crate::flash::FlashExt as _stm32l4_hal_FlashExt;
-pub use crate::gpio::GpioExt as _stm32l4_hal_GpioExt;
-pub use crate::time::U32Ext as _stm32l4_hal_time_U32Ext;
-pub use crate::datetime::U32Ext as _stm32l4_hal_datetime_U32Ext;
-pub use crate::dma::DmaExt as _stm32l4_hal_DmaExt;
-pub use crate::pwr::PwrExt as _stm32l4_hal_PwrExt;
-pub use crate::rng::RngExt as _stm32l4_hal_RngExt;
+// constrain / split extensions of the peripherals
+pub use crate::dma::DmaExt as _;
+pub use crate::flash::FlashExt as _;
+pub use crate::gpio::GpioExt as _;
+pub use crate::pwr::PwrExt as _;
+pub use crate::rcc::RccExt as _;
+pub use crate::rng::RngExt as _;
+pub use crate::syscfg::SysCfgExt as _;
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+pub use crate::crs::CrsExt as _;
+
+// units
+pub use crate::datetime::U32Ext as _;
+pub use crate::time::U32Ext as _;
+
+// methods of the drivers defined by traits
+pub use crate::debounce::Clock as _;
+pub use crate::pwr::Busy as _;
+pub use crate::serial::ReceiverTimeout as _;
+#[cfg(feature = "rtc-trim")]
+pub use crate::rtc_trim::Curve as _;
@@ -0,0 +1,710 @@
+//! Pulse width modulation on the advanced-control timers
+//!
+//! TIM1 (and TIM8 on the STM32L4x5/x6) drive up to four PWM channels. The first three channels
+//! can additionally drive a complementary output, with a programmable dead time inserted between
+//! the deactivation of one output and the activation of the other. A break input, fed by the
+//! BKIN pin and/or the outputs of the comparators, forces all outputs to their idle state.
+//!
+//! ``` ignore
+//! let ch1 = gpioa.pa8.into_af1(&mut gpioa.moder, &mut gpioa.afrh);
+//! let ch1n = gpioa.pa7.into_af1(&mut gpioa.moder, &mut gpioa.afrl);
+//! let ch2 = gpioa.pa9.into_af1(&mut gpioa.moder, &mut gpioa.afrh);
+//!
+//! let ((mut u, mut v), mut bdtr) =
+//!     pwm::tim1(dp.TIM1, ((ch1, ch1n), ch2), 20.khz(), clocks, &mut rcc.apb2)?;
+//!
+//! bdtr.set_dead_time(500); // ns
+//! bdtr.enable_break_source(BreakSource::Comp1);
+//! bdtr.enable_break(BreakPolarity::ActiveHigh);
+//! bdtr.enable_outputs();
+//!
+//! u.set_duty(u.get_max_duty() / 2);
+//! u.enable();
+//! ```
+//!
+//! The compare values of TIM1 can also be streamed by DMA, through the DMA burst registers: on
+//! every update event, the next values of a buffer are written to consecutive compare registers,
+//! which plays a waveform without an interrupt per period.
+//!
+//! ``` ignore
+//! // (CCR1, CCR2) pairs, one per period
+//! let ramp = singleton!(: [u16; 2 * STEPS] = RAMP).unwrap();
+//! let (ramp, chan, bdtr) = bdtr.write_burst(channels.6, Ccr::Ccr1, 2, ramp).wait()?;
+//! ```
+
+use core::marker::PhantomData;
+use core::sync::atomic::{self, Ordering};
+
+use cast::{u16, u32, u64};
+
+use crate::dma::{dma1, Priority, ReadBuffer, Transfer, R};
+use crate::hal::PwmPin;
+use crate::stm32::TIM1;
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+use crate::stm32::TIM8;
+
+use crate::gpio::gpioa::{PA10, PA11, PA6, PA7, PA8, PA9};
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+use crate::gpio::gpioa::PA5;
+use crate::gpio::gpiob::{PB0, PB1, PB12, PB13, PB14, PB15};
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+use crate::gpio::gpiob::PB7;
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+use crate::gpio::gpioc::{PC6, PC7, PC8, PC9};
+use crate::gpio::gpioe::{PE10, PE11, PE12, PE13, PE14, PE8, PE9};
+use crate::gpio::{Alternate, AF1};
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+use crate::gpio::AF3;
+use crate::rcc::{Clocks, APB2};
+use crate::time::Hertz;
+
+/// Channel 1
+pub struct C1;
+/// Channel 2
+pub struct C2;
+/// Channel 3
+pub struct C3;
+/// Channel 4
+pub struct C4;
+/// Channel 1 together with its complementary output
+pub struct C1N;
+/// Channel 2 together with its complementary output
+pub struct C2N;
+/// Channel 3 together with its complementary output
+pub struct C3N;
+
+// FIXME these should be "closed" traits
+/// CH1 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the CH1 output of `TIM` by its alternate function
+pub unsafe trait PinC1<TIM> {}
+/// CH2 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the CH2 output of `TIM` by its alternate function
+pub unsafe trait PinC2<TIM> {}
+/// CH3 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the CH3 output of `TIM` by its alternate function
+pub unsafe trait PinC3<TIM> {}
+/// CH4 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the CH4 output of `TIM` by its alternate function
+pub unsafe trait PinC4<TIM> {}
+/// CH1N pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the complementary CH1N output of `TIM`
+pub unsafe trait PinC1N<TIM> {}
+/// CH2N pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the complementary CH2N output of `TIM`
+pub unsafe trait PinC2N<TIM> {}
+/// CH3N pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the complementary CH3N output of `TIM`
+pub unsafe trait PinC3N<TIM> {}
+/// BKIN pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the break input of `TIM`
+pub unsafe trait PinBkin<TIM> {}
+
+unsafe impl<MODE> PinC1<TIM1> for PA8<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC1<TIM1> for PE9<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC2<TIM1> for PA9<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC2<TIM1> for PE11<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC3<TIM1> for PA10<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC3<TIM1> for PE13<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC4<TIM1> for PA11<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC4<TIM1> for PE14<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC1N<TIM1> for PA7<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC1N<TIM1> for PB13<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC1N<TIM1> for PE8<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC2N<TIM1> for PB0<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC2N<TIM1> for PB14<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC2N<TIM1> for PE10<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC3N<TIM1> for PB1<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC3N<TIM1> for PB15<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinC3N<TIM1> for PE12<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinBkin<TIM1> for PA6<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> PinBkin<TIM1> for PB12<Alternate<AF1, MODE>> {}
+
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> PinC1<TIM8> for PC6<Alternate<AF3, MODE>> {}
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> PinC2<TIM8> for PC7<Alternate<AF3, MODE>> {}
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> PinC3<TIM8> for PC8<Alternate<AF3, MODE>> {}
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> PinC4<TIM8> for PC9<Alternate<AF3, MODE>> {}
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> PinC1N<TIM8> for PA5<Alternate<AF3, MODE>> {}
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> PinC1N<TIM8> for PA7<Alternate<AF3, MODE>> {}
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> PinC2N<TIM8> for PB0<Alternate<AF3, MODE>> {}
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> PinC2N<TIM8> for PB14<Alternate<AF3, MODE>> {}
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> PinC3N<TIM8> for PB1<Alternate<AF3, MODE>> {}
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> PinC3N<TIM8> for PB15<Alternate<AF3, MODE>> {}
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> PinBkin<TIM8> for PA6<Alternate<AF3, MODE>> {}
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> PinBkin<TIM8> for PB7<Alternate<AF3, MODE>> {}
+
+/// Pins (or groups of pins) that can be driven by the PWM channels of `TIM`
+///
+/// A single pin selects a channel. A `(CHx, CHxN)` pair selects a channel together with its
+/// complementary output. Tuples of up to four of the above select several channels at once.
+pub trait Pins<TIM, CHANNELS> {
+    /// The PWM channel handles returned for these pins
+    type Channels;
+
+    #[doc(hidden)]
+    fn channels() -> Self::Channels;
+}
+
+macro_rules! channel {
+    ($($Cx:ident: $PinCx:ident,)+) => {
+        $(
+            impl<TIM, P> Pins<TIM, $Cx> for P
+            where
+                P: $PinCx<TIM>,
+            {
+                type Channels = Pwm<TIM, $Cx>;
+
+                fn channels() -> Self::Channels {
+                    Pwm::new()
+                }
+            }
+        )+
+    }
+}
+
+channel! {
+    C1: PinC1,
+    C2: PinC2,
+    C3: PinC3,
+    C4: PinC4,
+}
+
+macro_rules! complementary {
+    ($($CxN:ident: ($PinCx:ident, $PinCxN:ident),)+) => {
+        $(
+            impl<TIM, P, N> Pins<TIM, $CxN> for (P, N)
+            where
+                P: $PinCx<TIM>,
+                N: $PinCxN<TIM>,
+            {
+                type Channels = Pwm<TIM, $CxN>;
+
+                fn channels() -> Self::Channels {
+                    Pwm::new()
+                }
+            }
+        )+
+    }
+}
+
+complementary! {
+    C1N: (PinC1, PinC1N),
+    C2N: (PinC2, PinC2N),
+    C3N: (PinC3, PinC3N),
+}
+
+macro_rules! group {
+    ($(($($P:ident: $C:ident),+),)+) => {
+        $(
+            impl<TIM, $($P, $C),+> Pins<TIM, ($($C,)+)> for ($($P,)+)
+            where
+                $($P: Pins<TIM, $C>,)+
+            {
+                type Channels = ($($P::Channels,)+);
+
+                fn channels() -> Self::Channels {
+                    ($($P::channels(),)+)
+                }
+            }
+        )+
+    }
+}
+
+group! {
+    (A: CA, B: CB),
+    (A: CA, B: CB, C: CC),
+    (A: CA, B: CB, C: CC, D: CD),
+}
+
+/// A PWM channel of `TIM`
+pub struct Pwm<TIM, CHANNEL> {
+    _tim: PhantomData<TIM>,
+    _channel: PhantomData<CHANNEL>,
+}
+
+impl<TIM, CHANNEL> Pwm<TIM, CHANNEL> {
+    fn new() -> Self {
+        Pwm {
+            _tim: PhantomData,
+            _channel: PhantomData,
+        }
+    }
+}
+
+/// Counting mode of the timer, shared by all its channels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alignment {
+    /// The counter counts up, the outputs switch at the compare value and at the update event
+    Edge,
+    /// The counter counts up and down, the pulses are centered in the period, which is twice
+    /// as long as with `Edge`
+    Center,
+}
+
+/// PWM error
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The frequency is 0 or can't be generated from the timer clock
+    Frequency,
+}
+
+/// Break input sources
+pub enum BreakSource {
+    /// The BKIN pin
+    Pin,
+    /// The output of comparator 1
+    Comp1,
+    /// The output of comparator 2
+    Comp2,
+}
+
+/// Break input polarity
+pub enum BreakPolarity {
+    /// A low level on the break input deactivates the outputs
+    ActiveLow,
+    /// A high level on the break input deactivates the outputs
+    ActiveHigh,
+}
+
+/// First compare register written by a DMA burst
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ccr {
+    /// CCR1
+    Ccr1 = 13,
+    /// CCR2
+    Ccr2 = 14,
+    /// CCR3
+    Ccr3 = 15,
+    /// CCR4
+    Ccr4 = 16,
+}
+
+/// Break and dead-time configuration of an advanced-control timer
+pub struct Bdtr<TIM> {
+    clk: Hertz,
+    _tim: PhantomData<TIM>,
+}
+
+macro_rules! hal {
+    ($($TIM:ident: ($tim:ident, $timXen:ident, $timXrst:ident),)+) => {
+        $(
+            /// Configures `TIM` to generate PWM signals at `freq` on the given `pins`
+            ///
+            /// All channels start disabled with a duty cycle of 0. The main output enable (MOE) is
+            /// left cleared, the outputs only become active once `Bdtr::enable_outputs` is called.
+            ///
+            /// Fails if `freq` is 0 or above half of the timer clock.
+            pub fn $tim<PINS, CHANNELS, T>(
+                tim: $TIM,
+                _pins: PINS,
+                freq: T,
+                clocks: Clocks,
+                apb: &mut APB2,
+            ) -> Result<(PINS::Channels, Bdtr<$TIM>), Error>
+            where
+                PINS: Pins<$TIM, CHANNELS>,
+                T: Into<Hertz>,
+            {
+                // checked before the peripheral is touched
+                let clk = clocks.timclk2();
+                let ticks = match clk.0.checked_div(freq.into().0) {
+                    Some(ticks) if ticks >= 2 => ticks,
+                    _ => return Err(Error::Frequency),
+                };
+
+                // enable and reset peripheral to a clean slate state
+                apb.enr().modify(|_, w| w.$timXen().set_bit());
+                apb.rstr().modify(|_, w| w.$timXrst().set_bit());
+                apb.rstr().modify(|_, w| w.$timXrst().clear_bit());
+
+                // PWM mode 1 with preloaded compare registers on every channel
+                tim.ccmr1_output.write(|w| unsafe {
+                    w.oc1m().bits(0b110).oc1pe().set_bit().oc2m().bits(0b110).oc2pe().set_bit()
+                });
+                tim.ccmr2_output.write(|w| unsafe {
+                    w.oc3m().bits(0b110).oc3pe().set_bit().oc4m().bits(0b110).oc4pe().set_bit()
+                });
+
+                let psc = u16((ticks - 1) / (1 << 16)).unwrap();
+                tim.psc.write(|w| unsafe { w.psc().bits(psc) });
+                let arr = u16(ticks / (u32(psc) + 1) - 1).unwrap();
+                tim.arr.write(|w| unsafe { w.arr().bits(arr) });
+
+                tim.cr1.write(|w| w.arpe().set_bit());
+                // Trigger an update event to load the prescaler and auto-reload values
+                tim.egr.write(|w| w.ug().set_bit());
+                tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                Ok((
+                    PINS::channels(),
+                    Bdtr {
+                        clk,
+                        _tim: PhantomData,
+                    },
+                ))
+            }
+
+            impl Bdtr<$TIM> {
+                /// Changes the counting mode of the timer, e.g. to center the pulses of the
+                /// channels driving an H-bridge
+                ///
+                /// The counter is stopped while the mode changes, and restarts from 0. The duty
+                /// cycles are kept, but the period doubles in center-aligned mode.
+                pub fn set_alignment(&mut self, alignment: Alignment) {
+                    // NOTE(unsafe) this proxy grants exclusive access to the counter
+                    let tim = unsafe { &*$TIM::ptr() };
+                    let cms = match alignment {
+                        Alignment::Edge => 0b00,
+                        // the compare interrupt flags are set when counting down and up
+                        Alignment::Center => 0b11,
+                    };
+
+                    // the mode can't change from edge to center-aligned while the counter runs
+                    tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    tim.cr1.modify(|_, w| unsafe { w.cms().bits(cms).dir().clear_bit() });
+                    tim.cnt.reset();
+                    tim.cr1.modify(|_, w| w.cen().set_bit());
+                }
+
+                /// Returns the counting mode of the timer
+                pub fn alignment(&self) -> Alignment {
+                    // NOTE(unsafe) atomic read with no side effects
+                    match unsafe { (*$TIM::ptr()).cr1.read().cms().bits() } {
+                        0b00 => Alignment::Edge,
+                        _ => Alignment::Center,
+                    }
+                }
+
+                /// Sets the dead time inserted before a channel or its complementary output
+                /// becomes active, in nanoseconds
+                ///
+                /// Panics if the dead time exceeds 1008 timer clock periods
+                pub fn set_dead_time(&mut self, ns: u32) {
+                    let ticks = u64(ns) * u64(self.clk.0) / 1_000_000_000;
+                    let dtg = match ticks {
+                        0..=127 => ticks,
+                        128..=254 => 0b1000_0000 | (ticks / 2 - 64),
+                        255..=504 => 0b1100_0000 | (ticks.div_ceil(8) - 32),
+                        505..=1008 => 0b1110_0000 | (ticks.div_ceil(16) - 32),
+                        _ => panic!("dead time out of range"),
+                    };
+
+                    // NOTE(unsafe) this proxy grants exclusive access to this register
+                    unsafe { (*$TIM::ptr()).bdtr.modify(|_, w| w.dtg().bits(dtg as u8)) };
+                }
+
+                /// Connects `source` to the break input
+                pub fn enable_break_source(&mut self, source: BreakSource) {
+                    // NOTE(unsafe) this proxy grants exclusive access to this register
+                    let or2 = unsafe { &(*$TIM::ptr()).or2 };
+                    match source {
+                        BreakSource::Pin => or2.modify(|_, w| w.bkine().set_bit()),
+                        BreakSource::Comp1 => or2.modify(|_, w| w.bkcmp1e().set_bit()),
+                        BreakSource::Comp2 => or2.modify(|_, w| w.bkcmp2e().set_bit()),
+                    }
+                }
+
+                /// Disconnects `source` from the break input
+                pub fn disable_break_source(&mut self, source: BreakSource) {
+                    // NOTE(unsafe) this proxy grants exclusive access to this register
+                    let or2 = unsafe { &(*$TIM::ptr()).or2 };
+                    match source {
+                        BreakSource::Pin => or2.modify(|_, w| w.bkine().clear_bit()),
+                        BreakSource::Comp1 => or2.modify(|_, w| w.bkcmp1e().clear_bit()),
+                        BreakSource::Comp2 => or2.modify(|_, w| w.bkcmp2e().clear_bit()),
+                    }
+                }
+
+                /// Enables the break function, an active break clears the main output enable
+                pub fn enable_break(&mut self, polarity: BreakPolarity) {
+                    let high = match polarity {
+                        BreakPolarity::ActiveLow => false,
+                        BreakPolarity::ActiveHigh => true,
+                    };
+
+                    // NOTE(unsafe) this proxy grants exclusive access to this register
+                    unsafe {
+                        (*$TIM::ptr()).bdtr.modify(|_, w| w.bkp().bit(high).bke().set_bit());
+                    }
+                }
+
+                /// Disables the break function
+                pub fn disable_break(&mut self) {
+                    // NOTE(unsafe) this proxy grants exclusive access to this register
+                    unsafe { (*$TIM::ptr()).bdtr.modify(|_, w| w.bke().clear_bit()) };
+                }
+
+                /// Returns `true` if a break has been detected since the last `clear_break`
+                pub fn is_break(&self) -> bool {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$TIM::ptr()).sr.read().bif().bit_is_set() }
+                }
+
+                /// Clears the break flag
+                pub fn clear_break(&mut self) {
+                    // NOTE(unsafe) this proxy grants exclusive access to this register
+                    unsafe { (*$TIM::ptr()).sr.modify(|_, w| w.bif().clear_bit()) };
+                }
+
+                /// Sets whether the outputs are re-enabled automatically on the next update event
+                /// once the break input becomes inactive
+                pub fn set_automatic_output(&mut self, enabled: bool) {
+                    // NOTE(unsafe) this proxy grants exclusive access to this register
+                    unsafe { (*$TIM::ptr()).bdtr.modify(|_, w| w.aoe().bit(enabled)) };
+                }
+
+                /// Sets the main output enable (MOE), activating the enabled channels
+                pub fn enable_outputs(&mut self) {
+                    // NOTE(unsafe) this proxy grants exclusive access to this register
+                    unsafe { (*$TIM::ptr()).bdtr.modify(|_, w| w.moe().set_bit()) };
+                }
+
+                /// Clears the main output enable (MOE), forcing all outputs to their idle state
+                pub fn disable_outputs(&mut self) {
+                    // NOTE(unsafe) this proxy grants exclusive access to this register
+                    unsafe { (*$TIM::ptr()).bdtr.modify(|_, w| w.moe().clear_bit()) };
+                }
+
+                /// Returns `true` if the main output enable (MOE) is set
+                ///
+                /// MOE is cleared by hardware when a break occurs.
+                pub fn outputs_enabled(&self) -> bool {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$TIM::ptr()).bdtr.read().moe().bit_is_set() }
+                }
+            }
+
+            complementary_outputs! {
+                $TIM: [
+                    C1N: (cc1e, cc1ne),
+                    C2N: (cc2e, cc2ne),
+                    C3N: (cc3e, cc3ne),
+                ]
+            }
+
+            pwm_channels! {
+                $TIM: [
+                    C1: (ccr1, cc1e),
+                    C2: (ccr2, cc2e),
+                    C3: (ccr3, cc3e),
+                    C4: (ccr4, cc4e),
+                    C1N: (ccr1, cc1e, cc1ne),
+                    C2N: (ccr2, cc2e, cc2ne),
+                    C3N: (ccr3, cc3e, cc3ne),
+                ]
+            }
+        )+
+    }
+}
+
+macro_rules! complementary_outputs {
+    ($TIM:ident: [$($CxN:ident: ($ccXe:ident, $ccXne:ident),)+]) => {
+        $(
+            impl Pwm<$TIM, $CxN> {
+                /// Enables or disables the channel and its complementary output separately, e.g.
+                /// to switch the high side of a half-bridge off while the low side keeps
+                /// switching
+                ///
+                /// `enable` and `disable` switch both outputs.
+                pub fn set_outputs(&mut self, main: bool, complementary: bool) {
+                    // NOTE(unsafe) atomic read-modify-write, the CCER bits of each channel are
+                    // owned by its `Pwm` handle
+                    cortex_m::interrupt::free(|_| unsafe {
+                        (*$TIM::ptr())
+                            .ccer
+                            .modify(|_, w| w.$ccXe().bit(main).$ccXne().bit(complementary))
+                    });
+                }
+
+                /// Returns whether the channel and its complementary output are enabled
+                pub fn outputs(&self) -> (bool, bool) {
+                    // NOTE(unsafe) atomic read with no side effects
+                    let ccer = unsafe { (*$TIM::ptr()).ccer.read() };
+                    (ccer.$ccXe().bit_is_set(), ccer.$ccXne().bit_is_set())
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! pwm_channels {
+    ($TIM:ident: [$($C:ident: ($ccrX:ident, $ccXe:ident $(, $ccXne:ident)*),)+]) => {
+        $(
+            impl Pwm<$TIM, $C> {
+                /// Returns `true` if the channel is enabled, see `PwmPin::enable`
+                pub fn is_enabled(&self) -> bool {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$TIM::ptr()).ccer.read().$ccXe().bit_is_set() }
+                }
+            }
+
+            impl PwmPin for Pwm<$TIM, $C> {
+                type Duty = u16;
+
+                fn disable(&mut self) {
+                    // NOTE(unsafe) atomic read-modify-write, the CCER bits of each channel are
+                    // owned by its `Pwm` handle
+                    cortex_m::interrupt::free(|_| unsafe {
+                        (*$TIM::ptr())
+                            .ccer
+                            .modify(|_, w| w.$ccXe().clear_bit()$(.$ccXne().clear_bit())*)
+                    });
+                }
+
+                fn enable(&mut self) {
+                    // NOTE(unsafe) atomic read-modify-write, the CCER bits of each channel are
+                    // owned by its `Pwm` handle
+                    cortex_m::interrupt::free(|_| unsafe {
+                        (*$TIM::ptr())
+                            .ccer
+                            .modify(|_, w| w.$ccXe().set_bit()$(.$ccXne().set_bit())*)
+                    });
+                }
+
+                fn get_duty(&self) -> u16 {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$TIM::ptr()).$ccrX.read().$ccrX().bits() }
+                }
+
+                fn get_max_duty(&self) -> u16 {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$TIM::ptr()).arr.read().arr().bits() }
+                }
+
+                fn set_duty(&mut self, duty: u16) {
+                    // NOTE(unsafe) this register is owned by this `Pwm` handle
+                    unsafe { (*$TIM::ptr()).$ccrX.write(|w| w.$ccrX().bits(duty)) }
+                }
+            }
+        )+
+    }
+}
+
+hal! {
+    TIM1: (tim1, tim1en, tim1rst),
+}
+
+impl Bdtr<TIM1> {
+    /// Writes `buffer` to the compare registers by DMA, `count` values per update event starting
+    /// at `first`
+    ///
+    /// The buffer holds the values of each period one after the other, e.g. `[ccr1, ccr2, ccr1,
+    /// ccr2, ...]` for `Ccr::Ccr1` and a `count` of 2; its length has to be a multiple of `count`.
+    /// The compare registers are preloaded, so every set of values applies to the following
+    /// period. The update DMA request stays enabled until `stop_burst`.
+    pub fn write_burst<B>(
+        self,
+        mut chan: dma1::C6,
+        first: Ccr,
+        count: u8,
+        buffer: B,
+    ) -> Transfer<R, B, dma1::C6, Self>
+    where
+        B: ReadBuffer<Word = u16>,
+    {
+        assert!(count > 0 && first as u8 + count <= Ccr::Ccr4 as u8 + 1);
+
+        // NOTE(unsafe) this proxy grants exclusive access to the timer-wide registers
+        let tim = unsafe { &*TIM1::ptr() };
+
+        {
+            // NOTE(unsafe) the buffer is owned by the transfer until it's over
+            let (ptr, len) = unsafe { buffer.read_buffer() };
+            assert!(len % usize::from(count) == 0);
+
+            tim.dcr
+                .write(|w| unsafe { w.dba().bits(first as u8).dbl().bits(count - 1) });
+
+            chan.cmar().write(|w| w.ma().bits(ptr as usize as u32));
+            chan.cndtr().write(|w| w.ndt().bits(u16(len).unwrap()));
+            chan.cpar()
+                .write(|w| w.pa().bits(&tim.dmar as *const _ as usize as u32));
+
+            // Tell DMA to request from the TIM1 update event
+            chan.cselr().modify(|_, w| w.c6s().bits(0b0111));
+
+            // TODO can we weaken this compiler barrier?
+            // NOTE(compiler_fence) operations on `buffer` should not be reordered after the next
+            // statement, which starts the DMA transfer
+            atomic::compiler_fence(Ordering::SeqCst);
+
+            let pl = chan.priority_or(Priority::High) as u8;
+            chan.ccr().modify(|_, w| unsafe {
+                w.mem2mem()
+                    .clear_bit()
+                    .pl()
+                    .bits(pl)
+                    // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                    .msize()
+                    .bits(0b01)
+                    // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                    .psize()
+                    .bits(0b01)
+                    // incr mem address
+                    .minc()
+                    .set_bit()
+                    .pinc()
+                    .clear_bit()
+                    .circ()
+                    .clear_bit()
+                    // memory to peripheral
+                    .dir()
+                    .set_bit()
+                    .en()
+                    .set_bit()
+            });
+        }
+
+        tim.dier.modify(|_, w| w.ude().set_bit());
+
+        Transfer::r(buffer, chan, self)
+    }
+
+    /// Disables the update DMA request enabled by `write_burst`
+    pub fn stop_burst(&mut self) {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { (*TIM1::ptr()).dier.modify(|_, w| w.ude().clear_bit()) };
+    }
+}
+
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+hal! {
+    TIM8: (tim8, tim8en, tim8rst),
+}
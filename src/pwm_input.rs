@@ -0,0 +1,166 @@
+//! Measurement of the frequency and duty cycle of a PWM signal
+//!
+//! The signal is captured on the channel 1 pin of TIM2 or TIM15. Its rising edges are captured
+//! by channel 1 and reset the counter, while its falling edges are captured by channel 2, so
+//! both the period and the high time of the last pulse are always available without CPU
+//! involvement.
+//!
+//! ``` ignore
+//! let pa0 = gpioa.pa0.into_af1(&mut gpioa.moder, &mut gpioa.afrl);
+//! let tach = PwmInput::tim2(dp.TIM2, pa0, 10.hz(), clocks, &mut rcc.apb1r1);
+//!
+//! if let Some(frequency) = tach.frequency() {
+//!     let rpm = frequency.0 * 60 / 2; // two pulses per revolution
+//! }
+//! ```
+
+use core::ptr;
+
+use crate::stm32::{TIM15, TIM2};
+
+use crate::gpio::gpioa::{PA0, PA15, PA2, PA5};
+use crate::gpio::gpiob::PB14;
+use crate::gpio::{Alternate, AF1, AF14};
+use crate::rcc::{Clocks, APB1R1, APB2};
+use crate::time::Hertz;
+
+/// Offset of the slave mode control register
+const SMCR: usize = 0x08;
+/// Offset of the capture/compare register 2
+const CCR2: usize = 0x38;
+
+// FIXME these should be "closed" traits
+/// CH1 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the CH1 input of `TIM`, the slave mode
+/// controller resets the counter on the edges of TI1
+pub unsafe trait Pin<TIM> {}
+
+unsafe impl<MODE> Pin<TIM2> for PA0<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> Pin<TIM2> for PA5<Alternate<AF1, MODE>> {}
+unsafe impl<MODE> Pin<TIM2> for PA15<Alternate<AF1, MODE>> {}
+
+unsafe impl<MODE> Pin<TIM15> for PA2<Alternate<AF14, MODE>> {}
+unsafe impl<MODE> Pin<TIM15> for PB14<Alternate<AF14, MODE>> {}
+
+/// A timer measuring the PWM signal on its channel 1 pin
+pub struct PwmInput<TIM, PIN> {
+    tim: TIM,
+    pin: PIN,
+    tick: Hertz,
+}
+
+macro_rules! hal {
+    ($($TIM:ident: ($tim:ident, $timXen:ident, $timXrst:ident, $apb:ident, $timclk:ident, $max:expr),)+) => {
+        $(
+            impl<PIN> PwmInput<$TIM, PIN> {
+                /// Starts measuring the signal on `pin`
+                ///
+                /// The counter resolution is chosen as fine as possible while still being able to
+                /// measure a signal as slow as `min_frequency`.
+                pub fn $tim<F>(
+                    tim: $TIM,
+                    pin: PIN,
+                    min_frequency: F,
+                    clocks: Clocks,
+                    apb: &mut $apb,
+                ) -> Self
+                where
+                    PIN: Pin<$TIM>,
+                    F: Into<Hertz>,
+                {
+                    apb.enr().modify(|_, w| w.$timXen().set_bit());
+                    apb.rstr().modify(|_, w| w.$timXrst().set_bit());
+                    apb.rstr().modify(|_, w| w.$timXrst().clear_bit());
+
+                    let clk = clocks.$timclk().0;
+                    let ticks = u64::from(clk / min_frequency.into().0);
+                    let psc = ticks / ($max as u64 + 1);
+                    assert!(psc <= 0xffff);
+                    let tick = Hertz(clk / (psc as u32 + 1));
+
+                    tim.psc.write(|w| unsafe { w.bits(psc as u32) });
+                    tim.arr.write(|w| unsafe { w.bits($max) });
+
+                    // CC1S = TI1, CC2S = TI1
+                    tim.ccmr1_output.write(|w| unsafe { w.bits((0b10 << 8) | 0b01) });
+                    // CC1 on rising edges, CC2 on falling edges (CC2P)
+                    tim.ccer.write(|w| unsafe { w.bits((1 << 5) | (1 << 4) | 1) });
+
+                    // NOTE(unsafe) SMCR and CCR2 are missing from the SVD of TIM15, so they are
+                    // accessed by address for every timer; the timer is owned by `self`
+                    unsafe {
+                        // TS = TI1FP1, SMS = reset mode
+                        ptr::write_volatile(Self::reg(SMCR), (0b101 << 4) | 0b100);
+                    }
+
+                    // load the prescaler, then only raise an update event on overflows (URS) so
+                    // that UIF tells that the signal was lost
+                    tim.egr.write(|w| w.ug().set_bit());
+                    tim.cr1.write(|w| w.urs().set_bit().cen().set_bit());
+                    tim.sr.write(|w| unsafe { w.bits(0) });
+
+                    PwmInput { tim, pin, tick }
+                }
+
+                /// Returns the frequency of the counter, i.e. the resolution of the measurements
+                pub fn tick_frequency(&self) -> Hertz {
+                    self.tick
+                }
+
+                /// Returns the period and the high time of the last pulse in counter ticks
+                ///
+                /// Returns `None` until a full period has been captured, and once the counter
+                /// has overflowed without seeing a rising edge, i.e. the signal was lost or is
+                /// slower than the `min_frequency` given on creation.
+                pub fn read(&self) -> Option<(u32, u32)> {
+                    let sr = self.tim.sr.read();
+                    if sr.cc1if().bit_is_set() {
+                        // a rising edge reset the counter, forget about earlier overflows; CC1IF
+                        // itself is cleared by reading CCR1
+                        self.tim.sr.write(|w| unsafe { w.bits(!1) });
+                    } else if sr.uif().bit_is_set() {
+                        return None;
+                    }
+
+                    // NOTE(unsafe) see `$tim`; the reads have no side effects
+                    let period = self.tim.ccr1.read().bits();
+                    let high = unsafe { ptr::read_volatile(Self::reg(CCR2)) };
+                    if period == 0 {
+                        None
+                    } else {
+                        Some((period, high.min(period)))
+                    }
+                }
+
+                /// Returns the frequency of the signal, see `read`
+                pub fn frequency(&self) -> Option<Hertz> {
+                    self.read().map(|(period, _)| Hertz(self.tick.0 / period))
+                }
+
+                /// Returns the duty cycle of the signal as the high time over the period, see
+                /// `read`
+                pub fn duty_cycle(&self) -> Option<(u32, u32)> {
+                    self.read().map(|(period, high)| (high, period))
+                }
+
+                /// Releases the TIM peripheral and the pin
+                pub fn free(self) -> ($TIM, PIN) {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    (self.tim, self.pin)
+                }
+
+                fn reg(offset: usize) -> *mut u32 {
+                    ($TIM::ptr() as usize + offset) as *mut u32
+                }
+            }
+        )+
+    }
+}
+
+hal! {
+    TIM2: (tim2, tim2en, tim2rst, APB1R1, timclk1, 0xffff_ffff),
+    TIM15: (tim15, tim15en, tim15rst, APB2, timclk2, 0xffff),
+}
@@ -1,8 +1,95 @@
 //! Power management
 
+use cortex_m::interrupt;
+use cortex_m::peripheral::SCB;
+
 use crate::rcc::{APB1R1};
-use crate::stm32::{pwr, PWR};
+use crate::stm32::{pwr, EXTI, PWR};
+
+/// EXTI line of the PVD output
+const PVD_LINE: u8 = 16;
+
+/// Power error
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// A transfer was in flight, Stop mode wasn't entered
+    Busy,
+}
+
+/// Stop mode
+pub enum StopMode {
+    /// Stop 0, the main regulator stays on for the fastest wakeup
+    Stop0,
+    /// Stop 1, the main regulator is off
+    Stop1,
+    /// Stop 2, the lowest consumption, with fewer peripherals available as wakeup sources
+    Stop2,
+}
 
+/// Activity that has to be over before entering Stop mode, e.g. the transmission of the last
+/// byte written to a USART, which is cut (and corrupted) when its clock stops
+pub trait Busy {
+    /// Returns `true` while the activity is in progress
+    fn is_busy(&self) -> bool;
+}
+
+/// PVD threshold on VDD
+#[derive(Clone, Copy)]
+pub enum PvdThreshold {
+    /// 2.0 V
+    V2_0 = 0b000,
+    /// 2.2 V
+    V2_2 = 0b001,
+    /// 2.4 V
+    V2_4 = 0b010,
+    /// 2.5 V
+    V2_5 = 0b011,
+    /// 2.6 V
+    V2_6 = 0b100,
+    /// 2.8 V
+    V2_8 = 0b101,
+    /// 2.9 V
+    V2_9 = 0b110,
+    /// PVD_IN pin (PB7) compared to the internal reference voltage
+    External = 0b111,
+}
+
+/// Edges of the PVD (or PVM) output raising the `PVD_PVM` interrupt
+#[derive(Clone, Copy)]
+pub enum PvdMode {
+    /// No interrupt, the output is only polled with `Pwr::pvd_output` or `Pwr::pvm_output`
+    Polled,
+    /// VDD falls below the threshold
+    Falling,
+    /// VDD rises above the threshold
+    Rising,
+    /// VDD crosses the threshold in either direction
+    Both,
+}
+
+/// Peripheral voltage monitor
+#[derive(Clone, Copy)]
+pub enum Pvm {
+    /// PVM1, VDDUSB above 1.2 V
+    Vddusb,
+    /// PVM3, VDDA above 1.62 V, required by the ADC, the DAC and the comparators
+    Vdda1V62,
+    /// PVM4, VDDA above 2.2 V, required by the DAC buffers and the OPAMPs at full speed
+    Vdda2V2,
+}
+
+impl Pvm {
+    /// Returns the EXTI line and the bit of the monitor in CR2 (PVMEx) and SR2 (PVMOx)
+    fn line_and_bit(self) -> (u8, u8) {
+        match self {
+            Pvm::Vddusb => (35, 4),
+            Pvm::Vdda1V62 => (37, 6),
+            Pvm::Vdda2V2 => (38, 7),
+        }
+    }
+}
 
 pub struct Pwr {
     pub cr1: CR1,
@@ -30,6 +117,253 @@ impl PwrExt for PWR {
     }
 }
 
+impl Pwr {
+    /// Keeps the content of SRAM2 in Standby mode
+    ///
+    /// SRAM2 is otherwise lost when entering Standby, like SRAM1. Retention increases the
+    /// Standby consumption by the SRAM2 leakage.
+    pub fn set_sram2_retention(&mut self, retain: bool) {
+        self.cr3.reg().modify(|_, w| w.rrs().bit(retain));
+    }
+
+    /// Returns `true` if SRAM2 is retained in Standby mode
+    pub fn sram2_retention(&mut self) -> bool {
+        self.cr3.reg().read().rrs().bit_is_set()
+    }
+
+    /// Returns `true` if the device has been woken up from Standby mode, and clears the flag
+    ///
+    /// When it returns `true` and SRAM2 retention was enabled before entering Standby, the
+    /// content of SRAM2 is valid.
+    pub fn woke_from_standby(&mut self) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        let sbf = unsafe { (*PWR::ptr()).sr1.read().bits() } & (1 << 8) != 0;
+        // NOTE(unsafe) atomic write to a stateless register, CSBF
+        unsafe { (*PWR::ptr()).scr.write(|w| w.bits(1 << 8)) };
+        sbf
+    }
+
+    /// Enables the programmable voltage detector, comparing VDD to `threshold`
+    ///
+    /// The `PVD_PVM` interrupt (EXTI line 16) is raised on the edges selected by `mode`, it has
+    /// to be unmasked in the NVIC and acknowledged with `clear_pvd_interrupt`. On a falling
+    /// supply the interrupt leaves some time to save state before the brown-out reset, whose
+    /// level is set with `flash::OPTR::set_bor_level`.
+    pub fn enable_pvd(&mut self, threshold: PvdThreshold, mode: PvdMode) {
+        self.cr2
+            .reg()
+            .modify(|_, w| unsafe { w.pls().bits(threshold as u8).pvde().set_bit() });
+
+        // the output of the PVD is set when VDD is below the threshold
+        let (falling, rising) = match mode {
+            PvdMode::Polled => (false, false),
+            PvdMode::Falling => (false, true),
+            PvdMode::Rising => (true, false),
+            PvdMode::Both => (true, true),
+        };
+        exti_listen(PVD_LINE, rising, falling);
+    }
+
+    /// Disables the programmable voltage detector and its interrupt
+    pub fn disable_pvd(&mut self) {
+        exti_listen(PVD_LINE, false, false);
+        self.cr2.reg().modify(|_, w| w.pvde().clear_bit());
+    }
+
+    /// Returns `true` while VDD is below the PVD threshold
+    pub fn pvd_output(&self) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*PWR::ptr()).sr2.read().pvdo().bit_is_set() }
+    }
+
+    /// Returns `true` if the PVD interrupt is pending
+    pub fn is_pvd_pending(&self) -> bool {
+        exti_pending(PVD_LINE)
+    }
+
+    /// Clears the pending PVD interrupt
+    pub fn clear_pvd_interrupt(&mut self) {
+        exti_clear(PVD_LINE);
+    }
+
+    /// Enables the peripheral voltage monitor `pvm`
+    ///
+    /// The `PVD_PVM` interrupt is raised on the edges selected by `mode`, it has to be
+    /// acknowledged with `clear_pvm_interrupt`. The monitor takes some microseconds to settle.
+    ///
+    /// ``` ignore
+    /// // VDDUSB has to be present before the USB peripheral is used
+    /// pwr.enable_pvm(Pvm::Vddusb, PvdMode::Polled);
+    /// delay.delay_us(10_u32);
+    /// while pwr.pvm_output(Pvm::Vddusb) {}
+    /// ```
+    pub fn enable_pvm(&mut self, pvm: Pvm, mode: PvdMode) {
+        let (line, bit) = pvm.line_and_bit();
+        self.cr2
+            .reg()
+            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << bit)) });
+
+        // the output of the monitor is set when the supply is below the threshold
+        let (falling, rising) = match mode {
+            PvdMode::Polled => (false, false),
+            PvdMode::Falling => (false, true),
+            PvdMode::Rising => (true, false),
+            PvdMode::Both => (true, true),
+        };
+        exti_listen(line, rising, falling);
+    }
+
+    /// Disables the peripheral voltage monitor `pvm` and its interrupt
+    pub fn disable_pvm(&mut self, pvm: Pvm) {
+        let (line, bit) = pvm.line_and_bit();
+        exti_listen(line, false, false);
+        self.cr2
+            .reg()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << bit)) });
+    }
+
+    /// Returns `true` while the supply monitored by `pvm` is below its threshold
+    pub fn pvm_output(&self, pvm: Pvm) -> bool {
+        let (_, bit) = pvm.line_and_bit();
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*PWR::ptr()).sr2.read().bits() & (1 << (bit + 8)) != 0 }
+    }
+
+    /// Returns `true` if the interrupt of `pvm` is pending
+    pub fn is_pvm_pending(&self, pvm: Pvm) -> bool {
+        exti_pending(pvm.line_and_bit().0)
+    }
+
+    /// Clears the pending interrupt of `pvm`
+    pub fn clear_pvm_interrupt(&mut self, pvm: Pvm) {
+        exti_clear(pvm.line_and_bit().0);
+    }
+
+    /// Returns a guard entering Stop mode only once transfers are over
+    ///
+    /// ``` ignore
+    /// tx.write_str("going to sleep\n").unwrap();
+    /// pwr.guard()
+    ///     .wait(&tx)
+    ///     .check(&transfer)
+    ///     .enter_stop(StopMode::Stop2, &mut cp.SCB)?;
+    /// ```
+    pub fn guard(&mut self) -> PwrGuard<'_> {
+        PwrGuard {
+            pwr: self,
+            busy: false,
+        }
+    }
+
+    /// Enters Standby mode, the device restarts from reset on wakeup
+    ///
+    /// The wakeup flags are cleared before entering Standby, so that an already pending wakeup
+    /// event does not wake the device right away.
+    pub fn enter_standby(&mut self, scb: &mut SCB) -> ! {
+        // NOTE(unsafe) atomic write to a stateless register, CWUF1..5
+        unsafe { (*PWR::ptr()).scr.write(|w| w.bits(0b1_1111)) };
+        self.cr1.reg().modify(|_, w| unsafe { w.lpms().bits(0b011) });
+        scb.set_sleepdeep();
+
+        loop {
+            cortex_m::asm::dsb();
+            cortex_m::asm::wfi();
+        }
+    }
+}
+
+/// Unmasks the interrupt of the EXTI `line` on the selected edges, masks it if none is
+pub(crate) fn exti_listen(line: u8, rising: bool, falling: bool) {
+    let mask = 1 << (line % 32);
+    let set = |bits: u32, on: bool| if on { bits | mask } else { bits & !mask };
+
+    interrupt::free(|_| {
+        // NOTE(unsafe) read-modify-writes in a critical section, like all the read-modify-writes
+        // of these registers
+        let exti = unsafe { &*EXTI::ptr() };
+        if line < 32 {
+            exti.rtsr1.modify(|r, w| unsafe { w.bits(set(r.bits(), rising)) });
+            exti.ftsr1.modify(|r, w| unsafe { w.bits(set(r.bits(), falling)) });
+            exti.imr1.modify(|r, w| unsafe { w.bits(set(r.bits(), rising || falling)) });
+        } else {
+            exti.rtsr2.modify(|r, w| unsafe { w.bits(set(r.bits(), rising)) });
+            exti.ftsr2.modify(|r, w| unsafe { w.bits(set(r.bits(), falling)) });
+            exti.imr2.modify(|r, w| unsafe { w.bits(set(r.bits(), rising || falling)) });
+        }
+    });
+}
+
+/// Returns `true` if the interrupt of the EXTI `line` is pending
+pub(crate) fn exti_pending(line: u8) -> bool {
+    // NOTE(unsafe) atomic reads with no side effects
+    let exti = unsafe { &*EXTI::ptr() };
+    let pr = if line < 32 {
+        exti.pr1.read().bits()
+    } else {
+        exti.pr2.read().bits()
+    };
+    pr & (1 << (line % 32)) != 0
+}
+
+/// Clears the pending interrupt of the EXTI `line`
+pub(crate) fn exti_clear(line: u8) {
+    // NOTE(unsafe) the pending bits are write 1 to clear, other lines are not affected
+    let exti = unsafe { &*EXTI::ptr() };
+    if line < 32 {
+        exti.pr1.write(|w| unsafe { w.bits(1 << line) });
+    } else {
+        exti.pr2.write(|w| unsafe { w.bits(1 << (line - 32)) });
+    }
+}
+
+/// Guard entering Stop mode once the registered activities are over, see `Pwr::guard`
+pub struct PwrGuard<'a> {
+    pwr: &'a mut Pwr,
+    busy: bool,
+}
+
+impl<'a> PwrGuard<'a> {
+    /// Prevents entering Stop mode if `activity` is in progress
+    pub fn check<B>(mut self, activity: &B) -> Self
+    where
+        B: Busy,
+    {
+        self.busy |= activity.is_busy();
+        self
+    }
+
+    /// Waits for `activity` to be over
+    pub fn wait<B>(self, activity: &B) -> Self
+    where
+        B: Busy,
+    {
+        while activity.is_busy() {}
+        self
+    }
+
+    /// Enters Stop `mode` unless a checked activity was in progress, and returns on wakeup
+    ///
+    /// The system clock is MSI or HSI16 on wakeup, depending on `RCC_CFGR.STOPWUCK`.
+    pub fn enter_stop(self, mode: StopMode, scb: &mut SCB) -> Result<(), Error> {
+        if self.busy {
+            return Err(Error::Busy);
+        }
+
+        let lpms = match mode {
+            StopMode::Stop0 => 0b000,
+            StopMode::Stop1 => 0b001,
+            StopMode::Stop2 => 0b010,
+        };
+        self.pwr.cr1.reg().modify(|_, w| unsafe { w.lpms().bits(lpms) });
+        scb.set_sleepdeep();
+        cortex_m::asm::dsb();
+        cortex_m::asm::wfi();
+        scb.clear_sleepdeep();
+
+        Ok(())
+    }
+}
+
 /// CR1
 pub struct CR1 {
     _0: (),
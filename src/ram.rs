@@ -0,0 +1,109 @@
+//! RAM regions and DMA buffers
+//!
+//! The DMA controllers access the SRAM through the system bus only, at `0x2000_0000`: SRAM1,
+//! followed by SRAM2. SRAM2 is also mapped at `SRAM2_CODE`, for code executed from it, but a
+//! buffer placed there can't be reached by the DMA, and a transfer from or to it fails with a
+//! transfer error. A `memory.x` placing data in SRAM2 has to use its system bus address, e.g.
+//! `0x2000_C000` on the STM32L432 (48 KB of SRAM1) or `0x2001_8000` on the STM32L476 (96 KB):
+//!
+//! ``` text
+//! MEMORY
+//! {
+//!   FLASH : ORIGIN = 0x08000000, LENGTH = 256K
+//!   RAM : ORIGIN = 0x20000000, LENGTH = 48K
+//!   SRAM2 : ORIGIN = 0x2000C000, LENGTH = 16K
+//! }
+//!
+//! SECTIONS
+//! {
+//!   .sram2 (NOLOAD) : ALIGN(4)
+//!   {
+//!     *(.sram2 .sram2.*);
+//!     . = ALIGN(4);
+//!   } > SRAM2
+//! } INSERT AFTER .bss;
+//! ```
+//!
+//! `dma_buffer!` then places a buffer in a section, aligned for any DMA transfer size, and
+//! hands it out once:
+//!
+//! ``` ignore
+//! let buffer: &'static mut [u16; 64] =
+//!     dma_buffer!(#[link_section = ".sram2"] : [u16; 64] = [0; 64]).unwrap();
+//! assert!(ram::is_dma_accessible(buffer.as_ptr() as u32, 128));
+//! ```
+
+/// Start of the SRAM on the system bus, accessible to the DMA
+pub const SRAM_START: u32 = 0x2000_0000;
+/// End of the SRAM of the largest device of the family (160 KB on the STM32L45x), the actual
+/// size depends on the device
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2"))]
+pub const SRAM_END: u32 = 0x2002_8000;
+/// End of the SRAM (64 KB)
+#[cfg(feature = "stm32l4x3")]
+pub const SRAM_END: u32 = 0x2001_0000;
+/// End of the SRAM (128 KB)
+#[cfg(feature = "stm32l4x5")]
+pub const SRAM_END: u32 = 0x2002_0000;
+/// End of the SRAM of the largest device of the family (320 KB on the STM32L49x/L4Ax), the
+/// actual size depends on the device
+#[cfg(feature = "stm32l4x6")]
+pub const SRAM_END: u32 = 0x2005_0000;
+/// SRAM2 mapped for code execution, not accessible to the DMA
+pub const SRAM2_CODE: u32 = 0x1000_0000;
+
+/// Returns `true` if the `len` bytes starting at `address` are in the SRAM as seen by the DMA
+///
+/// The SRAM size of the device isn't checked, only that the range is within `SRAM_START` and
+/// `SRAM_END`, e.g. not in the SRAM2 code alias. The DMA can also read the flash memory, e.g.
+/// constant data sent to a peripheral, which this function doesn't cover.
+pub fn is_dma_accessible(address: u32, len: usize) -> bool {
+    address >= SRAM_START && u64::from(address) + len as u64 <= u64::from(SRAM_END)
+}
+
+/// Fails to compile unless the type `T` is aligned to at least `N` bytes, e.g. the size of the
+/// DMA transfers of a buffer
+///
+/// ``` ignore
+/// assert_dma_aligned!([u32; 16], 4);
+/// ```
+#[macro_export]
+macro_rules! assert_dma_aligned {
+    ($ty:ty, $align:expr) => {
+        const _: () = assert!(
+            core::mem::align_of::<$ty>() >= $align,
+            "the buffer isn't aligned to the DMA transfer size"
+        );
+    };
+}
+
+/// Returns a `&'static mut` reference to a buffer of type `T` initialized with `EXPR` the first
+/// time it's evaluated, `None` afterwards
+///
+/// The buffer is aligned to 4 bytes whatever its type, e.g. an array of bytes transferred by
+/// words, and can be placed in a linker section. It's only initialized by `EXPR`, so the section
+/// can be `NOLOAD`.
+///
+/// ``` ignore
+/// let rx: &'static mut [u8; 256] = dma_buffer!(: [u8; 256] = [0; 256]).unwrap();
+/// let tx = dma_buffer!(#[link_section = ".sram2"] : [u8; 256] = [0; 256]).unwrap();
+/// ```
+#[macro_export]
+macro_rules! dma_buffer {
+    ($(#[link_section = $section:literal])? : $ty:ty = $expr:expr) => {{
+        #[repr(C, align(4))]
+        struct Aligned(core::mem::MaybeUninit<$ty>);
+
+        static TAKEN: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+        $(#[link_section = $section])?
+        static mut BUFFER: Aligned = Aligned(core::mem::MaybeUninit::uninit());
+
+        if TAKEN.swap(true, core::sync::atomic::Ordering::AcqRel) {
+            None
+        } else {
+            // NOTE(unsafe) the buffer is only handed out once, see `TAKEN`
+            let buffer: &'static mut Aligned = unsafe { &mut *core::ptr::addr_of_mut!(BUFFER) };
+            Some(buffer.0.write($expr))
+        }
+    }};
+}
@@ -1,14 +1,17 @@
 //! Reset and Clock Control
 
 use core::cmp;
+use core::ptr;
 
 use cast::u32;
-use crate::stm32::{rcc, RCC};
+use crate::stm32::{self, rcc, RCC};
 
 use crate::flash::ACR;
-use crate::time::Hertz;
+use crate::pwr;
+use crate::time::{Hertz, U32Ext};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum MsiFreq {
     #[doc = "range 0 around 100 kHz"]
     RANGE100K = 0,
@@ -54,11 +57,12 @@ impl RccExt for RCC {
             apb2: APB2 { _0: () },
             bdcr: BDCR { _0: () },
             csr: CSR { _0: () },
-            #[cfg(not(feature = "stm32l47x"))]
+            icscr: ICSCR { _0: () },
+            #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
             crrcr: CRRCR { _0: () },
             cfgr: CFGR {
                 hclk: None,
-                #[cfg(not(feature = "stm32l47x"))]
+                #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
                 hsi48: false,
                 msi: None,
                 lsi: false,
@@ -91,8 +95,10 @@ pub struct Rcc {
     pub bdcr: BDCR,
     /// Control/Status Register
     pub csr: CSR,
+    /// Internal clock sources calibration register
+    pub icscr: ICSCR,
     /// Clock recovery RC register
-    #[cfg(not(feature = "stm32l47x"))]
+    #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
     pub crrcr: CRRCR,
 }
 
@@ -110,29 +116,107 @@ impl CSR {
     }
 }
 
+/// Largest HSI16 trimming value
+#[cfg(not(any(feature = "stm32l4x5", feature = "stm32l4x6")))]
+pub const HSI_TRIM_MAX: u8 = 0x1F;
+/// Largest HSI16 trimming value
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+pub const HSI_TRIM_MAX: u8 = 0x7F;
+
+/// ICSCR Internal clock sources calibration register
+///
+/// The factory calibration (`*_calibration`) is loaded at reset, and the trimming value is added
+/// to it; trimming values found during production test can be stored, e.g. in flash, and applied
+/// again at startup.
+pub struct ICSCR {
+    _0: (),
+}
+
+impl ICSCR {
+    fn icscr(&mut self) -> &rcc::ICSCR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*RCC::ptr()).icscr }
+    }
+
+    /// Returns the factory calibration of the MSI
+    pub fn msi_calibration(&mut self) -> u8 {
+        self.icscr().read().msical().bits()
+    }
+
+    /// Returns the trimming value of the MSI, the default is 0
+    pub fn msi_trim(&mut self) -> u8 {
+        self.icscr().read().msitrim().bits()
+    }
+
+    /// Sets the trimming value of the MSI, a two's complement added to the calibration, and
+    /// returns the value read back
+    pub fn set_msi_trim(&mut self, trim: u8) -> u8 {
+        self.icscr().modify(|_, w| unsafe { w.msitrim().bits(trim) });
+        self.msi_trim()
+    }
+
+    /// Returns the factory calibration of the HSI16
+    pub fn hsi_calibration(&mut self) -> u8 {
+        self.icscr().read().hsical().bits()
+    }
+
+    /// Returns the trimming value of the HSI16, the default is 16 (0x40 on the STM32L4x5/x6)
+    pub fn hsi_trim(&mut self) -> u8 {
+        self.icscr().read().hsitrim().bits()
+    }
+
+    /// Sets the trimming value of the HSI16, up to `HSI_TRIM_MAX`, and returns the value read
+    /// back
+    ///
+    /// A step is about 0.2 % of the frequency, increasing values increase the frequency.
+    pub fn set_hsi_trim(&mut self, trim: u8) -> u8 {
+        assert!(trim <= HSI_TRIM_MAX);
+
+        self.icscr().modify(|_, w| unsafe { w.hsitrim().bits(trim) });
+        self.hsi_trim()
+    }
+}
+
 /// Clock recovery RC register
-#[cfg(not(feature = "stm32l47x"))]
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
 pub struct CRRCR {
     _0: (),
 }
 
-#[cfg(not(feature = "stm32l47x"))]
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
 impl CRRCR {
-    // TODO remove `allow`
-    #[allow(dead_code)]
-    pub(crate) fn crrcr(&mut self) -> &rcc::CRRCR {
-        // NOTE(unsafe) this proxy grants exclusive access to this register
-        unsafe { &(*RCC::ptr()).crrcr }
+    /// Returns the address of the register, which the SVD of the STM32L4x3 omits
+    fn ptr() -> *mut u32 {
+        (RCC::ptr() as usize + 0x98) as *mut u32
+    }
+
+    fn bits() -> u32 {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { ptr::read_volatile(Self::ptr()) }
+    }
+
+    /// Turns HSI48 on and waits until it's ready
+    fn enable_hsi48() {
+        // NOTE(unsafe) the other bits are read-only, the clock configuration owns HSI48ON
+        unsafe { ptr::write_volatile(Self::ptr(), Self::bits() | HSI48ON) };
+        while Self::bits() & HSI48RDY == 0 {}
     }
 
     pub fn is_hsi48_on(&mut self) -> bool {
-        self.crrcr().read().hsi48on().bit()
+        Self::bits() & HSI48ON != 0
     }
     pub fn is_hsi48_ready(&mut self) -> bool {
-        self.crrcr().read().hsi48rdy().bit()
+        Self::bits() & HSI48RDY != 0
     }
 }
 
+/// CRRCR: HSI48ON
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+const HSI48ON: u32 = 1 << 0;
+/// CRRCR: HSI48RDY
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+const HSI48RDY: u32 = 1 << 1;
+
 /// BDCR Backup domain control register registers
 pub struct BDCR {
     _0: (),
@@ -145,6 +229,105 @@ impl BDCR {
         // NOTE(unsafe) this proxy grants exclusive access to this register
         unsafe { &(*RCC::ptr()).bdcr }
     }
+
+    /// Enables the write access to the backup domain, which keeps its state, and returns a
+    /// token of it
+    ///
+    /// The backup domain (RTC, LSE and backup registers) survives resets and Standby as long as
+    /// VBAT is supplied, so this can be called on every startup, and any number of times.
+    pub fn backup_domain(&mut self, apb1r1: &mut APB1R1, pwrcr1: &mut pwr::CR1) -> BackupDomain {
+        // the RTC registers are accessed through APB1
+        apb1r1.enr().modify(|_, w| w.rtcapben().set_bit());
+        pwrcr1.reg().read(); // read to allow the pwr clock to enable
+
+        pwrcr1.reg().modify(|_, w| w.dbp().set_bit());
+        while pwrcr1.reg().read().dbp().bit_is_clear() {}
+
+        BackupDomain { _0: () }
+    }
+
+    /// Resets the backup domain, and returns a token of it, see `backup_domain`
+    ///
+    /// The RTC is stopped and its clock source unselected, the LSE is turned off and the backup
+    /// registers are cleared. This is required to change the clock source of the RTC once
+    /// selected.
+    pub fn backup_domain_reset(
+        &mut self,
+        apb1r1: &mut APB1R1,
+        pwrcr1: &mut pwr::CR1,
+    ) -> BackupDomain {
+        let backup = self.backup_domain(apb1r1, pwrcr1);
+
+        self.enr().modify(|_, w| w.bdrst().set_bit());
+        self.enr().modify(|_, w| w.bdrst().clear_bit());
+
+        backup
+    }
+}
+
+/// Number of backup registers
+pub const BACKUP_REGISTERS: usize = 32;
+
+/// Write access to the backup domain, see `BDCR::backup_domain`
+///
+/// The RTC and the LSE are configured through it, which makes the backup domain reset of
+/// changing the RTC clock source explicit, and it gives access to the backup registers.
+pub struct BackupDomain {
+    _0: (),
+}
+
+impl BackupDomain {
+    pub(crate) fn bdcr(&mut self) -> &rcc::BDCR {
+        // NOTE(unsafe) the fields of BDCR are owned by this token once `BDCR::backup_domain` was
+        // called
+        unsafe { &(*RCC::ptr()).bdcr }
+    }
+
+    /// Turns the LSE on, bypassed by an external clock or with the crystal oscillator driven at
+    /// `drive` (0, the lowest, to 3), and waits until it's ready
+    ///
+    /// The LSE keeps running from VBAT, it's only turned on if it isn't already.
+    pub fn enable_lse(&mut self, bypass: bool, drive: u8) {
+        assert!(drive <= 3);
+
+        if self.is_lse_ready() {
+            return;
+        }
+
+        // LSEBYP and LSEDRV can only be written while the LSE is off
+        self.bdcr()
+            .modify(|_, w| unsafe { w.lsebyp().bit(bypass).lsedrv().bits(drive) });
+        self.bdcr().modify(|_, w| w.lseon().set_bit());
+        while !self.is_lse_ready() {}
+    }
+
+    /// Returns `true` if the LSE is on and stable
+    pub fn is_lse_ready(&mut self) -> bool {
+        self.bdcr().read().lserdy().bit_is_set()
+    }
+
+    /// Reads the backup register `index`, below `BACKUP_REGISTERS`
+    pub fn read_backup_register(&self, index: usize) -> u32 {
+        assert!(index < BACKUP_REGISTERS);
+
+        // NOTE(unsafe) atomic read with no side effects, the backup registers are consecutive
+        unsafe { ptr::read_volatile(backup_register(index)) }
+    }
+
+    /// Writes the backup register `index`, below `BACKUP_REGISTERS`
+    pub fn write_backup_register(&mut self, index: usize, value: u32) {
+        assert!(index < BACKUP_REGISTERS);
+
+        // NOTE(unsafe) the backup registers are owned by this token, they aren't used by the
+        // `Rtc`
+        unsafe { ptr::write_volatile(backup_register(index), value) }
+    }
+}
+
+/// Returns the address of the backup register `index`
+pub(crate) fn backup_register(index: usize) -> *mut u32 {
+    // NOTE(unsafe) the address of a register, no access
+    unsafe { (ptr::addr_of!((*stm32::RTC::ptr()).bkp0r) as *mut u32).add(index) }
 }
 
 /// AMBA High-performance Bus 1 (AHB1) registers
@@ -258,13 +441,242 @@ impl APB2 {
     }
 }
 
+// FIXME these should be "closed" traits
+/// Peripheral whose clock and reset are controlled by the RCC - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// `BIT` must be the bit of the peripheral in the registers of `Bus`, other bits
+/// would change the clocks of another peripheral
+pub unsafe trait RccPeripheral {
+    /// The bus the peripheral is on
+    type Bus;
+
+    #[doc(hidden)]
+    /// Bit of the peripheral in the enable, reset and Sleep mode enable registers of its bus
+    const BIT: u8;
+}
+
+macro_rules! bus {
+    ($($BUS:ident: ($enr:ident, $smenr:ident, $SMENR:ident, $memories:expr),)+) => {
+        $(
+            impl $BUS {
+                pub(crate) fn smenr(&mut self) -> &rcc::$SMENR {
+                    // NOTE(unsafe) this proxy grants exclusive access to this register
+                    unsafe { &(*RCC::ptr()).$smenr }
+                }
+
+                /// Keeps or stops the clock of the peripheral `P` in Sleep and Stop modes
+                ///
+                /// The clock of every peripheral is kept by default. Stopping the clocks of the
+                /// peripherals that don't need to run (e.g. to wake the device up) lowers the
+                /// consumption in these modes.
+                pub fn set_sleep_clock<P>(&mut self, enabled: bool)
+                where
+                    P: RccPeripheral<Bus = $BUS>,
+                {
+                    self.smenr().modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(1 << P::BIT)) | (u32::from(enabled) << P::BIT))
+                    });
+                }
+
+                /// Stops the clocks of all the peripherals of the bus in Sleep and Stop modes,
+                /// the ones still needed are then selected with `set_sleep_clock`
+                ///
+                /// The clocks of the memories (flash and SRAMs) are kept, so that DMA transfers
+                /// can still run.
+                pub fn disable_sleep_clocks(&mut self) {
+                    self.smenr().modify(|r, w| unsafe { w.bits(r.bits() & $memories) });
+                }
+
+                /// Returns `true` if the clock of the peripheral `P` is enabled
+                pub fn is_enabled<P>(&self) -> bool
+                where
+                    P: RccPeripheral<Bus = $BUS>,
+                {
+                    // NOTE(unsafe) atomic read with no side effects
+                    let enr = unsafe { &(*RCC::ptr()).$enr };
+                    enr.read().bits() & (1 << P::BIT) != 0
+                }
+
+                /// Resets the peripheral `P` by pulsing its reset bit, e.g. to recover it from
+                /// a fault state
+                ///
+                /// All the registers of the peripheral are reset, so the driver owning it has to
+                /// configure it again; the clock enable bit is kept.
+                ///
+                /// ``` ignore
+                /// rcc.apb2.reset::<USART1>();
+                /// ```
+                pub fn reset<P>(&mut self)
+                where
+                    P: RccPeripheral<Bus = $BUS>,
+                {
+                    self.rstr().modify(|r, w| unsafe { w.bits(r.bits() | (1 << P::BIT)) });
+                    self.rstr().modify(|r, w| unsafe { w.bits(r.bits() & !(1 << P::BIT)) });
+                }
+
+                /// Stops the clock of the unused peripheral `P`, e.g. one left enabled by a
+                /// bootloader
+                ///
+                /// The registers of the peripheral can't be accessed until it is enabled again
+                /// by its driver.
+                pub fn disable<P>(&mut self, _peripheral: &P)
+                where
+                    P: RccPeripheral<Bus = $BUS>,
+                {
+                    self.enr().modify(|r, w| unsafe { w.bits(r.bits() & !(1 << P::BIT)) });
+                }
+
+                /// Resets the peripheral `P` and stops its clock, see the `release` methods of
+                /// the drivers
+                ///
+                /// The peripheral is left as after a reset of the chip, its driver configures it
+                /// again from scratch.
+                pub fn deinit<P>(&mut self, peripheral: &P)
+                where
+                    P: RccPeripheral<Bus = $BUS>,
+                {
+                    self.reset::<P>();
+                    self.disable(peripheral);
+                }
+            }
+        )+
+    }
+}
+
+bus! {
+    // FLASHSMEN, SRAM1SMEN
+    AHB1: (ahb1enr, ahb1smenr, AHB1SMENR, (1 << 8) | (1 << 9)),
+    // SRAM2SMEN
+    AHB2: (ahb2enr, ahb2smenr, AHB2SMENR, 1 << 9),
+    AHB3: (ahb3enr, ahb3smenr, AHB3SMENR, 0),
+    APB1R1: (apb1enr1, apb1smenr1, APB1SMENR1, 0),
+    APB1R2: (apb1enr2, apb1smenr2, APB1SMENR2, 0),
+    APB2: (apb2enr, apb2smenr, APB2SMENR, 0),
+}
+
+macro_rules! peripherals {
+    ($($BUS:ident: [$($PER:ident: $bit:expr,)+],)+) => {
+        $(
+            $(
+                unsafe impl RccPeripheral for stm32::$PER {
+                    type Bus = $BUS;
+                    const BIT: u8 = $bit;
+                }
+            )+
+        )+
+    }
+}
+
+peripherals! {
+    AHB1: [
+        DMA1: 0,
+        DMA2: 1,
+        CRC: 12,
+        TSC: 16,
+    ],
+    AHB2: [
+        GPIOA: 0,
+        GPIOB: 1,
+        GPIOC: 2,
+        GPIOD: 3,
+        GPIOE: 4,
+        GPIOH: 7,
+        AES: 16,
+        RNG: 18,
+    ],
+    APB1R1: [
+        TIM2: 0,
+        TIM6: 4,
+        TIM7: 5,
+        LCD: 9,
+        SPI2: 14,
+        SPI3: 15,
+        USART2: 17,
+        USART3: 18,
+        I2C1: 21,
+        I2C2: 22,
+        I2C3: 23,
+        CRS: 24,
+        CAN1: 25,
+        PWR: 28,
+        OPAMP: 30,
+        LPTIM1: 31,
+    ],
+    APB1R2: [
+        LPUART1: 0,
+        SWPMI1: 2,
+        LPTIM2: 5,
+    ],
+    APB2: [
+        SYSCFG: 0,
+        TIM1: 11,
+        SPI1: 12,
+        USART1: 14,
+        TIM15: 16,
+        TIM16: 17,
+        SAI1: 21,
+    ],
+}
+
+#[cfg(not(feature = "stm32l4x3"))]
+peripherals! {
+    AHB3: [
+        QUADSPI: 8,
+    ],
+}
+
+#[cfg(not(feature = "stm32l4x6"))]
+peripherals! {
+    APB1R1: [
+        DAC1: 29,
+    ],
+}
+
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x5", feature = "stm32l4x6"))]
+peripherals! {
+    APB1R1: [
+        TIM3: 1,
+        UART4: 19,
+    ],
+}
+
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x6"))]
+peripherals! {
+    APB1R2: [
+        I2C4: 1,
+    ],
+}
+
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+peripherals! {
+    AHB2: [
+        GPIOF: 5,
+        GPIOG: 6,
+    ],
+    AHB3: [
+        FMC: 0,
+    ],
+    APB1R1: [
+        TIM4: 2,
+        TIM5: 3,
+        UART5: 20,
+    ],
+    APB2: [
+        TIM8: 13,
+        TIM17: 18,
+        SAI2: 22,
+    ],
+}
+
 const HSI: u32 = 16_000_000; // Hz
 
 /// Clock configuration
 pub struct CFGR {
     hclk: Option<u32>,
     // should we use an option? it can really only be on/off
-    #[cfg(not(feature = "stm32l47x"))]
+    #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
     hsi48: bool,
     msi: Option<MsiFreq>,
     lsi: bool,
@@ -285,7 +697,7 @@ impl CFGR {
     }
 
     /// Enable the 48Mh USB, RNG, SDMMC clock source.
-    #[cfg(not(feature = "stm32l47x"))]
+    #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
     pub fn hsi48(mut self, on: bool) -> Self
     {
         self.hsi48 = on;
@@ -424,13 +836,7 @@ impl CFGR {
         // adjust flash wait states
         unsafe {
             acr.acr().write(|w| {
-                w.latency().bits(if sysclk <= 24_000_000 {
-                    0b000
-                } else if sysclk <= 48_000_000 {
-                    0b001
-                } else {
-                    0b010
-                })
+                w.latency().bits(latency_range1(sysclk))
             })
         }
 
@@ -511,19 +917,16 @@ impl CFGR {
     }
 
 
-    #[cfg(not(feature = "stm32l47x"))]
+    #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
     pub fn freeze(self, acr: &mut ACR) -> Clocks {
 
         let (hclk, pclk1, pclk2, ppre1, ppre2, sysclk) = self.common_freeze(acr);
         let mut usb_rng = false;
 
-        let rcc = unsafe { &*RCC::ptr() };
         // Turn on USB, RNG Clock using the HSI48CLK source (default)
-        if !cfg!(feature = "stm32l47x") && self.hsi48 {
+        if self.hsi48 {
             // p. 180 in ref-manual
-            rcc.crrcr.modify(|_, w| w.hsi48on().set_bit());
-            // Wait until HSI48 is running
-            while rcc.crrcr.read().hsi48rdy().bit_is_clear() {}
+            CRRCR::enable_hsi48();
             usb_rng = true;
         }
 
@@ -538,10 +941,11 @@ impl CFGR {
             ppre1,
             ppre2,
             sysclk,
+            saved: Saved::capture(acr),
         }
     }
 
-    #[cfg(feature = "stm32l47x")]
+    #[cfg(not(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3")))]
     pub fn freeze(self, acr: &mut ACR) -> Clocks {
 
         let (hclk, pclk1, pclk2, ppre1, ppre2, sysclk) = self.common_freeze(acr);
@@ -565,9 +969,135 @@ impl CFGR {
             ppre1,
             ppre2,
             sysclk,
+            saved: Saved::capture(acr),
+        }
+    }
+
+}
+
+impl CFGR {
+    /// Freezes the fastest configuration: 80 MHz from the PLL fed by HSI16, for the core and
+    /// both APBs, in voltage range 1 with 4 wait states
+    ///
+    /// The other settings but `lsi`, `msi` and `hsi48` are replaced.
+    pub fn freeze_max_performance(self, acr: &mut ACR, pwrcr1: &mut pwr::CR1) -> Clocks {
+        set_voltage_range(pwrcr1, VoltageRange::Range1);
+
+        CFGR {
+            hclk: None,
+            pclk1: None,
+            pclk2: None,
+            ..self
+        }
+        // (16 MHz / 1) * 10 / 2
+        .sysclk_with_pll(80_000_000.hz(), PllConfig { m: 0, n: 10, r: 0 })
+        .freeze(acr)
+    }
+
+    /// Freezes `freeze_max_performance` with the 48 MHz clock of the USB, RNG and SDMMC: HSI48
+    /// on the STM32L4x1/x2/x3, MSI at 48 MHz on the other devices
+    ///
+    /// The USB needs these clocks trimmed, by the CRS for HSI48 or by the LSE for MSI (MSIPLLEN).
+    pub fn freeze_usb(self, acr: &mut ACR, pwrcr1: &mut pwr::CR1) -> Clocks {
+        #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+        let cfgr = self.hsi48(true);
+        #[cfg(not(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3")))]
+        let cfgr = self.msi(MsiFreq::RANGE48M);
+
+        cfgr.freeze_max_performance(acr, pwrcr1)
+    }
+
+    /// Freezes the low-power run configuration: 2 MHz from MSI, for the core and both APBs,
+    /// with the regulator in low-power mode (LPR) and no wait state
+    ///
+    /// The PLL and HSI16 are stopped, the settings but `lsi` are ignored. The 48 MHz clock isn't
+    /// available, and the flash memory can't be erased or programmed in this mode.
+    pub fn freeze_low_power_2mhz(self, acr: &mut ACR, pwrcr1: &mut pwr::CR1) -> Clocks {
+        // NOTE(unsafe) the clock configuration is being frozen, `ACR` grants exclusive access
+        let rcc = unsafe { &*RCC::ptr() };
+
+        // the range can only change while MSI is off or ready
+        if rcc.cr.read().msion().bit_is_set() {
+            while rcc.cr.read().msirdy().bit_is_clear() {}
+        }
+        rcc.cr.modify(|_, w| unsafe {
+            w.msirange()
+                .bits(MsiFreq::RANGE2M as u8)
+                .msirgsel()
+                .set_bit()
+                .msion()
+                .set_bit()
+        });
+        while rcc.cr.read().msirdy().bit_is_clear() {}
+
+        // SW: MSI selected as system clock, no prescaler
+        rcc.cfgr.modify(|_, w| unsafe {
+            w.ppre2().bits(0).ppre1().bits(0).hpre().bits(0).sw().bits(0b00)
+        });
+        while rcc.cfgr.read().sws().bits() != 0b00 {}
+
+        rcc.cr.modify(|_, w| w.pllon().clear_bit());
+        while rcc.cr.read().pllrdy().bit_is_set() {}
+        rcc.cr.modify(|_, w| w.hsion().clear_bit());
+
+        // the latency is only lowered once the frequency is
+        acr.acr().modify(|_, w| unsafe { w.latency().bits(0b000) });
+
+        if self.lsi {
+            rcc.csr.modify(|_, w| w.lsion().set_bit());
+            while rcc.csr.read().lsirdy().bit_is_clear() {}
+        }
+
+        set_voltage_range(pwrcr1, VoltageRange::Range2);
+        pwrcr1.reg().modify(|_, w| w.lpr().set_bit());
+
+        let clk = Hertz(2_000_000);
+        Clocks {
+            hclk: clk,
+            #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+            hsi48: false,
+            usb_rng: false,
+            msi: Some(MsiFreq::RANGE2M),
+            lsi: self.lsi,
+            pclk1: clk,
+            pclk2: clk,
+            ppre1: 1,
+            ppre2: 1,
+            sysclk: clk,
+            saved: Saved::capture(acr),
         }
     }
+}
+
+/// Voltage range of the core regulator (VOS)
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VoltageRange {
+    /// High performance, up to 80 MHz
+    Range1 = 0b01,
+    /// Low power, up to 26 MHz
+    Range2 = 0b10,
+}
+
+/// Selects the voltage `range` of the main regulator, leaving low-power run first
+fn set_voltage_range(pwrcr1: &mut pwr::CR1, range: VoltageRange) {
+    pwrcr1.reg().modify(|_, w| w.lpr().clear_bit());
+    // NOTE(unsafe) atomic read with no side effects
+    let pwr = unsafe { &*stm32::PWR::ptr() };
+    while pwr.sr2.read().reglpf().bit_is_set() {}
+
+    pwrcr1.reg().modify(|_, w| unsafe { w.vos().bits(range as u8) });
+    while pwr.sr2.read().vosf().bit_is_set() {}
+}
 
+/// Returns the flash wait states at `sysclk` in voltage range 1
+fn latency_range1(sysclk: u32) -> u8 {
+    match sysclk {
+        0..=16_000_000 => 0b000,
+        16_000_001..=32_000_000 => 0b001,
+        32_000_001..=48_000_000 => 0b010,
+        48_000_001..=64_000_000 => 0b011,
+        _ => 0b100,
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -581,13 +1111,26 @@ pub struct PllConfig {
     pub r: u8,
 }
 
+/// Clock prerequisite of a peripheral that isn't met, returned by the `try_` constructors
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum ClockError {
+    /// The 48 MHz clock of the USB, SDMMC and RNG isn't enabled
+    Clock48,
+    /// The baud rate can't be generated from the kernel clock
+    BaudRate,
+    /// The bus frequency can't be generated from the kernel clock
+    Timing,
+}
+
 /// Frozen clock frequencies
 ///
 /// The existence of this value indicates that the clock configuration can no longer be changed
 #[derive(Clone, Copy, Debug)]
 pub struct Clocks {
     hclk: Hertz,
-    #[cfg(not(feature = "stm32l47x"))]
+    #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
     hsi48: bool,
     usb_rng: bool,
     msi: Option<MsiFreq>,
@@ -599,16 +1142,106 @@ pub struct Clocks {
     ppre1: u8,
     ppre2: u8,
     sysclk: Hertz,
+    saved: Saved,
+}
+
+/// Register values of a frozen clock configuration, see `Clocks::restore`
+#[derive(Clone, Copy, Debug)]
+struct Saved {
+    cr: u32,
+    cfgr: u32,
+    pllcfgr: u32,
+    latency: u8,
+    #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+    hsi48: bool,
+}
+
+/// CR: MSION
+const MSION: u32 = 1 << 0;
+/// CR: MSIRGSEL and MSIRANGE
+const MSIRANGE: u32 = 0b1_1111 << 3;
+/// CR: HSION
+const HSION: u32 = 1 << 8;
+/// CR: PLLON
+const PLLON: u32 = 1 << 24;
+/// CFGR: SW, HPRE, PPRE1 and PPRE2
+const CFGR_PRESCALERS: u32 = 0x3ff3;
+
+impl Saved {
+    fn capture(acr: &mut ACR) -> Self {
+        // NOTE(unsafe) atomic reads with no side effects
+        let rcc = unsafe { &*RCC::ptr() };
+
+        Saved {
+            cr: rcc.cr.read().bits(),
+            cfgr: rcc.cfgr.read().bits(),
+            pllcfgr: rcc.pllcfgr.read().bits(),
+            latency: acr.acr().read().latency().bits(),
+            #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+            hsi48: CRRCR::bits() & HSI48ON != 0,
+        }
+    }
 }
 
 impl Clocks {
+    /// Applies the frozen configuration again, e.g. after a wakeup from Stop mode
+    ///
+    /// The PLL and HSI48 are stopped in Stop mode, and the system clock is switched to MSI or
+    /// HSI16 on wakeup, with the prescalers kept. This restarts the oscillators and the PLL used
+    /// by the configuration, then switches the system clock back to it.
+    ///
+    /// ``` ignore
+    /// pwr.guard().enter_stop(StopMode::Stop2, &mut cp.SCB)?;
+    /// clocks.restore(&mut flash.acr);
+    /// ```
+    pub fn restore(&self, acr: &mut ACR) {
+        // NOTE(unsafe) the clock configuration is frozen, the registers are only written to
+        // apply it again, with `ACR` as a token of exclusive access
+        let rcc = unsafe { &*RCC::ptr() };
+        let saved = &self.saved;
+
+        // the wakeup clock is at most 16 MHz (or 48 MHz for MSI), more wait states are fine
+        acr.acr().modify(|_, w| unsafe { w.latency().bits(saved.latency) });
+
+        if saved.cr & HSION != 0 {
+            rcc.cr.modify(|_, w| w.hsion().set_bit());
+            while rcc.cr.read().hsirdy().bit_is_clear() {}
+        }
+
+        if saved.cr & MSION != 0 {
+            rcc.cr.modify(|r, w| unsafe {
+                w.bits((r.bits() & !MSIRANGE) | (saved.cr & MSIRANGE) | MSION)
+            });
+            while rcc.cr.read().msirdy().bit_is_clear() {}
+        }
+
+        if saved.cr & PLLON != 0 && rcc.cr.read().pllrdy().bit_is_clear() {
+            rcc.pllcfgr.write(|w| unsafe { w.bits(saved.pllcfgr) });
+            rcc.cr.modify(|_, w| w.pllon().set_bit());
+            while rcc.cr.read().pllrdy().bit_is_clear() {}
+        }
+
+        rcc.cfgr.modify(|r, w| unsafe {
+            w.bits((r.bits() & !CFGR_PRESCALERS) | (saved.cfgr & CFGR_PRESCALERS))
+        });
+        let sw = (saved.cfgr & 0b11) as u8;
+        while rcc.cfgr.read().sws().bits() != sw {}
+
+        #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
+        {
+            if saved.hsi48 {
+                CRRCR::enable_hsi48();
+            }
+        }
+    }
+
     /// Returns the frequency of the AHB
     pub fn hclk(&self) -> Hertz {
         self.hclk
     }
 
     /// Returns status of HSI48
-    #[cfg(not(feature = "stm32l47x"))]
+    #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2", feature = "stm32l4x3"))]
     pub fn hsi48(&self) -> bool {
         self.hsi48
     }
@@ -618,6 +1251,15 @@ impl Clocks {
         self.usb_rng
     }
 
+    /// Checks that the 48 MHz clock is available
+    pub fn check_usb_rng(&self) -> Result<(), ClockError> {
+        if self.usb_rng {
+            Ok(())
+        } else {
+            Err(ClockError::Clock48)
+        }
+    }
+
     /// Returns status of HSI48
     pub fn lsi(&self) -> bool {
         self.lsi
@@ -644,6 +1286,28 @@ impl Clocks {
         self.ppre2
     }
 
+    /// Returns the kernel clock frequency of the timers on APB1
+    ///
+    /// The timer clock is twice the bus clock whenever the APB1 prescaler is not 1
+    pub fn timclk1(&self) -> Hertz {
+        if self.ppre1 == 1 {
+            self.pclk1
+        } else {
+            Hertz(self.pclk1.0 * 2)
+        }
+    }
+
+    /// Returns the kernel clock frequency of the timers on APB2
+    ///
+    /// The timer clock is twice the bus clock whenever the APB2 prescaler is not 1
+    pub fn timclk2(&self) -> Hertz {
+        if self.ppre2 == 1 {
+            self.pclk2
+        } else {
+            Hertz(self.pclk2.0 * 2)
+        }
+    }
+
     /// Returns the system (core) frequency
     pub fn sysclk(&self) -> Hertz {
         self.sysclk
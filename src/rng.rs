@@ -4,24 +4,32 @@ use core::cmp;
 #[cfg(feature = "unproven")]
 use core::mem::transmute;
 
-use crate::rcc::{AHB2, Clocks};
+use crate::rcc::{AHB2, ClockError, Clocks};
 use crate::stm32::RNG;
 
 /// Extension trait to activate the RNG
 pub trait RngExt {
     /// Enables the RNG
+    ///
+    /// Panics if the 48 MHz clock isn't available, see `try_enable`.
     fn enable(self, ahb2: &mut AHB2, clocks: Clocks) -> Rng;
+
+    /// Enables the RNG, fails if the 48 MHz clock isn't available
+    fn try_enable(self, ahb2: &mut AHB2, clocks: Clocks) -> Result<Rng, ClockError>;
 }
 
 impl RngExt for RNG {
 
     fn enable(self, ahb2: &mut AHB2, clocks: Clocks) -> Rng {
+        self.try_enable(ahb2, clocks).unwrap()
+    }
+
+    fn try_enable(self, ahb2: &mut AHB2, clocks: Clocks) -> Result<Rng, ClockError> {
         // crrcr.crrcr().modify(|_, w| w.hsi48on().set_bit()); // p. 180 in ref-manual
         // ...this is now supposed to be done in RCC configuration before freezing
 
         // hsi48 should be turned on previously
-        // TODO: should we return a Result instead of asserting here?
-        assert!(clocks.usb_rng());
+        clocks.check_usb_rng()?;
 
         ahb2.enr().modify(|_, w| w.rngen().set_bit());
         // if we don't do this... we can be "too fast", and
@@ -30,9 +38,9 @@ impl RngExt for RNG {
 
         self.cr.modify(|_, w| w.rngen().set_bit());
 
-        Rng {
+        Ok(Rng {
             rng: self
-        }
+        })
     }
 }
 
@@ -96,6 +104,7 @@ impl Rng {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {}
 
 #[cfg(feature = "unproven")]
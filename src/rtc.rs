@@ -1,29 +1,119 @@
 //! RTC peripheral abstraction
 
 use crate::datetime::*;
-use crate::rcc::{BDCR, APB1R1, Clocks};
-use crate::pwr;
+use crate::rcc::{BackupDomain, BDCR, APB1R1, Clocks};
+use crate::pwr::{self, exti_clear, exti_listen, exti_pending};
 use crate::stm32::{RTC};
 
+/// EXTI line of the RTC alarms
+const ALARM_LINE: u8 = 18;
+/// ALRMxR: MSK4, the date is ignored
+const MSK4: u32 = 1 << 31;
+/// RTC clock periods of a smooth calibration cycle
+const CALIBRATION_PERIOD: i64 = 1 << 20;
+/// Exclusive bound of the delays of `Rtc::alarm_in`, the alarm can't tell apart the same date in
+/// two months
+const MAX_ALARM_DELAY: u32 = 28 * 24 * 3600;
+/// ISR: the flags cleared by writing 0, i.e. RSF, ALRAF to TAMP3F and ITSF
+const ISR_FLAGS: u32 = (1 << 5) | (0xff << 8) | (1 << 17);
+/// ISR: alarm A flag
+const ALRAF: u32 = 1 << 8;
+/// ISR: alarm B flag
+const ALRBF: u32 = 1 << 9;
+
+/// RTC alarm
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alarm {
+    /// Alarm A
+    A,
+    /// Alarm B
+    B,
+}
+
+/// Daylight saving time change, see `Rtc::adjust_dst`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DstChange {
+    /// Summer time, one hour forward (ADD1H)
+    Add1h,
+    /// Winter time, one hour backward (SUB1H)
+    Sub1h,
+}
+
+/// Frequency of the calibration output, derived from the RTC clock with the default prescalers
+pub enum CalibrationOutput {
+    /// 512 Hz, the RTC clock divided by 64
+    Hz512,
+    /// 1 Hz, after the smooth calibration
+    Hz1,
+}
+
+/// Event signalled on the alarm output
+pub enum AlarmOutput {
+    /// Alarm A
+    AlarmA,
+    /// Alarm B
+    AlarmB,
+    /// Wakeup timer
+    Wakeup,
+}
+
+/// Level of the alarm output when the event occurs
+pub enum OutputPolarity {
+    /// High
+    High,
+    /// Low
+    Low,
+}
+
+/// Output driver of the alarm output
+pub enum OutputType {
+    /// Open drain
+    OpenDrain,
+    /// Push-pull
+    PushPull,
+}
+
+/// Pin RTC_OUT is routed to
+pub enum OutputPin {
+    /// PC13
+    PC13,
+    /// PB2
+    PB2,
+}
+
 /// RTC Abstraction
 pub struct Rtc {
     rtc: RTC
 }
 
 impl Rtc {
+    /// Configures the RTC clocked by the LSI, after a reset of the backup domain, see `new`
     pub fn rtc(rtc: RTC, apb1r1: &mut APB1R1, bdcr: &mut BDCR, pwrcr1: &mut pwr::CR1, clocks: Clocks) -> Self {
+        let mut backup = bdcr.backup_domain_reset(apb1r1, pwrcr1);
+        Self::new(rtc, &mut backup, clocks)
+    }
 
+    /// Configures the RTC clocked by the LSI
+    ///
+    /// An RTC already running from the LSI, e.g. after a reset, keeps its time and date, and the
+    /// backup registers are kept. The backup domain is only reset if the RTC was clocked by
+    /// another source.
+    pub fn new(rtc: RTC, backup: &mut BackupDomain, clocks: Clocks) -> Self {
         assert_eq!(clocks.lsi(), true); // make sure LSI is enabled
-        // enable peripheral clock for communication
-        apb1r1.enr().modify(|_, w| w.rtcapben().set_bit());
-        pwrcr1.reg().read(); // read to allow the pwr clock to enable
-        
-        pwrcr1.reg().modify(|_, w| w.dbp().set_bit());
-        while pwrcr1.reg().read().dbp().bit_is_clear() {}
-        
-        bdcr.enr().modify(|_, w| { w.bdrst().set_bit() }); // reset
-        
-        bdcr.enr().modify(|_, w| unsafe {
+
+        let bdcr = backup.bdcr().read();
+        let rtcsel = bdcr.rtcsel().bits();
+        if rtcsel == 0b10 && bdcr.rtcen().bit_is_set() && rtc.isr.read().inits().bit_is_set() {
+            return Self { rtc };
+        }
+
+        if rtcsel != 0b00 && rtcsel != 0b10 {
+            // reset required for clock source change
+            backup.bdcr().modify(|_, w| w.bdrst().set_bit());
+            backup.bdcr().modify(|_, w| w.bdrst().clear_bit());
+        }
+
+        backup.bdcr().modify(|_, w| unsafe {
             w.rtcsel()
                 /* 
                     00: No clock
@@ -34,8 +124,6 @@ impl Rtc {
                 .bits(0b10)
                 .rtcen()
                 .set_bit()
-                .bdrst() // reset required for clock source change
-                .clear_bit()
         });
 
 
@@ -104,10 +192,12 @@ impl Rtc {
 
                 });
 
+                // 24 hour format, the daylight saving time is stored in BKP
                 self.rtc.cr.modify(|_, w| {
                     w.fmt()
+                        .clear_bit()
+                        .bkp()
                         .bit(time.daylight_savings)
-
                 });
             }
             init_mode(&self.rtc, false);
@@ -123,7 +213,7 @@ impl Rtc {
         time = Time::new(bcd2_to_byte((timer.ht().bits(), timer.hu().bits())).into(), 
                         bcd2_to_byte((timer.mnt().bits(), timer.mnu().bits())).into(),
                         bcd2_to_byte((timer.st().bits(), timer.su().bits())).into(),
-                        cr.fmt().bit());
+                        cr.bkp().bit());
         
         write_protection(&self.rtc, true);
         
@@ -168,7 +258,223 @@ impl Rtc {
                         (bcd2_to_byte((dater.yt().bits(), dater.yu().bits())) as u16 + 1970_u16).into());
         date
     }
-    
+
+    /// Moves the time one hour forward or backward for a daylight saving time change, without
+    /// stopping the calendar, and updates the stored daylight saving time flag (see
+    /// `is_daylight_saving`)
+    ///
+    /// The time isn't moved backward between 00:00 and 00:59, the change has to be made at
+    /// another hour, e.g. 03:00.
+    pub fn adjust_dst(&mut self, change: DstChange) {
+        write_protection(&self.rtc, false);
+        match change {
+            DstChange::Add1h => self.rtc.cr.modify(|_, w| w.add1h().set_bit().bkp().set_bit()),
+            DstChange::Sub1h => self.rtc.cr.modify(|_, w| w.sub1h().set_bit().bkp().clear_bit()),
+        }
+        write_protection(&self.rtc, true);
+    }
+
+    /// Returns `true` if the daylight saving time is in effect, as stored by `set_time` or
+    /// `adjust_dst`
+    pub fn is_daylight_saving(&self) -> bool {
+        self.rtc.cr.read().bkp().bit_is_set()
+    }
+
+    /// Sets the smooth calibration of the RTC clock, in parts per billion
+    ///
+    /// A positive `ppb` speeds the clock up. The calibration adds or masks pulses of the RTC
+    /// clock over 32 s cycles, by steps of about 954 ppb, from -487 ppm to +488 ppm; `ppb` is
+    /// rounded and clamped to that range.
+    pub fn set_calibration(&mut self, ppb: i32) {
+        // pulses added per 2^20 RTC clock periods
+        let half = if ppb < 0 { -500_000_000 } else { 500_000_000 };
+        let pulses = (i64::from(ppb) * CALIBRATION_PERIOD + half) / 1_000_000_000;
+        let pulses = pulses.clamp(-511, 512) as i32;
+        let (calp, calm) = if pulses > 0 { (true, 512 - pulses) } else { (false, -pulses) };
+
+        write_protection(&self.rtc, false);
+        // the previous calibration is applied at the next 32 s cycle
+        while self.rtc.isr.read().recalpf().bit_is_set() {}
+        self.rtc.calr.write(|w| unsafe { w.calp().bit(calp).calm().bits(calm as u16) });
+        write_protection(&self.rtc, true);
+    }
+
+    /// Returns the smooth calibration of the RTC clock, in parts per billion
+    pub fn calibration(&self) -> i32 {
+        let calr = self.rtc.calr.read();
+        let pulses = if calr.calp().bit_is_set() { 512 } else { 0 } - i64::from(calr.calm().bits());
+        (pulses * 1_000_000_000 / CALIBRATION_PERIOD) as i32
+    }
+
+    /// Outputs the calibration clock on `pin`, e.g. to measure and trim the RTC clock
+    ///
+    /// The RTC takes over the pin, whatever its GPIO configuration. An enabled alarm output
+    /// takes precedence over the calibration output.
+    pub fn enable_calibration_output(&mut self, frequency: CalibrationOutput, pin: OutputPin) {
+        let cosel = match frequency {
+            CalibrationOutput::Hz512 => false,
+            CalibrationOutput::Hz1 => true,
+        };
+
+        write_protection(&self.rtc, false);
+        self.rtc.cr.modify(|_, w| w.cosel().bit(cosel).coe().set_bit());
+        self.rtc.or.modify(|_, w| w.rtc_out_rmp().bit(remap(pin)));
+        write_protection(&self.rtc, true);
+    }
+
+    /// Signals `output` on `pin`, e.g. to wake up or pet an external device
+    ///
+    /// The RTC takes over the pin, whatever its GPIO configuration. The output follows the
+    /// event flag, and is deactivated when the flag is cleared.
+    pub fn enable_alarm_output(
+        &mut self,
+        output: AlarmOutput,
+        polarity: OutputPolarity,
+        output_type: OutputType,
+        pin: OutputPin,
+    ) {
+        let osel = match output {
+            AlarmOutput::AlarmA => 0b01,
+            AlarmOutput::AlarmB => 0b10,
+            AlarmOutput::Wakeup => 0b11,
+        };
+        let pol = match polarity {
+            OutputPolarity::High => false,
+            OutputPolarity::Low => true,
+        };
+        let push_pull = match output_type {
+            OutputType::OpenDrain => false,
+            OutputType::PushPull => true,
+        };
+
+        write_protection(&self.rtc, false);
+        self.rtc.cr.modify(|_, w| unsafe { w.osel().bits(osel).pol().bit(pol) });
+        self.rtc.or.modify(|_, w| {
+            w.rtc_alarm_type()
+                .bit(push_pull)
+                .rtc_out_rmp()
+                .bit(remap(pin))
+        });
+        write_protection(&self.rtc, true);
+    }
+
+    /// Sets `alarm` to go off every day at `time`
+    ///
+    /// The alarm raises the `RTC_ALARM` interrupt (EXTI line 18, which is unmasked), and wakes
+    /// the device up from the Stop modes.
+    pub fn alarm_daily_at(&mut self, alarm: Alarm, time: &Time) {
+        self.set_alarm(alarm, MSK4 | time_bits(time));
+    }
+
+    /// Sets `alarm` to go off once `delay` (less than 28 days) has elapsed, see `alarm_daily_at`
+    ///
+    /// The alarm matches the date in the month and the time, computed across the ends of the
+    /// days, months and years. Panics if `delay` is 0 or too long.
+    pub fn alarm_in<S>(&mut self, alarm: Alarm, delay: S)
+    where
+        S: Into<Second>,
+    {
+        let delay = delay.into().0;
+        assert!(delay > 0 && delay < MAX_ALARM_DELAY);
+
+        // the time is read first, which locks the date until it is read
+        let now = self.get_time();
+        let today = self.get_date();
+        let (date, time) = add_seconds(&today, &now, delay as i32);
+        self.set_alarm(alarm, (bcd(date.date) << 24) | time_bits(&time));
+    }
+
+    /// Returns `true` if `alarm` went off since it was last cleared
+    pub fn is_alarm_pending(&self, alarm: Alarm) -> bool {
+        let isr = self.rtc.isr.read();
+        match alarm {
+            Alarm::A => isr.alraf().bit_is_set(),
+            Alarm::B => isr.alrbf().bit_is_set(),
+        }
+    }
+
+    /// Clears the flag of `alarm`, and the pending `RTC_ALARM` interrupt
+    pub fn clear_alarm(&mut self, alarm: Alarm) {
+        clear_flag(&self.rtc, alarm);
+        if exti_pending(ALARM_LINE) {
+            exti_clear(ALARM_LINE);
+        }
+    }
+
+    /// Disables `alarm`
+    pub fn disable_alarm(&mut self, alarm: Alarm) {
+        write_protection(&self.rtc, false);
+        match alarm {
+            Alarm::A => self.rtc.cr.modify(|_, w| w.alrae().clear_bit().alraie().clear_bit()),
+            Alarm::B => self.rtc.cr.modify(|_, w| w.alrbe().clear_bit().alrbie().clear_bit()),
+        }
+        write_protection(&self.rtc, true);
+    }
+
+    /// Writes the ALRMxR value `bits` of `alarm`, and enables it with its interrupt
+    fn set_alarm(&mut self, alarm: Alarm, bits: u32) {
+        self.disable_alarm(alarm);
+
+        write_protection(&self.rtc, false);
+        match alarm {
+            Alarm::A => {
+                while self.rtc.isr.read().alrawf().bit_is_clear() {}
+                self.rtc.alrmar.write(|w| unsafe { w.bits(bits) });
+                // MASKSS = 0: the sub-seconds are not compared
+                self.rtc.alrmassr.write(|w| unsafe { w.bits(0) });
+                clear_flag(&self.rtc, alarm);
+                self.rtc.cr.modify(|_, w| w.alrae().set_bit().alraie().set_bit());
+            }
+            Alarm::B => {
+                while self.rtc.isr.read().alrbwf().bit_is_clear() {}
+                self.rtc.alrmbr.write(|w| unsafe { w.bits(bits) });
+                self.rtc.alrmbssr.write(|w| unsafe { w.bits(0) });
+                clear_flag(&self.rtc, alarm);
+                self.rtc.cr.modify(|_, w| w.alrbe().set_bit().alrbie().set_bit());
+            }
+        }
+        write_protection(&self.rtc, true);
+
+        exti_listen(ALARM_LINE, true, false);
+    }
+
+    /// Disables the calibration and alarm outputs, releasing the pin
+    pub fn disable_outputs(&mut self) {
+        write_protection(&self.rtc, false);
+        self.rtc.cr.modify(|_, w| unsafe { w.osel().bits(0b00).coe().clear_bit() });
+        write_protection(&self.rtc, true);
+    }
+}
+
+/// Returns the value of RTC_OUT_RMP selecting `pin`
+fn remap(pin: OutputPin) -> bool {
+    match pin {
+        OutputPin::PC13 => false,
+        OutputPin::PB2 => true,
+    }
+}
+
+/// Returns `value` (0 to 99) in BCD
+fn bcd(value: u32) -> u32 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// Returns the hours, minutes and seconds fields of ALRMxR matching `time`, in 24 hour format
+fn time_bits(time: &Time) -> u32 {
+    (bcd(time.hours) << 16) | (bcd(time.minutes) << 8) | bcd(time.seconds)
+}
+
+/// Clears the flag of `alarm`
+///
+/// The flags are cleared by writing 0: 1 is written to the other ones, so that a flag set in the
+/// meantime isn't lost, and INIT is written back as read.
+fn clear_flag(rtc: &RTC, alarm: Alarm) {
+    let flag = match alarm {
+        Alarm::A => ALRAF,
+        Alarm::B => ALRBF,
+    };
+    let init = rtc.isr.read().init().bit();
+    rtc.isr.write(|w| unsafe { w.bits(ISR_FLAGS & !flag) }.init().bit(init));
 }
 
 fn write_protection(rtc: &RTC, enable: bool){
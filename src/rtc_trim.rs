@@ -0,0 +1,127 @@
+//! Temperature compensated RTC trim
+//!
+//! The frequency of a 32.768 kHz tuning fork crystal drops with the square of the temperature
+//! difference to its turnover temperature, by about 34 ppb/°C², i.e. more than a minute per
+//! month at 0 °C. `Trim` measures the temperature on the internal sensor of the ADC and sets the
+//! smooth calibration of the RTC (`Rtc::set_calibration`) to cancel the deviation given by a
+//! `Curve`, e.g. the `Parabolic` curve from the datasheet of the crystal, without an external
+//! TCXO.
+//!
+//! `update` is called periodically, e.g. every minute from an RTC alarm; the calibration is
+//! applied by 32 s cycles, more frequent updates don't improve it.
+//!
+//! ``` ignore
+//! let mut adc = Adc::adc1(dp.ADC, clocks, &mut rcc.ahb2);
+//! adc.set_sample_time(SampleTime::Cycles640_5);
+//! adc.enable_vref(true);
+//! adc.enable_temperature(true);
+//!
+//! // turnover at 25 °C, -34 ppb/°C², 5 ppm fast at the turnover
+//! let mut trim = Trim::new(Parabolic {
+//!     turnover: 25_000,
+//!     coefficient: -34,
+//!     offset: 5_000,
+//! });
+//!
+//! // every minute
+//! let temperature = trim.update(&mut rtc, &mut adc);
+//! ```
+
+use crate::adc::{Temperature, Vref};
+use crate::hal::adc::{Channel, OneShot};
+use crate::rtc::Rtc;
+
+/// Frequency deviation of the RTC clock over temperature
+pub trait Curve {
+    /// Returns the deviation in parts per billion at `millicelsius`, positive if the clock is fast
+    fn deviation(&self, millicelsius: i32) -> i32;
+}
+
+impl<F> Curve for F
+where
+    F: Fn(i32) -> i32,
+{
+    fn deviation(&self, millicelsius: i32) -> i32 {
+        self(millicelsius)
+    }
+}
+
+/// Parabolic curve of a tuning fork crystal
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Parabolic {
+    /// Turnover temperature, in thousandths of °C
+    pub turnover: i32,
+    /// Parabolic coefficient, in ppb/°C², negative for a tuning fork crystal
+    pub coefficient: i32,
+    /// Deviation at the turnover temperature, in ppb, e.g. measured on the calibration output
+    pub offset: i32,
+}
+
+impl Curve for Parabolic {
+    fn deviation(&self, millicelsius: i32) -> i32 {
+        let delta = i64::from(millicelsius - self.turnover);
+        let deviation =
+            i64::from(self.offset) + i64::from(self.coefficient) * delta * delta / 1_000_000;
+        deviation as i32
+    }
+}
+
+/// Weight of the last measurement in the filtered temperature, in 1/16
+const FILTER: i32 = 4;
+
+/// Temperature compensation of the RTC clock
+pub struct Trim<CURVE> {
+    curve: CURVE,
+    /// Filtered temperature, in thousandths of °C
+    temperature: Option<i32>,
+}
+
+impl<CURVE> Trim<CURVE>
+where
+    CURVE: Curve,
+{
+    /// Creates the compensation of the RTC clock deviating along `curve`
+    pub fn new(curve: CURVE) -> Self {
+        Trim {
+            curve,
+            temperature: None,
+        }
+    }
+
+    /// Measures the temperature and updates the calibration of `rtc`, returns the filtered
+    /// temperature in thousandths of °C
+    ///
+    /// The internal voltage reference and the temperature sensor have to be enabled, see
+    /// `Adc::enable_vref` and `Adc::enable_temperature`. The measurements are filtered, a single
+    /// noisy conversion only moves the calibration by a fraction of its error.
+    pub fn update<A, ADC>(&mut self, rtc: &mut Rtc, adc: &mut ADC) -> i32
+    where
+        ADC: OneShot<A, u16, Vref> + OneShot<A, u16, Temperature>,
+        Vref: Channel<A>,
+        Temperature: Channel<A>,
+    {
+        let vref = nb::block!(OneShot::<A, u16, Vref>::read(adc, &mut Vref)).unwrap_or(0);
+        let sample =
+            nb::block!(OneShot::<A, u16, Temperature>::read(adc, &mut Temperature)).unwrap_or(0);
+        let measured = Temperature::millicelsius(sample, Vref::vdda(vref));
+
+        let temperature = match self.temperature {
+            Some(filtered) => filtered + (measured - filtered) * FILTER / 16,
+            None => measured,
+        };
+        self.temperature = Some(temperature);
+
+        rtc.set_calibration(-self.curve.deviation(temperature));
+        temperature
+    }
+
+    /// Returns the filtered temperature of the last `update`, in thousandths of °C
+    pub fn temperature(&self) -> Option<i32> {
+        self.temperature
+    }
+
+    /// Releases the curve
+    pub fn free(self) -> CURVE {
+        self.curve
+    }
+}
@@ -0,0 +1,141 @@
+//! ADC/DAC loopback self-test
+//!
+//! `loopback` drives a DAC output to a few known levels, converts them back on the pin of the
+//! output with the ADC and reports the gain and offset errors of the signal path, along with
+//! VDDA measured on the internal voltage reference (`adc::Vref`). `Report::check` compares them
+//! to `Limits`, e.g. for a power-on self-test of the analog front end.
+//!
+//! ``` ignore
+//! let pin = gpioa.pa4.into_analog(&mut gpioa.moder, &mut gpioa.pupdr);
+//! let mut dac = dac(dp.DAC1, pin, &mut rcc.apb1r1);
+//! let mut adc = Adc::adc1(dp.ADC, clocks, &mut rcc.ahb2);
+//! adc.set_sample_time(SampleTime::Cycles640_5);
+//! adc.enable_vref(true);
+//!
+//! let report = selftest::loopback(&mut adc, &mut dac);
+//! if !report.check(&Limits::default()) {
+//!     // the analog path or the supply is out of tolerance
+//! }
+//! ```
+//!
+//! The DAC output is enabled and left at the last level. The buffer of the DAC output limits its
+//! range to about 0.2 V from the rails, the levels are inside it.
+
+use crate::adc::Vref;
+use crate::dac::{C1, C2};
+use crate::hal::adc::{Channel, OneShot};
+
+/// Levels driven on the DAC output, 12 bits
+pub const LEVELS: [u16; 3] = [512, 2048, 3584];
+
+/// DAC output of the loopback
+pub trait Output {
+    /// Enables the output
+    fn enable(&mut self);
+    /// Sets the 12-bit value of the output
+    fn set_value(&mut self, value: u16);
+}
+
+macro_rules! outputs {
+    ($($CX:ident,)+) => {
+        $(
+            impl Output for $CX {
+                fn enable(&mut self) {
+                    $CX::enable(self)
+                }
+
+                fn set_value(&mut self, value: u16) {
+                    $CX::set_value(self, value)
+                }
+            }
+        )+
+    }
+}
+
+outputs!(C1, C2,);
+
+/// Result of a loopback
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Report {
+    /// Level driven and conversion, for each of `LEVELS`
+    pub samples: [(u16, u16); 3],
+    /// Gain error of the signal path, in parts per million
+    pub gain_error_ppm: i32,
+    /// Offset of the signal path at 0, in LSB
+    pub offset: i32,
+    /// VDDA in millivolts, measured on the internal voltage reference
+    pub vdda: u32,
+}
+
+/// Tolerances of `Report::check`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// Largest gain error, in parts per million
+    pub gain_error_ppm: u32,
+    /// Largest offset, in LSB
+    pub offset: u32,
+    /// Range of VDDA, in millivolts
+    pub vdda: (u32, u32),
+}
+
+impl Default for Limits {
+    /// 2 % of gain error, 40 LSB of offset and VDDA within the operating range of the ADC
+    fn default() -> Self {
+        Limits {
+            gain_error_ppm: 20_000,
+            offset: 40,
+            vdda: (1_710, 3_600),
+        }
+    }
+}
+
+impl Report {
+    /// Returns `true` if the errors and VDDA are within `limits`
+    pub fn check(&self, limits: &Limits) -> bool {
+        self.gain_error_ppm.unsigned_abs() <= limits.gain_error_ppm
+            && self.offset.unsigned_abs() <= limits.offset
+            && self.vdda >= limits.vdda.0
+            && self.vdda <= limits.vdda.1
+    }
+}
+
+/// Drives `output` to each of `LEVELS`, converts it back with `adc` and measures VDDA
+///
+/// The internal voltage reference has to be enabled, see `Adc::enable_vref`, with a sample time
+/// long enough for it. Both the DAC and the ADC share VDDA as reference, so the gain and offset
+/// errors don't depend on it.
+pub fn loopback<A, ADC, OUT>(adc: &mut ADC, output: &mut OUT) -> Report
+where
+    ADC: OneShot<A, u16, OUT> + OneShot<A, u16, Vref>,
+    OUT: Output + Channel<A>,
+    Vref: Channel<A>,
+{
+    output.enable();
+
+    let mut samples = [(0, 0); 3];
+    for (sample, &level) in samples.iter_mut().zip(LEVELS.iter()) {
+        output.set_value(level);
+        // the first conversion lets the output and the sampling capacitor settle
+        let _ = nb::block!(OneShot::<A, u16, OUT>::read(adc, output));
+        let value = nb::block!(OneShot::<A, u16, OUT>::read(adc, output)).unwrap_or(0);
+        *sample = (level, value);
+    }
+
+    let _ = nb::block!(OneShot::<A, u16, Vref>::read(adc, &mut Vref));
+    let vref = nb::block!(OneShot::<A, u16, Vref>::read(adc, &mut Vref)).unwrap_or(0);
+
+    // line through the lowest and highest levels
+    let (x0, y0) = samples[0];
+    let (x1, y1) = samples[samples.len() - 1];
+    let (x0, y0, x1, y1) = (i64::from(x0), i64::from(y0), i64::from(x1), i64::from(y1));
+    let gain_ppm = (y1 - y0) * 1_000_000 / (x1 - x0);
+    let offset = y0 - x0 * gain_ppm / 1_000_000;
+
+    Report {
+        samples,
+        gain_error_ppm: (gain_ppm - 1_000_000) as i32,
+        offset: offset as i32,
+        vdda: Vref::vdda(vref),
+    }
+}
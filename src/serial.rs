@@ -1,26 +1,48 @@
 //! Serial
+//!
+//! The USARTs of the STM32L4x1/x2/x3/x5/x6 have a single data register in each direction: the
+//! transmit and receive FIFOs (and their RXFT/TXFT threshold events) only exist on the STM32L4+
+//! parts, which this crate doesn't support. DMA (`circ_read`) is the way to sustain high baud
+//! rates without an interrupt per byte.
 
 use core::fmt;
 use core::marker::PhantomData;
 use core::ptr;
-use core::sync::atomic::{self, Ordering};
+use core::sync::atomic::{self, AtomicBool, AtomicU32, Ordering};
 use core::ops::DerefMut;
 use stable_deref_trait::StableDeref;
 use as_slice::AsMutSlice;
 use cast::u16;
 
 use crate::hal::serial::{self, Write};
+use crate::hal::spi::{Mode, Phase, Polarity};
 use nb;
-use crate::stm32::{USART1, USART2};
+use crate::stm32::{USART1, USART2, USART3};
 use void::Void;
 
-use crate::gpio::gpioa::{PA10, PA2, PA3, PA9};
-use crate::gpio::gpiod::{PD5, PD6};
-use crate::gpio::gpiob::{PB6, PB7};
-use crate::gpio::{AF7, Alternate, Input, Floating};
-use crate::rcc::{APB1R1, APB2, Clocks};
+use crate::gpio::gpioa::{PA1, PA10, PA12, PA15, PA2, PA3, PA4, PA8, PA9};
+use crate::gpio::gpiob::{PB1, PB10, PB11, PB12, PB14, PB3, PB5, PB6, PB7};
+use crate::gpio::gpioc::{PC10, PC11, PC12, PC4, PC5};
+use crate::gpio::gpiod::{PD10, PD12, PD2, PD4, PD5, PD6, PD7, PD8, PD9};
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+use crate::gpio::gpiog::{PG10, PG9};
+use crate::gpio::{AF3, AF7, Alternate, Input, Floating};
+use crate::rcc::{APB1R1, APB2, ClockError, Clocks};
 use crate::time::Bps;
-use crate::dma::{dma1, CircBuffer};
+use crate::dma::{dma1, CircBuffer, Event as DmaEvent, Priority};
+use crate::pwr::Busy;
+
+#[cfg(feature = "async")]
+use core::convert::Infallible;
+#[cfg(feature = "async")]
+use cortex_m::interrupt;
+#[cfg(feature = "async")]
+use crate::asynch::{self, AtomicWaker};
+
+pub mod framed;
+pub mod lin;
+#[cfg(feature = "modbus")]
+pub mod modbus;
 
 /// Interrupt event
 pub enum Event {
@@ -29,11 +51,17 @@ pub enum Event {
     /// New data can be sent
     Txe,
     /// The line has gone idle
-    Idle
+    Idle,
+    /// A LIN break has been detected, see `Config::lin`
+    LinBreak,
+    /// The line has been idle for the receiver timeout, see `Config::receiver_timeout`
+    ReceiverTimeout,
 }
 
 /// Serial error
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum Error {
     /// Framing error
     Framing,
@@ -43,28 +71,371 @@ pub enum Error {
     Overrun,
     /// Parity check error
     Parity,
-    #[doc(hidden)]
-    _Extensible,
+    /// The baud rate couldn't be measured, or is out of range
+    AutoBaud,
+}
+
+#[cfg(feature = "async")]
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::Other
+    }
+}
+
+/// Handling of receiver overruns, i.e. of bytes received while the previous one wasn't read
+#[derive(Clone, Copy, PartialEq)]
+pub enum Overrun {
+    /// `read` returns `Error::Overrun` once, then the following bytes
+    Error,
+    /// `read` silently drops the overrun and returns the following bytes, the overruns are
+    /// counted, see `Rx::overruns`
+    Ignore,
+    /// Overrun detection is disabled (OVRDIS): a byte that isn't read in time is overwritten by
+    /// the next one, nothing is counted
+    Disabled,
+}
+
+/// Auto baud rate detection mode, i.e. what the first received character is expected to be
+#[derive(Clone, Copy)]
+pub enum AutoBaudMode {
+    /// Any character starting with a 1, the start bit is measured
+    StartBit = 0b00,
+    /// Any character starting with `10`, from falling edge to falling edge
+    FallingEdge = 0b01,
+    /// A 0x7F frame
+    Frame7F = 0b10,
+    /// A 0x55 frame
+    Frame55 = 0b11,
+}
+
+/// Serial configuration
+#[derive(Clone, Copy)]
+pub struct Config {
+    baud_rate: Bps,
+    overrun: Overrun,
+    swap: bool,
+    invert_tx: bool,
+    invert_rx: bool,
+    invert_data: bool,
+    lin: Option<LinBreakLength>,
+    receiver_timeout: Option<u32>,
+    de_assertion: u8,
+    de_deassertion: u8,
+    synchronous: Option<(Mode, bool)>,
+}
+
+/// Length of the LIN breaks detected by the receiver (LBDL)
+#[derive(Clone, Copy, PartialEq)]
+pub enum LinBreakLength {
+    /// 10 bits
+    Bits10,
+    /// 11 bits
+    Bits11,
+}
+
+impl Config {
+    /// Sets the baud rate
+    pub fn baud_rate(mut self, baud_rate: Bps) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    /// Sets the handling of receiver overruns
+    pub fn overrun(mut self, overrun: Overrun) -> Self {
+        self.overrun = overrun;
+        self
+    }
+
+    /// Swaps the functions of the TX and RX pins (SWAP), e.g. to fix crossed lines on a board
+    ///
+    /// The pins are still passed to the constructor in their usual `(tx, rx)` order.
+    pub fn swap_pins(mut self, swap: bool) -> Self {
+        self.swap = swap;
+        self
+    }
+
+    /// Inverts the levels of the TX pin (TXINV): idle low, e.g. for an optocoupled link
+    pub fn invert_tx(mut self, invert: bool) -> Self {
+        self.invert_tx = invert;
+        self
+    }
+
+    /// Inverts the levels of the RX pin (RXINV): idle low, e.g. for an optocoupled link
+    pub fn invert_rx(mut self, invert: bool) -> Self {
+        self.invert_rx = invert;
+        self
+    }
+
+    /// Inverts the data bits (DATAINV), in both directions; the start, stop and parity bits keep
+    /// their levels
+    pub fn invert_data(mut self, invert: bool) -> Self {
+        self.invert_data = invert;
+        self
+    }
+
+    /// Enables the LIN mode (LINEN), detecting breaks of `break_length` bits
+    ///
+    /// The frames are 8 bits with 1 stop bit. A detected break raises `Event::LinBreak`, see
+    /// `Serial::is_lin_break`, and breaks are sent with `Serial::send_break`; the `lin` module
+    /// helps with the rest of the frame.
+    pub fn lin(mut self, break_length: LinBreakLength) -> Self {
+        self.lin = Some(break_length);
+        self
+    }
+
+    /// Enables the receiver timeout (RTOEN): the line idle for `bits` bit durations after the
+    /// last received character raises `Event::ReceiverTimeout`, see `ReceiverTimeout`
+    ///
+    /// Unlike `Event::Idle`, which is one character long, the timeout can span several
+    /// characters, e.g. the 3.5 characters ending a Modbus RTU frame. `bits` is at most
+    /// `0xFF_FFFF`.
+    pub fn receiver_timeout(mut self, bits: u32) -> Self {
+        assert!(bits <= 0xFF_FFFF);
+
+        self.receiver_timeout = Some(bits);
+        self
+    }
+
+    /// Sets the driver enable assertion and deassertion times (DEAT and DEDT), in 1/16 of a bit
+    /// duration, up to 31
+    ///
+    /// The driver enable output (DEM) is used when a `DePin` is passed along with the TX and RX
+    /// pins: it's asserted (high) `assertion` before the start bit of the first character sent,
+    /// and deasserted `deassertion` after the stop bit of the last one, e.g. for the transceiver
+    /// of a RS-485 bus.
+    pub fn driver_enable_times(mut self, assertion: u8, deassertion: u8) -> Self {
+        assert!(assertion < 32 && deassertion < 32);
+
+        self.de_assertion = assertion;
+        self.de_deassertion = deassertion;
+        self
+    }
+
+    /// Enables the synchronous mode (CLKEN): the USART outputs a clock on its CK pin, e.g. to
+    /// drive shift registers, with the clock `mode` of an SPI
+    ///
+    /// The baud rate is then the maximum clock rate: the clock is the kernel clock divided by
+    /// the smallest divider not exceeding it, see `Serial::baud_rate`, and at most the kernel
+    /// clock / 16. With `last_bit_clock` (LBCL), the clock is also pulsed for the last data bit.
+    /// The CK pin, a `CkPin`, has to be passed along with the TX and RX pins. The synchronous
+    /// and LIN modes are exclusive.
+    pub fn synchronous(mut self, mode: Mode, last_bit_clock: bool) -> Self {
+        self.synchronous = Some((mode, last_bit_clock));
+        self
+    }
+}
+
+impl Default for Config {
+    /// 115200 bps, overruns reported as errors, pins neither swapped nor inverted
+    fn default() -> Self {
+        Config {
+            baud_rate: Bps(115_200),
+            overrun: Overrun::Error,
+            swap: false,
+            invert_tx: false,
+            invert_rx: false,
+            invert_data: false,
+            lin: None,
+            receiver_timeout: None,
+            de_assertion: 0,
+            de_deassertion: 0,
+            synchronous: None,
+        }
+    }
+}
+
+impl From<Bps> for Config {
+    fn from(baud_rate: Bps) -> Self {
+        Config::default().baud_rate(baud_rate)
+    }
+}
+
+/// Receiver statistics, see `Rx::stats`
+#[cfg(feature = "serial-stats")]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Stats {
+    /// Bytes received by `read` and `read_until_idle`
+    pub bytes: u32,
+    /// Parity errors
+    pub parity_errors: u32,
+    /// Framing errors
+    pub framing_errors: u32,
+    /// Noise errors
+    pub noise_errors: u32,
+    /// Overruns, reported or ignored, see also `Rx::overruns`
+    pub overruns: u32,
+}
+
+/// Counter of the receiver statistics
+#[derive(Clone, Copy)]
+enum Counter {
+    Bytes,
+    Parity,
+    Framing,
+    Noise,
+    Overrun,
+}
+
+/// State of a receiver
+struct RxState {
+    ignore_overrun: AtomicBool,
+    overruns: AtomicU32,
+    #[cfg(feature = "serial-stats")]
+    counters: [AtomicU32; 5],
 }
 
+impl RxState {
+    const fn new() -> Self {
+        RxState {
+            ignore_overrun: AtomicBool::new(false),
+            overruns: AtomicU32::new(0),
+            #[cfg(feature = "serial-stats")]
+            counters: [
+                AtomicU32::new(0),
+                AtomicU32::new(0),
+                AtomicU32::new(0),
+                AtomicU32::new(0),
+                AtomicU32::new(0),
+            ],
+        }
+    }
+
+    /// Adds `count` to `counter`, if the statistics are enabled
+    #[inline(always)]
+    fn count(&self, counter: Counter, count: u32) {
+        #[cfg(feature = "serial-stats")]
+        self.counters[counter as usize].fetch_add(count, Ordering::Relaxed);
+        #[cfg(not(feature = "serial-stats"))]
+        let _ = (counter, count);
+    }
+}
+
+/// Pins of a USART
+///
+/// The pins are routed to the USART by their alternate function, which is part of the pin
+/// types: a pin configured with the wrong alternate function, or not available on the part,
+/// doesn't implement `TxPin` / `RxPin` and fails to compile.
 pub trait Pins<USART> {
-    const REMAP: u8;
+    /// A driver enable pin is part of the pins
+    const DE: bool = false;
+    /// A clock pin is part of the pins, see `Config::synchronous`
+    const CK: bool = false;
 }
 
-impl Pins<USART1> for (PA9<Alternate<AF7, Input<Floating>>>, PA10<Alternate<AF7, Input<Floating>>>) {
-    const REMAP: u8 = 0;
+/// TX and RX pins
+impl<USART, TX, RX> Pins<USART> for (TX, RX)
+where
+    TX: TxPin<USART>,
+    RX: RxPin<USART>,
+{
 }
 
-impl Pins<USART1> for (PB6<Alternate<AF7, Input<Floating>>>, PB7<Alternate<AF7, Input<Floating>>>) {
-    const REMAP: u8 = 1;
+/// TX and RX pins, and the driver enable (RTS_DE) pin of a RS-485 transceiver, see
+/// `Config::driver_enable_times`
+impl<USART, TX, RX, DE> Pins<USART> for (TX, RX, DE)
+where
+    TX: TxPin<USART>,
+    RX: RxPin<USART>,
+    DE: DePin<USART>,
+{
+    const DE: bool = true;
+}
+
+// FIXME these should be "closed" traits
+/// TX pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the TX output of `USART`
+pub unsafe trait TxPin<USART> {}
+
+/// RX pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the RX input of `USART`
+pub unsafe trait RxPin<USART> {}
+
+unsafe impl<MODE> TxPin<USART1> for PA9<Alternate<AF7, MODE>> {}
+unsafe impl<MODE> RxPin<USART1> for PA10<Alternate<AF7, MODE>> {}
+unsafe impl<MODE> TxPin<USART1> for PB6<Alternate<AF7, MODE>> {}
+unsafe impl<MODE> RxPin<USART1> for PB7<Alternate<AF7, MODE>> {}
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> TxPin<USART1> for PG9<Alternate<AF7, MODE>> {}
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+unsafe impl<MODE> RxPin<USART1> for PG10<Alternate<AF7, MODE>> {}
+
+unsafe impl<MODE> TxPin<USART2> for PA2<Alternate<AF7, MODE>> {}
+unsafe impl<MODE> RxPin<USART2> for PA3<Alternate<AF7, MODE>> {}
+unsafe impl<MODE> RxPin<USART2> for PA15<Alternate<AF3, MODE>> {}
+unsafe impl<MODE> TxPin<USART2> for PD5<Alternate<AF7, MODE>> {}
+unsafe impl<MODE> RxPin<USART2> for PD6<Alternate<AF7, MODE>> {}
+
+unsafe impl<MODE> TxPin<USART3> for PB10<Alternate<AF7, MODE>> {}
+unsafe impl<MODE> RxPin<USART3> for PB11<Alternate<AF7, MODE>> {}
+unsafe impl<MODE> TxPin<USART3> for PC4<Alternate<AF7, MODE>> {}
+unsafe impl<MODE> RxPin<USART3> for PC5<Alternate<AF7, MODE>> {}
+unsafe impl<MODE> TxPin<USART3> for PC10<Alternate<AF7, MODE>> {}
+unsafe impl<MODE> RxPin<USART3> for PC11<Alternate<AF7, MODE>> {}
+unsafe impl<MODE> TxPin<USART3> for PD8<Alternate<AF7, MODE>> {}
+unsafe impl<MODE> RxPin<USART3> for PD9<Alternate<AF7, MODE>> {}
+
+// FIXME these should be "closed" traits
+/// Driver enable pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the DE output of `USART`
+pub unsafe trait DePin<USART> {}
+
+unsafe impl DePin<USART1> for PA12<Alternate<AF7, Input<Floating>>> {}
+unsafe impl DePin<USART1> for PB3<Alternate<AF7, Input<Floating>>> {}
+unsafe impl DePin<USART2> for PA1<Alternate<AF7, Input<Floating>>> {}
+unsafe impl DePin<USART2> for PD4<Alternate<AF7, Input<Floating>>> {}
+unsafe impl DePin<USART3> for PB1<Alternate<AF7, Input<Floating>>> {}
+unsafe impl DePin<USART3> for PB14<Alternate<AF7, Input<Floating>>> {}
+unsafe impl DePin<USART3> for PD2<Alternate<AF7, Input<Floating>>> {}
+unsafe impl DePin<USART3> for PD12<Alternate<AF7, Input<Floating>>> {}
+
+// FIXME these should be "closed" traits
+/// Clock pin of the synchronous mode - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// The pin must be routed to the CK output of `USART`
+pub unsafe trait CkPin<USART> {}
+
+macro_rules! ck_pins {
+    ($($USARTX:ident: [$($CK:ident),+],)+) => {
+        $(
+            $(
+                unsafe impl<MODE> CkPin<$USARTX> for $CK<Alternate<AF7, MODE>> {}
+
+                /// TX and RX pins, and the clock pin of the synchronous mode
+                impl<TX, RX, MODE> Pins<$USARTX> for (TX, RX, $CK<Alternate<AF7, MODE>>)
+                where
+                    TX: TxPin<$USARTX>,
+                    RX: RxPin<$USARTX>,
+                {
+                    const CK: bool = true;
+                }
+            )+
+        )+
+    }
 }
 
-impl Pins<USART2> for (PA2<Alternate<AF7, Input<Floating>>>, PA3<Alternate<AF7, Input<Floating>>>) {
-    const REMAP: u8 = 0;
+ck_pins! {
+    USART1: [PA8, PB5],
+    USART2: [PA4, PD7],
+    USART3: [PB12, PC12, PD10],
 }
 
-impl Pins<USART2> for (PD5<Alternate<AF7, Input<Floating>>>, PD6<Alternate<AF7, Input<Floating>>>) {
-    const REMAP: u8 = 0;
+/// Detection of the receiver timeout, see `Config::receiver_timeout`
+pub trait ReceiverTimeout {
+    /// Returns `true` if the receiver timeout has elapsed since the flag was last cleared, and
+    /// clears the flag if `clear` is set
+    fn is_receiver_timeout(&mut self, clear: bool) -> bool;
 }
 
 
@@ -84,23 +455,83 @@ pub struct Tx<USART> {
     _usart: PhantomData<USART>,
 }
 
+/// Transmit queue fed by DMA, see `Tx::queue`
+///
+/// The two halves of the buffer are used in turn: the DMA sends one while `write_bytes` fills
+/// the other, and the next transfer starts as soon as the previous one completes.
+pub struct TxQueue<USART, CHANNEL, BUFFER> {
+    tx: Tx<USART>,
+    channel: CHANNEL,
+    buffer: BUFFER,
+    /// Half being sent
+    sending: Option<usize>,
+    /// Half being filled, the other one
+    filling: usize,
+    /// Bytes queued in the half being filled
+    queued: usize,
+}
+
 macro_rules! hal {
     ($(
-        $USARTX:ident: ($usartX:ident, $APB:ident, $usartXen:ident, $usartXrst:ident, $pclkX:ident, tx: ($dmacst:ident, $tx_chan:path), rx: ($dmacsr:ident, $rx_chan:path)),
+        $USARTX:ident: ($usartX:ident, $try_usartX:ident, $APB:ident, $usartXen:ident, $usartXrst:ident, $pclkX:ident, tx: ($dmacst:ident, $tx_chan:path), rx: ($dmacsr:ident, $rx_chan:path)),
     )+) => {
         $(
             impl<PINS> Serial<$USARTX, PINS> {
                 /// Configures a USART peripheral to provide serial communication
-                pub fn $usartX(
+                ///
+                /// `config` is either a `Config` or just a baud rate. Panics if the baud rate
+                /// can't be generated, see the `try_` constructor.
+                pub fn $usartX<C>(
                     usart: $USARTX,
                     pins: PINS,
-                    baud_rate: Bps,
+                    config: C,
                     clocks: Clocks,
                     apb: &mut $APB,
                 ) -> Self
                 where
                     PINS: Pins<$USARTX>,
+                    C: Into<Config>,
+                {
+                    Self::$try_usartX(usart, pins, config, clocks, apb).unwrap()
+                }
+
+                /// Configures a USART peripheral to provide serial communication, fails if the
+                /// baud rate can't be generated from the kernel clock
+                pub fn $try_usartX<C>(
+                    usart: $USARTX,
+                    pins: PINS,
+                    config: C,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                ) -> Result<Self, ClockError>
+                where
+                    PINS: Pins<$USARTX>,
+                    C: Into<Config>,
                 {
+                    let config = config.into();
+
+                    assert!(
+                        config.lin.is_none() || config.synchronous.is_none(),
+                        "the LIN and synchronous modes are exclusive"
+                    );
+                    assert!(
+                        config.synchronous.is_none() || PINS::CK,
+                        "the synchronous mode needs a CK pin"
+                    );
+
+                    // BRR is 16 bits, and at least 16 with 16 times oversampling; the clock of
+                    // the synchronous mode is rounded down so that it doesn't exceed its maximum
+                    let pclk = clocks.$pclkX().0;
+                    let baud_rate = config.baud_rate.0.max(1);
+                    let brr = if config.synchronous.is_some() {
+                        pclk.div_ceil(baud_rate)
+                    } else {
+                        pclk / baud_rate
+                    };
+                    if !(16..=0xffff).contains(&brr) {
+                        return Err(ClockError::BaudRate);
+                    }
+
                     // enable or reset $USARTX
                     apb.enr().modify(|_, w| w.$usartXen().set_bit());
                     apb.rstr().modify(|_, w| w.$usartXrst().set_bit());
@@ -108,23 +539,86 @@ macro_rules! hal {
 
                     // TODO implement pin remaping
 
+                    let (mode, last_bit_clock) = config.synchronous.unwrap_or((
+                        Mode {
+                            polarity: Polarity::IdleLow,
+                            phase: Phase::CaptureOnFirstTransition,
+                        },
+                        false,
+                    ));
+
+                    // pin swapping, level inversions, LIN and synchronous modes, only writable
+                    // while UE is cleared; LIN also requires STOP and CLKEN, and SCEN, HDSEL and
+                    // IREN in CR3, to be cleared, which they are after the reset
+                    usart.cr2.write(|w| {
+                        w.linen()
+                            .bit(config.lin.is_some())
+                            .lbdl()
+                            .bit(config.lin == Some(LinBreakLength::Bits11))
+                            .swap()
+                            .bit(config.swap)
+                            .txinv()
+                            .bit(config.invert_tx)
+                            .rxinv()
+                            .bit(config.invert_rx)
+                            .datainv()
+                            .bit(config.invert_data)
+                            .rtoen()
+                            .bit(config.receiver_timeout.is_some())
+                            .clken()
+                            .bit(config.synchronous.is_some())
+                            .cpol()
+                            .bit(mode.polarity == Polarity::IdleHigh)
+                            .cpha()
+                            .bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .lbcl()
+                            .bit(last_bit_clock)
+                    });
+                    usart
+                        .rtor
+                        .write(|w| w.rto().bits(config.receiver_timeout.unwrap_or(0)));
+
                     // disable hardware flow control
                     // usart.cr3.write(|w| w.rtse().clear_bit().ctse().clear_bit());
-                    usart.cr3.write(|w| w.dmat().set_bit().dmar().set_bit()); // enable DMA transfers
+                    // enable DMA transfers
+                    usart.cr3.write(|w| {
+                        w.dmat()
+                            .set_bit()
+                            .dmar()
+                            .set_bit()
+                            .ovrdis()
+                            .bit(config.overrun == Overrun::Disabled)
+                            .dem()
+                            .bit(PINS::DE)
+                    });
                     //usart.cr3.write(|w| w.onebit().set_bit());
 
-                    let brr = clocks.$pclkX().0 / baud_rate.0;
-                    assert!(brr >= 16, "impossible baud rate");
+                    let state = Rx::<$USARTX>::state();
+                    state.ignore_overrun.store(config.overrun == Overrun::Ignore, Ordering::Relaxed);
+                    state.overruns.store(0, Ordering::Relaxed);
+                    #[cfg(feature = "serial-stats")]
+                    for counter in state.counters.iter() {
+                        counter.store(0, Ordering::Relaxed);
+                    }
+
                     usart.brr.write(|w| unsafe { w.bits(brr) });
 
+                    // the driver enable times are only writable while UE is cleared
+                    usart.cr1.write(|w| {
+                        w.deat()
+                            .bits(config.de_assertion)
+                            .dedt()
+                            .bits(config.de_deassertion)
+                    });
+
                     // UE: enable USART
                     // RE: enable receiver
                     // TE: enable transceiver
                     usart
                         .cr1
-                        .write(|w| w.ue().set_bit().re().set_bit().te().set_bit());
+                        .modify(|_, w| w.ue().set_bit().re().set_bit().te().set_bit());
 
-                    Serial { usart, pins }
+                    Ok(Serial { usart, pins })
                 }
 
                 /// Starts listening for an interrupt event
@@ -139,6 +633,12 @@ macro_rules! hal {
                         Event::Idle => {
                             self.usart.cr1.modify(|_, w| w.idleie().set_bit())
                         },
+                        Event::LinBreak => {
+                            self.usart.cr2.modify(|_, w| w.lbdie().set_bit())
+                        },
+                        Event::ReceiverTimeout => {
+                            self.usart.cr1.modify(|_, w| w.rtoie().set_bit())
+                        },
                     }
                 }
 
@@ -154,9 +654,74 @@ macro_rules! hal {
                         Event::Idle => {
                             self.usart.cr1.modify(|_, w| w.idleie().clear_bit())
                         },
+                        Event::LinBreak => {
+                            self.usart.cr2.modify(|_, w| w.lbdie().clear_bit())
+                        },
+                        Event::ReceiverTimeout => {
+                            self.usart.cr1.modify(|_, w| w.rtoie().clear_bit())
+                        },
                     }
                 }
 
+                /// Starts measuring the baud rate on the next received character
+                ///
+                /// BRR is updated by the hardware once the character matching `mode` is received,
+                /// see `auto_baud`. The character itself is received as usual.
+                pub fn start_auto_baud(&mut self, mode: AutoBaudMode) {
+                    // ABREN and ABRMOD can only be written while the USART is disabled
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.usart
+                        .cr2
+                        .modify(|_, w| w.abren().set_bit().abrmod().bits(mode as u8));
+                    self.usart.cr1.modify(|_, w| w.ue().set_bit());
+                    self.usart.rqr.write(|w| w.abrrq().set_bit());
+                }
+
+                /// Returns the measured BRR once the baud rate detection is over
+                ///
+                /// The baud rate is the kernel clock of the USART divided by BRR, see `baud_rate`.
+                pub fn auto_baud(&mut self) -> nb::Result<u32, Error> {
+                    let isr = self.usart.isr.read();
+                    if isr.abre().bit_is_set() {
+                        Err(nb::Error::Other(Error::AutoBaud))
+                    } else if isr.abrf().bit_is_set() {
+                        Ok(self.usart.brr.read().bits())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /// Stops the baud rate detection, the last measured baud rate is kept
+                pub fn stop_auto_baud(&mut self) {
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.usart.cr2.modify(|_, w| w.abren().clear_bit());
+                    self.usart.cr1.modify(|_, w| w.ue().set_bit());
+                }
+
+                /// Sends a break after the current character: 13 low bits in LIN mode, a low
+                /// frame otherwise
+                pub fn send_break(&mut self) {
+                    self.usart.rqr.write(|w| w.sbkrq().set_bit());
+                }
+
+                /// Returns `true` if a LIN break has been detected since the flag was last
+                /// cleared
+                ///
+                /// The break is also received as a `0x00` byte with a framing error.
+                pub fn is_lin_break(&self) -> bool {
+                    self.usart.isr.read().lbdf().bit_is_set()
+                }
+
+                /// Clears the LIN break detection flag
+                pub fn clear_lin_break(&mut self) {
+                    self.usart.icr.write(|w| w.lbdcf().set_bit());
+                }
+
+                /// Returns the current baud rate
+                pub fn baud_rate(&self, clocks: Clocks) -> Bps {
+                    Bps(clocks.$pclkX().0 / self.usart.brr.read().bits())
+                }
+
                 /// Splits the `Serial` abstraction into a transmitter and a receiver half
                 pub fn split(self) -> (Tx<$USARTX>, Rx<$USARTX>) {
                     (
@@ -173,6 +738,14 @@ macro_rules! hal {
                 pub fn free(self) -> ($USARTX, PINS) {
                     (self.usart, self.pins)
                 }
+
+                /// Waits until the last frame has been sent, then resets the USART peripheral,
+                /// stops its clock and releases it with the pins
+                pub fn release(self, apb: &mut $APB) -> ($USARTX, PINS) {
+                    while self.usart.isr.read().tc().bit_is_clear() {}
+                    apb.deinit(&self.usart);
+                    (self.usart, self.pins)
+                }
             }
 
             impl serial::Read<u8> for Rx<$USARTX> {
@@ -180,17 +753,45 @@ macro_rules! hal {
 
                 fn read(&mut self) -> nb::Result<u8, Error> {
                     // NOTE(unsafe) atomic read with no side effects
-                    let isr = unsafe { (*$USARTX::ptr()).isr.read() };
+                    let mut isr = unsafe { (*$USARTX::ptr()).isr.read() };
+                    // NOTE(unsafe) atomic write to a stateless register, the error flags are
+                    // only cleared by the receiver
+                    let icr = unsafe { &(*$USARTX::ptr()).icr };
 
+                    let state = Self::state();
+                    if isr.ore().bit_is_set() && state.ignore_overrun.load(Ordering::Relaxed) {
+                        state.overruns.fetch_add(1, Ordering::Relaxed);
+                        state.count(Counter::Overrun, 1);
+                        trace!("{=str}: overrun ignored", stringify!($USARTX));
+                        icr.write(|w| w.orecf().set_bit());
+                        // NOTE(unsafe) see above
+                        isr = unsafe { (*$USARTX::ptr()).isr.read() };
+                    }
+
+                    // the error flags are cleared once reported, the next read returns the
+                    // following byte
                     Err(if isr.pe().bit_is_set() {
+                        icr.write(|w| w.pecf().set_bit());
+                        state.count(Counter::Parity, 1);
+                        trace!("{=str}: parity error", stringify!($USARTX));
                         nb::Error::Other(Error::Parity)
                     } else if isr.fe().bit_is_set() {
+                        icr.write(|w| w.fecf().set_bit());
+                        state.count(Counter::Framing, 1);
+                        trace!("{=str}: framing error", stringify!($USARTX));
                         nb::Error::Other(Error::Framing)
                     } else if isr.nf().bit_is_set() {
+                        icr.write(|w| w.ncf().set_bit());
+                        state.count(Counter::Noise, 1);
+                        trace!("{=str}: noise error", stringify!($USARTX));
                         nb::Error::Other(Error::Noise)
                     } else if isr.ore().bit_is_set() {
+                        icr.write(|w| w.orecf().set_bit());
+                        state.count(Counter::Overrun, 1);
+                        trace!("{=str}: overrun error", stringify!($USARTX));
                         nb::Error::Other(Error::Overrun)
                     } else if isr.rxne().bit_is_set() {
+                        state.count(Counter::Bytes, 1);
                         // NOTE(read_volatile) see `write_volatile` below
                         return Ok(unsafe {
                             ptr::read_volatile(&(*$USARTX::ptr()).rdr as *const _ as *const _)
@@ -201,6 +802,61 @@ macro_rules! hal {
                 }
             }
 
+            impl Rx<$USARTX> {
+                /// Returns the number of overruns ignored since the USART was configured with
+                /// `Overrun::Ignore`; at least one byte was lost at each overrun
+                pub fn overruns(&self) -> u32 {
+                    Self::state().overruns.load(Ordering::Relaxed)
+                }
+
+                /// Resets the overrun counter
+                pub fn clear_overruns(&mut self) {
+                    Self::state().overruns.store(0, Ordering::Relaxed);
+                }
+
+                /// Returns the statistics of the receiver since the USART was configured or
+                /// `clear_stats` was called
+                ///
+                /// The bytes received by the circular DMA transfers aren't counted.
+                #[cfg(feature = "serial-stats")]
+                pub fn stats(&self) -> Stats {
+                    let counters = &Self::state().counters;
+                    let counter = |c: Counter| counters[c as usize].load(Ordering::Relaxed);
+                    Stats {
+                        bytes: counter(Counter::Bytes),
+                        parity_errors: counter(Counter::Parity),
+                        framing_errors: counter(Counter::Framing),
+                        noise_errors: counter(Counter::Noise),
+                        overruns: counter(Counter::Overrun),
+                    }
+                }
+
+                /// Resets the statistics of the receiver
+                #[cfg(feature = "serial-stats")]
+                pub fn clear_stats(&mut self) {
+                    for counter in Self::state().counters.iter() {
+                        counter.store(0, Ordering::Relaxed);
+                    }
+                }
+
+                /// Returns `true` if a received byte is waiting, i.e. `read` won't block
+                pub fn is_rx_not_empty(&self) -> bool {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$USARTX::ptr()).isr.read().rxne().bit_is_set() }
+                }
+
+                /// Returns `true` while a character is being received
+                pub fn is_busy(&self) -> bool {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$USARTX::ptr()).isr.read().busy().bit_is_set() }
+                }
+
+                fn state() -> &'static RxState {
+                    static STATE: RxState = RxState::new();
+                    &STATE
+                }
+            }
+
             impl serial::Write<u8> for Tx<$USARTX> {
                 // NOTE(Void) See section "29.7 USART interrupts"; the only possible errors during
                 // transmission are: clear to send (which is disabled in this case) errors and
@@ -227,7 +883,7 @@ macro_rules! hal {
                         // NOTE(unsafe) atomic write to stateless register
                         // NOTE(write_volatile) 8-bit write that's not possible through the svd2rust API
                         unsafe {
-                            ptr::write_volatile(&(*$USARTX::ptr()).tdr as *const _ as *mut _, byte)
+                            ptr::write_volatile(ptr::addr_of!((*$USARTX::ptr()).tdr) as *mut u8, byte)
                         }
                         Ok(())
                     } else {
@@ -236,6 +892,198 @@ macro_rules! hal {
                 }
             }
 
+            impl Tx<$USARTX> {
+                /// Waits until the last byte written has been shifted out on the line
+                ///
+                /// This has to be done before entering Stop mode or disabling the USART clock,
+                /// which would otherwise corrupt the byte being sent.
+                pub fn flush_and_wait_tc(&mut self) {
+                    // NOTE(unsafe) atomic reads with no side effects
+                    let usart = unsafe { &*$USARTX::ptr() };
+                    while usart.isr.read().txe().bit_is_clear() {}
+                    while usart.isr.read().tc().bit_is_clear() {}
+                }
+
+                /// Sends a break after the current character, see `Serial::send_break`
+                ///
+                /// Whether the transmission is over, the break included, is returned by
+                /// `Busy::is_busy`.
+                pub fn send_break(&mut self) {
+                    // NOTE(unsafe) atomic write to a stateless register, the other requests
+                    // are not affected by writing 0
+                    unsafe { (*$USARTX::ptr()).rqr.write(|w| w.sbkrq().set_bit()) };
+                }
+
+                /// Returns `true` if the transmit data register is empty, i.e. `write` won't
+                /// block
+                pub fn is_tx_empty(&self) -> bool {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$USARTX::ptr()).isr.read().txe().bit_is_set() }
+                }
+            }
+
+            impl Tx<$USARTX> {
+                /// Turns the transmitter into a queue sending the bytes written by DMA, through
+                /// the two halves of `buffer`
+                ///
+                /// The queue is fed by `TxQueue::poll`, which has to be called from the transfer
+                /// complete interrupt of the channel (see `TxQueue::listen`), or regularly.
+                pub fn queue<B, H>(
+                    self,
+                    chan: $tx_chan,
+                    buffer: B,
+                ) -> TxQueue<$USARTX, $tx_chan, B>
+                where
+                    B: StableDeref<Target = [H; 2]> + DerefMut + 'static,
+                    H: AsMutSlice<Element = u8>,
+                {
+                    TxQueue {
+                        tx: self,
+                        channel: chan,
+                        buffer,
+                        sending: None,
+                        filling: 0,
+                        queued: 0,
+                    }
+                }
+            }
+
+            impl<B, H> TxQueue<$USARTX, $tx_chan, B>
+            where
+                B: StableDeref<Target = [H; 2]> + DerefMut + 'static,
+                H: AsMutSlice<Element = u8>,
+            {
+                /// Queues as many `bytes` as the free half of the buffer takes, and returns their
+                /// number
+                ///
+                /// Never blocks: bytes that don't fit are left to the caller, e.g. dropped by a
+                /// logger.
+                pub fn write_bytes(&mut self, bytes: &[u8]) -> usize {
+                    self.poll();
+
+                    let half = self.filling;
+                    let free = &mut self.buffer[half].as_mut_slice()[self.queued..];
+                    let n = free.len().min(bytes.len());
+                    free[..n].copy_from_slice(&bytes[..n]);
+                    self.queued += n;
+
+                    self.poll();
+                    n
+                }
+
+                /// Starts sending the queued bytes once the transfer in progress is complete
+                pub fn poll(&mut self) {
+                    if self.sending.is_some() {
+                        if self.channel.get_cndtr() != 0 {
+                            return;
+                        }
+
+                        self.channel.ccr().modify(|_, w| w.en().clear_bit());
+                        self.channel.clear_flags();
+                        // NOTE(compiler_fence) the half sent can't be written before the
+                        // transfer is stopped
+                        atomic::compiler_fence(Ordering::SeqCst);
+                        self.sending = None;
+                    }
+
+                    if self.queued == 0 {
+                        return;
+                    }
+
+                    let half = self.filling;
+                    let address = self.buffer[half].as_mut_slice().as_ptr() as usize as u32;
+                    let len = u16(self.queued).unwrap();
+                    self.channel.cmar().write(|w| w.ma().bits(address));
+                    self.channel.cndtr().write(|w| w.ndt().bits(len));
+                    self.channel.cpar().write(|w| unsafe {
+                        w.pa().bits(&(*$USARTX::ptr()).tdr as *const _ as usize as u32)
+                    });
+                    self.channel.cselr().modify(|_, w| w.$dmacst().bits(0b0010));
+
+                    // NOTE(compiler_fence) operations on the buffer should not be reordered after
+                    // the next statement, which starts the DMA transfer
+                    atomic::compiler_fence(Ordering::SeqCst);
+
+                    let pl = self.channel.priority_or(Priority::Low) as u8;
+                    self.channel.ccr().modify(|_, w| unsafe {
+                        w.mem2mem()
+                            .clear_bit()
+                            .pl()
+                            .bits(pl)
+                            .msize()
+                            .bits(0b00)
+                            .psize()
+                            .bits(0b00)
+                            .minc()
+                            .set_bit()
+                            .pinc()
+                            .clear_bit()
+                            .circ()
+                            .clear_bit()
+                            // read from memory
+                            .dir()
+                            .set_bit()
+                            .en()
+                            .set_bit()
+                    });
+
+                    self.sending = Some(half);
+                    self.filling = 1 - half;
+                    self.queued = 0;
+                }
+
+                /// Enables the transfer complete interrupt of the channel, to call `poll` from
+                pub fn listen(&mut self) {
+                    self.channel.listen(DmaEvent::TransferComplete);
+                }
+
+                /// Disables the transfer complete interrupt of the channel
+                pub fn unlisten(&mut self) {
+                    self.channel.unlisten(DmaEvent::TransferComplete);
+                }
+
+                /// Returns `true` once all the queued bytes have been handed to the USART
+                pub fn is_empty(&mut self) -> bool {
+                    self.poll();
+                    self.sending.is_none()
+                }
+
+                /// Waits until all the queued bytes have been sent, and returns the parts
+                pub fn free(mut self) -> (Tx<$USARTX>, $tx_chan, B) {
+                    while !self.is_empty() {}
+                    self.tx.flush_and_wait_tc();
+                    (self.tx, self.channel, self.buffer)
+                }
+            }
+
+            impl<B, H> fmt::Write for TxQueue<$USARTX, $tx_chan, B>
+            where
+                B: StableDeref<Target = [H; 2]> + DerefMut + 'static,
+                H: AsMutSlice<Element = u8>,
+            {
+                /// Queues `s`, fails if it doesn't fit
+                fn write_str(&mut self, s: &str) -> fmt::Result {
+                    let mut bytes = s.as_bytes();
+                    while !bytes.is_empty() {
+                        let n = self.write_bytes(bytes);
+                        if n == 0 && self.sending.is_some() {
+                            return Err(fmt::Error);
+                        }
+                        bytes = &bytes[n..];
+                    }
+                    Ok(())
+                }
+            }
+
+            impl Busy for Tx<$USARTX> {
+                fn is_busy(&self) -> bool {
+                    // NOTE(unsafe) atomic read with no side effects; writing TDR clears TC, and a
+                    // break requested with `send_break` is pending until SBKF clears
+                    let isr = unsafe { (*$USARTX::ptr()).isr.read() };
+                    isr.tc().bit_is_clear() || isr.sbkf().bit_is_set()
+                }
+            }
+
             impl Rx<$USARTX> {
                 pub fn circ_read<B, H>(
                     &self,
@@ -267,12 +1115,12 @@ macro_rules! hal {
                         // the next statement, which starts the DMA transfer
                         atomic::compiler_fence(Ordering::SeqCst);
 
+                        let pl = chan.priority_or(Priority::Medium) as u8;
                         chan.ccr().modify(|_, w| unsafe {
                             w.mem2mem()
                                 .clear_bit()
-                                // 00: Low, 01: Medium, 10: High, 11: Very high
                                 .pl()
-                                .bits(0b01)
+                                .bits(pl)
                                 // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
                                 .msize()
                                 .bits(0b00)
@@ -296,6 +1144,110 @@ macro_rules! hal {
                     CircBuffer::new(buffer, chan)
                 }
 
+                /// Receives into `buffer` until the line goes idle, e.g. at the end of a response
+                /// of variable length, and returns the number of bytes received
+                ///
+                /// The bytes are moved by the DMA, and the reception stops at the first idle line
+                /// following a byte, or once `buffer` (up to its first 65535 bytes) is full.
+                /// Blocks until then, including while waiting for the first byte. A reception
+                /// error is returned once the reception is over, the bytes received are then
+                /// lost.
+                pub fn read_until_idle(
+                    &mut self,
+                    chan: &mut $rx_chan,
+                    buffer: &mut [u8],
+                ) -> Result<usize, Error> {
+                    if buffer.is_empty() {
+                        return Ok(0);
+                    }
+
+                    // NOTE(unsafe) atomic reads with no side effects, and writes to a stateless
+                    // register
+                    let isr = || unsafe { (*$USARTX::ptr()).isr.read() };
+                    let icr = unsafe { &(*$USARTX::ptr()).icr };
+                    // an idle line before the reception doesn't end it
+                    icr.write(|w| w.idlecf().set_bit());
+
+                    // the DMA counter is 16-bit
+                    let len = buffer.len().min(usize::from(u16::MAX));
+                    chan.cmar().write(|w| w.ma().bits(buffer.as_mut_ptr() as usize as u32));
+                    // NOTE(as) clamped just above
+                    chan.cndtr().write(|w| w.ndt().bits(len as u16));
+                    chan.cpar().write(|w| unsafe {
+                        w.pa().bits(&(*$USARTX::ptr()).rdr as *const _ as usize as u32)
+                    });
+                    chan.cselr().modify(|_, w| w.$dmacsr().bits(0b0010));
+
+                    // NOTE(compiler_fence) operations on `buffer` should not be reordered after
+                    // the next statement, which starts the DMA transfer
+                    atomic::compiler_fence(Ordering::SeqCst);
+
+                    let pl = chan.priority_or(Priority::Medium) as u8;
+                    chan.ccr().modify(|_, w| unsafe {
+                        w.mem2mem()
+                            .clear_bit()
+                            .pl()
+                            .bits(pl)
+                            .msize()
+                            .bits(0b00)
+                            .psize()
+                            .bits(0b00)
+                            .minc()
+                            .set_bit()
+                            .pinc()
+                            .clear_bit()
+                            .circ()
+                            .clear_bit()
+                            .dir()
+                            .clear_bit()
+                            .en()
+                            .set_bit()
+                    });
+
+                    // IDLE is only set once a byte has been received
+                    while !isr().idle().bit_is_set() && chan.get_cndtr() != 0 {}
+
+                    chan.ccr().modify(|_, w| w.en().clear_bit());
+                    chan.clear_flags();
+
+                    // NOTE(compiler_fence) operations on `buffer` should not be reordered before
+                    // the previous statement, which stops the DMA transfer
+                    atomic::compiler_fence(Ordering::SeqCst);
+
+                    let received = len - chan.get_cndtr() as usize;
+                    icr.write(|w| w.idlecf().set_bit());
+
+                    let isr = isr();
+                    let state = Self::state();
+                    let ignore_overrun = state.ignore_overrun.load(Ordering::Relaxed);
+                    state.count(Counter::Bytes, received as u32);
+                    if isr.ore().bit_is_set() && ignore_overrun {
+                        state.overruns.fetch_add(1, Ordering::Relaxed);
+                        state.count(Counter::Overrun, 1);
+                        icr.write(|w| w.orecf().set_bit());
+                    }
+
+                    if isr.pe().bit_is_set() {
+                        icr.write(|w| w.pecf().set_bit());
+                        state.count(Counter::Parity, 1);
+                        Err(Error::Parity)
+                    } else if isr.fe().bit_is_set() {
+                        icr.write(|w| w.fecf().set_bit());
+                        state.count(Counter::Framing, 1);
+                        Err(Error::Framing)
+                    } else if isr.nf().bit_is_set() {
+                        icr.write(|w| w.ncf().set_bit());
+                        state.count(Counter::Noise, 1);
+                        Err(Error::Noise)
+                    } else if isr.ore().bit_is_set() && !ignore_overrun {
+                        icr.write(|w| w.orecf().set_bit());
+                        state.count(Counter::Overrun, 1);
+                        Err(Error::Overrun)
+                    } else {
+                        Ok(received)
+                    }
+                }
+
                 /// Checks to see if the usart peripheral has detected an idle line and clears the flag
                 pub fn is_idle(&mut self, clear: bool) -> bool {
                     let isr = unsafe { &(*$USARTX::ptr()).isr.read() };
@@ -314,13 +1266,157 @@ macro_rules! hal {
                     }
                 }
             }
+
+            impl ReceiverTimeout for Rx<$USARTX> {
+                fn is_receiver_timeout(&mut self, clear: bool) -> bool {
+                    // NOTE(unsafe) atomic read with no side effects
+                    let timeout = unsafe { (*$USARTX::ptr()).isr.read().rtof().bit_is_set() };
+                    if timeout && clear {
+                        // NOTE(unsafe) atomic write to a stateless register
+                        unsafe { (*$USARTX::ptr()).icr.write(|w| w.rtocf().set_bit()) };
+                    }
+                    timeout
+                }
+            }
+
+            #[cfg(feature = "async")]
+            impl asynch::Interrupt for $USARTX {
+                fn on_interrupt() {
+                    // NOTE(unsafe) atomic read with no side effects
+                    let isr = unsafe { (*$USARTX::ptr()).isr.read() };
+                    let cr1 = unsafe { &(*$USARTX::ptr()).cr1 };
+                    let enabled = cr1.read();
+
+                    if enabled.rxneie().bit_is_set()
+                        && (isr.rxne().bit_is_set() || isr.ore().bit_is_set())
+                    {
+                        cr1.modify(|_, w| w.rxneie().clear_bit());
+                        Rx::<$USARTX>::waker().wake();
+                    }
+
+                    if (enabled.txeie().bit_is_set() && isr.txe().bit_is_set())
+                        || (enabled.tcie().bit_is_set() && isr.tc().bit_is_set())
+                    {
+                        cr1.modify(|_, w| w.txeie().clear_bit().tcie().clear_bit());
+                        Tx::<$USARTX>::waker().wake();
+                    }
+                }
+            }
+
+            #[cfg(feature = "async")]
+            impl Rx<$USARTX> {
+                fn waker() -> &'static AtomicWaker {
+                    static WAKER: AtomicWaker = AtomicWaker::new();
+                    &WAKER
+                }
+            }
+
+            #[cfg(feature = "async")]
+            impl Tx<$USARTX> {
+                fn waker() -> &'static AtomicWaker {
+                    static WAKER: AtomicWaker = AtomicWaker::new();
+                    &WAKER
+                }
+            }
+
+            #[cfg(feature = "async")]
+            impl embedded_io::ErrorType for Rx<$USARTX> {
+                type Error = Error;
+            }
+
+            #[cfg(feature = "async")]
+            impl embedded_io_async::Read for Rx<$USARTX> {
+                /// Waits for at least one byte and then returns everything already received
+                async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+                    if buf.is_empty() {
+                        return Ok(0);
+                    }
+
+                    buf[0] = asynch::wait(
+                        Self::waker(),
+                        || asynch::ready(serial::Read::read(self)),
+                        || interrupt::free(|_| unsafe {
+                            (*$USARTX::ptr()).cr1.modify(|_, w| w.rxneie().set_bit())
+                        }),
+                    ).await?;
+
+                    let mut count = 1;
+                    for byte in buf[1..].iter_mut() {
+                        match serial::Read::read(self) {
+                            Ok(received) => *byte = received,
+                            Err(nb::Error::WouldBlock) => break,
+                            Err(nb::Error::Other(e)) => return Err(e),
+                        }
+                        count += 1;
+                    }
+
+                    Ok(count)
+                }
+            }
+
+            #[cfg(feature = "async")]
+            impl embedded_io::ErrorType for Tx<$USARTX> {
+                type Error = Infallible;
+            }
+
+            #[cfg(feature = "async")]
+            impl embedded_io_async::Write for Tx<$USARTX> {
+                /// Waits until the first byte can be sent and then writes as many as fit
+                async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+                    if buf.is_empty() {
+                        return Ok(0);
+                    }
+
+                    asynch::wait(
+                        Self::waker(),
+                        || serial::Write::write(self, buf[0]).ok(),
+                        || interrupt::free(|_| unsafe {
+                            (*$USARTX::ptr()).cr1.modify(|_, w| w.txeie().set_bit())
+                        }),
+                    ).await;
+
+                    let mut count = 1;
+                    for byte in &buf[1..] {
+                        if serial::Write::write(self, *byte).is_err() {
+                            break;
+                        }
+                        count += 1;
+                    }
+
+                    Ok(count)
+                }
+
+                /// Waits for the transmission complete flag
+                async fn flush(&mut self) -> Result<(), Infallible> {
+                    asynch::wait(
+                        Self::waker(),
+                        || serial::Write::flush(self).ok(),
+                        || interrupt::free(|_| unsafe {
+                            (*$USARTX::ptr()).cr1.modify(|_, w| w.tcie().set_bit())
+                        }),
+                    ).await;
+
+                    Ok(())
+                }
+            }
         )+
     }
 }
 
 hal! {
-    USART1: (usart1, APB2, usart1en, usart1rst, pclk2, tx: (c4s, dma1::C4), rx: (c5s, dma1::C5)),
-    USART2: (usart2, APB1R1, usart2en, usart2rst, pclk1, tx: (c7s, dma1::C7), rx: (c6s, dma1::C6)),
+    USART1: (usart1, try_usart1, APB2, usart1en, usart1rst, pclk2, tx: (c4s, dma1::C4), rx: (c5s, dma1::C5)),
+    USART2: (usart2, try_usart2, APB1R1, usart2en, usart2rst, pclk1, tx: (c7s, dma1::C7), rx: (c6s, dma1::C6)),
+}
+
+// NOTE the SVD of these parts names the USART3 reset bit `USART1RST`
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x2"))]
+hal! {
+    USART3: (usart3, try_usart3, APB1R1, usart3en, usart1rst, pclk1, tx: (c2s, dma1::C2), rx: (c3s, dma1::C3)),
+}
+
+#[cfg(not(any(feature = "stm32l4x1", feature = "stm32l4x2")))]
+hal! {
+    USART3: (usart3, try_usart3, APB1R1, usart3en, usart3rst, pclk1, tx: (c2s, dma1::C2), rx: (c3s, dma1::C3)),
 }
 
 impl<USART> fmt::Write for Tx<USART>
@@ -0,0 +1,229 @@
+//! Framed serial reception
+//!
+//! A `FrameReader` splits the byte stream received by a `Source`, e.g. the `CircBuffer` returned
+//! by `Rx::circ_read`, into complete frames. Each frame is decoded into a buffer provided by the
+//! user and handed to a closure as soon as its delimiter has been received.
+//!
+//! ``` ignore
+//! let buf = singleton!(: [[u8; 64]; 2] = [[0; 64]; 2]).unwrap();
+//! let mut circ_buffer = rx.circ_read(channels.5, buf);
+//!
+//! let line = singleton!(: [u8; 128] = [0; 128]).unwrap();
+//! let mut lines = FrameReader::new(Framing::Line, line);
+//!
+//! loop {
+//!     lines.poll(&mut circ_buffer, |frame| match frame {
+//!         Ok(line) => handle(line),
+//!         Err(Error::Overflow) => { /* the line didn't fit in the buffer and was dropped */ }
+//!         Err(_) => {}
+//!     }).unwrap();
+//! }
+//! ```
+
+use as_slice::AsMutSlice;
+
+/// SLIP frame delimiter
+const SLIP_END: u8 = 0xC0;
+/// SLIP escape character
+const SLIP_ESC: u8 = 0xDB;
+/// Escaped `SLIP_END`
+const SLIP_ESC_END: u8 = 0xDC;
+/// Escaped `SLIP_ESC`
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+/// Framing error
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The frame didn't fit in the buffer of the `FrameReader` and was dropped
+    Overflow,
+    /// Received data was lost before it could be read
+    Overrun,
+    /// The frame isn't validly encoded and was dropped
+    Decode,
+}
+
+/// Frame delimitation and encoding
+#[derive(Clone, Copy, PartialEq)]
+pub enum Framing {
+    /// Lines terminated by `\n`, a trailing `\r` is removed
+    Line,
+    /// Consistent Overhead Byte Stuffing, frames are terminated by `0x00`
+    Cobs,
+    /// Serial Line Internet Protocol (RFC 1055), frames are terminated by `0xC0`
+    Slip,
+}
+
+/// A stream of received bytes
+pub trait Source {
+    /// Passes all the bytes received since the previous call to `f`, in one or more slices
+    fn drain<F>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnMut(&[u8]);
+}
+
+/// Reassembles the frames of a byte stream
+pub struct FrameReader<BUFFER> {
+    buffer: BUFFER,
+    framing: Framing,
+    len: usize,
+    escaped: bool,
+    overflow: bool,
+    invalid: bool,
+}
+
+impl<BUFFER> FrameReader<BUFFER>
+where
+    BUFFER: AsMutSlice<Element = u8>,
+{
+    /// Creates a reader for `framing` that decodes frames into `buffer`
+    ///
+    /// `buffer` bounds the size of a decoded frame, except for COBS where it has to hold the
+    /// encoded frame, which is one byte longer per 254 bytes of payload.
+    pub fn new(framing: Framing, buffer: BUFFER) -> Self {
+        FrameReader {
+            buffer,
+            framing,
+            len: 0,
+            escaped: false,
+            overflow: false,
+            invalid: false,
+        }
+    }
+
+    /// Reads all the bytes available from `source` and passes every completed frame to `f`
+    ///
+    /// Frames that were dropped are reported to `f` as an error. Errors of `source` itself are
+    /// returned.
+    pub fn poll<S, F>(&mut self, source: &mut S, mut f: F) -> Result<(), Error>
+    where
+        S: Source,
+        F: FnMut(Result<&[u8], Error>),
+    {
+        source.drain(|data| self.feed(data, &mut f))
+    }
+
+    /// Decodes `data` and passes every completed frame to `f`
+    ///
+    /// Frames that were dropped are reported to `f` as an error.
+    pub fn feed<F>(&mut self, data: &[u8], mut f: F)
+    where
+        F: FnMut(Result<&[u8], Error>),
+    {
+        for &byte in data {
+            match self.framing {
+                Framing::Line => {
+                    if byte == b'\n' {
+                        if self.len > 0 && self.buffer.as_mut_slice()[self.len - 1] == b'\r' {
+                            self.len -= 1;
+                        }
+                        self.complete(&mut f);
+                    } else {
+                        self.store(byte);
+                    }
+                }
+                Framing::Cobs => {
+                    if byte == 0 {
+                        if !self.overflow && !self.invalid && self.len > 0 {
+                            match cobs_decode(&mut self.buffer.as_mut_slice()[..self.len]) {
+                                Some(len) => self.len = len,
+                                None => self.invalid = true,
+                            }
+                        }
+                        self.complete(&mut f);
+                    } else {
+                        self.store(byte);
+                    }
+                }
+                Framing::Slip => {
+                    if byte == SLIP_END {
+                        // an escape right before the end of the frame can't be valid
+                        if self.escaped {
+                            self.invalid = true;
+                        }
+                        self.complete(&mut f);
+                    } else if self.escaped {
+                        self.escaped = false;
+                        match byte {
+                            SLIP_ESC_END => self.store(SLIP_END),
+                            SLIP_ESC_ESC => self.store(SLIP_ESC),
+                            _ => self.invalid = true,
+                        }
+                    } else if byte == SLIP_ESC {
+                        self.escaped = true;
+                    } else {
+                        self.store(byte);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drops the partially received frame
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.escaped = false;
+        self.overflow = false;
+        self.invalid = false;
+    }
+
+    /// Releases the buffer
+    pub fn free(self) -> BUFFER {
+        self.buffer
+    }
+
+    fn store(&mut self, byte: u8) {
+        let buffer = self.buffer.as_mut_slice();
+        if self.len < buffer.len() {
+            buffer[self.len] = byte;
+            self.len += 1;
+        } else {
+            self.overflow = true;
+        }
+    }
+
+    fn complete<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(Result<&[u8], Error>),
+    {
+        if self.overflow {
+            f(Err(Error::Overflow));
+        } else if self.invalid {
+            f(Err(Error::Decode));
+        } else if self.len > 0 {
+            // empty frames, e.g. the leading delimiter of SLIP, are skipped
+            f(Ok(&self.buffer.as_mut_slice()[..self.len]));
+        }
+
+        self.reset();
+    }
+}
+
+/// Decodes a COBS frame without its delimiter in place, returns the decoded length
+fn cobs_decode(buffer: &mut [u8]) -> Option<usize> {
+    let mut read = 0;
+    let mut write = 0;
+
+    while read < buffer.len() {
+        let code = buffer[read] as usize;
+        read += 1;
+
+        if read + code - 1 > buffer.len() {
+            return None;
+        }
+        for _ in 1..code {
+            buffer[write] = buffer[read];
+            write += 1;
+            read += 1;
+        }
+
+        // every group but the last one and the ones of maximal length ends with a zero
+        if code != 0xFF && read < buffer.len() {
+            buffer[write] = 0;
+            write += 1;
+        }
+    }
+
+    Some(write)
+}
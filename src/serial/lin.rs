@@ -0,0 +1,88 @@
+//! LIN frames
+//!
+//! A LIN frame is a header sent by the master, i.e. a break, the sync byte `0x55` and the
+//! protected identifier (PID), followed by a response of up to 8 data bytes and a checksum, sent
+//! by the master or by a slave. The USART generates and detects the breaks (see `Config::lin`),
+//! these helpers compute the rest.
+//!
+//! ``` ignore
+//! let config = Config::default().baud_rate(19_200.bps()).lin(LinBreakLength::Bits11);
+//! let mut serial = Serial::usart1(dp.USART1, (tx, rx), config, clocks, &mut rcc.apb2);
+//!
+//! // master: header of the frame 0x10, and its response
+//! serial.send_break();
+//! for byte in lin::header(0x10).iter().chain(data.iter()) {
+//!     block!(serial.write(*byte)).ok();
+//! }
+//! block!(serial.write(Checksum::Enhanced.compute(lin::pid(0x10), &data))).ok();
+//! ```
+
+/// Sync byte, sent after the break
+pub const SYNC: u8 = 0x55;
+
+/// Largest identifier
+pub const MAX_ID: u8 = 0x3F;
+
+/// Checksum model
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Checksum {
+    /// LIN 1.x: the data bytes only, also used for the diagnostic frames 0x3C and 0x3D
+    Classic,
+    /// LIN 2.x: the PID and the data bytes
+    Enhanced,
+}
+
+impl Checksum {
+    /// Returns the checksum of the response `data` of the frame identified by `pid`
+    pub fn compute(self, pid: u8, data: &[u8]) -> u8 {
+        let init = match self {
+            Checksum::Classic => 0,
+            Checksum::Enhanced => u16::from(pid),
+        };
+
+        // sum with end-around carry, inverted
+        let sum = data.iter().fold(init, |sum, byte| {
+            let sum = sum + u16::from(*byte);
+            if sum > 0xFF {
+                sum - 0xFF
+            } else {
+                sum
+            }
+        });
+        !(sum as u8)
+    }
+
+    /// Returns `true` if `checksum` matches the response `data` of the frame identified by `pid`
+    pub fn verify(self, pid: u8, data: &[u8], checksum: u8) -> bool {
+        self.compute(pid, data) == checksum
+    }
+}
+
+/// Returns the protected identifier of the identifier `id`, i.e. `id` with its 2 parity bits
+///
+/// Panics if `id` is greater than `MAX_ID`.
+pub fn pid(id: u8) -> u8 {
+    assert!(id <= MAX_ID);
+
+    let bit = |n: u8| (id >> n) & 1;
+    let p0 = bit(0) ^ bit(1) ^ bit(2) ^ bit(4);
+    let p1 = !(bit(1) ^ bit(3) ^ bit(4) ^ bit(5)) & 1;
+    id | (p0 << 6) | (p1 << 7)
+}
+
+/// Returns the identifier of the protected identifier `pid`, or `None` if its parity bits are
+/// wrong
+pub fn id(pid: u8) -> Option<u8> {
+    let id = pid & MAX_ID;
+    if self::pid(id) == pid {
+        Some(id)
+    } else {
+        None
+    }
+}
+
+/// Returns the bytes of the header of the frame `id` sent after the break, i.e. the sync byte and
+/// the protected identifier
+pub fn header(id: u8) -> [u8; 2] {
+    [SYNC, pid(id)]
+}
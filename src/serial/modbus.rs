@@ -0,0 +1,212 @@
+//! Modbus RTU framing
+//!
+//! A Modbus RTU frame (ADU) is the address of the slave, the PDU (function code and data) and a
+//! CRC-16, and frames are separated by at least 3.5 characters of silence. The USART detects the
+//! silence with its receiver timeout, see `config`, and drives the transceiver of the RS-485 bus
+//! with its driver enable output, see `Config::driver_enable_times`.
+//!
+//! The frames are 8 data bits, no parity and 1 stop bit, the other character formats of the
+//! specification aren't supported by `Config`.
+//!
+//! ``` ignore
+//! let config = modbus::config(19_200.bps()).driver_enable_times(16, 16);
+//! let serial = Serial::usart2(dp.USART2, (tx, rx, de), config, clocks, &mut rcc.apb1r1);
+//! let (mut tx, mut rx) = serial.split();
+//! let mut requests = FrameReader::new([0; MAX_ADU]);
+//!
+//! loop {
+//!     requests.poll(&mut rx, |frame| {
+//!         if let Ok(frame) = frame {
+//!             if frame.address == ADDRESS {
+//!                 let response = handle(frame.pdu);
+//!                 modbus::write_frame(&mut tx, ADDRESS, &response).ok();
+//!             }
+//!         }
+//!     });
+//! }
+//! ```
+
+use as_slice::AsMutSlice;
+
+use crate::hal::serial;
+use crate::time::Bps;
+
+use super::{Config, ReceiverTimeout};
+
+/// Largest frame, address and CRC included
+pub const MAX_ADU: usize = 256;
+
+/// Shortest frame: the address, the function code and the CRC
+const MIN_ADU: usize = 4;
+
+/// Modbus error
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Serial error, the frame being received was dropped
+    Serial(super::Error),
+    /// The frame didn't fit in the buffer of the `FrameReader` and was dropped
+    Overflow,
+    /// The frame is shorter than an address, a function code and a CRC
+    TooShort,
+    /// The frame doesn't match its CRC
+    Crc,
+}
+
+/// Received frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame<'a> {
+    /// Address of the slave, 0 for a broadcast
+    pub address: u8,
+    /// Function code and data
+    pub pdu: &'a [u8],
+}
+
+/// Returns the Modbus CRC-16 of `data`, sent low byte first
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter()
+        .fold(0xFFFF, |crc, byte| crc16_update(crc, *byte))
+}
+
+/// Returns the silence ending a frame at `baud_rate`, in bit durations
+///
+/// This is 3.5 characters of 11 bits, or 1.75 ms above 19200 bps as recommended by the
+/// specification.
+pub fn frame_timeout(baud_rate: Bps) -> u32 {
+    if baud_rate.0 > 19_200 {
+        (1750 * u64::from(baud_rate.0)).div_ceil(1_000_000) as u32
+    } else {
+        // 38.5, rounded up
+        39
+    }
+}
+
+/// Returns the serial configuration of a Modbus RTU link at `baud_rate`, with the receiver
+/// timeout set to the silence ending a frame
+pub fn config(baud_rate: Bps) -> Config {
+    Config::default()
+        .baud_rate(baud_rate)
+        .receiver_timeout(frame_timeout(baud_rate))
+}
+
+/// Sends the frame of `pdu` to or from the slave `address`, and returns once the last byte is
+/// written
+///
+/// The characters are sent back to back. A slave answering a request already waited for the
+/// silence ending it, a master has to wait for the silence ending the previous frame, e.g. a
+/// response or a broadcast.
+pub fn write_frame<W>(tx: &mut W, address: u8, pdu: &[u8]) -> Result<(), W::Error>
+where
+    W: serial::Write<u8>,
+{
+    let mut crc = crc16_update(0xFFFF, address);
+    nb::block!(tx.write(address))?;
+    for byte in pdu {
+        crc = crc16_update(crc, *byte);
+        nb::block!(tx.write(*byte))?;
+    }
+    nb::block!(tx.write(crc as u8))?;
+    nb::block!(tx.write((crc >> 8) as u8))
+}
+
+fn crc16_update(crc: u16, byte: u8) -> u16 {
+    (0..8).fold(crc ^ u16::from(byte), |crc, _| {
+        if crc & 1 != 0 {
+            (crc >> 1) ^ 0xA001
+        } else {
+            crc >> 1
+        }
+    })
+}
+
+/// Reassembles the frames received, delimited by the receiver timeout
+pub struct FrameReader<BUFFER> {
+    buffer: BUFFER,
+    len: usize,
+    overflow: bool,
+}
+
+impl<BUFFER> FrameReader<BUFFER>
+where
+    BUFFER: AsMutSlice<Element = u8>,
+{
+    /// Creates a reader that receives frames into `buffer`, `MAX_ADU` bytes for any frame
+    pub fn new(buffer: BUFFER) -> Self {
+        FrameReader {
+            buffer,
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    /// Reads all the bytes available from `rx` and passes the frame to `f` once completed by
+    /// the receiver timeout
+    ///
+    /// Frames that were dropped are reported to `f` as an error. Has to be called at least once
+    /// per character time, or from the RXNE and receiver timeout interrupts.
+    pub fn poll<RX, F>(&mut self, rx: &mut RX, mut f: F)
+    where
+        RX: serial::Read<u8, Error = super::Error> + ReceiverTimeout,
+        F: FnMut(Result<Frame, Error>),
+    {
+        loop {
+            match rx.read() {
+                Ok(byte) => self.store(byte),
+                Err(nb::Error::Other(error)) => {
+                    self.reset();
+                    f(Err(Error::Serial(error)));
+                }
+                Err(nb::Error::WouldBlock) => break,
+            }
+        }
+
+        // a timeout without a character is left by the previous frame
+        if rx.is_receiver_timeout(true) && (self.len > 0 || self.overflow) {
+            self.complete(&mut f);
+        }
+    }
+
+    /// Drops the partially received frame
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.overflow = false;
+    }
+
+    /// Releases the buffer
+    pub fn free(self) -> BUFFER {
+        self.buffer
+    }
+
+    fn store(&mut self, byte: u8) {
+        let buffer = self.buffer.as_mut_slice();
+        if self.len < buffer.len() {
+            buffer[self.len] = byte;
+            self.len += 1;
+        } else {
+            self.overflow = true;
+        }
+    }
+
+    fn complete<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(Result<Frame, Error>),
+    {
+        let adu = &self.buffer.as_mut_slice()[..self.len];
+        if self.overflow {
+            f(Err(Error::Overflow));
+        } else if adu.len() < MIN_ADU {
+            f(Err(Error::TooShort));
+        } else if crc16(adu) != 0 {
+            // the CRC of a frame followed by its CRC is 0
+            f(Err(Error::Crc));
+        } else {
+            f(Ok(Frame {
+                address: adu[0],
+                pdu: &adu[1..adu.len() - 2],
+            }));
+        }
+
+        self.reset();
+    }
+}
@@ -0,0 +1,52 @@
+//! Peripherals split one part at a time
+//!
+//! `split` hands out all the pins of a GPIO port, or all the channels of a DMA controller, at
+//! once. With `split_lazy` the peripheral is turned into a copyable handle instead, from which
+//! each part is taken with `split_one` where it is needed, e.g. in different RTIC tasks or
+//! driver constructors:
+//!
+//! ``` ignore
+//! let gpioa = dp.GPIOA.split_lazy(&mut rcc.ahb2);
+//!
+//! let mut moder = gpioa.split_one::<gpioa::MODER>().unwrap();
+//! let mut otyper = gpioa.split_one::<gpioa::OTYPER>().unwrap();
+//! let led = gpioa
+//!     .split_one::<gpioa::PA5<_>>()
+//!     .unwrap()
+//!     .into_push_pull_output(&mut moder, &mut otyper);
+//!
+//! // a part can only be taken once
+//! assert!(gpioa.split_one::<gpioa::PA5<_>>().is_none());
+//! ```
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::interrupt;
+
+// FIXME these should be "closed" traits
+/// Part of the peripheral handle `PARTS` - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// `BIT` must be unique among the parts of `PARTS` and `new` may only be called once
+/// that bit is taken
+pub unsafe trait Singleton<PARTS> {
+    #[doc(hidden)]
+    const BIT: u8;
+
+    #[doc(hidden)]
+    fn new() -> Self;
+}
+
+/// Marks the part `bit` of `taken` as taken, returns `false` if it already was
+pub(crate) fn take(taken: &AtomicU32, bit: u8) -> bool {
+    interrupt::free(|_| {
+        let bits = taken.load(Ordering::Relaxed);
+        if bits & (1 << bit) != 0 {
+            false
+        } else {
+            taken.store(bits | (1 << bit), Ordering::Relaxed);
+            true
+        }
+    })
+}
@@ -0,0 +1,119 @@
+//! Software PWM on any GPIO pins
+//!
+//! `SoftPwm` drives up to `N` output pins from the update interrupt of one timer, for pins that
+//! aren't channels of a hardware timer, e.g. LEDs on a board. A period is made of `resolution`
+//! ticks: each pin is set at the start of the period and cleared once its duty cycle, in ticks,
+//! has elapsed. The timer has to run at `tick_rate`, i.e. the PWM frequency times the
+//! resolution, which bounds both: e.g. 200 Hz with 100 steps is 20 kHz of interrupts.
+//!
+//! ``` ignore
+//! // the pins of one port, downgraded to the same type
+//! let leds = [pa5.downgrade(), pa8.downgrade(), pa11.downgrade()];
+//! let mut pwm = SoftPwm::new(leds, 100);
+//! let mut timer = Timer::tim6(dp.TIM6, pwm.tick_rate(200.hz()), clocks, &mut rcc.apb1r1);
+//! timer.listen(Event::TimeOut);
+//!
+//! pwm.set_duty(0, 25);
+//!
+//! // TIM6 interrupt
+//! timer.wait().ok();
+//! pwm.tick();
+//! ```
+//!
+//! A new duty cycle takes effect at the start of the next period, there are no glitches. The
+//! edges jitter by the latency of the interrupt.
+
+use crate::hal::digital::v2::OutputPin;
+use crate::time::Hertz;
+
+/// PWM of `N` output pins, clocked by `tick`
+pub struct SoftPwm<PIN, const N: usize> {
+    pins: [PIN; N],
+    /// Duty cycles of the current period
+    duty: [u16; N],
+    /// Duty cycles of the next periods
+    next: [u16; N],
+    resolution: u16,
+    step: u16,
+}
+
+impl<PIN, const N: usize> SoftPwm<PIN, N>
+where
+    PIN: OutputPin,
+{
+    /// Drives `pins` with periods of `resolution` ticks, and clears them until a duty cycle is
+    /// set
+    ///
+    /// Pins of different ports have to be downgraded to the same type, e.g. `PAx<Output<_>>`.
+    pub fn new(mut pins: [PIN; N], resolution: u16) -> Self {
+        assert!(resolution > 0);
+        for pin in pins.iter_mut() {
+            pin.set_low().ok();
+        }
+
+        SoftPwm {
+            pins,
+            duty: [0; N],
+            next: [0; N],
+            resolution,
+            step: 0,
+        }
+    }
+
+    /// Returns the rate `tick` has to be called at for a PWM at `frequency`
+    pub fn tick_rate<F>(&self, frequency: F) -> Hertz
+    where
+        F: Into<Hertz>,
+    {
+        Hertz(frequency.into().0 * u32::from(self.resolution))
+    }
+
+    /// Sets the duty cycle of the pin `channel`, in ticks from 0 (always low) to `get_max_duty`
+    /// (always high)
+    ///
+    /// Panics if `channel` is out of range.
+    pub fn set_duty(&mut self, channel: usize, duty: u16) {
+        self.next[channel] = duty.min(self.resolution);
+    }
+
+    /// Returns the duty cycle of the pin `channel`, as set by `set_duty`
+    pub fn get_duty(&self, channel: usize) -> u16 {
+        self.next[channel]
+    }
+
+    /// Returns the maximum duty cycle, i.e. the resolution
+    pub fn get_max_duty(&self) -> u16 {
+        self.resolution
+    }
+
+    /// Advances the PWM by one tick, to be called from the timer interrupt
+    pub fn tick(&mut self) {
+        if self.step == 0 {
+            self.duty = self.next;
+            for (pin, &duty) in self.pins.iter_mut().zip(self.duty.iter()) {
+                if duty > 0 {
+                    pin.set_high().ok();
+                }
+            }
+        }
+
+        self.step += 1;
+        for (pin, &duty) in self.pins.iter_mut().zip(self.duty.iter()) {
+            if duty == self.step && duty < self.resolution {
+                pin.set_low().ok();
+            }
+        }
+
+        if self.step == self.resolution {
+            self.step = 0;
+        }
+    }
+
+    /// Clears the pins and releases them
+    pub fn free(mut self) -> [PIN; N] {
+        for pin in self.pins.iter_mut() {
+            pin.set_low().ok();
+        }
+        self.pins
+    }
+}
@@ -1,18 +1,61 @@
 //! Serial Peripheral Interface (SPI) bus
+//!
+//! `Spi` is a bus master. `SpiSlave` is clocked by a master, e.g. a host SoC, and selected either
+//! by its NSS pin or by software; on SPI1 the received bytes can be stored in the background by
+//! DMA (`SpiSlave::circ_read`).
+//!
+//! The devices of a bus are selected either by the NSS pin of the master, driven by the hardware
+//! (`Spi::set_hardware_nss`), or by GPIOs with `ChipSelect`:
+//!
+//! ``` ignore
+//! let cs = [flash_cs.downgrade(), display_cs.downgrade()];
+//! let mut devices = ChipSelect::new(cs, CyclesDelay::new(clocks), 1, 1);
+//!
+//! let mut jedec_id = [0x9F, 0, 0, 0];
+//! devices.transaction(FLASH, &mut spi, |spi| spi.transfer(&mut jedec_id).map(|_| ()))?;
+//! ```
+//!
+//! With embedded-hal 1.0 (the `embedded-hal-1` feature, enabled by `async`), `Spi` is an
+//! `SpiBus`, which `bus::BusManager` shares between drivers as an `SpiDevice` for each device.
+//!
+//! The SPIs of the STM32L4 have no I2S mode (there is no I2SCFGR register, unlike the SPIs of the
+//! STM32F4): audio goes through the SAI, which the STM32L4x1/x2/x3 also have.
 
+use core::ops::DerefMut;
 use core::ptr;
+use core::sync::atomic::{self, Ordering};
 
+use as_slice::AsMutSlice;
+use cast::u16;
+use stable_deref_trait::StableDeref;
+
+use crate::hal::blocking::delay::DelayUs;
+use crate::hal::digital::v2::OutputPin;
 use crate::hal::spi::{FullDuplex, Mode, Phase, Polarity};
 use nb;
+use crate::stm32::spi1::RegisterBlock;
 use crate::stm32::{SPI1, /* TODO SPI2, */ SPI3};
 
-use crate::gpio::gpioa::{PA5, PA6, PA7};
+use crate::dma::{dma1, CircBuffer, Priority};
+use crate::gpio::gpioa::{PA15, PA4, PA5, PA6, PA7};
 use crate::gpio::{AF5, Input, Floating, Alternate};
 use crate::rcc::{APB1R1, APB2, Clocks};
 use crate::time::Hertz;
 
+#[cfg(feature = "async")]
+use cortex_m::interrupt;
+#[cfg(feature = "embedded-hal-1")]
+use embedded_hal_1::spi::ErrorKind;
+#[cfg(feature = "async")]
+use crate::asynch::{self, AtomicWaker};
+
+#[cfg(feature = "embedded-hal-1")]
+pub mod bus;
+
 /// SPI error
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum Error {
     /// Overrun occurred
     Overrun,
@@ -20,8 +63,17 @@ pub enum Error {
     ModeFault,
     /// CRC error
     Crc,
-    #[doc(hidden)]
-    _Extensible,
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl embedded_hal_1::spi::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::Overrun => ErrorKind::Overrun,
+            Error::ModeFault => ErrorKind::ModeFault,
+            Error::Crc => ErrorKind::Other,
+        }
+    }
 }
 
 pub trait Pins<SPI> {
@@ -38,12 +90,169 @@ impl Pins<SPI1>
     const REMAP: bool = false; // TODO REMAP
 }
 
+/// Frame format
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameFormat {
+    /// Motorola, with the clock polarity and phase of the `Mode`
+    Motorola,
+    /// TI synchronous serial: the master pulses NSS for one clock period before each frame, the
+    /// clock polarity and phase are fixed. The NSS pin of the master is driven by the hardware
+    /// and has to be configured in its alternate function
+    Ti,
+}
+
+/// Order of the bits of a frame on the bus
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BitOrder {
+    /// Most significant bit first
+    MsbFirst,
+    /// Least significant bit first
+    LsbFirst,
+}
+
+/// Frame configuration methods, shared by `Spi` and `SpiSlave`
+macro_rules! frame_config {
+    () => {
+        /// Sets the size of the frames, from 4 to 16 bits (8 by default)
+        ///
+        /// Frames of up to 8 bits are exchanged as `u8`, larger frames as `u16`. Panics if
+        /// `bits` is out of range.
+        pub fn set_data_size(&mut self, bits: u8) {
+            assert!((4..=16).contains(&bits));
+            self.reconfigure(|spi| {
+                // FRXTH: RXNE event once 8 bits are received, for frames of up to 8 bits
+                spi.cr2
+                    .modify(|_, w| unsafe { w.ds().bits(bits - 1).frxth().bit(bits <= 8) })
+            });
+        }
+
+        /// Sets the order of the bits on the bus (MSB first by default)
+        pub fn set_bit_order(&mut self, order: BitOrder) {
+            self.reconfigure(|spi| {
+                spi.cr1.modify(|_, w| w.lsbfirst().bit(order == BitOrder::LsbFirst))
+            });
+        }
+
+        /// Sets the frame format (Motorola by default)
+        pub fn set_frame_format(&mut self, format: FrameFormat) {
+            self.reconfigure(|spi| {
+                spi.cr2.modify(|_, w| w.frf().bit(format == FrameFormat::Ti))
+            });
+        }
+
+        /// Disables the peripheral once the current frame is exchanged, while `f` changes its
+        /// configuration
+        fn reconfigure<F>(&mut self, f: F)
+        where
+            F: FnOnce(&RegisterBlock),
+        {
+            while self.spi.sr.read().bsy().bit_is_set() {}
+            self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+            f(&self.spi);
+            self.spi.cr1.modify(|_, w| w.spe().set_bit());
+        }
+    };
+}
+
+/// Slave select of a `SpiSlave` driven by software, see `SpiSlave::select`
+pub struct SoftwareNss;
+
+// FIXME these should be "closed" traits
+/// NSS pin of a SPI slave, or `SoftwareNss` - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// `HARDWARE` must only be true for a pin routed to the NSS input of `SPI`
+pub unsafe trait NssPin<SPI> {
+    #[doc(hidden)]
+    const HARDWARE: bool;
+}
+
+unsafe impl NssPin<SPI1> for PA4<Alternate<AF5, Input<Floating>>> {
+    const HARDWARE: bool = true;
+}
+
+unsafe impl NssPin<SPI1> for PA15<Alternate<AF5, Input<Floating>>> {
+    const HARDWARE: bool = true;
+}
+
+unsafe impl<SPI> NssPin<SPI> for SoftwareNss {
+    const HARDWARE: bool = false;
+}
+
 /// SPI peripheral operating in full duplex master mode
 pub struct Spi<SPI, PINS> {
     spi: SPI,
     pins: PINS,
 }
 
+/// SPI peripheral operating in full duplex slave mode
+pub struct SpiSlave<SPI, PINS, NSS> {
+    spi: SPI,
+    pins: PINS,
+    nss: NSS,
+}
+
+/// Active low chip select GPIOs of the `N` devices of a bus
+///
+/// A device is selected for the duration of a `transaction`, with a setup delay between its
+/// selection and the first clock edge, and a hold delay between the last clock edge and its
+/// deselection, both in microseconds and waited for with the `D` delay provider.
+pub struct ChipSelect<CS, D, const N: usize> {
+    pins: [CS; N],
+    delay: D,
+    setup_us: u32,
+    hold_us: u32,
+}
+
+impl<CS, D, const N: usize> ChipSelect<CS, D, N>
+where
+    CS: OutputPin,
+    D: DelayUs<u32>,
+{
+    /// Deselects all the devices
+    ///
+    /// The pins all have the same type, so they have to be pins of a single port, downgraded to
+    /// e.g. `PAx<Output<_>>`: the pins of different ports can't be mixed.
+    pub fn new(mut pins: [CS; N], delay: D, setup_us: u32, hold_us: u32) -> Self {
+        for pin in pins.iter_mut() {
+            pin.set_high().ok();
+        }
+
+        ChipSelect {
+            pins,
+            delay,
+            setup_us,
+            hold_us,
+        }
+    }
+
+    /// Selects the device `device` while `f` exchanges data on `spi`, and returns the result of
+    /// `f`
+    ///
+    /// `f` has to wait for the end of its exchanges, as the blocking traits do. Panics if
+    /// `device` is out of range.
+    pub fn transaction<SPI, F, R>(&mut self, device: usize, spi: &mut SPI, f: F) -> R
+    where
+        F: FnOnce(&mut SPI) -> R,
+    {
+        let pin = &mut self.pins[device];
+        pin.set_low().ok();
+        self.delay.delay_us(self.setup_us);
+
+        let result = f(spi);
+
+        self.delay.delay_us(self.hold_us);
+        pin.set_high().ok();
+        result
+    }
+
+    /// Releases the pins and the delay provider
+    pub fn free(self) -> ([CS; N], D) {
+        (self.pins, self.delay)
+    }
+}
+
 macro_rules! hal {
     ($($SPIX:ident: ($spiX:ident, $APBX:ident, $spiXen:ident, $spiXrst:ident, $pclkX:ident),)+) => {
         $(
@@ -123,10 +332,32 @@ macro_rules! hal {
                     Spi { spi, pins }
                 }
 
+                frame_config!();
+
+                /// Drives the NSS pin of the master by hardware (SSOE), low while the
+                /// peripheral is enabled; with `pulse`, NSS also goes high for one clock period
+                /// between consecutive frames (NSSP), e.g. as a conversion strobe
+                ///
+                /// The NSS pin has to be configured in its alternate function.
+                pub fn set_hardware_nss(&mut self, pulse: bool) {
+                    self.reconfigure(|spi| {
+                        spi.cr1.modify(|_, w| w.ssm().clear_bit());
+                        spi.cr2.modify(|_, w| w.ssoe().set_bit().nssp().bit(pulse));
+                    });
+                }
+
                 /// Releases the SPI peripheral and associated pins
                 pub fn free(self) -> ($SPIX, PINS) {
                     (self.spi, self.pins)
                 }
+
+                /// Waits until the bus is idle, then resets the SPI peripheral, stops its clock
+                /// and releases it with the pins
+                pub fn release(self, apb: &mut $APBX) -> ($SPIX, PINS) {
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+                    apb.deinit(&self.spi);
+                    (self.spi, self.pins)
+                }
             }
 
             impl<PINS> FullDuplex<u8> for Spi<$SPIX, PINS> {
@@ -163,7 +394,7 @@ macro_rules! hal {
                         nb::Error::Other(Error::Crc)
                     } else if sr.txe().bit_is_set() {
                         // NOTE(write_volatile) see note above
-                        unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut u8, byte) }
+                        unsafe { ptr::write_volatile(ptr::addr_of!(self.spi.dr) as *mut u8, byte) }
                         return Ok(());
                     } else {
                         nb::Error::WouldBlock
@@ -171,15 +402,415 @@ macro_rules! hal {
                 }
             }
 
+            impl<PINS, NSS> SpiSlave<$SPIX, PINS, NSS> {
+                /// Configures the SPI peripheral to operate in full duplex slave mode
+                ///
+                /// The slave is selected by its NSS pin, or deselected until `select` with
+                /// `SoftwareNss`. The first byte sent to the master has to be written (`send`)
+                /// before the slave is selected.
+                pub fn $spiX(
+                    spi: $SPIX,
+                    pins: PINS,
+                    nss: NSS,
+                    mode: Mode,
+                    apb2: &mut $APBX,
+                ) -> Self
+                where
+                    PINS: Pins<$SPIX>,
+                    NSS: NssPin<$SPIX>,
+                {
+                    // enable or reset $SPIX
+                    apb2.enr().modify(|_, w| w.$spiXen().set_bit());
+                    apb2.rstr().modify(|_, w| w.$spiXrst().set_bit());
+                    apb2.rstr().modify(|_, w| w.$spiXrst().clear_bit());
+
+                    // FRXTH: RXNE event is generated if the FIFO level is greater than or equal to
+                    //        8-bit
+                    // DS: 8-bit data size
+                    spi.cr2.write(|w| unsafe { w.frxth().set_bit().ds().bits(0b111) });
+
+                    // MSTR: slave mode
+                    // SSM: software slave management without a NSS pin
+                    // SSI: slave deselected
+                    spi.cr1.write(|w| {
+                        w.cpha()
+                            .bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .cpol()
+                            .bit(mode.polarity == Polarity::IdleHigh)
+                            .mstr()
+                            .clear_bit()
+                            .ssm()
+                            .bit(!NSS::HARDWARE)
+                            .ssi()
+                            .set_bit()
+                            .spe()
+                            .set_bit()
+                    });
+
+                    SpiSlave { spi, pins, nss }
+                }
+
+                frame_config!();
+
+                /// Returns `true` while a byte is being exchanged, or the transmit FIFO isn't
+                /// empty
+                pub fn is_busy(&self) -> bool {
+                    self.spi.sr.read().bsy().bit_is_set()
+                }
+
+                /// Releases the SPI peripheral, the associated pins and the NSS pin
+                pub fn free(self) -> ($SPIX, PINS, NSS) {
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    (self.spi, self.pins, self.nss)
+                }
+            }
+
+            impl<PINS> SpiSlave<$SPIX, PINS, SoftwareNss> {
+                /// Selects or deselects the slave
+                pub fn select(&mut self, selected: bool) {
+                    self.spi.cr1.modify(|_, w| w.ssi().bit(!selected));
+                }
+            }
+
+            impl<PINS, NSS> FullDuplex<u8> for SpiSlave<$SPIX, PINS, NSS> {
+                type Error = Error;
+
+                /// Returns the next received byte
+                ///
+                /// Returns `Error::Overrun` once if bytes were received while the receive FIFO
+                /// was full; these bytes are lost.
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    let sr = self.spi.sr.read();
+
+                    if sr.ovr().bit_is_set() {
+                        // OVR is cleared by reading DR then SR
+                        // NOTE(read_volatile) see `Spi::read`
+                        unsafe {
+                            ptr::read_volatile(&self.spi.dr as *const _ as *const u8);
+                        }
+                        self.spi.sr.read();
+                        trace!("{=str}: slave overrun", stringify!($SPIX));
+                        Err(nb::Error::Other(Error::Overrun))
+                    } else if sr.rxne().bit_is_set() {
+                        // NOTE(read_volatile) read only 1 byte (the svd2rust API only allows
+                        // reading a half-word)
+                        Ok(unsafe { ptr::read_volatile(&self.spi.dr as *const _ as *const u8) })
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /// Queues a byte, sent during the next exchange clocked by the master
+                fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
+                    if self.spi.sr.read().txe().bit_is_set() {
+                        // NOTE(write_volatile) see note above
+                        unsafe { ptr::write_volatile(ptr::addr_of!(self.spi.dr) as *mut u8, byte) }
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+            }
+
+            impl<PINS> FullDuplex<u16> for Spi<$SPIX, PINS> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u16, Error> {
+                    let sr = self.spi.sr.read();
+
+                    Err(if sr.ovr().bit_is_set() {
+                        nb::Error::Other(Error::Overrun)
+                    } else if sr.modf().bit_is_set() {
+                        nb::Error::Other(Error::ModeFault)
+                    } else if sr.crcerr().bit_is_set() {
+                        nb::Error::Other(Error::Crc)
+                    } else if sr.rxne().bit_is_set() {
+                        // NOTE(read_volatile) a half-word access reads a single frame of more
+                        // than 8 bits
+                        return Ok(unsafe {
+                            ptr::read_volatile(&self.spi.dr as *const _ as *const u16)
+                        });
+                    } else {
+                        nb::Error::WouldBlock
+                    })
+                }
+
+                fn send(&mut self, word: u16) -> nb::Result<(), Error> {
+                    let sr = self.spi.sr.read();
+
+                    Err(if sr.ovr().bit_is_set() {
+                        nb::Error::Other(Error::Overrun)
+                    } else if sr.modf().bit_is_set() {
+                        nb::Error::Other(Error::ModeFault)
+                    } else if sr.crcerr().bit_is_set() {
+                        nb::Error::Other(Error::Crc)
+                    } else if sr.txe().bit_is_set() {
+                        // NOTE(write_volatile) see note above
+                        unsafe { ptr::write_volatile(ptr::addr_of!(self.spi.dr) as *mut u16, word) }
+                        return Ok(());
+                    } else {
+                        nb::Error::WouldBlock
+                    })
+                }
+            }
+
+            impl<PINS, NSS> FullDuplex<u16> for SpiSlave<$SPIX, PINS, NSS> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u16, Error> {
+                    let sr = self.spi.sr.read();
+
+                    if sr.ovr().bit_is_set() {
+                        // OVR is cleared by reading DR then SR
+                        // NOTE(read_volatile) see `Spi::read`
+                        unsafe {
+                            ptr::read_volatile(&self.spi.dr as *const _ as *const u16);
+                        }
+                        self.spi.sr.read();
+                        trace!("{=str}: slave overrun", stringify!($SPIX));
+                        Err(nb::Error::Other(Error::Overrun))
+                    } else if sr.rxne().bit_is_set() {
+                        // NOTE(read_volatile) see `Spi::read`
+                        Ok(unsafe { ptr::read_volatile(&self.spi.dr as *const _ as *const u16) })
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                fn send(&mut self, word: u16) -> nb::Result<(), Error> {
+                    if self.spi.sr.read().txe().bit_is_set() {
+                        // NOTE(write_volatile) see `Spi::send`
+                        unsafe { ptr::write_volatile(ptr::addr_of!(self.spi.dr) as *mut u16, word) }
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+            }
+
             impl<PINS> crate::hal::blocking::spi::transfer::Default<u8> for Spi<$SPIX, PINS> {}
 
+            impl<PINS> crate::hal::blocking::spi::transfer::Default<u16> for Spi<$SPIX, PINS> {}
+
+            impl<PINS> crate::hal::blocking::spi::write::Default<u16> for Spi<$SPIX, PINS> {}
+
             impl<PINS> crate::hal::blocking::spi::write::Default<u8> for Spi<$SPIX, PINS> {}
+
+            #[cfg(feature = "async")]
+            impl asynch::Interrupt for $SPIX {
+                fn on_interrupt() {
+                    // NOTE(unsafe) only clears interrupt enables set by a pending future
+                    unsafe {
+                        (*$SPIX::ptr()).cr2.modify(|_, w| {
+                            w.txeie().clear_bit().rxneie().clear_bit().errie().clear_bit()
+                        })
+                    }
+                    Spi::<$SPIX, ()>::waker().wake();
+                }
+            }
+
+            #[cfg(feature = "async")]
+            impl<PINS> Spi<$SPIX, PINS> {
+                fn waker() -> &'static AtomicWaker {
+                    static WAKER: AtomicWaker = AtomicWaker::new();
+                    &WAKER
+                }
+
+                /// Sends `byte` and returns the byte received in exchange
+                async fn exchange(&mut self, byte: u8) -> Result<u8, Error> {
+                    asynch::wait(
+                        Self::waker(),
+                        || asynch::ready(FullDuplex::send(self, byte)),
+                        || interrupt::free(|_| unsafe {
+                            (*$SPIX::ptr()).cr2.modify(|_, w| w.txeie().set_bit().errie().set_bit())
+                        }),
+                    ).await?;
+
+                    asynch::wait(
+                        Self::waker(),
+                        || asynch::ready(FullDuplex::read(self)),
+                        || interrupt::free(|_| unsafe {
+                            (*$SPIX::ptr()).cr2.modify(|_, w| w.rxneie().set_bit().errie().set_bit())
+                        }),
+                    ).await
+                }
+            }
+
+            #[cfg(feature = "embedded-hal-1")]
+            impl<PINS> embedded_hal_1::spi::ErrorType for Spi<$SPIX, PINS> {
+                type Error = Error;
+            }
+
+            #[cfg(feature = "embedded-hal-1")]
+            impl<PINS> embedded_hal_1::spi::SpiBus<u8> for Spi<$SPIX, PINS> {
+                fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    for word in words {
+                        *word = self.exchange_blocking(0)?;
+                    }
+                    Ok(())
+                }
+
+                fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+                    for word in words {
+                        self.exchange_blocking(*word)?;
+                    }
+                    Ok(())
+                }
+
+                fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+                    for i in 0..core::cmp::max(read.len(), write.len()) {
+                        let received = self.exchange_blocking(write.get(i).cloned().unwrap_or(0))?;
+                        if let Some(word) = read.get_mut(i) {
+                            *word = received;
+                        }
+                    }
+                    Ok(())
+                }
+
+                fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    for word in words {
+                        *word = self.exchange_blocking(*word)?;
+                    }
+                    Ok(())
+                }
+
+                fn flush(&mut self) -> Result<(), Error> {
+                    // every exchange waits for its received byte, only the BSY flag is left
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+                    Ok(())
+                }
+            }
+
+            #[cfg(feature = "embedded-hal-1")]
+            impl<PINS> Spi<$SPIX, PINS> {
+                /// Sends `byte` and returns the byte received in exchange
+                fn exchange_blocking(&mut self, byte: u8) -> Result<u8, Error> {
+                    nb::block!(FullDuplex::send(self, byte))?;
+                    nb::block!(FullDuplex::read(self))
+                }
+            }
+
+            #[cfg(feature = "async")]
+            impl<PINS> embedded_hal_async::spi::SpiBus<u8> for Spi<$SPIX, PINS> {
+                async fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    for word in words {
+                        *word = self.exchange(0).await?;
+                    }
+                    Ok(())
+                }
+
+                async fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+                    for word in words {
+                        self.exchange(*word).await?;
+                    }
+                    Ok(())
+                }
+
+                async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+                    for i in 0..core::cmp::max(read.len(), write.len()) {
+                        let received = self.exchange(write.get(i).cloned().unwrap_or(0)).await?;
+                        if let Some(word) = read.get_mut(i) {
+                            *word = received;
+                        }
+                    }
+                    Ok(())
+                }
+
+                async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    for word in words {
+                        *word = self.exchange(*word).await?;
+                    }
+                    Ok(())
+                }
+
+                async fn flush(&mut self) -> Result<(), Error> {
+                    // every exchange waits for its received byte, only the BSY flag is left
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+                    Ok(())
+                }
+            }
         )+
     }
 }
 
+impl<PINS, NSS> SpiSlave<SPI1, PINS, NSS> {
+    /// Receives into `buffer` in the background, as a circular buffer
+    ///
+    /// The bytes received while both halves are unread are lost, see
+    /// `CircBuffer::partial_peek`.
+    pub fn circ_read<B, H>(
+        &mut self,
+        mut chan: dma1::C2,
+        mut buffer: B,
+    ) -> CircBuffer<B, dma1::C2>
+    where
+        B: StableDeref<Target = [H; 2]> + DerefMut,
+        H: AsMutSlice<Element = u8>,
+    {
+        {
+            chan.cmar().write(|w| {
+                w.ma().bits(buffer[0].as_mut_slice().as_ptr() as usize as u32)
+            });
+            chan.cndtr().write(|w| {
+                w.ndt().bits(u16(buffer[0].as_mut_slice().len() * 2).unwrap())
+            });
+            chan.cpar().write(|w| unsafe {
+                w.pa().bits(&(*SPI1::ptr()).dr as *const _ as usize as u32)
+            });
+
+            // Tell DMA to request from SPI1 RX
+            chan.cselr().modify(|_, w| w.c2s().bits(0b0001));
+
+            // TODO can we weaken this compiler barrier?
+            // NOTE(compiler_fence) operations on `buffer` should not be reordered after the next
+            // statement, which starts the DMA transfer
+            atomic::compiler_fence(Ordering::SeqCst);
+
+            let pl = chan.priority_or(Priority::High) as u8;
+            chan.ccr().modify(|_, w| unsafe {
+                w.mem2mem()
+                    .clear_bit()
+                    .pl()
+                    .bits(pl)
+                    // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                    .msize()
+                    .bits(0b00)
+                    // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                    .psize()
+                    .bits(0b00)
+                    // incr mem address
+                    .minc()
+                    .set_bit()
+                    .pinc()
+                    .clear_bit()
+                    .circ()
+                    .set_bit()
+                    // peripheral to memory
+                    .dir()
+                    .clear_bit()
+                    .en()
+                    .set_bit()
+            });
+        }
+
+        self.spi.cr2.modify(|_, w| w.rxdmaen().set_bit());
+
+        CircBuffer::new(buffer, chan)
+    }
+}
+
 hal! {
     SPI1: (spi1, APB2, spi1en, spi1rst, pclk2),
     // SPI2: (spi2, APB1R1, spi2en, spi2rst, pclk1), // NOT Avail on 32k(b|c)
+}
+
+#[cfg(not(feature = "stm32l4x3"))]
+hal! {
     SPI3: (spi3, APB1R1, spi3en, spi3rst, pclk1),
 }
+
+// NOTE the SVD of these parts names the SPI3 enable bit `SP3EN`
+#[cfg(feature = "stm32l4x3")]
+hal! {
+    SPI3: (spi3, APB1R1, sp3en, spi3rst, pclk1),
+}
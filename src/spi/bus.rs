@@ -0,0 +1,140 @@
+//! SPI bus shared between drivers, as `SpiDevice`s
+//!
+//! With embedded-hal 1.0, drivers take an `SpiDevice`: a device of a bus, which selects itself
+//! for each transaction. A `BusManager` owns the bus, e.g. an `Spi`, and hands out an
+//! `SpiProxy` for each device, with its chip select pin:
+//!
+//! ``` ignore
+//! let bus = BusManager::new(spi);
+//! // any `DelayNs`
+//! let delay = CyclesDelay::new(clocks);
+//!
+//! let mut flash = Flash::new(bus.acquire(flash_cs, delay));
+//! let mut display = Display::new(bus.acquire(display_cs, delay));
+//! ```
+//!
+//! The transactions of the devices don't interleave: each one borrows the bus, with its device
+//! selected, until it is over. As with `i2c::bus`, the manager isn't `Sync` and stays in the
+//! context it was created in.
+
+use core::cell::RefCell;
+use core::fmt;
+
+use embedded_hal_1::delay::DelayNs;
+use embedded_hal_1::spi::{self, ErrorKind, Operation, SpiBus};
+
+use crate::hal::digital::v2::OutputPin;
+
+/// Error of an `SpiProxy`
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error<SPI, CS> {
+    /// Bus error
+    Spi(SPI),
+    /// The chip select pin couldn't be driven
+    ChipSelect(CS),
+}
+
+impl<SPI, CS> spi::Error for Error<SPI, CS>
+where
+    SPI: spi::Error,
+    CS: fmt::Debug,
+{
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Spi(error) => error.kind(),
+            Error::ChipSelect(_) => ErrorKind::ChipSelectFault,
+        }
+    }
+}
+
+/// Owner of an SPI bus shared by several devices
+pub struct BusManager<SPI> {
+    bus: RefCell<SPI>,
+}
+
+impl<SPI> BusManager<SPI> {
+    /// Takes ownership of the bus
+    pub fn new(spi: SPI) -> Self {
+        BusManager {
+            bus: RefCell::new(spi),
+        }
+    }
+
+    /// Returns the device selected by the active low `cs`, which is deselected
+    ///
+    /// `delay` runs the delay operations of the transactions.
+    pub fn acquire<CS, D>(&self, mut cs: CS, delay: D) -> SpiProxy<'_, SPI, CS, D>
+    where
+        CS: OutputPin,
+        D: DelayNs,
+    {
+        cs.set_high().ok();
+        SpiProxy {
+            bus: &self.bus,
+            cs,
+            delay,
+        }
+    }
+
+    /// Releases the bus, once the proxies are dropped
+    pub fn free(self) -> SPI {
+        self.bus.into_inner()
+    }
+}
+
+/// Device of a shared SPI bus, see `BusManager::acquire`
+pub struct SpiProxy<'a, SPI, CS, D> {
+    bus: &'a RefCell<SPI>,
+    cs: CS,
+    delay: D,
+}
+
+impl<'a, SPI, CS, D> SpiProxy<'a, SPI, CS, D> {
+    /// Releases the chip select pin and the delay provider
+    pub fn free(self) -> (CS, D) {
+        (self.cs, self.delay)
+    }
+}
+
+impl<'a, SPI, CS, D> spi::ErrorType for SpiProxy<'a, SPI, CS, D>
+where
+    SPI: spi::ErrorType,
+    CS: OutputPin,
+    CS::Error: fmt::Debug,
+{
+    type Error = Error<SPI::Error, CS::Error>;
+}
+
+impl<'a, SPI, CS, D> spi::SpiDevice<u8> for SpiProxy<'a, SPI, CS, D>
+where
+    SPI: SpiBus<u8>,
+    CS: OutputPin,
+    CS::Error: fmt::Debug,
+    D: DelayNs,
+{
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        let mut bus = self.bus.borrow_mut();
+        self.cs.set_low().map_err(Error::ChipSelect)?;
+
+        let mut result = Ok(());
+        for operation in operations {
+            result = match operation {
+                Operation::Read(words) => bus.read(words),
+                Operation::Write(words) => bus.write(words),
+                Operation::Transfer(read, write) => bus.transfer(read, write),
+                Operation::TransferInPlace(words) => bus.transfer_in_place(words),
+                Operation::DelayNs(ns) => bus.flush().map(|_| self.delay.delay_ns(*ns)),
+            };
+            if result.is_err() {
+                break;
+            }
+        }
+        // the device is deselected once the last byte is out, even after an error
+        let flushed = bus.flush();
+
+        self.cs.set_high().map_err(Error::ChipSelect)?;
+        result.and(flushed).map_err(Error::Spi)
+    }
+}
@@ -0,0 +1,168 @@
+//! System configuration controller
+//!
+//! Unlike the STM32F0/G0 parts, the STM32L4 have no remap of PA11/PA12 over PA9/PA10 on their
+//! small packages: PA9 to PA12 are distinct pins on all the packages, e.g. USART1 on PA9/PA10
+//! and USB on PA11/PA12 on the 32-pin STM32L432, and the USB function of PA11/PA12 is selected by
+//! their alternate function (AF10) like any other, so there is no SYSCFG setting to expose.
+
+use crate::rcc::APB2;
+use crate::stm32::SYSCFG;
+
+/// Extension trait to constrain the SYSCFG peripheral
+pub trait SysCfgExt {
+    /// Enables the SYSCFG clock and constrains the peripheral
+    fn constrain(self, apb2: &mut APB2) -> SysCfg;
+}
+
+impl SysCfgExt for SYSCFG {
+    fn constrain(self, apb2: &mut APB2) -> SysCfg {
+        apb2.enr().modify(|_, w| w.syscfgen().set_bit());
+
+        SysCfg { syscfg: self }
+    }
+}
+
+/// GPIO port driving an EXTI line
+#[derive(Clone, Copy)]
+pub enum Port {
+    /// GPIOA
+    A = 0,
+    /// GPIOB
+    B = 1,
+    /// GPIOC
+    C = 2,
+    /// GPIOD
+    D = 3,
+    /// GPIOE
+    E = 4,
+    /// GPIOF
+    #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+    F = 5,
+    /// GPIOG
+    #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+    G = 6,
+    /// GPIOH
+    H = 7,
+    /// GPIOI
+    #[cfg(feature = "stm32l4x6")]
+    I = 8,
+}
+
+/// Memory mapped at address 0x0000_0000
+pub enum MemoryMapping {
+    /// Main flash memory
+    MainFlash = 0b000,
+    /// System flash memory (bootloader)
+    SystemFlash = 0b001,
+    /// FMC bank 1 (NOR/PSRAM 1 and 2)
+    #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+    Fmc = 0b010,
+    /// SRAM1
+    Sram1 = 0b011,
+    /// QUADSPI memory
+    Quadspi = 0b110,
+}
+
+/// Floating point unit exceptions that can raise the FPU interrupt
+pub enum FpuInterrupt {
+    /// Invalid operation
+    InvalidOperation = 0,
+    /// Divide-by-zero
+    DivideByZero = 1,
+    /// Underflow
+    Underflow = 2,
+    /// Overflow
+    Overflow = 3,
+    /// Input denormal
+    InputDenormal = 4,
+    /// Inexact
+    Inexact = 5,
+}
+
+/// Constrained SYSCFG peripheral
+pub struct SysCfg {
+    syscfg: SYSCFG,
+}
+
+impl SysCfg {
+    /// Connects EXTI `line` (0 to 15) to the pin with the same number on `port`
+    pub fn select_exti_source(&mut self, line: u8, port: Port) {
+        assert!(line < 16);
+
+        let offset = 4 * u32::from(line % 4);
+        let mask = !(0b1111 << offset);
+        let bits = (port as u32) << offset;
+        match line / 4 {
+            0 => self.syscfg.exticr1.modify(|r, w| unsafe { w.bits((r.bits() & mask) | bits) }),
+            1 => self.syscfg.exticr2.modify(|r, w| unsafe { w.bits((r.bits() & mask) | bits) }),
+            2 => self.syscfg.exticr3.modify(|r, w| unsafe { w.bits((r.bits() & mask) | bits) }),
+            _ => self.syscfg.exticr4.modify(|r, w| unsafe { w.bits((r.bits() & mask) | bits) }),
+        }
+    }
+
+    /// Maps `memory` at address 0x0000_0000, e.g. to run from SRAM1 with the vector table at
+    /// its start
+    pub fn remap_memory(&mut self, memory: MemoryMapping) {
+        self.syscfg.memrmp.modify(|_, w| unsafe { w.mem_mode().bits(memory as u8) });
+    }
+
+    /// Allows `interrupt` to raise the FPU interrupt
+    pub fn enable_fpu_interrupt(&mut self, interrupt: FpuInterrupt) {
+        let bit = 1 << (26 + interrupt as u32);
+        self.syscfg.cfgr1.modify(|r, w| unsafe { w.bits(r.bits() | bit) });
+    }
+
+    /// Prevents `interrupt` from raising the FPU interrupt
+    pub fn disable_fpu_interrupt(&mut self, interrupt: FpuInterrupt) {
+        let bit = 1 << (26 + interrupt as u32);
+        self.syscfg.cfgr1.modify(|r, w| unsafe { w.bits(r.bits() & !bit) });
+    }
+
+    /// Write protects the 1 KB page `page` (0 to 31) of SRAM2
+    ///
+    /// The protection can only be removed by a system reset.
+    pub fn write_protect_sram2_page(&mut self, page: u8) {
+        assert!(page < 32);
+
+        self.syscfg
+            .swpr
+            .write(|w| unsafe { w.bits(1 << u32::from(page)) });
+    }
+
+    /// Erases the whole SRAM2 by hardware, e.g. to clear secrets
+    ///
+    /// Returns once the erase is complete. Must not be called while the stack or any live data
+    /// is located in SRAM2.
+    pub fn erase_sram2(&mut self) {
+        self.syscfg.skr.write(|w| unsafe { w.key().bits(0xCA) });
+        self.syscfg.skr.write(|w| unsafe { w.key().bits(0x53) });
+        self.syscfg.scsr.modify(|_, w| w.sram2er().set_bit());
+        while self.syscfg.scsr.read().sram2bsy().bit_is_set() {}
+    }
+
+    /// Returns `true` if an SRAM2 parity error has been detected
+    ///
+    /// Parity checking is enabled by the SRAM2_PE option bit, see
+    /// `flash::OPTR::set_sram2_parity`. A parity error also raises an NMI, whose handler can use
+    /// this flag to tell it apart from other NMI sources.
+    pub fn sram2_parity_error(&self) -> bool {
+        self.syscfg.cfgr2.read().spf().bit_is_set()
+    }
+
+    /// Clears the SRAM2 parity error flag
+    pub fn clear_sram2_parity_error(&mut self) {
+        self.syscfg.cfgr2.modify(|_, w| w.spf().set_bit());
+    }
+
+    /// Connects SRAM2 parity errors to the break inputs of TIM1, TIM15 and TIM16
+    ///
+    /// The connection can only be removed by a system reset.
+    pub fn lock_sram2_parity_to_break(&mut self) {
+        self.syscfg.cfgr2.modify(|_, w| w.spl().set_bit());
+    }
+
+    /// Releases the SYSCFG peripheral
+    pub fn free(self) -> SYSCFG {
+        self.syscfg
+    }
+}
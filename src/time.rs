@@ -115,3 +115,152 @@ impl Instant {
         DWT::get_cycle_count().wrapping_sub(self.now)
     }
 }
+
+/// Measures the duration of code sections in cycles of the DWT cycle counter
+///
+/// The cycles spent reading the counter itself are measured on creation and subtracted from
+/// every measurement, so that an empty section measures 0 cycles.
+///
+/// ``` ignore
+/// let timer = MonoTimer::new(cp.DWT, clocks);
+/// let mut stopwatch = Stopwatch::new(timer);
+///
+/// let (_, cycles) = stopwatch.measure(|| filter.process(&mut samples));
+/// let (_, cycles) = measure!(stopwatch, filter.process(&mut samples));
+/// let us = stopwatch.to_micros(cycles);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Stopwatch {
+    frequency: Hertz,
+    overhead: u32,
+    start: u32,
+}
+
+impl Stopwatch {
+    /// Creates a stopwatch counting the cycles of `timer`
+    pub fn new(timer: MonoTimer) -> Self {
+        let mut stopwatch = Stopwatch {
+            frequency: timer.frequency(),
+            overhead: 0,
+            start: 0,
+        };
+
+        // the overhead may vary with the state of the pipeline and of the flash accelerator, keep
+        // the smallest one so that measurements are never underestimated
+        let mut overhead = u32::MAX;
+        for _ in 0..4 {
+            stopwatch.start();
+            overhead = overhead.min(stopwatch.stop());
+        }
+        stopwatch.overhead = overhead;
+
+        stopwatch
+    }
+
+    /// Starts a measurement
+    #[inline(always)]
+    pub fn start(&mut self) {
+        self.start = DWT::get_cycle_count();
+    }
+
+    /// Returns the cycles elapsed since the last `start`
+    ///
+    /// Sections longer than 2^32 cycles, i.e. about 54 seconds at 80 MHz, can't be measured.
+    #[inline(always)]
+    pub fn stop(&mut self) -> u32 {
+        DWT::get_cycle_count()
+            .wrapping_sub(self.start)
+            .saturating_sub(self.overhead)
+    }
+
+    /// Runs `f`, returns its result and the cycles it took
+    #[inline(always)]
+    pub fn measure<F, R>(&mut self, f: F) -> (R, u32)
+    where
+        F: FnOnce() -> R,
+    {
+        self.start();
+        let result = f();
+        (result, self.stop())
+    }
+
+    /// Starts a measurement that ends, and is written to `cycles`, when the returned guard is
+    /// dropped
+    pub fn scope<'a>(&mut self, cycles: &'a mut u32) -> Scope<'a> {
+        Scope {
+            start: DWT::get_cycle_count(),
+            overhead: self.overhead,
+            cycles,
+        }
+    }
+
+    /// Returns the cycles subtracted from every measurement
+    pub fn overhead(&self) -> u32 {
+        self.overhead
+    }
+
+    /// Returns the frequency of the counted cycles
+    pub fn frequency(&self) -> Hertz {
+        self.frequency
+    }
+
+    /// Converts `cycles` into microseconds
+    pub fn to_micros(&self, cycles: u32) -> u32 {
+        (u64::from(cycles) * 1_000_000 / u64::from(self.frequency.0)) as u32
+    }
+
+    /// Converts `cycles` into nanoseconds
+    pub fn to_nanos(&self, cycles: u32) -> u64 {
+        u64::from(cycles) * 1_000_000_000 / u64::from(self.frequency.0)
+    }
+}
+
+/// A measurement in progress, see `Stopwatch::scope`
+pub struct Scope<'a> {
+    start: u32,
+    overhead: u32,
+    cycles: &'a mut u32,
+}
+
+impl<'a> Drop for Scope<'a> {
+    fn drop(&mut self) {
+        *self.cycles = DWT::get_cycle_count()
+            .wrapping_sub(self.start)
+            .saturating_sub(self.overhead);
+    }
+}
+
+/// Evaluates an expression, returns its result and the cycles it took according to a
+/// `Stopwatch`
+///
+/// ``` ignore
+/// let (value, cycles) = measure!(stopwatch, adc.read(&mut pin));
+/// ```
+#[macro_export]
+macro_rules! measure {
+    ($stopwatch:expr, $e:expr) => {{
+        $stopwatch.start();
+        let result = $e;
+        (result, $stopwatch.stop())
+    }};
+}
+
+/// Measures the rest of the enclosing scope, writing the cycles it took to a `u32` place when
+/// the scope ends, including through an early return
+///
+/// ``` ignore
+/// fn isr(stopwatch: &mut Stopwatch, worst: &mut u32) {
+///     let mut cycles = 0;
+///     {
+///         measure_scope!(stopwatch, cycles);
+///         // ...
+///     }
+///     *worst = (*worst).max(cycles);
+/// }
+/// ```
+#[macro_export]
+macro_rules! measure_scope {
+    ($stopwatch:expr, $cycles:expr) => {
+        let _scope = $stopwatch.scope(&mut $cycles);
+    };
+}
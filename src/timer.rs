@@ -4,7 +4,13 @@ use cast::{u16, u32};
 use crate::hal::timer::{CountDown, Periodic};
 use nb;
 use crate::stm32::{TIM2, TIM6, TIM7, TIM15, TIM16};
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+use crate::stm32::{TIM3, TIM4, TIM5, TIM17};
 use void::Void;
+#[cfg(feature = "monotonic")]
+use fugit::{TimerDurationU32, TimerInstantU32};
+#[cfg(feature = "monotonic")]
+use rtic_monotonic::Monotonic;
 
 use crate::rcc::{APB1R1, Clocks, APB2};
 use crate::time::Hertz;
@@ -16,12 +22,98 @@ pub struct Timer<TIM> {
     timeout: Hertz,
 }
 
+/// RTIC monotonic on a 32-bit timer counting at `FREQ` Hz, with the `monotonic` feature
+///
+/// The instants wrap around after 2^32 counts, e.g. about 71 minutes at 1 MHz; instants less
+/// than half of that apart are still ordered correctly.
+#[cfg(feature = "monotonic")]
+pub struct MonoTimer<TIM, const FREQ: u32> {
+    tim: TIM,
+}
+
 /// Interrupt events
 pub enum Event {
     /// Timer timed out / count down ended
     TimeOut,
 }
 
+/// Trigger output (TRGO) sent to other timers or to the ADC / DAC
+pub enum TriggerOutput {
+    /// The UG bit, or a reset from the slave mode controller
+    Reset,
+    /// The counter enable signal
+    Enable,
+    /// The update event
+    Update,
+    /// A pulse when a capture or a compare match occurs on channel 1
+    ComparePulse,
+    /// The OC1REF signal
+    Oc1Ref,
+    /// The OC2REF signal
+    Oc2Ref,
+    /// The OC3REF signal
+    Oc3Ref,
+    /// The OC4REF signal
+    Oc4Ref,
+}
+
+/// Trigger output (TRGO) of the basic timers TIM6 and TIM7, e.g. to pace the DAC or the ADC
+pub enum BasicTriggerOutput {
+    /// The UG bit
+    Reset,
+    /// The counter enable signal
+    Enable,
+    /// The update event, i.e. one trigger per period of the timer
+    Update,
+}
+
+/// Slave mode, i.e. the effect of the trigger input on the counter
+pub enum SlaveMode {
+    /// The counter is clocked by the internal clock
+    Disabled,
+    /// A rising edge of the trigger input reinitializes the counter
+    Reset,
+    /// The counter is enabled while the trigger input is high
+    Gated,
+    /// A rising edge of the trigger input starts the counter
+    Trigger,
+}
+
+/// Trigger input of the slave mode controller
+///
+/// The internal triggers ITR0 to ITR3 are connected to the TRGO of other timers, see the
+/// "TIMx internal trigger connection" table of the reference manual.
+pub enum TriggerInput {
+    /// Internal trigger 0
+    Itr0,
+    /// Internal trigger 1
+    Itr1,
+    /// Internal trigger 2
+    Itr2,
+    /// Internal trigger 3
+    Itr3,
+    /// TI1 edge detector
+    Ti1FEd,
+    /// Filtered timer input 1
+    Ti1Fp1,
+    /// Filtered timer input 2
+    Ti2Fp2,
+    /// External trigger input
+    Etrf,
+}
+
+/// Capture/compare channels
+pub enum Channel {
+    /// Channel 1
+    C1,
+    /// Channel 2
+    C2,
+    /// Channel 3
+    C3,
+    /// Channel 4
+    C4,
+}
+
 macro_rules! hal {
     ($($TIM:ident: ($tim:ident, $timXen:ident, $timXrst:ident, $apb:ident),)+) => {
         $(
@@ -122,6 +214,12 @@ macro_rules! hal {
                     self.tim.cr1.modify(|_, w| w.cen().clear_bit());
                     self.tim
                 }
+
+                /// Resets the TIM peripheral, stops its clock and releases it
+                pub fn release(self, apb: &mut $apb) -> $TIM {
+                    apb.deinit(&self.tim);
+                    self.tim
+                }
             }
         )+
     }
@@ -134,3 +232,265 @@ hal! {
     TIM15: (tim15, tim15en, tim15rst, APB2),
     TIM16: (tim16, tim16en, tim16rst, APB2),
 }
+
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+hal! {
+    TIM3: (tim3, tim3en, tim3rst, APB1R1),
+    TIM4: (tim4, tim4en, tim4rst, APB1R1),
+    TIM5: (tim5, tim5en, tim5rst, APB1R1),
+    TIM17: (tim17, tim17en, tim17rst, APB2),
+}
+
+#[cfg(feature = "monotonic")]
+macro_rules! monotonic {
+    ($($TIM:ident: ($tim:ident, $timXen:ident, $timXrst:ident, $apb:ident),)+) => {
+        $(
+            impl<const FREQ: u32> MonoTimer<$TIM, FREQ> {
+                /// Configures the timer to count at `FREQ` Hz, which has to divide its clock
+                pub fn $tim(tim: $TIM, clocks: Clocks, apb: &mut $apb) -> Self {
+                    let clk = clocks.timclk1().0;
+                    assert!(FREQ > 0 && clk % FREQ == 0 && clk / FREQ <= 1 << 16);
+
+                    apb.enr().modify(|_, w| w.$timXen().set_bit());
+                    apb.rstr().modify(|_, w| w.$timXrst().set_bit());
+                    apb.rstr().modify(|_, w| w.$timXrst().clear_bit());
+
+                    tim.psc.write(|w| unsafe { w.bits(clk / FREQ - 1) });
+                    tim.arr.write(|w| unsafe { w.bits(u32::MAX) });
+                    // CC1 is in frozen output compare mode after the reset, it only raises CC1IF
+                    tim.egr.write(|w| w.ug().set_bit());
+                    tim.sr.write(|w| unsafe { w.bits(0) });
+                    tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                    MonoTimer { tim }
+                }
+
+                /// Releases the TIM peripheral
+                pub fn free(self) -> $TIM {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    self.tim
+                }
+            }
+
+            impl<const FREQ: u32> Monotonic for MonoTimer<$TIM, FREQ> {
+                type Instant = TimerInstantU32<FREQ>;
+                type Duration = TimerDurationU32<FREQ>;
+
+                fn now(&mut self) -> Self::Instant {
+                    Self::Instant::from_ticks(self.tim.cnt.read().bits())
+                }
+
+                fn set_compare(&mut self, instant: Self::Instant) {
+                    self.tim.ccr1.write(|w| unsafe { w.bits(instant.ticks()) });
+                }
+
+                fn clear_compare_flag(&mut self) {
+                    self.tim.sr.modify(|_, w| w.cc1if().clear_bit());
+                }
+
+                fn zero() -> Self::Instant {
+                    Self::Instant::from_ticks(0)
+                }
+
+                unsafe fn reset(&mut self) {
+                    self.tim.egr.write(|w| w.ug().set_bit());
+                }
+
+                fn enable_timer(&mut self) {
+                    self.tim.dier.modify(|_, w| w.cc1ie().set_bit());
+                }
+
+                fn disable_timer(&mut self) {
+                    self.tim.dier.modify(|_, w| w.cc1ie().clear_bit());
+                }
+            }
+        )+
+    }
+}
+
+#[cfg(feature = "monotonic")]
+monotonic! {
+    TIM2: (tim2, tim2en, tim2rst, APB1R1),
+}
+
+#[cfg(all(feature = "monotonic", any(feature = "stm32l4x5", feature = "stm32l4x6")))]
+monotonic! {
+    TIM5: (tim5, tim5en, tim5rst, APB1R1),
+}
+
+macro_rules! sync {
+    ($($TIM:ident: $arr_max:expr,)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Selects the signal sent on the trigger output (TRGO)
+                // NOTE(allow) `w.mms().bits()` is safe for some timers only due to SVD omissions
+                #[allow(unused_unsafe)]
+                pub fn set_trigger_output(&mut self, trgo: TriggerOutput) {
+                    let mms = match trgo {
+                        TriggerOutput::Reset => 0b000,
+                        TriggerOutput::Enable => 0b001,
+                        TriggerOutput::Update => 0b010,
+                        TriggerOutput::ComparePulse => 0b011,
+                        TriggerOutput::Oc1Ref => 0b100,
+                        TriggerOutput::Oc2Ref => 0b101,
+                        TriggerOutput::Oc3Ref => 0b110,
+                        TriggerOutput::Oc4Ref => 0b111,
+                    };
+                    self.tim.cr2.modify(|_, w| unsafe { w.mms().bits(mms) });
+                }
+
+                /// Configures the slave mode controller to react to `trigger`
+                ///
+                /// The master/slave mode bit is set as well, so that timers slaved to this one
+                /// through its TRGO are synchronized with the trigger input.
+                pub fn set_slave_mode(&mut self, mode: SlaveMode, trigger: TriggerInput) {
+                    let sms = match mode {
+                        SlaveMode::Disabled => 0b000,
+                        SlaveMode::Reset => 0b100,
+                        SlaveMode::Gated => 0b101,
+                        SlaveMode::Trigger => 0b110,
+                    };
+                    let ts = match trigger {
+                        TriggerInput::Itr0 => 0b000,
+                        TriggerInput::Itr1 => 0b001,
+                        TriggerInput::Itr2 => 0b010,
+                        TriggerInput::Itr3 => 0b011,
+                        TriggerInput::Ti1FEd => 0b100,
+                        TriggerInput::Ti1Fp1 => 0b101,
+                        TriggerInput::Ti2Fp2 => 0b110,
+                        TriggerInput::Etrf => 0b111,
+                    };
+                    let msm = match mode {
+                        SlaveMode::Disabled => false,
+                        _ => true,
+                    };
+
+                    // TS may only be changed while the slave mode is disabled
+                    self.tim.smcr.modify(|_, w| unsafe { w.sms().bits(0) });
+                    self.tim.smcr.modify(|_, w| unsafe { w.ts().bits(ts).msm().bit(msm) });
+                    self.tim.smcr.modify(|_, w| unsafe { w.sms().bits(sms) });
+                }
+
+                /// Enables or disables one-pulse mode, where the counter stops at the next
+                /// update event
+                pub fn set_one_pulse(&mut self, enabled: bool) {
+                    self.tim.cr1.modify(|_, w| w.opm().bit(enabled));
+                }
+
+                /// Prepares a single pulse of `width` counter ticks on `channel`, starting
+                /// `delay` counter ticks after the counter is started
+                ///
+                /// A counter tick is a period of the timer clock divided by the prescaler chosen
+                /// by the last `start`. The counter is stopped and one-pulse mode is enabled; the
+                /// pulse is emitted on `resume`, or on the trigger input when the slave mode is
+                /// `SlaveMode::Trigger`. `delay` and `width` must be at least 1, and
+                /// `delay + width - 1` must fit in the counter (16 bits, 32 bits for TIM2 and
+                /// TIM5).
+                pub fn one_pulse(&mut self, channel: Channel, delay: u32, width: u32) {
+                    assert!(delay > 0 && width > 0);
+                    // the output is active from CNT = delay to CNT = ARR included
+                    let arr = u64::from(delay) + u64::from(width) - 1;
+                    assert!(arr <= $arr_max);
+
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    self.tim.cnt.write(|w| unsafe { w.bits(0) });
+                    self.tim.arr.write(|w| unsafe { w.bits(arr as u32) });
+
+                    // PWM mode 2: the output is active once the counter reaches the compare value
+                    match channel {
+                        Channel::C1 => {
+                            self.tim.ccr1.write(|w| unsafe { w.bits(delay) });
+                            self.tim.ccmr1_output.modify(|_, w| unsafe {
+                                w.cc1s().bits(0b00).oc1m().bits(0b111)
+                            });
+                            self.tim.ccer.modify(|_, w| w.cc1e().set_bit());
+                        }
+                        Channel::C2 => {
+                            self.tim.ccr2.write(|w| unsafe { w.bits(delay) });
+                            self.tim.ccmr1_output.modify(|_, w| unsafe {
+                                w.cc2s().bits(0b00).oc2m().bits(0b111)
+                            });
+                            self.tim.ccer.modify(|_, w| w.cc2e().set_bit());
+                        }
+                        Channel::C3 => {
+                            self.tim.ccr3.write(|w| unsafe { w.bits(delay) });
+                            self.tim.ccmr2_output.modify(|_, w| unsafe {
+                                w.cc3s().bits(0b00).oc3m().bits(0b111)
+                            });
+                            self.tim.ccer.modify(|_, w| w.cc3e().set_bit());
+                        }
+                        Channel::C4 => {
+                            self.tim.ccr4.write(|w| unsafe { w.bits(delay) });
+                            self.tim.ccmr2_output.modify(|_, w| unsafe {
+                                w.cc4s().bits(0b00).oc4m().bits(0b111)
+                            });
+                            self.tim.ccer.modify(|_, w| w.cc4e().set_bit());
+                        }
+                    }
+
+                    self.tim.cr1.modify(|_, w| w.opm().set_bit());
+                }
+
+                /// Stops the counter
+                pub fn pause(&mut self) {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                }
+
+                /// Starts the counter again
+                pub fn resume(&mut self) {
+                    self.tim.cr1.modify(|_, w| w.cen().set_bit());
+                }
+            }
+        )+
+    }
+}
+
+sync! {
+    TIM2: 0xFFFF_FFFF,
+}
+
+#[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
+sync! {
+    TIM3: 0xFFFF,
+    TIM4: 0xFFFF,
+    TIM5: 0xFFFF_FFFF,
+}
+
+macro_rules! basic {
+    ($($TIM:ident,)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Selects the signal sent on the trigger output (TRGO)
+                pub fn set_trigger_output(&mut self, trgo: BasicTriggerOutput) {
+                    let mms = match trgo {
+                        BasicTriggerOutput::Reset => 0b000,
+                        BasicTriggerOutput::Enable => 0b001,
+                        BasicTriggerOutput::Update => 0b010,
+                    };
+                    self.tim.cr2.modify(|_, w| unsafe { w.mms().bits(mms) });
+                }
+
+                /// Enables or disables the DMA request raised on every update event, e.g. to
+                /// feed a peripheral at the rate of the timer
+                pub fn set_update_dma(&mut self, enabled: bool) {
+                    self.tim.dier.modify(|_, w| w.ude().bit(enabled));
+                }
+
+                /// Stops the counter
+                pub fn pause(&mut self) {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                }
+
+                /// Starts the counter again
+                pub fn resume(&mut self) {
+                    self.tim.cr1.modify(|_, w| w.cen().set_bit());
+                }
+            }
+        )+
+    }
+}
+
+basic! {
+    TIM6,
+    TIM7,
+}
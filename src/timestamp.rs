@@ -0,0 +1,275 @@
+//! Time-of-arrival timestamps of external events
+//!
+//! `Timestamps` runs TIM2 as a free-running 32-bit counter, e.g. at 1 MHz where it wraps around
+//! after 71 minutes, and stamps events in two ways:
+//!
+//! - in hardware, by `capture`: a channel of TIM2 latches the counter on an edge of its pin,
+//!   without any latency, e.g. on a sensor data-ready line, or on the RX line of a USART also
+//!   wired to a TIM2 pin, to time the breaks of DMX or the pulses of IrDA
+//! - in software, by `now` at the start of the interrupt handler of the event, e.g. an EXTI line
+//!   or the RXNE interrupt of a USART; the stamp is then late by the interrupt latency
+//!
+//! ``` ignore
+//! let timestamps = Timestamps::tim2(dp.TIM2, 1.mhz(), clocks, &mut rcc.apb1r1);
+//!
+//! let pa3 = gpioa.pa3.into_af1(&mut gpioa.moder, &mut gpioa.afrl);
+//! let mut data_ready = timestamps.capture(pa3, Edge::Falling).ok().expect("channel 4 in use");
+//!
+//! if let Some(start) = data_ready.read() {
+//!     let elapsed = timestamps.now().wrapping_sub(start);
+//! }
+//! ```
+
+use core::ptr;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use cortex_m::interrupt;
+
+use crate::gpio::gpioa::{PA0, PA1, PA15, PA2, PA3, PA5};
+use crate::gpio::gpiob::{PB10, PB11, PB3};
+use crate::gpio::{Alternate, AF1};
+use crate::rcc::{Clocks, APB1R1};
+use crate::stm32::TIM2;
+use crate::time::Hertz;
+
+/// Offset of the capture/compare register 1, followed by those of the channels 2 to 4
+const CCR1: usize = 0x34;
+
+// FIXME these should be "closed" traits
+/// Channel pin of TIM2 - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// `CHANNEL` must be the index of the TIM2 channel the pin is routed to, the
+/// driver reads the capture register at that index
+pub unsafe trait Pin {
+    #[doc(hidden)]
+    /// Index of the channel, from 0
+    const CHANNEL: u8;
+}
+
+unsafe impl<MODE> Pin for PA0<Alternate<AF1, MODE>> {
+    const CHANNEL: u8 = 0;
+}
+unsafe impl<MODE> Pin for PA5<Alternate<AF1, MODE>> {
+    const CHANNEL: u8 = 0;
+}
+unsafe impl<MODE> Pin for PA15<Alternate<AF1, MODE>> {
+    const CHANNEL: u8 = 0;
+}
+unsafe impl<MODE> Pin for PA1<Alternate<AF1, MODE>> {
+    const CHANNEL: u8 = 1;
+}
+unsafe impl<MODE> Pin for PB3<Alternate<AF1, MODE>> {
+    const CHANNEL: u8 = 1;
+}
+unsafe impl<MODE> Pin for PA2<Alternate<AF1, MODE>> {
+    const CHANNEL: u8 = 2;
+}
+unsafe impl<MODE> Pin for PB10<Alternate<AF1, MODE>> {
+    const CHANNEL: u8 = 2;
+}
+unsafe impl<MODE> Pin for PA3<Alternate<AF1, MODE>> {
+    const CHANNEL: u8 = 3;
+}
+unsafe impl<MODE> Pin for PB11<Alternate<AF1, MODE>> {
+    const CHANNEL: u8 = 3;
+}
+
+/// Edges captured
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    /// Rising edges
+    Rising,
+    /// Falling edges
+    Falling,
+    /// Both edges
+    Both,
+}
+
+/// Free-running TIM2 counter stamping events
+pub struct Timestamps {
+    tim: TIM2,
+    tick: Hertz,
+    /// Channels used by a `Capture`, one bit each
+    claimed: AtomicU8,
+}
+
+impl Timestamps {
+    /// Starts TIM2 counting at `resolution`
+    ///
+    /// The resolution is rounded to a division of the timer clock, see `tick_frequency`.
+    pub fn tim2<F>(tim: TIM2, resolution: F, clocks: Clocks, apb: &mut APB1R1) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        apb.enr().modify(|_, w| w.tim2en().set_bit());
+        apb.rstr().modify(|_, w| w.tim2rst().set_bit());
+        apb.rstr().modify(|_, w| w.tim2rst().clear_bit());
+
+        let clk = clocks.timclk1().0;
+        let psc = (clk / resolution.into().0).max(1) - 1;
+        assert!(psc <= 0xffff);
+
+        tim.psc.write(|w| unsafe { w.bits(psc) });
+        tim.arr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        // load the prescaler
+        tim.egr.write(|w| w.ug().set_bit());
+        tim.sr.write(|w| unsafe { w.bits(0) });
+        tim.cr1.write(|w| w.cen().set_bit());
+
+        Timestamps {
+            tim,
+            tick: Hertz(clk / (psc + 1)),
+            claimed: AtomicU8::new(0),
+        }
+    }
+
+    /// Returns the frequency of the counter, i.e. the resolution of the timestamps
+    pub fn tick_frequency(&self) -> Hertz {
+        self.tick
+    }
+
+    /// Returns the counter, e.g. to stamp an event at the start of its interrupt handler
+    #[inline(always)]
+    pub fn now(&self) -> u32 {
+        self.tim.cnt.read().bits()
+    }
+
+    /// Captures the counter on `edge` of `pin`, by the channel of TIM2 of the pin
+    ///
+    /// Returns the pin back if its channel is already used by another `Capture`, e.g. of PA0
+    /// and PA5, which share channel 1.
+    pub fn capture<PIN>(&self, pin: PIN, edge: Edge) -> Result<Capture<'_, PIN>, PIN>
+    where
+        PIN: Pin,
+    {
+        let bit = 1 << PIN::CHANNEL;
+        if self.claimed.fetch_or(bit, Ordering::Relaxed) & bit != 0 {
+            return Err(pin);
+        }
+
+        let c = u32::from(PIN::CHANNEL);
+        // CCxP, CCxNP
+        let polarity = match edge {
+            Edge::Rising => 0b0000,
+            Edge::Falling => 0b0010,
+            Edge::Both => 0b1010,
+        };
+
+        // NOTE(unsafe) the channel bits are only written by its `Capture`, in critical sections
+        interrupt::free(|_| unsafe {
+            self.tim
+                .ccer
+                .modify(|r, w| w.bits(r.bits() & !(0b1111 << (4 * c))));
+            // CCxS = TIx, no filter, no prescaler
+            let offset = 8 * (c % 2);
+            if c < 2 {
+                self.tim
+                    .ccmr1_output
+                    .modify(|r, w| w.bits((r.bits() & !(0xff << offset)) | (0b01 << offset)));
+            } else {
+                self.tim
+                    .ccmr2_output
+                    .modify(|r, w| w.bits((r.bits() & !(0xff << offset)) | (0b01 << offset)));
+            }
+            // CCxIF, CCxOF
+            self.tim
+                .sr
+                .write(|w| w.bits(!((1 << (c + 1)) | (1 << (c + 9)))));
+            self.tim
+                .ccer
+                .modify(|r, w| w.bits(r.bits() | ((polarity | 1) << (4 * c))));
+        });
+
+        Ok(Capture {
+            pin,
+            timestamps: self,
+        })
+    }
+
+    /// Stops the counter and releases TIM2
+    pub fn free(self) -> TIM2 {
+        self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+        self.tim
+    }
+}
+
+/// Input capture of the counter on a pin, see `Timestamps::capture`
+pub struct Capture<'a, PIN> {
+    pin: PIN,
+    timestamps: &'a Timestamps,
+}
+
+impl<'a, PIN> Capture<'a, PIN>
+where
+    PIN: Pin,
+{
+    /// Returns the counter latched by the last edge, if there has been one since the last `read`
+    pub fn read(&mut self) -> Option<u32> {
+        let c = usize::from(PIN::CHANNEL);
+        // NOTE(unsafe) atomic read with no side effects
+        let sr = unsafe { (*TIM2::ptr()).sr.read().bits() };
+        if sr & (1 << (c + 1)) == 0 {
+            return None;
+        }
+
+        // NOTE(unsafe) the read of CCRx clears CCxIF, which is only cleared by this `Capture`
+        Some(unsafe { ptr::read_volatile((TIM2::ptr() as usize + CCR1 + 4 * c) as *const u32) })
+    }
+
+    /// Returns `true` if an edge was captured while the previous one wasn't read yet, i.e.
+    /// timestamps were lost, and clears the flag
+    pub fn is_overcaptured(&mut self) -> bool {
+        let c = PIN::CHANNEL;
+        // NOTE(unsafe) atomic read, and write clearing only the CCxOF flag
+        unsafe {
+            let tim = &*TIM2::ptr();
+            let overcaptured = tim.sr.read().bits() & (1 << (c + 9)) != 0;
+            if overcaptured {
+                tim.sr.write(|w| w.bits(!(1 << (c + 9))));
+            }
+            overcaptured
+        }
+    }
+
+    /// Starts raising the TIM2 interrupt on every capture
+    pub fn listen(&mut self) {
+        self.set_interrupt(true);
+    }
+
+    /// Stops raising the TIM2 interrupt on captures
+    pub fn unlisten(&mut self) {
+        self.set_interrupt(false);
+    }
+
+    /// Stops capturing and releases the pin, and its channel
+    pub fn free(mut self) -> PIN {
+        self.set_interrupt(false);
+        let c = PIN::CHANNEL;
+        // NOTE(unsafe) atomic read-modify-write, in a critical section
+        interrupt::free(|_| unsafe {
+            (*TIM2::ptr())
+                .ccer
+                .modify(|r, w| w.bits(r.bits() & !(1 << (4 * c))))
+        });
+        self.timestamps
+            .claimed
+            .fetch_and(!(1 << c), Ordering::Relaxed);
+        self.pin
+    }
+
+    fn set_interrupt(&mut self, enabled: bool) {
+        let bit = 1 << (PIN::CHANNEL + 1);
+        // NOTE(unsafe) atomic read-modify-write, in a critical section
+        interrupt::free(|_| unsafe {
+            (*TIM2::ptr()).dier.modify(|r, w| {
+                w.bits(if enabled {
+                    r.bits() | bit
+                } else {
+                    r.bits() & !bit
+                })
+            })
+        });
+    }
+}
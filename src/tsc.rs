@@ -20,7 +20,10 @@ pub enum Event {
     EndOfAcquisition
 }
 
+/// TSC error
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum Error {
     /// Max count error
     MaxCountError,
@@ -92,6 +95,7 @@ pub enum ClockPrescaler {
     HclkDiv128 = 0b111,
 }
 
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum MaxCountError {
     /// 000: 255
     U255 = 000,
@@ -271,4 +275,111 @@ impl<SPIN> Tsc<SPIN> {
     pub fn free(self) -> (TSC, SPIN) {
         (self.tsc, self.sample_pin)
     }
-}
\ No newline at end of file
+}
+
+/// Number of acquisitions averaged to compute the initial baseline of a `TouchKey`
+const CALIBRATION_SAMPLES: u8 = 8;
+
+/// A touch key on top of the raw TSC counts
+///
+/// Touching the electrode increases its capacitance, which lowers the count of an acquisition.
+/// The key tracks the untouched count (the baseline) to follow slow drifts, e.g. due to
+/// temperature or humidity, and reports a touch once the count has dropped below the baseline by
+/// at least the threshold for a number of consecutive acquisitions. The first acquisitions after
+/// creation are used to calibrate the baseline and must be done while the key is not touched.
+pub struct TouchKey<PIN> {
+    pin: PIN,
+    /// Baseline with 4 fractional bits
+    baseline: u32,
+    threshold: u16,
+    debounce: u8,
+    calibration: u8,
+    counter: u8,
+    touched: bool,
+}
+
+impl<PIN> TouchKey<PIN>
+where
+    PIN: ChannelPin<TSC>,
+{
+    /// Creates a key reporting touches that lower the count by at least `threshold` during
+    /// `debounce` consecutive acquisitions
+    pub fn new(pin: PIN, threshold: u16, debounce: u8) -> Self {
+        TouchKey {
+            pin,
+            baseline: 0,
+            threshold,
+            debounce: debounce.max(1),
+            calibration: CALIBRATION_SAMPLES,
+            counter: 0,
+            touched: false,
+        }
+    }
+
+    /// Acquires the key, returns whether it is touched
+    pub fn acquire<SPIN>(&mut self, tsc: &Tsc<SPIN>) -> Result<bool, Error> {
+        let count = tsc.acquire(&mut self.pin)?;
+        Ok(self.update(count))
+    }
+
+    /// Updates the key with the `count` of an acquisition, returns whether it is touched
+    ///
+    /// Use this when the acquisition is driven by interrupts rather than by `acquire`.
+    pub fn update(&mut self, count: u16) -> bool {
+        let count = u32::from(count) << 4;
+
+        if self.calibration > 0 {
+            self.baseline += count / u32::from(CALIBRATION_SAMPLES);
+            self.calibration -= 1;
+            return false;
+        }
+
+        let delta = self.baseline.saturating_sub(count) >> 4;
+        // release at half the threshold to avoid chattering around it
+        let touched = if self.touched {
+            delta >= u32::from(self.threshold / 2)
+        } else {
+            delta >= u32::from(self.threshold)
+        };
+
+        if touched == self.touched {
+            self.counter = 0;
+        } else {
+            self.counter += 1;
+            if self.counter >= self.debounce {
+                self.touched = touched;
+                self.counter = 0;
+            }
+        }
+
+        // follow the drift while the key is released, with a time constant of 16 acquisitions
+        if !self.touched && !touched {
+            self.baseline = self.baseline - self.baseline / 16 + count / 16;
+        }
+
+        self.touched
+    }
+
+    /// Returns whether the key was touched at the last acquisition
+    pub fn is_touched(&self) -> bool {
+        self.touched
+    }
+
+    /// Returns the count of the untouched key
+    pub fn baseline(&self) -> u16 {
+        (self.baseline >> 4) as u16
+    }
+
+    /// Restarts the calibration of the baseline with the next acquisitions
+    pub fn recalibrate(&mut self) {
+        self.baseline = 0;
+        self.calibration = CALIBRATION_SAMPLES;
+        self.counter = 0;
+        self.touched = false;
+    }
+
+    /// Releases the channel pin
+    pub fn free(self) -> PIN {
+        self.pin
+    }
+}
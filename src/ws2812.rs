@@ -0,0 +1,297 @@
+//! WS2812 ("NeoPixel") LED strips
+//!
+//! WS2812 LEDs are daisy-chained on a single data line, and receive 24 bits per LED at 800 kHz,
+//! each bit being a pulse whose width tells a 0 from a 1. The pulses are generated without CPU
+//! involvement, by DMA, either:
+//!
+//! - on the MOSI pin of SPI1 clocked at about 2.4 MHz (2 to 3 MHz), every bit being encoded in 3
+//!   SPI bits, see `encode_spi` and `Spi::write_ws2812`
+//! - on a TIM2 channel 1 pin, every bit being encoded in a PWM duty cycle, see `encode_pwm` and
+//!   `Ws2812Pwm`
+//!
+//! The data line has to stay low for at least 50 µs after a transfer before a new one starts.
+//!
+//! ``` ignore
+//! let spi = Spi::spi1(dp.SPI1, (sck, miso, mosi), MODE_0, 2_500.khz(), clocks, &mut rcc.apb2);
+//!
+//! let buffer = singleton!(: [u8; 9 * LEDS] = [0; 9 * LEDS]).unwrap();
+//! ws2812::encode_spi(&colors, &mut buffer[..]);
+//! let (buffer, chan, spi) = spi.write_ws2812(channels.3, buffer).wait().unwrap();
+//! ```
+
+use core::sync::atomic::{self, Ordering};
+
+use cast::u16;
+
+use crate::dma::{dma1, Priority, ReadBuffer, Transfer, R};
+use crate::pwm_input::Pin;
+use crate::rcc::{ClockError, Clocks, APB1R1};
+use crate::spi::Spi;
+use crate::stm32::{SPI1, TIM2};
+
+/// Bit rate of the data line
+const BIT_RATE: u32 = 800_000;
+
+/// Color of a LED
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rgb {
+    /// Red
+    pub r: u8,
+    /// Green
+    pub g: u8,
+    /// Blue
+    pub b: u8,
+}
+
+impl Rgb {
+    /// Creates a color from its components
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+
+    /// Returns the bytes in the order they are sent, i.e. green, red then blue
+    fn grb(self) -> [u8; 3] {
+        [self.g, self.r, self.b]
+    }
+}
+
+/// Encodes `colors` into the SPI stream `buffer`, returns the number of bytes used
+///
+/// Every LED takes 9 bytes; a 0 is encoded as `100` and a 1 as `110`. Panics if `buffer` is too
+/// small.
+pub fn encode_spi(colors: &[Rgb], buffer: &mut [u8]) -> usize {
+    let len = 9 * colors.len();
+    assert!(buffer.len() >= len);
+
+    for (color, chunk) in colors.iter().zip(buffer.chunks_mut(9)) {
+        let mut bits: u32 = 0;
+        let mut count = 0;
+        let mut out = chunk.iter_mut();
+
+        for byte in color.grb().iter() {
+            for i in (0..8).rev() {
+                let bit = (byte >> i) & 1;
+                bits = (bits << 3) | 0b100 | (u32::from(bit) << 1);
+                count += 3;
+
+                while count >= 8 {
+                    count -= 8;
+                    if let Some(o) = out.next() {
+                        *o = (bits >> count) as u8;
+                    }
+                }
+            }
+        }
+    }
+
+    len
+}
+
+/// Encodes `colors` into the duty cycles `buffer`, for a PWM `period`, returns the number of
+/// words used
+///
+/// Every LED takes 24 words, and a trailing 0 keeps the line low once the transfer is done. A 0
+/// has a duty cycle of 1/3, and a 1 of 2/3. Panics if `buffer` is too small.
+pub fn encode_pwm(colors: &[Rgb], buffer: &mut [u16], period: u16) -> usize {
+    let len = 24 * colors.len() + 1;
+    assert!(buffer.len() >= len);
+
+    let zero = period / 3;
+    let one = 2 * (period / 3);
+    let mut out = buffer.iter_mut();
+    for color in colors {
+        for byte in color.grb().iter() {
+            for i in (0..8).rev() {
+                if let Some(o) = out.next() {
+                    *o = if byte & (1 << i) != 0 { one } else { zero };
+                }
+            }
+        }
+    }
+    if let Some(o) = out.next() {
+        *o = 0;
+    }
+
+    len
+}
+
+impl<PINS> Spi<SPI1, PINS> {
+    /// Sends a buffer encoded by `encode_spi` on MOSI
+    ///
+    /// The SPI has to be clocked at 2 to 3 MHz, with an idle low clock (`MODE_0` or `MODE_1`).
+    pub fn write_ws2812<B>(
+        self,
+        mut chan: dma1::C3,
+        buffer: B,
+    ) -> Transfer<R, B, dma1::C3, Self>
+    where
+        B: ReadBuffer<Word = u8>,
+    {
+        {
+            // NOTE(unsafe) the buffer is owned by the transfer until it's over
+            let (ptr, len) = unsafe { buffer.read_buffer() };
+            chan.cmar().write(|w| w.ma().bits(ptr as usize as u32));
+            chan.cndtr().write(|w| w.ndt().bits(u16(len).unwrap()));
+            chan.cpar().write(|w| unsafe {
+                w.pa().bits(&(*SPI1::ptr()).dr as *const _ as usize as u32)
+            });
+
+            // Tell DMA to request from SPI1 TX
+            chan.cselr().modify(|_, w| w.c3s().bits(0b0001));
+
+            // TODO can we weaken this compiler barrier?
+            // NOTE(compiler_fence) operations on `buffer` should not be reordered after the next
+            // statement, which starts the DMA transfer
+            atomic::compiler_fence(Ordering::SeqCst);
+
+            let pl = chan.priority_or(Priority::High) as u8;
+            chan.ccr().modify(|_, w| unsafe {
+                w.mem2mem()
+                    .clear_bit()
+                    .pl()
+                    .bits(pl)
+                    // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                    .msize()
+                    .bits(0b00)
+                    // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                    .psize()
+                    .bits(0b00)
+                    // incr mem address
+                    .minc()
+                    .set_bit()
+                    .pinc()
+                    .clear_bit()
+                    .circ()
+                    .clear_bit()
+                    // memory to peripheral
+                    .dir()
+                    .set_bit()
+                    .en()
+                    .set_bit()
+            });
+        }
+
+        // NOTE(unsafe) the SPI is owned by `self`
+        unsafe { (*SPI1::ptr()).cr2.modify(|_, w| w.txdmaen().set_bit()) };
+
+        Transfer::r(buffer, chan, self)
+    }
+}
+
+/// WS2812 data line driven by the channel 1 of TIM2
+pub struct Ws2812Pwm<PIN> {
+    tim: TIM2,
+    pin: PIN,
+    period: u16,
+}
+
+impl<PIN> Ws2812Pwm<PIN>
+where
+    PIN: Pin<TIM2>,
+{
+    /// Configures TIM2 to output the 800 kHz bit stream on `pin`
+    ///
+    /// Panics if the bit rate can't be generated, see `try_tim2`.
+    pub fn tim2(tim: TIM2, pin: PIN, clocks: Clocks, apb: &mut APB1R1) -> Self {
+        Self::try_tim2(tim, pin, clocks, apb).unwrap()
+    }
+
+    /// Configures TIM2 to output the 800 kHz bit stream on `pin`, fails if the timer clock is
+    /// below 800 kHz
+    pub fn try_tim2(
+        tim: TIM2,
+        pin: PIN,
+        clocks: Clocks,
+        apb: &mut APB1R1,
+    ) -> Result<Self, ClockError> {
+        // checked before the peripheral is touched
+        let period = match u16(clocks.timclk1().0 / BIT_RATE) {
+            Ok(period) if period > 0 => period,
+            _ => return Err(ClockError::BaudRate),
+        };
+
+        apb.enr().modify(|_, w| w.tim2en().set_bit());
+        apb.rstr().modify(|_, w| w.tim2rst().set_bit());
+        apb.rstr().modify(|_, w| w.tim2rst().clear_bit());
+
+        tim.psc.write(|w| unsafe { w.bits(0) });
+        tim.arr.write(|w| unsafe { w.bits(u32::from(period) - 1) });
+        tim.ccr1.write(|w| unsafe { w.bits(0) });
+
+        // PWM mode 1, with the compare value loaded by DMA on each update
+        tim.ccmr1_output
+            .write(|w| unsafe { w.oc1m().bits(0b110).oc1pe().set_bit() });
+        tim.ccer.write(|w| w.cc1e().set_bit());
+        tim.cr1.write(|w| w.arpe().set_bit());
+        tim.egr.write(|w| w.ug().set_bit());
+        tim.cr1.modify(|_, w| w.cen().set_bit());
+
+        Ok(Ws2812Pwm { tim, pin, period })
+    }
+
+    /// Returns the PWM period to pass to `encode_pwm`
+    pub fn period(&self) -> u16 {
+        self.period
+    }
+
+    /// Sends a buffer encoded by `encode_pwm`
+    pub fn write<B>(self, mut chan: dma1::C2, buffer: B) -> Transfer<R, B, dma1::C2, Self>
+    where
+        B: ReadBuffer<Word = u16>,
+    {
+        {
+            // NOTE(unsafe) the buffer is owned by the transfer until it's over
+            let (ptr, len) = unsafe { buffer.read_buffer() };
+            chan.cmar().write(|w| w.ma().bits(ptr as usize as u32));
+            chan.cndtr().write(|w| w.ndt().bits(u16(len).unwrap()));
+            chan.cpar().write(|w| unsafe {
+                w.pa().bits(&(*TIM2::ptr()).ccr1 as *const _ as usize as u32)
+            });
+
+            // Tell DMA to request from the TIM2 update event
+            chan.cselr().modify(|_, w| w.c2s().bits(0b0100));
+
+            // TODO can we weaken this compiler barrier?
+            // NOTE(compiler_fence) operations on `buffer` should not be reordered after the next
+            // statement, which starts the DMA transfer
+            atomic::compiler_fence(Ordering::SeqCst);
+
+            let pl = chan.priority_or(Priority::VeryHigh) as u8;
+            chan.ccr().modify(|_, w| unsafe {
+                w.mem2mem()
+                    .clear_bit()
+                    .pl()
+                    .bits(pl)
+                    // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                    .msize()
+                    .bits(0b01)
+                    // 00: 8-bits, 01: 16-bits, 10: 32-bits, 11: Reserved
+                    .psize()
+                    .bits(0b10)
+                    // incr mem address
+                    .minc()
+                    .set_bit()
+                    .pinc()
+                    .clear_bit()
+                    .circ()
+                    .clear_bit()
+                    // memory to peripheral
+                    .dir()
+                    .set_bit()
+                    .en()
+                    .set_bit()
+            });
+        }
+
+        self.tim.dier.modify(|_, w| w.ude().set_bit());
+
+        Transfer::r(buffer, chan, self)
+    }
+
+    /// Releases the TIM peripheral and the pin
+    pub fn free(self) -> (TIM2, PIN) {
+        self.tim.dier.modify(|_, w| w.ude().clear_bit());
+        self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+        (self.tim, self.pin)
+    }
+}